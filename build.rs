@@ -0,0 +1,63 @@
+//! embeds the Sigma rules under `rules/core` (or the directory in
+//! `SIGMARS_CORE_RULES`) when the `embedded_rules` feature is enabled
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn main() {
+    println!("cargo:rerun-if-env-changed=SIGMARS_CORE_RULES");
+    if env::var_os("CARGO_FEATURE_EMBEDDED_RULES").is_none() {
+        return;
+    }
+
+    let dir = env::var_os("SIGMARS_CORE_RULES")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("rules/core"));
+    println!("cargo:rerun-if-changed={}", dir.display());
+
+    let mut files = vec![];
+    rule_files(&dir, &mut files);
+    files.sort();
+    if files.is_empty() {
+        panic!(
+            "no Sigma rules (*.yml) in {} to embed, see rules/core/README.md",
+            dir.display()
+        );
+    }
+
+    let entries = files
+        .iter()
+        .map(|path| {
+            println!("cargo:rerun-if-changed={}", path.display());
+            format!(
+                "    ({:?}, include_str!({:?})),\n",
+                path.strip_prefix(&dir).unwrap().display().to_string(),
+                path.display().to_string()
+            )
+        })
+        .collect::<String>();
+
+    fs::write(
+        Path::new(&env::var("OUT_DIR").unwrap()).join("core_rules.rs"),
+        format!("pub(crate) static CORE_RULES: &[(&str, &str)] = &[\n{entries}];\n"),
+    )
+    .unwrap();
+}
+
+fn rule_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+    {
+        if path.is_dir() {
+            println!("cargo:rerun-if-changed={}", path.display());
+            rule_files(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "yml") {
+            files.push(path);
+        }
+    }
+}
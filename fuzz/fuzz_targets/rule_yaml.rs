@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sigmars::SigmaCollection;
+
+fuzz_target!(|s: &str| {
+    let _ = s.parse::<SigmaCollection>();
+});
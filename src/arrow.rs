@@ -0,0 +1,185 @@
+//! Evaluation of detection rules against Arrow record batches
+//! and Parquet files
+//!
+//! Only the columns read by the rules are converted, each row of a batch
+//! is evaluated as an event with these columns as fields. Columns with
+//! dotted names (e.g. `process.name`) are nested like dotted rule fields
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use arrow_array::cast::AsArray;
+use arrow_array::types::{
+    Float32Type, Float64Type, Int16Type, Int32Type, Int64Type, Int8Type, UInt16Type, UInt32Type,
+    UInt64Type, UInt8Type,
+};
+use arrow_array::{downcast_dictionary_array, Array, RecordBatch};
+use arrow_schema::{DataType, Schema};
+use serde_json::{Map, Value};
+
+use crate::detection::engine::Engine;
+use crate::detection::MatchOptions;
+use crate::rule::{RuleId, RuleType};
+
+/// the rows matched by each rule
+pub(crate) type Matches = HashMap<RuleId, Vec<usize>>;
+
+/// the top-level fields read by the rules of `engine`,
+/// `None` if a rule reads every field
+fn referenced_fields(engine: &Engine) -> Option<HashSet<Arc<str>>> {
+    let mut fields = HashSet::new();
+    for rule in engine.rules() {
+        if let RuleType::Detection(ref detection) = rule.rule {
+            fields.extend(detection.referenced_fields()?);
+        }
+    }
+    Some(fields)
+}
+
+/// the indices of the columns of `schema` read by the rules of `engine`
+fn projection(engine: &Engine, schema: &Schema) -> Vec<usize> {
+    let fields = referenced_fields(engine);
+    schema
+        .fields()
+        .iter()
+        .enumerate()
+        .filter(|(_, field)| {
+            fields.as_ref().is_none_or(|fields| {
+                let root = field.name().split('.').next().unwrap_or_default();
+                fields.contains(root)
+            })
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// apply the rules of `engine` to each row of `batch`, adding the
+/// matching rows to `matches` with their index offset by `offset`
+pub(crate) fn scan(
+    engine: &Engine,
+    batch: &RecordBatch,
+    options: &MatchOptions,
+    offset: usize,
+    matches: &mut Matches,
+) {
+    let columns = projection(engine, batch.schema_ref())
+        .into_iter()
+        .map(|i| (batch.schema_ref().field(i).name(), batch.column(i)))
+        .collect::<Vec<_>>();
+
+    for row in 0..batch.num_rows() {
+        let mut fields = Map::new();
+        for (name, column) in &columns {
+            if let Some(value) = value(column, row) {
+                insert(&mut fields, name, value);
+            }
+        }
+        let data = Value::Object(fields);
+        for rule in engine.candidates(&data) {
+            if let RuleType::Detection(ref detection) = rule.rule {
+                if detection.is_match(&data, options) {
+                    matches
+                        .entry(rule.id.clone())
+                        .or_default()
+                        .push(offset + row);
+                }
+            }
+        }
+    }
+}
+
+/// apply the rules of `engine` to each row of the Parquet file at
+/// `path`, reading only the columns they reference
+#[cfg(feature = "parquet")]
+pub(crate) fn scan_parquet(
+    engine: &Engine,
+    path: &std::path::Path,
+    options: &MatchOptions,
+) -> Result<Matches, Box<dyn std::error::Error + Send + Sync>> {
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use parquet::arrow::ProjectionMask;
+
+    let builder = ParquetRecordBatchReaderBuilder::try_new(std::fs::File::open(path)?)?;
+    let mask = ProjectionMask::roots(
+        builder.parquet_schema(),
+        projection(engine, builder.schema()),
+    );
+    let mut matches = Matches::new();
+    let mut offset = 0;
+    for batch in builder.with_projection(mask).build()? {
+        let batch = batch?;
+        scan(engine, &batch, options, offset, &mut matches);
+        offset += batch.num_rows();
+    }
+    Ok(matches)
+}
+
+/// insert `value` at the dotted path `name`
+fn insert(fields: &mut Map<String, Value>, name: &str, value: Value) {
+    let mut fields = fields;
+    let mut path = name.split('.').peekable();
+    while let Some(key) = path.next() {
+        if path.peek().is_none() {
+            fields.insert(key.to_string(), value);
+            return;
+        }
+        let Value::Object(nested) = fields
+            .entry(key)
+            .or_insert_with(|| Value::Object(Map::new()))
+        else {
+            return;
+        };
+        fields = nested;
+    }
+}
+
+/// the JSON value of `row` in `array`, `None` if it is null or
+/// of an unsupported type
+fn value(array: &dyn Array, row: usize) -> Option<Value> {
+    if array.is_null(row) {
+        return None;
+    }
+    let value = match array.data_type() {
+        DataType::Boolean => Value::Bool(array.as_boolean().value(row)),
+        DataType::Int8 => array.as_primitive::<Int8Type>().value(row).into(),
+        DataType::Int16 => array.as_primitive::<Int16Type>().value(row).into(),
+        DataType::Int32 => array.as_primitive::<Int32Type>().value(row).into(),
+        DataType::Int64 => array.as_primitive::<Int64Type>().value(row).into(),
+        DataType::UInt8 => array.as_primitive::<UInt8Type>().value(row).into(),
+        DataType::UInt16 => array.as_primitive::<UInt16Type>().value(row).into(),
+        DataType::UInt32 => array.as_primitive::<UInt32Type>().value(row).into(),
+        DataType::UInt64 => array.as_primitive::<UInt64Type>().value(row).into(),
+        DataType::Float32 => f64::from(array.as_primitive::<Float32Type>().value(row)).into(),
+        DataType::Float64 => array.as_primitive::<Float64Type>().value(row).into(),
+        DataType::Utf8 => array.as_string::<i32>().value(row).into(),
+        DataType::LargeUtf8 => array.as_string::<i64>().value(row).into(),
+        DataType::Utf8View => array.as_string_view().value(row).into(),
+        DataType::List(_) => list(&*array.as_list::<i32>().value(row)),
+        DataType::LargeList(_) => list(&*array.as_list::<i64>().value(row)),
+        DataType::Struct(_) => {
+            let array = array.as_struct();
+            Value::Object(
+                array
+                    .column_names()
+                    .into_iter()
+                    .zip(array.columns())
+                    .filter_map(|(name, column)| Some((name.to_string(), value(column, row)?)))
+                    .collect(),
+            )
+        }
+        DataType::Dictionary(_, _) => downcast_dictionary_array!(
+            array => return value(array.values(), array.key(row)?),
+            _ => return None
+        ),
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn list(values: &dyn Array) -> Value {
+    Value::Array(
+        (0..values.len())
+            .map(|i| value(values, i).unwrap_or(Value::Null))
+            .collect(),
+    )
+}
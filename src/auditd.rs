@@ -0,0 +1,184 @@
+//! Folding of auditd log records into events
+
+use std::collections::HashMap;
+
+use serde_json::{Map, Value};
+
+use crate::event::{Event, LogSource};
+use crate::kv;
+
+/// fields holding untrusted strings, which auditd writes hex-encoded
+/// rather than quoted if they contain spaces or control characters
+const ENCODED_FIELDS: &[&str] = &["name", "proctitle", "comm", "exe", "cwd", "key", "cmd"];
+
+/// Folds the records of auditd events into one event each, with the
+/// `linux`/`auditd` logsource
+///
+/// the records of an event (e.g. `SYSCALL`, `EXECVE`, `CWD` and `PATH`)
+/// share the `msg=audit(time:serial)` id and are merged into one event,
+/// keeping the auditd field names read by Linux Sigma rules (`exe`,
+/// `comm`, `a0`, `name`...). `type` holds the types of the records, so
+/// `type: EXECVE` matches any event with an `EXECVE` record
+///
+/// `EXECVE` arguments replace the `a0`-`a3` syscall arguments, fields
+/// repeated by later records with other values (e.g. the `name` of
+/// several `PATH` records) hold all of the values. Hex-encoded values
+/// are decoded. The time and serial of an event are in its metadata
+///
+/// ```rust
+/// # use serde_json::json;
+/// # use sigmars::AuditdParser;
+/// let log = r#"type=SYSCALL msg=audit(1700000000.123:42): syscall=59 comm="wget" exe="/usr/bin/wget"
+/// type=EXECVE msg=audit(1700000000.123:42): argc=2 a0="wget" a1="http://example.com/x.sh"
+/// type=CWD msg=audit(1700000000.123:42): cwd="/tmp"
+/// type=EOE msg=audit(1700000000.123:42):"#;
+///
+/// let mut parser = AuditdParser::new();
+/// let events = log.lines().filter_map(|line| parser.push(line)).collect::<Vec<_>>();
+/// assert_eq!(events.len(), 1);
+/// assert_eq!(events[0].data["type"], json!(["SYSCALL", "EXECVE", "CWD"]));
+/// assert_eq!(events[0].data["a1"], "http://example.com/x.sh");
+/// assert_eq!(events[0].data["exe"], "/usr/bin/wget");
+/// ```
+#[derive(Debug, Default)]
+pub struct AuditdParser {
+    current: Option<Pending>,
+}
+
+#[derive(Debug)]
+struct Pending {
+    id: String,
+    time: f64,
+    serial: u64,
+    types: Vec<Value>,
+    data: Map<String, Value>,
+}
+
+impl AuditdParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// add a line of an auditd log, returning the event it completes
+    ///
+    /// an event is complete at its `EOE` record or at the first record
+    /// of the next event. Lines that are not auditd records are ignored
+    pub fn push(&mut self, line: &str) -> Option<Event> {
+        let (kind, id, fields) = parse(line)?;
+        let done = match self.current {
+            Some(ref pending) if pending.id != id => self.finish(),
+            _ => None,
+        };
+        if kind == "EOE" {
+            return done.or_else(|| self.finish());
+        }
+
+        let pending = self.current.get_or_insert_with(|| {
+            let (time, serial) = id.split_once(':').unwrap_or((id, ""));
+            Pending {
+                id: id.to_string(),
+                time: time.parse().unwrap_or_default(),
+                serial: serial.parse().unwrap_or_default(),
+                types: vec![],
+                data: Map::new(),
+            }
+        });
+        if !pending.types.iter().any(|t| t == kind) {
+            pending.types.push(kind.into());
+        }
+        for (key, value) in fields {
+            let execve_argument = kind == "EXECVE" && is_argument(&key);
+            match pending.data.get_mut(&key) {
+                None => {
+                    pending.data.insert(key, value);
+                }
+                Some(existing) if execve_argument => *existing = value,
+                Some(Value::Array(values)) => {
+                    if !values.contains(&value) {
+                        values.push(value);
+                    }
+                }
+                Some(existing) => {
+                    if *existing != value {
+                        *existing = Value::Array(vec![existing.take(), value]);
+                    }
+                }
+            }
+        }
+        done
+    }
+
+    /// the event of the last records, if they were not completed
+    pub fn finish(&mut self) -> Option<Event> {
+        let mut pending = self.current.take()?;
+        pending
+            .data
+            .insert("type".to_string(), Value::Array(pending.types));
+        let metadata = HashMap::from([
+            ("time".to_string(), Value::from(pending.time)),
+            ("serial".to_string(), Value::from(pending.serial)),
+        ]);
+        let logsource = LogSource::default().product("linux").service("auditd");
+        Some(
+            Event::new(Value::Object(pending.data))
+                .logsource(logsource)
+                .metadata(metadata),
+        )
+    }
+}
+
+/// whether `key` is an argument (`a0`, `a1`...)
+fn is_argument(key: &str) -> bool {
+    key.strip_prefix('a')
+        .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// the type, event id and fields of an auditd record
+type Record<'a> = (&'a str, &'a str, Vec<(String, Value)>);
+
+/// parse an auditd record, `None` for other lines
+fn parse(line: &str) -> Option<Record<'_>> {
+    let record = &line[line.find("type=")?..];
+    let (kind, rest) = record["type=".len()..].split_once(' ')?;
+    let rest = rest.trim_start().strip_prefix("msg=audit(")?;
+    let (id, rest) = rest.split_once("):")?;
+
+    let mut fields = vec![];
+    parse_fields(kind, rest, &mut fields);
+    Some((kind, id, fields))
+}
+
+/// add the `key=value` fields of `s` to `fields`, including those of
+/// single-quoted values (e.g. `msg='op=PAM:session_open acct="root"'`)
+fn parse_fields(kind: &str, s: &str, fields: &mut Vec<(String, Value)>) {
+    // enriched logs separate the interpreted fields with a group separator
+    for (key, value, quote) in kv::pairs(s, |c| c.is_whitespace() || c == '\x1d') {
+        match quote {
+            Some('\'') => parse_fields(kind, value, fields),
+            Some(_) => fields.push((key.to_string(), value.into())),
+            None => {
+                let encoded = ENCODED_FIELDS.contains(&key) || kind == "EXECVE" && is_argument(key);
+                let value = match encoded {
+                    true => decode_hex(value).unwrap_or_else(|| value.to_string()),
+                    false => value.to_string(),
+                };
+                fields.push((key.to_string(), value.into()));
+            }
+        }
+    }
+}
+
+/// decode a hex-encoded value, NUL separators (e.g. between the
+/// arguments of `proctitle`) become spaces
+fn decode_hex(s: &str) -> Option<String> {
+    if s.is_empty() || !s.len().is_multiple_of(2) || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let bytes = (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16))
+        .collect::<Result<Vec<_>, _>>()
+        .ok()?;
+    let decoded = String::from_utf8_lossy(&bytes);
+    Some(decoded.trim_end_matches('\0').replace('\0', " "))
+}
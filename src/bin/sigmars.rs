@@ -0,0 +1,254 @@
+//! Evaluate a directory of Sigma rules against NDJSON events
+//!
+//! ```text
+//! sigmars --rules /path/to/sigma/rules/ events.ndjson
+//! cat events.ndjson | sigmars --rules /path/to/sigma/rules/ --format ocsf
+//! sigmars --rules /path/to/sigma/rules/ --test
+//! sigmars --rules /path/to/sigma/rules/windows/ --pipeline sysmon-ecs winlogbeat.ndjson
+//! sigmars --rules /path/to/sigma/rules/cloud/aws/ --input cloudtrail cloudtrail.json
+//! sigmars --rules /path/to/sigma/rules/linux/auditd/ --input auditd /var/log/audit/audit.log
+//! sigmars --rules /path/to/sigma/rules/network/ --input suricata-eve --pipeline suricata-eve eve.json
+//! ```
+use std::error::Error;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::PathBuf;
+
+use clap::{Parser, ValueEnum};
+use serde_json::{json, Value};
+use sigmars::event::LogSource;
+use sigmars::{AuditdParser, Event, LoadFilter, MemBackend, Pipeline, SigmaCollection};
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Format {
+    /// one `{"rule_id", "title"}` object per match
+    Ids,
+    /// one OCSF Detection Finding per match
+    Ocsf,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum BackendType {
+    /// detection rules only
+    None,
+    /// evaluate correlation rules with an in-memory backend
+    Mem,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Input {
+    /// one event per line
+    Ndjson,
+    /// CloudTrail log files or records, one per line, with the
+    /// `aws`/`cloudtrail` logsource
+    Cloudtrail,
+    /// Suricata EVE records with the logsource of their `event_type`
+    SuricataEve,
+    /// Zeek JSON log records with the logsource of their `_path`
+    Zeek,
+    /// auditd logs, folding the records of each event
+    Auditd,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum PipelineName {
+    /// winlogbeat/Sysmon ECS fields for Windows rules
+    SysmonEcs,
+    /// Suricata EVE fields for dns, proxy and firewall rules
+    SuricataEve,
+    /// Zeek fields for dns, proxy and firewall rules
+    Zeek,
+}
+
+impl PipelineName {
+    fn pipeline(self) -> Pipeline {
+        match self {
+            PipelineName::SysmonEcs => Pipeline::sysmon_ecs(),
+            PipelineName::SuricataEve => Pipeline::suricata_eve(),
+            PipelineName::Zeek => Pipeline::zeek(),
+        }
+    }
+}
+
+/// Evaluate Sigma rules against NDJSON events, writing matches as NDJSON
+#[derive(Debug, Parser)]
+#[command(name = "sigmars", version)]
+struct Args {
+    /// directory of Sigma rules (YAML)
+    #[arg(short, long)]
+    rules: String,
+
+    /// event files in the `--input` format, stdin if none or `-`
+    files: Vec<PathBuf>,
+
+    /// input format
+    #[arg(short, long, value_enum, default_value = "ndjson")]
+    input: Input,
+
+    /// output format
+    #[arg(short, long, value_enum, default_value = "ids")]
+    format: Format,
+
+    /// correlation backend
+    #[arg(short, long, value_enum, default_value = "none")]
+    backend: BackendType,
+
+    /// logsource category of the events
+    #[arg(long)]
+    category: Option<String>,
+
+    /// logsource product of the events
+    #[arg(long)]
+    product: Option<String>,
+
+    /// logsource service of the events
+    #[arg(long)]
+    service: Option<String>,
+
+    /// map the fields of the rules with a built-in processing pipeline
+    #[arg(long, value_enum)]
+    pipeline: Vec<PipelineName>,
+
+    /// skip rules with this status when loading
+    #[arg(long, value_parser = ["stable", "test", "experimental", "deprecated", "unsupported"])]
+    skip_status: Vec<String>,
+
+    /// evaluate all rules regardless of logsource
+    #[arg(long)]
+    unfiltered: bool,
+
+    /// compare values case-sensitively unless rules say otherwise
+    #[arg(long)]
+    case_sensitive: bool,
+
+    /// apply Unicode NFKC normalization before comparing values
+    #[arg(long)]
+    normalize_unicode: bool,
+
+    /// fold Cyrillic and Greek look-alike letters to Latin
+    #[arg(long)]
+    fold_homoglyphs: bool,
+
+    /// run the sample events in the `tests` field of the rules
+    /// instead of evaluating events, failing if any rule fails
+    #[arg(long)]
+    test: bool,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    let args = Args::parse();
+
+    let load_filter = args
+        .skip_status
+        .iter()
+        .fold(LoadFilter::new(), |filter, status| {
+            filter.exclude_status(status.as_str())
+        });
+    let mut rules = args
+        .pipeline
+        .iter()
+        .fold(SigmaCollection::builder(), |builder, name| {
+            builder.pipeline(name.pipeline())
+        })
+        .load_filter(load_filter)
+        .case_sensitive(args.case_sensitive)
+        .normalize_unicode(args.normalize_unicode)
+        .fold_homoglyphs(args.fold_homoglyphs)
+        .build_from_dir(&args.rules)
+        .map_err(|e| e.to_string())?;
+
+    if args.test {
+        let mut out = io::stdout().lock();
+        let results = rules.run_rule_tests();
+        let failed = results.iter().filter(|result| !result.passed()).count();
+        for result in results.iter().filter(|result| !result.passed()) {
+            let output = json!({
+                "rule_id": result.rule,
+                "missed": result.missed,
+                "false_positives": result.false_positives,
+                "error": result.error,
+            });
+            writeln!(out, "{}", output)?;
+        }
+        eprintln!("{} rules tested, {} failed", results.len(), failed);
+        if failed > 0 {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    // the backend holds the correlation state and must outlive evaluation
+    let _backend = match args.backend {
+        BackendType::Mem => {
+            let mut backend = MemBackend::new().await;
+            rules.init(&mut backend).await;
+            Some(backend)
+        }
+        BackendType::None => None,
+    };
+
+    let logsource = LogSource::new(
+        args.category.clone(),
+        args.product.clone(),
+        args.service.clone(),
+    );
+
+    let inputs: Vec<Box<dyn BufRead>> = match args.files.as_slice() {
+        [] => vec![Box::new(io::stdin().lock())],
+        files => files
+            .iter()
+            .map(|path| -> io::Result<Box<dyn BufRead>> {
+                if path.as_os_str() == "-" {
+                    Ok(Box::new(io::stdin().lock()))
+                } else {
+                    Ok(Box::new(BufReader::new(File::open(path)?)))
+                }
+            })
+            .collect::<Result<_, _>>()?,
+    };
+
+    let mut out = io::stdout().lock();
+    for input in inputs {
+        let mut auditd = AuditdParser::new();
+        // `None` marks the end of the input, completing any auditd event
+        for line in input.lines().map(Some).chain([None]) {
+            let events = match (args.input, line.transpose()?) {
+                (Input::Auditd, Some(line)) => auditd.push(&line).into_iter().collect(),
+                (Input::Auditd, None) => auditd.finish().into_iter().collect(),
+                (_, None) => vec![],
+                (_, Some(line)) if line.trim().is_empty() => vec![],
+                (input, Some(line)) => {
+                    let data: Value = serde_json::from_str(&line)?;
+                    match input {
+                        Input::Cloudtrail => Event::from_cloudtrail(data),
+                        Input::SuricataEve => vec![Event::from_suricata_eve(data)],
+                        Input::Zeek => vec![Event::from_zeek(data)],
+                        _ => vec![Event::new(data).logsource(logsource.clone())],
+                    }
+                }
+            };
+
+            for event in events {
+                let matches = match (args.backend, args.unfiltered) {
+                    (BackendType::None, false) => rules.get_detection_matches(&event),
+                    (BackendType::None, true) => rules.get_detection_matches_unfiltered(&event),
+                    (BackendType::Mem, false) => rules.get_matches(&event).await?,
+                    (BackendType::Mem, true) => rules.get_matches_unfiltered(&event).await?,
+                };
+
+                for id in matches {
+                    let Some(rule) = rules.get(&id) else {
+                        continue;
+                    };
+                    let output = match args.format {
+                        Format::Ids => json!({"rule_id": rule.id, "title": rule.title}),
+                        Format::Ocsf => rule.to_finding(&event),
+                    };
+                    writeln!(out, "{}", output)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
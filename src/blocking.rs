@@ -0,0 +1,337 @@
+//! Synchronous wrappers of the async correlation APIs
+//!
+//! The wrappers drive the correlation rules of a collection on their
+//! own single-threaded runtime, so applications without an async
+//! runtime (e.g. CLIs and plugins) can evaluate them. Their methods
+//! block the calling thread and panic if called from within an async
+//! runtime, where the async APIs should be used instead
+//!
+//! The synchronous methods of the wrapped collections are available
+//! through `Deref`
+
+use std::future::Future;
+use std::io;
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+use tokio::runtime::{Builder, Runtime};
+
+use crate::collection::{CollectionError, Evaluation};
+use crate::correlation::Backend;
+use crate::event::{Event, LogSource};
+use crate::footprint::MemoryFootprint;
+use crate::matches::Matches;
+use crate::rule::{RuleId, SigmaRule};
+
+#[cfg(feature = "mem_backend")]
+use crate::correlation::state::mem::MemBackend;
+
+fn runtime() -> io::Result<Runtime> {
+    Builder::new_current_thread().enable_all().build()
+}
+
+/// A [`SigmaCollection`] evaluating correlation rules synchronously
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use serde_json::json;
+/// # use sigmars::{blocking, Event};
+/// static RULES: &str = r#"
+/// title: test rule
+/// id: test-rule
+/// logsource:
+///   category: test
+/// detection:
+///   selection:
+///     foo: bar
+///   condition: selection
+/// ---
+/// title: repeated test rule
+/// id: repeated-test-rule
+/// correlation:
+///   type: event_count
+///   rules:
+///     - test-rule
+///   group-by:
+///     - user
+///   timespan: 1m
+///   condition:
+///     gte: 2
+/// "#;
+///
+/// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+/// let mut rules = blocking::SigmaCollection::new(RULES.parse()?)?;
+/// let _backend = rules.init_mem_backend();
+///
+/// let event = Event::new(json!({"foo": "bar", "user": "root"}));
+/// assert_eq!(rules.get_matches(&event)?, vec!["test-rule".into()]);
+/// assert_eq!(
+///     rules.get_matches(&event)?,
+///     vec!["test-rule".into(), "repeated-test-rule".into()]
+/// );
+/// // synchronous methods of the collection are available too
+/// assert_eq!(rules.get_detection_matches(&event), vec!["test-rule".into()]);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`SigmaCollection`]: ../struct.SigmaCollection.html
+#[derive(Debug)]
+pub struct SigmaCollection {
+    inner: crate::SigmaCollection,
+    runtime: Runtime,
+}
+
+impl SigmaCollection {
+    /// wrap `collection`, failing if the runtime cannot be created
+    pub fn new(collection: crate::SigmaCollection) -> io::Result<Self> {
+        Ok(SigmaCollection {
+            inner: collection,
+            runtime: runtime()?,
+        })
+    }
+
+    /// the wrapped collection
+    pub fn into_inner(self) -> crate::SigmaCollection {
+        self.inner
+    }
+
+    /// run a future to completion on the runtime of the collection,
+    /// e.g. to create a backend or read its state
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    /// see [`SigmaCollection::init`]
+    ///
+    /// [`SigmaCollection::init`]: ../struct.SigmaCollection.html#method.init
+    pub fn init(&mut self, backend: &mut impl Backend) {
+        self.runtime.block_on(self.inner.init(backend))
+    }
+
+    /// initialize the correlation rules with a new in-memory backend,
+    /// which must be kept for as long as the collection is used
+    #[cfg(feature = "mem_backend")]
+    pub fn init_mem_backend(&mut self) -> MemBackend {
+        let mut backend = self.runtime.block_on(MemBackend::new());
+        self.init(&mut backend);
+        backend
+    }
+
+    /// see [`SigmaCollection::get_matches`]
+    ///
+    /// [`SigmaCollection::get_matches`]: ../struct.SigmaCollection.html#method.get_matches
+    pub fn get_matches(
+        &self,
+        event: &Event,
+    ) -> Result<Matches, Box<dyn std::error::Error + Send + Sync>> {
+        self.runtime.block_on(self.inner.get_matches(event))
+    }
+
+    /// see [`SigmaCollection::get_matches_for`]
+    ///
+    /// [`SigmaCollection::get_matches_for`]: ../struct.SigmaCollection.html#method.get_matches_for
+    pub fn get_matches_for(
+        &self,
+        event: &Event,
+        logsource: &LogSource,
+    ) -> Result<Matches, Box<dyn std::error::Error + Send + Sync>> {
+        self.runtime
+            .block_on(self.inner.get_matches_for(event, logsource))
+    }
+
+    /// see [`SigmaCollection::evaluate`]
+    ///
+    /// [`SigmaCollection::evaluate`]: ../struct.SigmaCollection.html#method.evaluate
+    pub fn evaluate(
+        &self,
+        event: &Event,
+    ) -> Result<Evaluation, Box<dyn std::error::Error + Send + Sync>> {
+        self.runtime.block_on(self.inner.evaluate(event))
+    }
+
+    /// see [`SigmaCollection::evaluate_for`]
+    ///
+    /// [`SigmaCollection::evaluate_for`]: ../struct.SigmaCollection.html#method.evaluate_for
+    pub fn evaluate_for(
+        &self,
+        event: &Event,
+        logsource: &LogSource,
+    ) -> Result<Evaluation, Box<dyn std::error::Error + Send + Sync>> {
+        self.runtime
+            .block_on(self.inner.evaluate_for(event, logsource))
+    }
+
+    /// see [`SigmaCollection::get_matches_unfiltered`]
+    ///
+    /// [`SigmaCollection::get_matches_unfiltered`]: ../struct.SigmaCollection.html#method.get_matches_unfiltered
+    pub fn get_matches_unfiltered(
+        &self,
+        event: &Event,
+    ) -> Result<Matches, Box<dyn std::error::Error + Send + Sync>> {
+        self.runtime
+            .block_on(self.inner.get_matches_unfiltered(event))
+    }
+
+    /// see [`SigmaCollection::push_correlation_matches`]
+    ///
+    /// [`SigmaCollection::push_correlation_matches`]: ../struct.SigmaCollection.html#method.push_correlation_matches
+    pub fn push_correlation_matches(
+        &self,
+        event: &Event,
+        prior: &mut Vec<RuleId>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        self.runtime
+            .block_on(self.inner.push_correlation_matches(event, prior))
+    }
+
+    /// see [`SigmaCollection::get_matches_batch`]
+    ///
+    /// [`SigmaCollection::get_matches_batch`]: ../struct.SigmaCollection.html#method.get_matches_batch
+    pub fn get_matches_batch(
+        &self,
+        events: &[Event],
+    ) -> Result<Vec<Matches>, Box<dyn std::error::Error + Send + Sync>> {
+        self.runtime.block_on(self.inner.get_matches_batch(events))
+    }
+
+    /// see [`SigmaCollection::evaluate_batch`]
+    ///
+    /// [`SigmaCollection::evaluate_batch`]: ../struct.SigmaCollection.html#method.evaluate_batch
+    pub fn evaluate_batch(
+        &self,
+        events: &[Event],
+    ) -> Result<Vec<Evaluation>, Box<dyn std::error::Error + Send + Sync>> {
+        self.runtime.block_on(self.inner.evaluate_batch(events))
+    }
+
+    /// see [`SigmaCollection::memory_footprint`]
+    ///
+    /// [`SigmaCollection::memory_footprint`]: ../struct.SigmaCollection.html#method.memory_footprint
+    pub fn memory_footprint(&self) -> MemoryFootprint {
+        self.runtime.block_on(self.inner.memory_footprint())
+    }
+}
+
+impl Deref for SigmaCollection {
+    type Target = crate::SigmaCollection;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+impl DerefMut for SigmaCollection {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inner
+    }
+}
+
+/// A [`SharedSigmaCollection`] evaluating correlation rules
+/// synchronously
+///
+/// [`SharedSigmaCollection`]: ../struct.SharedSigmaCollection.html
+pub struct SharedSigmaCollection {
+    inner: crate::SharedSigmaCollection,
+    runtime: Runtime,
+}
+
+impl SharedSigmaCollection {
+    /// share `collection`, failing if the runtime cannot be created
+    pub fn new(collection: crate::SigmaCollection) -> io::Result<Self> {
+        Ok(SharedSigmaCollection {
+            inner: crate::SharedSigmaCollection::new(collection),
+            runtime: runtime()?,
+        })
+    }
+
+    /// run a future to completion on the runtime of the collection
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    /// see [`SharedSigmaCollection::replace`]
+    ///
+    /// [`SharedSigmaCollection::replace`]: ../struct.SharedSigmaCollection.html#method.replace
+    pub fn replace(
+        &self,
+        collection: crate::SigmaCollection,
+        backend: &mut impl Backend,
+    ) -> Arc<crate::SigmaCollection> {
+        self.runtime
+            .block_on(self.inner.replace(collection, backend))
+    }
+
+    /// see [`SharedSigmaCollection::add_with_backend`]
+    ///
+    /// [`SharedSigmaCollection::add_with_backend`]: ../struct.SharedSigmaCollection.html#method.add_with_backend
+    pub fn add_with_backend(
+        &self,
+        rule: SigmaRule,
+        backend: &mut impl Backend,
+    ) -> Result<(), CollectionError> {
+        self.runtime
+            .block_on(self.inner.add_with_backend(rule, backend))
+    }
+
+    /// see [`SharedSigmaCollection::get_matches`]
+    ///
+    /// [`SharedSigmaCollection::get_matches`]: ../struct.SharedSigmaCollection.html#method.get_matches
+    pub fn get_matches(
+        &self,
+        event: &Event,
+    ) -> Result<Matches, Box<dyn std::error::Error + Send + Sync>> {
+        self.runtime.block_on(self.inner.get_matches(event))
+    }
+
+    /// see [`SharedSigmaCollection::get_matches_for`]
+    ///
+    /// [`SharedSigmaCollection::get_matches_for`]: ../struct.SharedSigmaCollection.html#method.get_matches_for
+    pub fn get_matches_for(
+        &self,
+        event: &Event,
+        logsource: &LogSource,
+    ) -> Result<Matches, Box<dyn std::error::Error + Send + Sync>> {
+        self.runtime
+            .block_on(self.inner.get_matches_for(event, logsource))
+    }
+
+    /// see [`SharedSigmaCollection::evaluate`]
+    ///
+    /// [`SharedSigmaCollection::evaluate`]: ../struct.SharedSigmaCollection.html#method.evaluate
+    pub fn evaluate(
+        &self,
+        event: &Event,
+    ) -> Result<Evaluation, Box<dyn std::error::Error + Send + Sync>> {
+        self.runtime.block_on(self.inner.evaluate(event))
+    }
+
+    /// see [`SharedSigmaCollection::get_matches_batch`]
+    ///
+    /// [`SharedSigmaCollection::get_matches_batch`]: ../struct.SharedSigmaCollection.html#method.get_matches_batch
+    pub fn get_matches_batch(
+        &self,
+        events: &[Event],
+    ) -> Result<Vec<Matches>, Box<dyn std::error::Error + Send + Sync>> {
+        self.runtime.block_on(self.inner.get_matches_batch(events))
+    }
+
+    /// see [`SharedSigmaCollection::get_matches_unfiltered`]
+    ///
+    /// [`SharedSigmaCollection::get_matches_unfiltered`]: ../struct.SharedSigmaCollection.html#method.get_matches_unfiltered
+    pub fn get_matches_unfiltered(
+        &self,
+        event: &Event,
+    ) -> Result<Matches, Box<dyn std::error::Error + Send + Sync>> {
+        self.runtime
+            .block_on(self.inner.get_matches_unfiltered(event))
+    }
+}
+
+impl Deref for SharedSigmaCollection {
+    type Target = crate::SharedSigmaCollection;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
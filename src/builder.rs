@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::collection::{CollectionError, SigmaCollection};
+use crate::event::Event;
+use crate::limits::ParseLimits;
+use crate::dir_options::DirOptions;
+use crate::load_filter::LoadFilter;
+use crate::overrides::Overrides;
+use crate::dedup::Dedup;
+use crate::suppression::SuppressionStore;
+use crate::matcher::Matcher;
+use crate::observer::Match;
+use crate::pipeline::Pipeline;
+#[cfg(feature = "time")]
+use crate::schedule::Schedule;
+use crate::rule::SigmaRule;
+use serde_json::Value;
+
+/// Builds a [`SigmaCollection`] with its loading and evaluation
+/// options set before any rules are loaded
+///
+/// Options that affect parsing (such as [`limits`] and
+/// [`require_signatures`]) only apply to rules loaded after they are
+/// set, building the collection from the builder guarantees that
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use serde_json::json;
+/// # use sigmars::{Event, ParseLimits, SigmaCollection};
+/// static RULES: &str = r#"
+/// title: test rule
+/// id: test-rule
+/// logsource:
+///   category: test
+/// detection:
+///   selection:
+///     foo: bar
+///   condition: selection
+/// "#;
+///
+/// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+/// let rules = SigmaCollection::builder()
+///     .case_sensitive(true)
+///     .limits(ParseLimits::untrusted())
+///     .build_from_str(RULES)?;
+/// assert!(rules.get_detection_matches(&Event::new(json!({"foo": "BAR"}))).is_empty());
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`SigmaCollection`]: struct.SigmaCollection.html
+/// [`limits`]: #method.limits
+/// [`require_signatures`]: #method.require_signatures
+#[derive(Debug, Default, Clone)]
+pub struct SigmaCollectionBuilder {
+    collection: SigmaCollection,
+}
+
+impl SigmaCollectionBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// see [`SigmaCollection::drop_raw_detections`]
+    ///
+    /// [`SigmaCollection::drop_raw_detections`]: struct.SigmaCollection.html#method.drop_raw_detections
+    pub fn drop_raw_detections(mut self, drop: bool) -> Self {
+        self.collection = self.collection.drop_raw_detections(drop);
+        self
+    }
+
+    /// see [`SigmaCollection::allow_missing_dependencies`]
+    ///
+    /// [`SigmaCollection::allow_missing_dependencies`]: struct.SigmaCollection.html#method.allow_missing_dependencies
+    pub fn allow_missing_dependencies(mut self, allow: bool) -> Self {
+        self.collection = self.collection.allow_missing_dependencies(allow);
+        self
+    }
+
+    /// see [`SigmaCollection::case_sensitive`]
+    ///
+    /// [`SigmaCollection::case_sensitive`]: struct.SigmaCollection.html#method.case_sensitive
+    pub fn case_sensitive(mut self, case_sensitive: bool) -> Self {
+        self.collection = self.collection.case_sensitive(case_sensitive);
+        self
+    }
+
+    /// see [`SigmaCollection::normalize_unicode`]
+    ///
+    /// [`SigmaCollection::normalize_unicode`]: struct.SigmaCollection.html#method.normalize_unicode
+    pub fn normalize_unicode(mut self, normalize: bool) -> Self {
+        self.collection = self.collection.normalize_unicode(normalize);
+        self
+    }
+
+    /// see [`SigmaCollection::fold_homoglyphs`]
+    ///
+    /// [`SigmaCollection::fold_homoglyphs`]: struct.SigmaCollection.html#method.fold_homoglyphs
+    pub fn fold_homoglyphs(mut self, fold: bool) -> Self {
+        self.collection = self.collection.fold_homoglyphs(fold);
+        self
+    }
+
+    /// see [`SigmaCollection::matcher`]
+    ///
+    /// [`SigmaCollection::matcher`]: struct.SigmaCollection.html#method.matcher
+    pub fn matcher(mut self, matcher: Arc<dyn Matcher>) -> Self {
+        self.collection = self.collection.matcher(matcher);
+        self
+    }
+
+    /// see [`SigmaCollection::keyword_options`]
+    ///
+    /// [`SigmaCollection::keyword_options`]: struct.SigmaCollection.html#method.keyword_options
+    pub fn keyword_options(mut self, options: crate::KeywordOptions) -> Self {
+        self.collection = self.collection.keyword_options(options);
+        self
+    }
+
+    /// see [`SigmaCollection::placeholders`]
+    ///
+    /// [`SigmaCollection::placeholders`]: struct.SigmaCollection.html#method.placeholders
+    pub fn placeholders(mut self, placeholders: HashMap<String, Value>) -> Self {
+        self.collection = self.collection.placeholders(placeholders);
+        self
+    }
+
+    /// see [`SigmaCollection::limits`]
+    ///
+    /// [`SigmaCollection::limits`]: struct.SigmaCollection.html#method.limits
+    pub fn limits(mut self, limits: ParseLimits) -> Self {
+        self.collection = self.collection.limits(limits);
+        self
+    }
+
+    /// see [`SigmaCollection::generate_missing_ids`]
+    ///
+    /// [`SigmaCollection::generate_missing_ids`]: struct.SigmaCollection.html#method.generate_missing_ids
+    pub fn generate_missing_ids(mut self, generate: bool) -> Self {
+        self.collection = self.collection.generate_missing_ids(generate);
+        self
+    }
+
+    /// see [`SigmaCollection::timeout`]
+    ///
+    /// [`SigmaCollection::timeout`]: struct.SigmaCollection.html#method.timeout
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.collection = self.collection.timeout(timeout);
+        self
+    }
+
+    /// see [`SigmaCollection::max_matches`]
+    ///
+    /// [`SigmaCollection::max_matches`]: struct.SigmaCollection.html#method.max_matches
+    pub fn max_matches(mut self, max: usize) -> Self {
+        self.collection = self.collection.max_matches(max);
+        self
+    }
+
+    /// see [`SigmaCollection::first_match_only`]
+    ///
+    /// [`SigmaCollection::first_match_only`]: struct.SigmaCollection.html#method.first_match_only
+    pub fn first_match_only(mut self, first: bool) -> Self {
+        self.collection = self.collection.first_match_only(first);
+        self
+    }
+
+    /// see [`SigmaCollection::load_filter`]
+    ///
+    /// [`SigmaCollection::load_filter`]: struct.SigmaCollection.html#method.load_filter
+    pub fn load_filter(mut self, filter: LoadFilter) -> Self {
+        self.collection = self.collection.load_filter(filter);
+        self
+    }
+
+    /// see [`SigmaCollection::dir_options`]
+    ///
+    /// [`SigmaCollection::dir_options`]: struct.SigmaCollection.html#method.dir_options
+    pub fn dir_options(mut self, options: DirOptions) -> Self {
+        self.collection = self.collection.dir_options(options);
+        self
+    }
+
+    /// see [`SigmaCollection::overrides`]
+    ///
+    /// [`SigmaCollection::overrides`]: struct.SigmaCollection.html#method.overrides
+    pub fn overrides(mut self, overrides: Overrides) -> Self {
+        self.collection = self.collection.overrides(overrides);
+        self
+    }
+
+    /// see [`SigmaCollection::suppression_store`]
+    ///
+    /// [`SigmaCollection::suppression_store`]: struct.SigmaCollection.html#method.suppression_store
+    pub fn suppression_store(mut self, store: SuppressionStore) -> Self {
+        self.collection = self.collection.suppression_store(store);
+        self
+    }
+
+    /// see [`SigmaCollection::dedup`]
+    ///
+    /// [`SigmaCollection::dedup`]: struct.SigmaCollection.html#method.dedup
+    pub fn dedup(mut self, dedup: Dedup) -> Self {
+        self.collection = self.collection.dedup(dedup);
+        self
+    }
+
+    /// see [`SigmaCollection::track_telemetry`]
+    ///
+    /// [`SigmaCollection::track_telemetry`]: struct.SigmaCollection.html#method.track_telemetry
+    pub fn track_telemetry(mut self, track: bool) -> Self {
+        self.collection = self.collection.track_telemetry(track);
+        self
+    }
+
+    /// see [`SigmaCollection::schedule`]
+    ///
+    /// [`SigmaCollection::schedule`]: struct.SigmaCollection.html#method.schedule
+    #[cfg(feature = "time")]
+    pub fn schedule(mut self, rule: &str, schedule: Schedule) -> Self {
+        self.collection = self.collection.schedule(rule, schedule);
+        self
+    }
+
+    /// see [`SigmaCollection::pipeline`]
+    ///
+    /// [`SigmaCollection::pipeline`]: struct.SigmaCollection.html#method.pipeline
+    pub fn pipeline(mut self, pipeline: Pipeline) -> Self {
+        self.collection = self.collection.pipeline(pipeline);
+        self
+    }
+
+    /// see [`SigmaCollection::on_match`]
+    ///
+    /// [`SigmaCollection::on_match`]: struct.SigmaCollection.html#method.on_match
+    pub fn on_match(
+        mut self,
+        observer: impl FnMut(&SigmaRule, &Event, &Match) + Send + 'static,
+    ) -> Self {
+        self.collection = self.collection.on_match(observer);
+        self
+    }
+
+    /// see [`SigmaCollection::map_group_by_field`]
+    ///
+    /// [`SigmaCollection::map_group_by_field`]: struct.SigmaCollection.html#method.map_group_by_field
+    #[cfg(feature = "correlation")]
+    pub fn map_group_by_field(mut self, rule: &str, group_by: &str, field: &str) -> Self {
+        self.collection = self.collection.map_group_by_field(rule, group_by, field);
+        self
+    }
+
+    /// see [`SigmaCollection::throttle`]
+    ///
+    /// [`SigmaCollection::throttle`]: struct.SigmaCollection.html#method.throttle
+    #[cfg(feature = "correlation")]
+    pub fn throttle(mut self, throttle: crate::Throttle) -> Self {
+        self.collection = self.collection.throttle(throttle);
+        self
+    }
+
+    /// see [`SigmaCollection::correlation_type`]
+    ///
+    /// [`SigmaCollection::correlation_type`]: struct.SigmaCollection.html#method.correlation_type
+    #[cfg(feature = "correlation")]
+    pub fn correlation_type(
+        mut self,
+        name: &str,
+        handler: impl crate::CorrelationHandler + 'static,
+    ) -> Self {
+        self.collection = self.collection.correlation_type(name, handler);
+        self
+    }
+
+    /// see [`SigmaCollection::allow_unknown_correlation_types`]
+    ///
+    /// [`SigmaCollection::allow_unknown_correlation_types`]: struct.SigmaCollection.html#method.allow_unknown_correlation_types
+    #[cfg(feature = "correlation")]
+    pub fn allow_unknown_correlation_types(mut self, allow: bool) -> Self {
+        self.collection = self.collection.allow_unknown_correlation_types(allow);
+        self
+    }
+
+    /// see [`SigmaCollection::require_signatures`]
+    ///
+    /// [`SigmaCollection::require_signatures`]: struct.SigmaCollection.html#method.require_signatures
+    #[cfg(feature = "signing")]
+    pub fn require_signatures(mut self, keys: impl IntoIterator<Item = crate::PublicKey>) -> Self {
+        self.collection = self.collection.require_signatures(keys);
+        self
+    }
+
+    /// an empty collection with the builder's options
+    pub fn build(self) -> SigmaCollection {
+        self.collection
+    }
+
+    /// a collection with the builder's options and the rules
+    /// of a (multi-document) YAML string
+    pub fn build_from_str(self, s: &str) -> Result<SigmaCollection, CollectionError> {
+        let mut collection = self.collection;
+        collection.load_from_str(s)?;
+        Ok(collection)
+    }
+
+    /// a collection with the builder's options and the rules
+    /// of a directory of YAML files
+    pub fn build_from_dir(
+        self,
+        path: &str,
+    ) -> Result<SigmaCollection, Box<dyn std::error::Error + Send + Sync>> {
+        let mut collection = self.collection;
+        collection.load_from_dir(path)?;
+        Ok(collection)
+    }
+}
@@ -0,0 +1,46 @@
+//! Conversion of AWS CloudTrail logs into events
+
+use serde_json::Value;
+
+use crate::event::{Event, LogSource};
+
+impl Event {
+    /// The events of a CloudTrail log file (`{"Records": [...]}`) or
+    /// of a single CloudTrail record, with the `aws`/`cloudtrail`
+    /// log source of SigmaHQ cloud rules
+    ///
+    /// records are evaluated as they are, Sigma rules read CloudTrail
+    /// fields by their JSON names (`eventName`, `requestParameters.*`)
+    ///
+    /// ```rust
+    /// # use serde_json::json;
+    /// # use sigmars::Event;
+    /// let events = Event::from_cloudtrail(json!({"Records": [
+    ///     {"eventSource": "s3.amazonaws.com", "eventName": "PutBucketPolicy"},
+    ///     {"eventSource": "iam.amazonaws.com", "eventName": "CreateAccessKey"},
+    /// ]}));
+    /// assert_eq!(events.len(), 2);
+    /// assert_eq!(events[1].data["eventName"], "CreateAccessKey");
+    /// assert_eq!(events[1].logsource.service.as_deref(), Some("cloudtrail"));
+    /// ```
+    pub fn from_cloudtrail(data: Value) -> Vec<Self> {
+        let records = match data {
+            Value::Object(mut log) => match log.remove("Records") {
+                Some(Value::Array(records)) => records,
+                Some(records) => {
+                    log.insert("Records".to_string(), records);
+                    vec![Value::Object(log)]
+                }
+                None => vec![Value::Object(log)],
+            },
+            Value::Array(records) => records,
+            _ => vec![],
+        };
+        let logsource = LogSource::default().product("aws").service("cloudtrail");
+        records
+            .into_iter()
+            .filter(Value::is_object)
+            .map(|record| Event::new(record).logsource(logsource.clone()))
+            .collect()
+    }
+}
@@ -1,15 +1,47 @@
+use crate::builder::SigmaCollectionBuilder;
+use crate::detection::engine::Engines;
 use crate::detection::filter::Filter;
-use crate::event::Event;
+use crate::detection::{KeywordOptions, MatchOptions};
+use crate::event::{Event, LogSource};
+use crate::limits::{self, ParseLimits};
+use crate::dir_options::DirOptions;
+use crate::footprint::{self, MemoryFootprint};
+use crate::load_filter::LoadFilter;
+use crate::matches::Matches;
+use crate::matcher::Matcher;
+use crate::observer::{Match, Observers};
+use crate::overrides::Overrides;
+use crate::pipeline::Pipeline;
+use crate::dedup::Dedup;
+use crate::explain::{RuleExplanation, RuleOutcome};
+use crate::fields::ReferencedFields;
+use crate::overlap::{Normalized, RuleOverlap};
+use crate::suppression::SuppressionStore;
+use crate::telemetry::{Telemetry, TelemetrySnapshot};
+use crate::replay::{self, LineMatch};
+use crate::rule_tests::{self, RuleTestResult};
+use crate::metrics;
+use crate::warnings::RuleWarning;
 
 #[cfg(feature = "correlation")]
 use crate::correlation;
-
+#[cfg(feature = "correlation")]
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+#[cfg(feature = "correlation")]
+use std::borrow::Cow;
+#[cfg(feature = "correlation")]
 use petgraph::{graph, Directed, Graph};
+#[cfg(feature = "time")]
+use crate::schedule::Schedule;
+
 use serde::Deserialize;
-use std::{collections::HashMap, str::FromStr};
+use serde_json::Value;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use std::{collections::HashMap, str::FromStr, sync::Arc, time::Instant};
 use thiserror::Error;
 
-use crate::rule::{RuleType, SigmaRule};
+use crate::rule::{self, RelationType, Related, RuleId, RuleType, SigmaRule, Source};
 
 #[derive(Error, Debug)]
 pub enum CollectionError {
@@ -21,17 +53,44 @@ pub enum CollectionError {
     ParseError(String),
     #[error("error reading file: {0}")]
     IoError(#[from] std::io::Error),
+    #[error("backend error: {0}")]
+    BackendError(String),
+    #[error("signature verification failed: {0}")]
+    SignatureError(String),
+}
+
+/// How the rules referenced by correlation rules resolve,
+/// see [`SigmaCollection::dependency_report`]
+///
+/// [`SigmaCollection::dependency_report`]: struct.SigmaCollection.html#method.dependency_report
+#[cfg(feature = "correlation")]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DependencyReport {
+    /// `(correlation rule, reference)` for references to rules
+    /// not in the collection
+    pub unresolved: Vec<(RuleId, String)>,
+    /// `(correlation rule, name, rule)` for references by rule name
+    pub by_name: Vec<(RuleId, String, RuleId)>,
+    /// `(correlation rule, rule)` for references by rule ID
+    pub by_id: Vec<(RuleId, RuleId)>,
+    /// correlation rules with unresolved references, which never match
+    pub orphaned: Vec<RuleId>,
 }
 
-#[derive(Debug, Default)]
+#[cfg(feature = "correlation")]
+#[derive(Debug, Default, Clone)]
 pub(crate) struct DependencyGraph {
-    graph: Graph<String, (), Directed>,
-    idx: HashMap<String, graph::NodeIndex>,
+    graph: Graph<RuleId, (), Directed>,
+    idx: HashMap<RuleId, graph::NodeIndex>,
     sorted: Vec<graph::NodeIndex>,
+    /// the nodes reachable from each node, itself included, by node
+    /// index: the correlation rules a match of the rule can trigger
+    reachable: Vec<HashSet<graph::NodeIndex>>,
 }
 
+#[cfg(feature = "correlation")]
 impl DependencyGraph {
-    fn add_node(&mut self, id: &String) -> graph::NodeIndex {
+    fn add_node(&mut self, id: &RuleId) -> graph::NodeIndex {
         match self.idx.get(id) {
             Some(idx) => *idx,
             None => {
@@ -41,7 +100,7 @@ impl DependencyGraph {
             }
         }
     }
-    fn add_edge(&mut self, from: &String, to: &String) -> Result<(), CollectionError> {
+    fn add_edge(&mut self, from: &RuleId, to: &RuleId) -> Result<(), CollectionError> {
         let from = self.add_node(from);
         let to = self.add_node(to);
         self.graph.add_edge(from, to, ());
@@ -49,21 +108,150 @@ impl DependencyGraph {
         Ok(())
     }
 
+    /// the approximate heap size of the graph, petgraph keeps two
+    /// indices per node and four per edge
+    fn heap_size(&self) -> usize {
+        let index = std::mem::size_of::<graph::NodeIndex>();
+        self.graph.node_count() * (std::mem::size_of::<RuleId>() + 2 * index)
+            + self.graph.edge_count() * 4 * index
+            + footprint::entries::<RuleId, graph::NodeIndex>(self.idx.len())
+            + self.sorted.len() * index
+            + self
+                .reachable
+                .iter()
+                .map(|nodes| footprint::entries::<graph::NodeIndex, ()>(nodes.len()))
+                .sum::<usize>()
+    }
+
     fn sort(&mut self) -> Result<(), CollectionError> {
         self.sorted = petgraph::algo::toposort(&self.graph, None)
             .map_err(|_| CollectionError::DependencyCycle)?;
         Ok(())
     }
+
+    /// compute the nodes reachable from each node, walking the sorted
+    /// nodes backwards so that the dependents of a node come first
+    fn reach(&mut self) {
+        let mut reachable = vec![HashSet::new(); self.graph.node_count()];
+        for idx in self.sorted.iter().rev() {
+            let mut nodes = HashSet::from([*idx]);
+            for next in self.graph.neighbors(*idx) {
+                nodes.extend(&reachable[next.index()]);
+            }
+            reachable[idx.index()] = nodes;
+        }
+        self.reachable = reachable;
+    }
+
+    /// the nodes reachable from the rules in `prior`
+    fn reachable_from(&self, prior: &[RuleId]) -> HashSet<graph::NodeIndex> {
+        prior
+            .iter()
+            .filter_map(|id| self.idx.get(id))
+            .flat_map(|idx| &self.reachable[idx.index()])
+            .copied()
+            .collect()
+    }
+}
+
+/// The result of evaluating an event, see [`evaluate`]
+///
+/// [`evaluate`]: struct.SigmaCollection.html#method.evaluate
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Evaluation {
+    /// IDs of the rules that match
+    pub matches: Vec<RuleId>,
+    /// IDs of the rules skipped once the timeout expired
+    pub not_evaluated: Vec<RuleId>,
+    /// the distinct values counted by matching `value_count`
+    /// correlation rules
+    pub values: HashMap<RuleId, Vec<Value>>,
+    /// IDs of the matching rules dropped as duplicates, see [`Dedup`]
+    ///
+    /// [`Dedup`]: struct.Dedup.html
+    pub duplicates: Vec<RuleId>,
+    /// IDs of the matching rules dropped by a [`Throttle`]
+    ///
+    /// [`Throttle`]: correlation/throttle/struct.Throttle.html
+    #[cfg(feature = "correlation")]
+    pub throttled: Vec<RuleId>,
+}
+
+impl Evaluation {
+    /// whether every rule was evaluated before the timeout
+    pub fn is_complete(&self) -> bool {
+        self.not_evaluated.is_empty()
+    }
+
+    /// remove the duplicate rules from the matches
+    fn drop_duplicates(&mut self) {
+        if !self.duplicates.is_empty() {
+            let duplicates = &self.duplicates;
+            self.matches.retain(|id| !duplicates.contains(id));
+            self.values.retain(|id, _| !duplicates.contains(id));
+        }
+    }
+
+    /// remove the throttled rules from the matches
+    #[cfg(feature = "correlation")]
+    fn drop_throttled(&mut self) {
+        if !self.throttled.is_empty() {
+            let throttled = &self.throttled;
+            self.matches.retain(|id| !throttled.contains(id));
+            self.values.retain(|id, _| !throttled.contains(id));
+        }
+    }
 }
 
 /// A collection of Sigma rules, with dependency resolution
 /// and log source filtering
-#[derive(Debug, Default)]
+///
+/// Rules are shared between clones of a collection, so cloning
+/// does not recompile rules and clones share correlation state.
+/// To update rules while evaluating events concurrently, see
+/// [`SharedSigmaCollection`]
+///
+/// [`SharedSigmaCollection`]: struct.SharedSigmaCollection.html
+#[derive(Debug, Default, Clone)]
 pub struct SigmaCollection {
-    rules: HashMap<String, SigmaRule>,
+    rules: HashMap<RuleId, Arc<SigmaRule>>,
     filters: Filter,
-    named: HashMap<String, String>,
+    engines: Engines,
+    named: HashMap<String, RuleId>,
+    /// rule ID -> warnings about the other rules loaded with it
+    lineage: Vec<(RuleId, RuleWarning)>,
+    #[cfg(feature = "correlation")]
     deps: DependencyGraph,
+    drop_raw_detections: bool,
+    allow_missing_dependencies: bool,
+    options: MatchOptions,
+    limits: ParseLimits,
+    generate_missing_ids: bool,
+    load_filter: LoadFilter,
+    dir_options: DirOptions,
+    pipelines: Vec<Pipeline>,
+    overrides: Overrides,
+    observers: Observers,
+    suppressions: SuppressionStore,
+    dedup: Option<Dedup>,
+    telemetry: Option<Arc<Telemetry>>,
+    /// rule ID or name -> schedule
+    #[cfg(feature = "time")]
+    schedules: HashMap<String, Schedule>,
+    timeout: Option<Duration>,
+    max_matches: Option<usize>,
+    dirs: Vec<String>,
+    files: HashMap<PathBuf, SystemTime>,
+    /// rule ID or name -> group-by field -> event field
+    #[cfg(feature = "correlation")]
+    group_by_fields: HashMap<String, HashMap<String, String>>,
+    #[cfg(feature = "correlation")]
+    correlation_types: correlation::custom::CorrelationTypes,
+    #[cfg(feature = "correlation")]
+    throttles: Vec<Arc<correlation::throttle::Throttled>>,
+    #[cfg(feature = "signing")]
+    trusted_keys: Vec<crate::PublicKey>,
 }
 
 impl SigmaCollection {
@@ -72,217 +260,2243 @@ impl SigmaCollection {
         Self::default()
     }
 
-    /// Create a new `SigmaCollection` from a directory of Sigma rules
-    /// 
-    /// Rules must be in YAML format
-    pub fn new_from_dir(path: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        let mut collection = Self::default();
-        collection.load_from_dir(path)?;
-        Ok(collection)
+    /// Configure a new `SigmaCollection` before loading rules into it,
+    /// see [`SigmaCollectionBuilder`]
+    ///
+    /// [`SigmaCollectionBuilder`]: struct.SigmaCollectionBuilder.html
+    pub fn builder() -> SigmaCollectionBuilder {
+        SigmaCollectionBuilder::new()
     }
 
-    /// Load and add Sigma rules from a directory of YAML files
-    pub fn load_from_dir(
-        &mut self,
-        path: &str,
-    ) -> Result<u32, Box<dyn std::error::Error + Send + Sync>> {
-        let newrules: Vec<SigmaRule> = glob::glob(format!("{}/**/*.yml", path).as_str())?
-            .collect::<Result<Vec<_>, _>>()?
-            .into_iter()
-            .map(|entry| std::fs::read_to_string(&entry))
-            .collect::<Result<Vec<_>, _>>()?
-            .into_iter()
-            .map(|s| {
-                s.parse::<SigmaCollection>()
-                    .map(|r| Into::<Vec<SigmaRule>>::into(r))
-                    .map_err(|e| CollectionError::ParseError(e.to_string()))
-            })
-            .collect::<Result<Vec<_>, _>>()?
-            .into_iter()
-            .flatten()
-            .collect();
+    /// Drop the raw detection YAML of rules once compiled
+    ///
+    /// Reduces memory use for large collections, but rules
+    /// can no longer be fully re-serialized
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use sigmars::SigmaCollection;
+    /// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    /// let mut rules = SigmaCollection::new().drop_raw_detections(true);
+    /// rules.load_from_dir("/path/to/sigma/rules/")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn drop_raw_detections(mut self, drop: bool) -> Self {
+        self.drop_raw_detections = drop;
+        if drop {
+            self.rules
+                .values_mut()
+                .filter_map(Arc::get_mut)
+                .for_each(Self::drop_raw);
+        }
+        self
+    }
 
-        let count = newrules.len() as u32;
-        newrules.into_iter().for_each(|rule| {
-            self.filters.add(&rule);
-            self.insert(rule);
-        });
-        self.solve()?;
+    /// Load correlation rules whose referenced rules are not in the
+    /// collection instead of failing, see [`dependency_report`]
+    ///
+    /// such correlation rules never match
+    ///
+    /// [`dependency_report`]: #method.dependency_report
+    pub fn allow_missing_dependencies(mut self, allow: bool) -> Self {
+        self.allow_missing_dependencies = allow;
+        self
+    }
 
-        Ok(count)
+    /// Compare values case-sensitively in all rules
+    ///
+    /// Sigma matches values without the `cased` modifier
+    /// case-insensitively, this disables that for the whole collection
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use serde_json::json;
+    /// # use sigmars::{Event, SigmaCollection};
+    /// # static RULES: &str = r#"
+    /// # title: test rule
+    /// # id: test-rule
+    /// # logsource:
+    /// #   category: test
+    /// # detection:
+    /// #   selection:
+    /// #     foo: bar
+    /// #   condition: selection
+    /// # "#;
+    /// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    /// let rules = RULES.parse::<SigmaCollection>()?.case_sensitive(true);
+    /// assert!(rules.get_detection_matches(&Event::new(json!({"foo": "BAR"}))).is_empty());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn case_sensitive(mut self, case_sensitive: bool) -> Self {
+        self.options.case_sensitive = case_sensitive;
+        self
     }
 
-    /// apply Sigma rules to an [`Event`], returning a list of rule IDs
-    /// that match
-    /// 
-    /// [`LogSource`] fields set in the [`Event`] act as a filter: `None` is a wildcard,
-    /// and any field set in the [`Event`] must match the corresponding field in the
-    /// [`LogSource`] for the rule to match
-    /// 
-    /// [`LogSource`]: event/struct.LogSource.html
-    /// [`Event`]: event/struct.Event.html
-    /// 
+    /// Apply Unicode NFKC normalization to rule values and event
+    /// strings before comparing them
+    ///
+    /// catches evasion with compatibility characters, e.g. fullwidth
+    /// letters in command lines
+    ///
     /// ```rust
     /// # use std::error::Error;
     /// # use serde_json::json;
-    /// # use sigmars::event::{Event, LogSource};
-    /// # use sigmars::SigmaCollection;
-    /// static RULES: &str = r#"
-    /// title: test rule
-    /// id: test-rule
+    /// # use sigmars::{Event, SigmaCollection};
+    /// # static RULES: &str = r#"
+    /// # title: test rule
+    /// # id: test-rule
+    /// # logsource:
+    /// #   category: test
+    /// # detection:
+    /// #   selection:
+    /// #     CommandLine|contains: mimikatz
+    /// #   condition: selection
+    /// # "#;
+    /// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    /// let rules = RULES.parse::<SigmaCollection>()?.normalize_unicode(true);
+    /// let event = Event::new(json!({"CommandLine": "ｍｉｍｉｋａｔｚ.exe"}));
+    /// assert!(rules.get_detection_matches(&event).len() == 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn normalize_unicode(mut self, normalize: bool) -> Self {
+        self.options.normalize_unicode = normalize;
+        self
+    }
+
+    /// Fold Cyrillic and Greek letters that look like Latin ones
+    /// (e.g. Cyrillic `о`) to their Latin counterpart, after NFKC
+    /// normalization
+    pub fn fold_homoglyphs(mut self, fold: bool) -> Self {
+        self.options.fold_homoglyphs = fold;
+        self
+    }
+
+    /// Evaluate selections with a custom [`Matcher`] instead of the
+    /// built-in engine, see the [`matcher`] module
+    ///
+    /// [`Matcher`]: matcher/trait.Matcher.html
+    /// [`matcher`]: matcher/index.html
+    pub fn matcher(mut self, matcher: Arc<dyn Matcher>) -> Self {
+        self.options.matcher = Some(matcher);
+        self
+    }
+
+    /// Select the values of events searched by keyword lists, see
+    /// [`KeywordOptions`]
+    ///
+    /// [`KeywordOptions`]: struct.KeywordOptions.html
+    pub fn keyword_options(mut self, options: KeywordOptions) -> Self {
+        self.options.keywords = options;
+        self
+    }
+
+    /// Set the values of `%name%` placeholders expanded by the `expand`
+    /// modifier, a string or a list of alternatives
+    ///
+    /// rules with undefined placeholders do not match, placeholders
+    /// can be set for each event with [`Event::context`]
+    ///
+    /// ```rust
+    /// # use std::collections::HashMap;
+    /// # use serde_json::json;
+    /// # use sigmars::{Event, SigmaCollection};
+    /// # static RULES: &str = r#"
+    /// # title: ssh key added
+    /// # id: ssh-key-added
+    /// # logsource:
+    /// #   category: test
+    /// # detection:
+    /// #   selection:
+    /// #     TargetFilename|expand: '%home%/.ssh/authorized_keys'
+    /// #   condition: selection
+    /// # "#;
+    /// let rules = RULES
+    ///     .parse::<SigmaCollection>()
+    ///     .unwrap()
+    ///     .placeholders(HashMap::from([(
+    ///         "home".to_string(),
+    ///         json!(["/root", "/home/admin"]),
+    ///     )]));
+    /// let event = Event::new(json!({"TargetFilename": "/home/admin/.ssh/authorized_keys"}));
+    /// assert_eq!(rules.get_detection_matches(&event).len(), 1);
+    /// ```
+    ///
+    /// [`Event::context`]: event/struct.Event.html#method.context
+    pub fn placeholders(mut self, placeholders: HashMap<String, Value>) -> Self {
+        self.options.placeholders = Arc::new(placeholders);
+        self
+    }
+
+    /// Enforce [`ParseLimits`] on rules loaded with [`load_from_dir`]
+    /// and [`load_from_str`]
+    ///
+    /// [`ParseLimits`]: struct.ParseLimits.html
+    /// [`load_from_dir`]: #method.load_from_dir
+    /// [`load_from_str`]: #method.load_from_str
+    pub fn limits(mut self, limits: ParseLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Load rules without an `id` instead of failing, generating a
+    /// stable ID from their title, logsource and detection (or
+    /// correlation) sections
+    ///
+    /// such rules have [`SigmaRule::generated_id`] set, and correlation
+    /// rules can refer to them by `name`
+    ///
+    /// ```rust
+    /// # use serde_json::json;
+    /// # use sigmars::{Event, SigmaCollection};
+    /// let mut rules = SigmaCollection::new().generate_missing_ids(true);
+    /// rules
+    ///     .load_from_str(
+    ///         r#"
+    /// title: private rule
+    /// name: private_rule
     /// logsource:
     ///   category: test
     /// detection:
     ///   selection:
     ///     foo: bar
     ///   condition: selection
-    /// ---
-    /// title: test rule 2
-    /// id: test-rule-2
+    /// "#,
+    ///     )
+    ///     .unwrap();
+    /// let matches = rules.get_detection_matches(&Event::new(json!({"foo": "bar"})));
+    /// assert!(rules.get(&matches[0]).unwrap().generated_id);
+    /// ```
+    ///
+    /// [`SigmaRule::generated_id`]: struct.SigmaRule.html#structfield.generated_id
+    pub fn generate_missing_ids(mut self, generate: bool) -> Self {
+        self.generate_missing_ids = generate;
+        self
+    }
+
+    /// Only load the rules selected by a [`LoadFilter`] with
+    /// [`load_from_dir`] and [`load_from_str`]
+    ///
+    /// [`LoadFilter`]: struct.LoadFilter.html
+    /// [`load_from_dir`]: #method.load_from_dir
+    /// [`load_from_str`]: #method.load_from_str
+    pub fn load_filter(mut self, filter: LoadFilter) -> Self {
+        self.load_filter = filter;
+        self
+    }
+
+    /// Select the files loaded by [`load_from_dir`] and
+    /// [`reload_changed`] with [`DirOptions`]
+    ///
+    /// [`DirOptions`]: struct.DirOptions.html
+    /// [`load_from_dir`]: #method.load_from_dir
+    /// [`reload_changed`]: #method.reload_changed
+    pub fn dir_options(mut self, options: DirOptions) -> Self {
+        self.dir_options = options;
+        self
+    }
+
+    /// Map the fields of rules loaded into the collection with a
+    /// processing [`Pipeline`], such as [`Pipeline::sysmon_ecs`]
+    ///
+    /// pipelines apply in the order they were added, to rules loaded
+    /// after they are added
+    ///
+    /// [`Pipeline`]: struct.Pipeline.html
+    /// [`Pipeline::sysmon_ecs`]: struct.Pipeline.html#method.sysmon_ecs
+    pub fn pipeline(mut self, pipeline: Pipeline) -> Self {
+        self.pipelines.push(pipeline);
+        self
+    }
+
+    /// Apply local [`Overrides`] to rules loaded into the collection,
+    /// including when they are reloaded with [`reload_changed`]
+    ///
+    /// rules disabled by an override are not loaded, like rules
+    /// excluded by a [`LoadFilter`]
+    ///
+    /// [`Overrides`]: struct.Overrides.html
+    /// [`LoadFilter`]: struct.LoadFilter.html
+    /// [`reload_changed`]: #method.reload_changed
+    pub fn overrides(mut self, overrides: Overrides) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    /// Invoke `observer` for every rule matching an event during
+    /// evaluation
+    ///
+    /// observers are shared by clones of the collection and are invoked
+    /// in the order they were registered, one evaluation at a time
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+    /// # use serde_json::json;
+    /// # use sigmars::{Event, SigmaCollection};
+    /// # static RULES: &str = r#"
+    /// # title: test rule
+    /// # id: test-rule
+    /// # logsource:
+    /// #   category: test
+    /// # detection:
+    /// #   selection:
+    /// #     foo: bar
+    /// #   condition: selection
+    /// # "#;
+    /// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    /// let count = Arc::new(AtomicUsize::new(0));
+    /// let counter = count.clone();
+    /// let rules = RULES.parse::<SigmaCollection>()?.on_match(move |rule, _event, _m| {
+    ///     assert_eq!(&*rule.id, "test-rule");
+    ///     counter.fetch_add(1, Ordering::Relaxed);
+    /// });
+    /// rules.get_detection_matches(&Event::new(json!({"foo": "bar"})));
+    /// assert_eq!(count.load(Ordering::Relaxed), 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn on_match(
+        mut self,
+        observer: impl FnMut(&SigmaRule, &Event, &Match) + Send + 'static,
+    ) -> Self {
+        self.observers.push(observer);
+        self
+    }
+
+    /// Use a [`SuppressionStore`] shared with other collections, e.g. to
+    /// keep suppressions when replacing a [`SharedSigmaCollection`]
+    ///
+    /// [`SuppressionStore`]: struct.SuppressionStore.html
+    /// [`SharedSigmaCollection`]: struct.SharedSigmaCollection.html
+    pub fn suppression_store(mut self, store: SuppressionStore) -> Self {
+        self.suppressions = store;
+        self
+    }
+
+    /// the suppressions of known false positives, see [`SuppressionStore`]
+    ///
+    /// [`SuppressionStore`]: struct.SuppressionStore.html
+    pub fn suppressions(&self) -> &SuppressionStore {
+        &self.suppressions
+    }
+
+    /// Only evaluate the rule with ID or name `rule` for events whose
+    /// time is within `schedule`, for rules whose behavior is only
+    /// suspicious at some times
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use serde_json::json;
+    /// # use sigmars::{Event, Schedule, SigmaCollection};
+    /// # static RULES: &str = r#"
+    /// # title: test rule
+    /// # id: test-rule
+    /// # logsource:
+    /// #   category: test
+    /// # detection:
+    /// #   selection:
+    /// #     foo: bar
+    /// #   condition: selection
+    /// # "#;
+    /// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    /// let rules = RULES
+    ///     .parse::<SigmaCollection>()?
+    ///     .schedule("test-rule", Schedule::new().between("22:00", "06:00")?);
+    ///
+    /// let event = Event::new(json!({"foo": "bar", "@timestamp": "2024-06-01T23:30:00Z"}));
+    /// assert_eq!(rules.get_detection_matches(&event).len(), 1);
+    /// let event = Event::new(json!({"foo": "bar", "@timestamp": "2024-06-01T12:00:00Z"}));
+    /// assert!(rules.get_detection_matches(&event).is_empty());
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "time")]
+    pub fn schedule(mut self, rule: &str, schedule: Schedule) -> Self {
+        self.schedules.insert(rule.to_string(), schedule);
+        self
+    }
+
+    /// Drop repeated matches of a rule, see [`Dedup`]
+    ///
+    /// deduplication keeps replays and bursty sources from producing
+    /// identical findings: duplicate matches are dropped from the
+    /// results, listed in [`Evaluation::duplicates`] and not passed to
+    /// observers. They still count towards correlation rules depending
+    /// on the rule
+    ///
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use serde_json::json;
+    /// # use sigmars::{Dedup, Event, SigmaCollection};
+    /// # static RULES: &str = r#"
+    /// # title: test rule
+    /// # id: test-rule
+    /// # logsource:
+    /// #   category: test
+    /// # detection:
+    /// #   selection:
+    /// #     foo: bar
+    /// #   condition: selection
+    /// # "#;
+    /// let rules = RULES
+    ///     .parse::<SigmaCollection>()
+    ///     .unwrap()
+    ///     .dedup(Dedup::new(Duration::from_secs(60)).fields(["host"]));
+    ///
+    /// let event = |host: &str| {
+    ///     Event::new(json!({"foo": "bar", "host": host, "@timestamp": "2024-06-01T12:00:00Z"}))
+    /// };
+    /// assert_eq!(rules.get_detection_matches(&event("a")).len(), 1);
+    /// let evaluation = rules.evaluate_detections(&event("a"));
+    /// assert!(evaluation.matches.is_empty());
+    /// assert_eq!(evaluation.duplicates.len(), 1);
+    ///
+    /// assert_eq!(rules.get_detection_matches(&event("b")).len(), 1);
+    /// ```
+    ///
+    /// [`Dedup`]: struct.Dedup.html
+    /// [`Evaluation::duplicates`]: struct.Evaluation.html#structfield.duplicates
+    pub fn dedup(mut self, dedup: Dedup) -> Self {
+        self.dedup = Some(dedup);
+        self
+    }
+
+    /// whether the match of `rule` for `event` is a duplicate
+    fn is_duplicate(&self, rule: &SigmaRule, event: &Event) -> bool {
+        let duplicate = self
+            .dedup
+            .as_ref()
+            .is_some_and(|dedup| dedup.is_duplicate(rule, event));
+        if duplicate {
+            metrics::match_deduplicated(&rule.id);
+        }
+        duplicate
+    }
+
+    /// whether `rule` is scheduled to be evaluated for `event`
+    #[cfg(feature = "time")]
+    fn is_scheduled(&self, rule: &SigmaRule, event: &Event) -> bool {
+        if self.schedules.is_empty() {
+            return true;
+        }
+        let schedule = self
+            .schedules
+            .get(&*rule.id)
+            .or_else(|| rule.name.as_ref().and_then(|name| self.schedules.get(name)));
+        schedule.is_none_or(|schedule| {
+            schedule.is_active(event.timestamp().unwrap_or_else(chrono::Utc::now))
+        })
+    }
+
+    #[cfg(not(feature = "time"))]
+    fn is_scheduled(&self, _rule: &SigmaRule, _event: &Event) -> bool {
+        true
+    }
+
+    /// Stop evaluating an event once `timeout` has elapsed
+    ///
+    /// the remaining rules are skipped and do not match, use
+    /// [`evaluate_detections`] to find out which rules were skipped.
+    /// Rules are only checked against the deadline between rules, so a
+    /// single rule can still overrun it
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::time::Duration;
+    /// # use serde_json::json;
+    /// # use sigmars::{Event, SigmaCollection};
+    /// # static RULES: &str = r#"
+    /// # title: test rule
+    /// # id: test-rule
+    /// # logsource:
+    /// #   category: test
+    /// # detection:
+    /// #   selection:
+    /// #     foo: bar
+    /// #   condition: selection
+    /// # "#;
+    /// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    /// let rules = RULES.parse::<SigmaCollection>()?.timeout(Duration::ZERO);
+    /// let res = rules.evaluate_detections(&Event::new(json!({"foo": "bar"})));
+    /// assert!(res.matches.is_empty());
+    /// assert_eq!(&*res.not_evaluated[0], "test-rule");
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`evaluate_detections`]: #method.evaluate_detections
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Stop evaluating an event once `max` rules have matched it
+    ///
+    /// rules are evaluated in no particular order, so which of the
+    /// matching rules are returned is unspecified. Correlation rules
+    /// only see the detection matches found before stopping
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use serde_json::json;
+    /// # use sigmars::{Event, SigmaCollection};
+    /// # static RULES: &str = r#"
+    /// # title: test rule
+    /// # id: test-rule
+    /// # logsource:
+    /// #   category: test
+    /// # detection:
+    /// #   selection:
+    /// #     foo: bar
+    /// #   condition: selection
+    /// # ---
+    /// # title: test rule 2
+    /// # id: test-rule-2
+    /// # logsource:
+    /// #   category: test
+    /// # detection:
+    /// #   selection:
+    /// #     foo|startswith: b
+    /// #   condition: selection
+    /// # "#;
+    /// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    /// let rules = RULES.parse::<SigmaCollection>()?.max_matches(1);
+    /// assert_eq!(rules.get_detection_matches(&Event::new(json!({"foo": "bar"}))).len(), 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn max_matches(mut self, max: usize) -> Self {
+        self.max_matches = Some(max);
+        self
+    }
+
+    /// Stop evaluating an event at the first matching rule,
+    /// see [`max_matches`]
+    ///
+    /// [`max_matches`]: #method.max_matches
+    pub fn first_match_only(mut self, first: bool) -> Self {
+        self.max_matches = first.then_some(1);
+        self
+    }
+
+    /// whether `matches` reached [`max_matches`](#method.max_matches)
+    fn is_done(&self, matches: &[RuleId]) -> bool {
+        self.max_matches.is_some_and(|max| matches.len() >= max)
+    }
+
+    /// Require rule files to be signed by one of `keys`
+    ///
+    /// [`load_from_dir`] then only loads files with a valid detached
+    /// [minisign](https://jedisct1.github.io/minisign/) signature
+    /// (`<file>.minisig`), failing on the first file without one
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use sigmars::{PublicKey, SigmaCollection};
+    /// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    /// let key = PublicKey::from_base64("RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3")?;
+    /// let mut rules = SigmaCollection::new().require_signatures([key]);
+    /// rules.load_from_dir("/path/to/signed/rules/")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`load_from_dir`]: #method.load_from_dir
+    #[cfg(feature = "signing")]
+    pub fn require_signatures(mut self, keys: impl IntoIterator<Item = crate::PublicKey>) -> Self {
+        self.trusted_keys = keys.into_iter().collect();
+        self
+    }
+
+    /// Parse and add Sigma rules from a YAML string with a minisign
+    /// signature made by one of the keys given to [`require_signatures`]
+    ///
+    /// [`require_signatures`]: #method.require_signatures
+    #[cfg(feature = "signing")]
+    pub fn load_from_signed_str(
+        &mut self,
+        s: &str,
+        signature: &str,
+    ) -> Result<u32, CollectionError> {
+        crate::signing::verify(&self.trusted_keys, s.as_bytes(), signature)
+            .map_err(CollectionError::SignatureError)?;
+        self.load_from_str(s)
+    }
+
+    /// Create a new `SigmaCollection` from a directory of Sigma rules
+    /// 
+    /// Rules must be in YAML format
+    pub fn new_from_dir(path: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut collection = Self::default();
+        collection.load_from_dir(path)?;
+        Ok(collection)
+    }
+
+    /// Load and add Sigma rules from a directory of YAML files,
+    /// `.yml` and `.yaml` unless configured with [`dir_options`]
+    ///
+    /// Files are parsed in parallel, errors are reported with the
+    /// path of the file that failed to load. The directory is
+    /// remembered for [`reload_changed`]
+    ///
+    /// [`dir_options`]: #method.dir_options
+    /// [`reload_changed`]: #method.reload_changed
+    pub fn load_from_dir(
+        &mut self,
+        path: &str,
+    ) -> Result<u32, Box<dyn std::error::Error + Send + Sync>> {
+        let files = self.dir_options.files(path)?;
+        let paths = files.keys().cloned().collect::<Vec<_>>();
+        let newrules = self.parse_files(&paths)?;
+
+        let count = newrules.len() as u32;
+        newrules.into_iter().for_each(|rule| self.insert(rule));
+        self.solve()?;
+        self.engines.compile(&self.filters, &self.rules);
+
+        if !self.dirs.iter().any(|dir| dir == path) {
+            self.dirs.push(path.to_string());
+        }
+        self.files.extend(files);
+        Ok(count)
+    }
+
+    /// Re-parse the files under directories loaded with [`load_from_dir`]
+    /// that were added, modified or removed since they were (re)loaded,
+    /// returning the number of rules loaded
+    ///
+    /// Rules from modified and removed files are replaced, other rules
+    /// are left as they are. If a file fails to parse or a dependency
+    /// can't be resolved, the collection is left unchanged. Correlation
+    /// rules loaded must be initialized with [`init`]
+    ///
+    /// [`load_from_dir`]: #method.load_from_dir
+    /// [`init`]: #method.init
+    pub fn reload_changed(&mut self) -> Result<u32, Box<dyn std::error::Error + Send + Sync>> {
+        let mut files = HashMap::new();
+        for dir in &self.dirs {
+            files.extend(self.dir_options.files(dir)?);
+        }
+
+        let changed = files
+            .iter()
+            .filter(|(path, modified)| self.files.get(*path) != Some(*modified))
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        let removed = self
+            .files
+            .keys()
+            .filter(|path| !files.contains_key(*path))
+            .cloned()
+            .collect::<Vec<_>>();
+        if changed.is_empty() && removed.is_empty() {
+            return Ok(0);
+        }
+
+        let newrules = self.parse_files(&changed)?;
+        let count = newrules.len() as u32;
+
+        let mut next = self.clone();
+        changed
+            .into_iter()
+            .chain(removed)
+            .for_each(|path| next.remove_source(&Source::File(path)));
+        newrules.into_iter().for_each(|rule| next.insert(rule));
+        next.solve()?;
+        next.engines.compile(&next.filters, &next.rules);
+
+        next.files = files;
+        *self = next;
+        Ok(count)
+    }
+
+    /// the files under `path` that [`load_from_dir`] would load,
+    /// with their modification times
+    ///
+    /// [`load_from_dir`]: #method.load_from_dir
+    #[cfg(feature = "updater")]
+    pub(crate) fn dir_files(
+        &self,
+        path: &str,
+    ) -> Result<HashMap<PathBuf, SystemTime>, Box<dyn std::error::Error + Send + Sync>> {
+        self.dir_options.files(path)
+    }
+
+    /// run `f` with the collection's [`ParseLimits`], custom
+    /// correlation types and ID generation applied to the rules it parses
+    ///
+    /// [`ParseLimits`]: struct.ParseLimits.html
+    fn parsing<T>(&self, f: impl FnOnce() -> T) -> T {
+        #[cfg(feature = "correlation")]
+        let f = || correlation::custom::with(&self.correlation_types, f);
+        let f = || rule::generating_ids(self.generate_missing_ids, f);
+        limits::with(self.limits, f)
+    }
+
+    /// parse rule files in parallel
+    fn parse_files(&self, paths: &[PathBuf]) -> Result<Vec<SigmaRule>, CollectionError> {
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let chunk_size = paths.len().div_ceil(threads).max(1);
+
+        let rules = std::thread::scope(|scope| {
+            paths
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        self.parsing(|| {
+                            chunk
+                                .iter()
+                                .map(|path| self.load_file(path))
+                                .collect::<Result<Vec<_>, _>>()
+                        })
+                    })
+                })
+                .collect::<Vec<_>>()
+                .into_iter()
+                .map(|handle| {
+                    handle.join().map_err(|_| {
+                        CollectionError::ParseError("rule loader thread panicked".to_string())
+                    })?
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+
+        Ok(rules.into_iter().flatten().flatten().collect())
+    }
+
+    /// Parse and add Sigma rules from a (multi-document) YAML string,
+    /// enforcing the collection's [`ParseLimits`]
+    ///
+    /// [`ParseLimits`]: struct.ParseLimits.html
+    pub fn load_from_str(&mut self, s: &str) -> Result<u32, CollectionError> {
+        let mut newrules = self
+            .parsing(|| parse_rules(s))
+            .map_err(|e| CollectionError::ParseError(e.to_string()))?;
+        newrules.retain(|rule| self.loads(rule));
+
+        let count = newrules.len() as u32;
+        newrules.into_iter().for_each(|rule| self.insert(rule));
+        self.solve()?;
+        self.engines.compile(&self.filters, &self.rules);
+
+        Ok(count)
+    }
+
+    fn load_file(&self, path: &std::path::Path) -> Result<Vec<SigmaRule>, CollectionError> {
+        let s = std::fs::read_to_string(path)
+            .map_err(|e| std::io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        #[cfg(feature = "signing")]
+        if !self.trusted_keys.is_empty() {
+            crate::signing::verify_file(&self.trusted_keys, path, s.as_bytes()).map_err(|e| {
+                CollectionError::SignatureError(format!("{}: {}", path.display(), e))
+            })?;
+        }
+        let mut rules = parse_rules(&s)
+            .map_err(|e| CollectionError::ParseError(format!("{}: {}", path.display(), e)))?;
+        rules
+            .iter_mut()
+            .for_each(|rule| rule.source = Source::File(path.to_path_buf()));
+        rules.retain(|rule| self.loads(rule));
+        Ok(rules)
+    }
+
+    /// apply Sigma rules to an [`Event`], returning a list of rule IDs
+    /// that match
+    /// 
+    /// [`LogSource`] fields set in the [`Event`] act as a filter: `None` is a wildcard,
+    /// and any field set in the [`Event`] must match the corresponding field in the
+    /// [`LogSource`] for the rule to match
+    /// 
+    /// [`LogSource`]: event/struct.LogSource.html
+    /// [`Event`]: event/struct.Event.html
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use serde_json::json;
+    /// # use sigmars::event::{Event, LogSource};
+    /// # use sigmars::SigmaCollection;
+    /// static RULES: &str = r#"
+    /// title: test rule
+    /// id: test-rule
+    /// logsource:
+    ///   category: test
+    /// detection:
+    ///   selection:
+    ///     foo: bar
+    ///   condition: selection
+    /// ---
+    /// title: test rule 2
+    /// id: test-rule-2
+    /// logsource:
+    ///   category: nomatch
+    /// detection:
+    ///   selection:
+    ///     foo: bar
+    ///   condition: selection
+    /// "#;
+    /// 
+    /// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    /// let rules: SigmaCollection = RULES.parse()?;
+    /// let event = Event::new(json!({"foo": "bar"}))
+    ///            .logsource(LogSource::default().category("test"));
+    /// let res = rules.get_detection_matches(&event);
+    /// assert!(res.len() == 1);
+    /// assert_eq!(&*res[0], "test-rule");
+    /// # Ok(())
+    /// # }
+    /// 
+    pub fn get_detection_matches(&self, event: &Event) -> Matches {
+        self.get_detection_matches_for(event, &event.logsource)
+    }
+
+    /// apply Sigma rules to an [`Event`] as if it came from `logsource`,
+    /// returning a list of rule IDs that match
+    ///
+    /// the [`LogSource`] of the event is ignored, see
+    /// [`get_detection_matches`]
+    ///
+    /// [`LogSource`]: event/struct.LogSource.html
+    /// [`Event`]: event/struct.Event.html
+    /// [`get_detection_matches`]: #method.get_detection_matches
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use serde_json::json;
+    /// # use sigmars::event::{Event, LogSource};
+    /// # use sigmars::SigmaCollection;
+    /// static RULES: &str = r#"
+    /// title: test rule
+    /// id: test-rule
+    /// logsource:
+    ///   category: test
+    /// detection:
+    ///   selection:
+    ///     foo: bar
+    ///   condition: selection
+    /// "#;
+    ///
+    /// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    /// let rules: SigmaCollection = RULES.parse()?;
+    /// let event = Event::new(json!({"foo": "bar"}))
+    ///            .logsource(LogSource::default().category("other"));
+    /// assert!(rules.get_detection_matches(&event).is_empty());
+    /// let test = LogSource::default().category("test");
+    /// assert!(rules.get_detection_matches_for(&event, &test).len() == 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn get_detection_matches_for(&self, event: &Event, logsource: &LogSource) -> Matches {
+        let matches = self.evaluate_detections_for(event, logsource).matches;
+        let detections = matches.len();
+        Matches::new(matches, detections)
+    }
+
+    /// apply the detection rules to the events of an NDJSON stream (one
+    /// JSON event per line) from `logsource`, yielding the events that
+    /// match
+    ///
+    /// events are read and evaluated one at a time, in stream order.
+    /// Replay is detection-only: correlation rules measure their
+    /// timespans as events are evaluated rather than by event time, so
+    /// they are not evaluated and their state is left untouched, see
+    /// [`warm_up`] to rebuild it
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use sigmars::event::LogSource;
+    /// # use sigmars::SigmaCollection;
+    /// static RULES: &str = r#"
+    /// title: test rule
+    /// id: test-rule
+    /// logsource:
+    ///   category: test
+    /// detection:
+    ///   selection:
+    ///     foo: bar
+    ///   condition: selection
+    /// "#;
+    ///
+    /// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    /// let rules: SigmaCollection = RULES.parse()?;
+    ///
+    /// let log = "{\"foo\": \"baz\"}\n{\"foo\": \"bar\"}\n";
+    /// let logsource = LogSource::default().category("test");
+    /// let matches = rules
+    ///     .scan_ndjson(log.as_bytes(), &logsource)
+    ///     .collect::<Result<Vec<_>, _>>()?;
+    /// assert_eq!(matches.len(), 1);
+    /// assert_eq!(matches[0].line, 2);
+    /// assert_eq!(matches[0].matches, vec!["test-rule".into()]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`warm_up`]: #method.warm_up
+    pub fn scan_ndjson<'a>(
+        &'a self,
+        reader: impl std::io::BufRead + 'a,
+        logsource: &'a LogSource,
+    ) -> impl Iterator<Item = Result<LineMatch, Box<dyn std::error::Error + Send + Sync>>> + 'a
+    {
+        replay::read(reader).filter_map(move |line| {
+            let (line, data) = match line {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
+            let event = Event::new(data).logsource(logsource.clone());
+            let matches = self.get_detection_matches(&event);
+            (!matches.is_empty()).then_some(Ok(LineMatch {
+                line,
+                event,
+                matches,
+            }))
+        })
+    }
+
+    /// apply Sigma rules to an [`Event`] like [`get_detection_matches`],
+    /// also returning the rules skipped after the [`timeout`]
+    ///
+    /// [`Event`]: event/struct.Event.html
+    /// [`get_detection_matches`]: #method.get_detection_matches
+    /// [`timeout`]: #method.timeout
+    pub fn evaluate_detections(&self, event: &Event) -> Evaluation {
+        self.evaluate_detections_for(event, &event.logsource)
+    }
+
+    /// apply Sigma rules to an [`Event`] as if it came from `logsource`,
+    /// see [`evaluate_detections`]
+    ///
+    /// [`Event`]: event/struct.Event.html
+    /// [`evaluate_detections`]: #method.evaluate_detections
+    pub fn evaluate_detections_for(&self, event: &Event, logsource: &LogSource) -> Evaluation {
+        let start = Instant::now();
+        let engine = self.engines.get(&self.filters, &self.rules, logsource);
+        let mut evaluation = self.detect(event, logsource, engine.candidates(&event.data), start);
+        self.notify_detections(event, &evaluation);
+        evaluation.drop_duplicates();
+        evaluation
+    }
+
+    /// evaluate detection `rules` on `event` as if it came from
+    /// `logsource` until the deadline, without notifying observers
+    fn detect<'a>(
+        &self,
+        event: &Event,
+        logsource: &LogSource,
+        rules: impl Iterator<Item = &'a Arc<SigmaRule>>,
+        start: Instant,
+    ) -> Evaluation {
+        let deadline = self.timeout.map(|timeout| start + timeout);
+        let options = self.options.for_event(event, logsource);
+        let mut evaluation = Evaluation::default();
+        for rule in rules {
+            if self.is_done(&evaluation.matches) {
+                break;
+            }
+            let RuleType::Detection(ref d) = rule.rule else {
+                continue;
+            };
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                evaluation.not_evaluated.push(rule.id.clone());
+            } else if self.is_scheduled(rule, event)
+                && d.is_match(&event.data, &options)
+                && !self.suppressions.suppresses(rule, event)
+            {
+                evaluation.matches.push(rule.id.clone());
+                if self.is_duplicate(rule, event) {
+                    evaluation.duplicates.push(rule.id.clone());
+                }
+            }
+        }
+        metrics::detection_evaluated(start, &evaluation.matches);
+        if let Some(ref telemetry) = self.telemetry {
+            telemetry.detection_evaluated(start, &evaluation.matches);
+        }
+        metrics::rules_not_evaluated(&evaluation.not_evaluated);
+        evaluation
+    }
+
+    /// notify observers of the detection rules matching in `evaluation`
+    fn notify_detections(&self, event: &Event, evaluation: &Evaluation) {
+        for id in &evaluation.matches {
+            if evaluation.duplicates.contains(id) {
+                continue;
+            }
+            #[cfg(feature = "correlation")]
+            if evaluation.throttled.contains(id) {
+                continue;
+            }
+            if let Some(rule) = self.rules.get(id) {
+                self.observers.notify(rule, event, false, &[]);
+            }
+        }
+    }
+
+    /// apply all Sigma rules to an `Event`, returning a list of rule IDs
+    /// that match, without filtering by `LogSource`
+    /// 
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use serde_json::json;
+    /// # use sigmars::event::{Event, LogSource};
+    /// # use sigmars::SigmaCollection;
+    /// static RULES: &str = r#"
+    /// title: test rule
+    /// id: test-rule
+    /// logsource:
+    ///   category: test
+    /// detection:
+    ///   selection:
+    ///     foo: bar
+    ///   condition: selection
+    /// ---
+    /// title: test rule 2
+    /// id: test-rule-2
+    /// logsource:
+    ///   category: nomatch
+    /// detection:
+    ///   selection:
+    ///     foo: bar
+    ///   condition: selection
+    /// "#;
+    /// 
+    /// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    /// let rules: SigmaCollection = RULES.parse()?;
+    /// let event = Event::new(json!({"foo": "bar"}))
+    ///            .logsource(LogSource::default().category("test"));
+    /// let res = rules.get_detection_matches_unfiltered(&event);
+    /// assert!(res.len() == 2);
+    /// # Ok(())
+    /// # }
+    ///
+    pub fn get_detection_matches_unfiltered(&self, event: &Event) -> Matches {
+        let mut evaluation = self.detect(event, &event.logsource, self.rules.values(), Instant::now());
+        self.notify_detections(event, &evaluation);
+        evaluation.drop_duplicates();
+        let matches = evaluation.matches;
+        let detections = matches.len();
+        Matches::new(matches, detections)
+    }
+
+    /// Explain why each detection rule for the log source of an event
+    /// matched it or not, by rule ID
+    ///
+    /// every rule selected by the log source is evaluated, with the
+    /// result of each of its selections. Explaining an event has no
+    /// side effects: observers are not notified, suppressions do not
+    /// count it and it is not recorded for [`dedup`]. Correlation rules
+    /// are not explained
+    ///
+    /// ```rust
+    /// # use serde_json::json;
+    /// # use sigmars::{Event, SigmaCollection};
+    /// # static RULES: &str = r#"
+    /// # title: test rule
+    /// # id: test-rule
+    /// # logsource:
+    /// #   category: test
+    /// # detection:
+    /// #   selection:
+    /// #     foo: bar
+    /// #   filter:
+    /// #     user: admin
+    /// #   condition: selection and not filter
+    /// # "#;
+    /// let rules: SigmaCollection = RULES.parse().unwrap();
+    /// let event = Event::new(json!({"foo": "bar", "user": "admin"}));
+    /// let explanation = &rules.explain(&event)[0];
+    /// assert!(!explanation.matched());
+    /// assert_eq!(explanation.selections["selection"], true);
+    /// assert_eq!(explanation.selections["filter"], true);
+    /// ```
+    ///
+    /// [`dedup`]: #method.dedup
+    pub fn explain(&self, event: &Event) -> Vec<RuleExplanation> {
+        self.explain_for(event, &event.logsource)
+    }
+
+    /// explain the evaluation of an [`Event`] as if it came from
+    /// `logsource`, see [`explain`]
+    ///
+    /// [`Event`]: event/struct.Event.html
+    /// [`explain`]: #method.explain
+    pub fn explain_for(&self, event: &Event, logsource: &LogSource) -> Vec<RuleExplanation> {
+        let options = self.options.for_event(event, logsource);
+        let mut ids = self.filters.filter(logsource);
+        ids.sort();
+        ids.iter()
+            .filter_map(|id| self.rules.get(id))
+            .filter_map(|rule| {
+                let RuleType::Detection(ref detection) = rule.rule else {
+                    return None;
+                };
+                let (selections, missing_fields) = detection.explain(&event.data, &options);
+                let outcome = if !self.is_scheduled(rule, event) {
+                    RuleOutcome::NotScheduled
+                } else if !missing_fields.is_empty() || !detection.condition_matches(&selections) {
+                    RuleOutcome::NotMatched
+                } else if let Some(id) = self.suppressions.find(rule, event) {
+                    RuleOutcome::Suppressed(id)
+                } else {
+                    RuleOutcome::Matched
+                };
+                Some(RuleExplanation {
+                    rule: rule.id.clone(),
+                    outcome,
+                    selections,
+                    missing_fields,
+                })
+            })
+            .collect()
+    }
+
+    /// Pairs of detection rules matching the same events, or one
+    /// matching every event the other does, to prune redundant rules
+    ///
+    /// a rule covers another if its log source is the same or broader,
+    /// and each conjunction of the condition of the other (in disjunctive
+    /// normal form) is covered by one of its own: one constraining each
+    /// of its fields at most as much, and excluding at most as much.
+    /// Plain values and `contains`, `startswith` and `endswith` values
+    /// are compared by their text, other values by equality, so rules
+    /// may overlap without being reported. Rules with conditions over
+    /// 64 conjunctions are not compared
+    ///
+    /// ```rust
+    /// # use sigmars::{OverlapKind, SigmaCollection};
+    /// # static RULES: &str = r#"
+    /// # title: curl
+    /// # id: curl
+    /// # logsource:
+    /// #   category: process_creation
+    /// # detection:
+    /// #   selection:
+    /// #     Image|endswith: '/curl'
+    /// #   condition: selection
+    /// # ---
+    /// # title: curl to tmp
+    /// # id: curl-to-tmp
+    /// # logsource:
+    /// #   category: process_creation
+    /// #   product: linux
+    /// # detection:
+    /// #   selection:
+    /// #     Image: '/usr/bin/curl'
+    /// #     CommandLine|contains: ' -o /tmp/'
+    /// #   condition: selection
+    /// # "#;
+    /// let rules: SigmaCollection = RULES.parse().unwrap();
+    /// let overlaps = rules.overlaps();
+    /// assert_eq!(overlaps.len(), 1);
+    /// assert_eq!(overlaps[0].general, "curl".into());
+    /// assert_eq!(overlaps[0].specific, "curl-to-tmp".into());
+    /// assert_eq!(overlaps[0].kind, OverlapKind::Subsumes);
+    /// ```
+    pub fn overlaps(&self) -> Vec<RuleOverlap> {
+        let mut rules = self.rules.values().collect::<Vec<_>>();
+        rules.sort_by(|a, b| a.id.cmp(&b.id));
+        let rules = rules
+            .into_iter()
+            .filter_map(|rule| match rule.rule {
+                RuleType::Detection(ref d) => Normalized::new(&rule.id, d),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        let mut overlaps = vec![];
+        for (i, rule) in rules.iter().enumerate() {
+            overlaps.extend(rules[i + 1..].iter().filter_map(|other| rule.overlap(other)));
+        }
+        overlaps
+    }
+
+    /// The event fields inspected by the rules of the collection, to
+    /// parse or store only the fields needed, see [`ReferencedFields`]
+    ///
+    /// fields are those of the selections of detection rules after field
+    /// mappings, the fields referenced with `fieldref`, and the group-by
+    /// and `value_count` fields of correlation rules
+    ///
+    /// ```rust
+    /// # use sigmars::SigmaCollection;
+    /// # static RULES: &str = r#"
+    /// # title: curl
+    /// # id: curl
+    /// # logsource:
+    /// #   category: process_creation
+    /// # detection:
+    /// #   selection:
+    /// #     process.name: curl
+    /// #     CommandLine|contains: ' -o /tmp/'
+    /// #   condition: selection
+    /// # "#;
+    /// let rules: SigmaCollection = RULES.parse().unwrap();
+    /// let fields = rules.referenced_fields();
+    /// assert!(fields.fields.contains("process.name"));
+    /// assert!(fields.contains("process"));
+    /// assert!(!fields.contains("User"));
+    /// ```
+    ///
+    /// [`ReferencedFields`]: struct.ReferencedFields.html
+    pub fn referenced_fields(&self) -> ReferencedFields {
+        let mut fields = ReferencedFields::default();
+        for (_, referenced) in self.referenced_fields_by_logsource() {
+            fields.extend(referenced);
+        }
+        fields
+    }
+
+    /// [`referenced_fields`] by the log source (category, product and
+    /// service) of detection rules, ordered by category, product and
+    /// service
+    ///
+    /// the fields of correlation rules are listed under the log sources
+    /// of the detection rules they depend on
+    ///
+    /// [`referenced_fields`]: #method.referenced_fields
+    pub fn referenced_fields_by_logsource(&self) -> Vec<(LogSource, ReferencedFields)> {
+        #[allow(unused_mut)]
+        let mut by_rule = self
+            .rules
+            .iter()
+            .filter_map(|(id, rule)| match rule.rule {
+                RuleType::Detection(ref d) => Some((id, (&d.logsource, d.fields()))),
+                _ => None,
+            })
+            .collect::<HashMap<_, _>>();
+        #[cfg(feature = "correlation")]
+        self.correlation_fields(&mut by_rule);
+
+        let mut by_logsource: Vec<(LogSource, ReferencedFields)> = vec![];
+        for (logsource, fields) in by_rule.into_values() {
+            let logsource = logsource_key(logsource);
+            match by_logsource.iter_mut().find(|(l, _)| *l == logsource) {
+                Some((_, referenced)) => referenced.extend(fields),
+                None => by_logsource.push((logsource, fields)),
+            }
+        }
+        by_logsource.sort_by(|(a, _), (b, _)| logsource_order(a, b));
+        by_logsource
+    }
+
+    /// The log sources (category, product and service) the detection
+    /// rules of the collection are written for, with the number of rules
+    /// for each, to check that the log types they depend on are collected
+    ///
+    /// log sources are ordered by category, product and service
+    ///
+    /// ```rust
+    /// # use sigmars::SigmaCollection;
+    /// # static RULES: &str = r#"
+    /// # title: curl
+    /// # id: curl
+    /// # logsource:
+    /// #   category: process_creation
+    /// #   product: linux
+    /// # detection:
+    /// #   selection:
+    /// #     Image|endswith: '/curl'
+    /// #   condition: selection
+    /// # ---
+    /// # title: wget
+    /// # id: wget
+    /// # logsource:
+    /// #   category: process_creation
+    /// #   product: linux
+    /// # detection:
+    /// #   selection:
+    /// #     Image|endswith: '/wget'
+    /// #   condition: selection
+    /// # "#;
+    /// let rules: SigmaCollection = RULES.parse().unwrap();
+    /// let logsources = rules.required_logsources();
+    /// assert_eq!(logsources.len(), 1);
+    /// assert_eq!(logsources[0].0.category.as_deref(), Some("process_creation"));
+    /// assert_eq!(logsources[0].0.product.as_deref(), Some("linux"));
+    /// assert_eq!(logsources[0].1, 2);
+    /// ```
+    pub fn required_logsources(&self) -> Vec<(LogSource, usize)> {
+        let mut logsources: Vec<(LogSource, usize)> = vec![];
+        for rule in self.rules.values() {
+            let RuleType::Detection(ref detection) = rule.rule else {
+                continue;
+            };
+            let logsource = logsource_key(&detection.logsource);
+            match logsources.iter_mut().find(|(l, _)| *l == logsource) {
+                Some((_, count)) => *count += 1,
+                None => logsources.push((logsource, 1)),
+            }
+        }
+        logsources.sort_by(|(a, _), (b, _)| logsource_order(a, b));
+        logsources
+    }
+
+    /// Run the sample events in the `tests` field of each rule,
+    /// see [`rule_tests`], returning the results by rule ID
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use sigmars::SigmaCollection;
+    /// static RULES: &str = r#"
+    /// title: test rule
+    /// id: test-rule
+    /// logsource:
+    ///   category: test
+    /// detection:
+    ///   selection:
+    ///     foo: bar
+    ///   condition: selection
+    /// tests:
+    ///   positive:
+    ///     - foo: bar
+    ///   negative:
+    ///     - foo: baz
+    ///     - foo: bar
+    /// "#;
+    ///
+    /// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    /// let rules: SigmaCollection = RULES.parse()?;
+    /// let results = rules.run_rule_tests();
+    /// assert!(!results[0].passed());
+    /// assert_eq!(results[0].false_positives, vec![1]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`rule_tests`]: rule_tests/index.html
+    pub fn run_rule_tests(&self) -> Vec<RuleTestResult> {
+        let mut results = self
+            .rules
+            .values()
+            .filter_map(|rule| rule_tests::run(rule, &self.options))
+            .collect::<Vec<_>>();
+        results.sort_by(|a, b| a.rule.cmp(&b.rule));
+        results
+    }
+
+    /// apply the detection rules selected by `logsource` to each row of
+    /// an Arrow record batch, returning the indices of the rows matched
+    /// by each rule
+    ///
+    /// each row is evaluated as an event with the columns read by the
+    /// rules as fields, columns with dotted names (e.g. `process.name`)
+    /// being nested like dotted rule fields
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::sync::Arc;
+    /// # use arrow_array::{ArrayRef, RecordBatch, StringArray};
+    /// # use sigmars::event::LogSource;
+    /// # use sigmars::SigmaCollection;
+    /// static RULES: &str = r#"
+    /// title: test rule
+    /// id: test-rule
+    /// logsource:
+    ///   category: test
+    /// detection:
+    ///   selection:
+    ///     foo: bar
+    ///   condition: selection
+    /// "#;
+    ///
+    /// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    /// let rules: SigmaCollection = RULES.parse()?;
+    /// let foo: ArrayRef = Arc::new(StringArray::from(vec!["baz", "bar", "BAR"]));
+    /// let batch = RecordBatch::try_from_iter([("foo", foo)])?;
+    ///
+    /// let matches = rules.scan_record_batch(&batch, &LogSource::default().category("test"));
+    /// assert_eq!(matches["test-rule"], vec![1, 2]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "arrow")]
+    pub fn scan_record_batch(
+        &self,
+        batch: &arrow_array::RecordBatch,
+        logsource: &LogSource,
+    ) -> HashMap<RuleId, Vec<usize>> {
+        let engine = self.engines.get(&self.filters, &self.rules, logsource);
+        let mut matches = HashMap::new();
+        crate::arrow::scan(&engine, batch, &self.options.for_logsource(logsource), 0, &mut matches);
+        matches
+    }
+
+    /// apply the detection rules selected by `logsource` to each row of
+    /// a Parquet file, returning the indices of the rows matched by each
+    /// rule, see [`scan_record_batch`]
+    ///
+    /// only the columns read by the rules are read from the file
+    ///
+    /// [`scan_record_batch`]: #method.scan_record_batch
+    #[cfg(feature = "parquet")]
+    pub fn scan_parquet(
+        &self,
+        path: impl AsRef<std::path::Path>,
+        logsource: &LogSource,
+    ) -> Result<HashMap<RuleId, Vec<usize>>, Box<dyn std::error::Error + Send + Sync>> {
+        let engine = self.engines.get(&self.filters, &self.rules, logsource);
+        crate::arrow::scan_parquet(&engine, path.as_ref(), &self.options.for_logsource(logsource))
+    }
+
+    /// Add a Sigma rule to the collection
+    pub fn add(&mut self, rule: SigmaRule) -> Result<(), CollectionError> {
+        self.insert(rule);
+        self.solve()
+    }
+
+    /// Add Sigma rules to the collection, solving dependencies once
+    /// all rules are added
+    ///
+    /// fails if a correlation rule depends on a missing rule, leaving
+    /// the collection unchanged
+    pub fn try_extend(
+        &mut self,
+        rules: impl IntoIterator<Item = SigmaRule>,
+    ) -> Result<(), CollectionError> {
+        let previous = self.clone();
+        rules.into_iter().for_each(|rule| self.insert(rule));
+        self.solve().inspect_err(|_| *self = previous)
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    // retrieve a Sigma rule by ID
+    pub fn get(&self, id: &str) -> Option<&SigmaRule> {
+        self.rules.get(id).map(|rule| rule.as_ref())
+    }
+
+    /// Remove a Sigma rule from the collection
+    ///
+    /// fails if a correlation rule in the collection depends on it
+    pub fn remove(&mut self, id: &str) -> Result<Option<Arc<SigmaRule>>, CollectionError> {
+        let Some(rule) = self.rules.remove(id) else {
+            return Ok(None);
+        };
+        if let Err(e) = self.solve() {
+            self.rules.insert(rule.id.clone(), rule);
+            return Err(e);
+        }
+        self.unlink(&rule);
+        Ok(Some(rule))
+    }
+
+    /// remove the rules loaded from `source`, without resolving dependencies
+    fn remove_source(&mut self, source: &Source) {
+        let ids = self
+            .rules
+            .values()
+            .filter(|rule| &rule.source == source)
+            .map(|rule| rule.id.clone())
+            .collect::<Vec<_>>();
+        for id in ids {
+            if let Some(rule) = self.rules.remove(&id) {
+                self.unlink(&rule);
+            }
+        }
+    }
+
+    /// remove a rule's name and filter entries
+    fn unlink(&mut self, rule: &SigmaRule) {
+        if let Some(ref name) = rule.name {
+            if self.named.get(name) == Some(&rule.id) {
+                self.named.remove(name);
+            }
+        }
+        self.filters.remove(&rule.id);
+        self.engines.clear();
+    }
+
+    /// retrieve the rules loaded from a [`Source`]
+    ///
+    /// a file may contain several rules, and a rule with a legacy
+    /// aggregation is expanded into several rules from the same file
+    ///
+    /// [`Source`]: rule/enum.Source.html
+    pub fn from_source(&self, source: &Source) -> Vec<&SigmaRule> {
+        self.rules
+            .values()
+            .filter(|rule| &rule.source == source)
+            .map(|rule| rule.as_ref())
+            .collect()
+    }
+
+    /// Estimate the memory used by the rules, their indexes and the
+    /// state of correlation rules, see [`MemoryFootprint`]
+    ///
+    /// [`MemoryFootprint`]: struct.MemoryFootprint.html
+    #[cfg(feature = "correlation")]
+    pub async fn memory_footprint(&self) -> MemoryFootprint {
+        let mut footprint = self.static_footprint();
+        for rule in self.rules.values() {
+            if let RuleType::Correlation(ref corr) = rule.rule {
+                if let Some(bytes) = corr.memory_usage().await {
+                    *footprint.correlation_state.get_or_insert(0) += bytes;
+                }
+            }
+        }
+        footprint
+    }
+
+    /// Estimate the memory used by the rules and their indexes,
+    /// see [`MemoryFootprint`]
+    ///
+    /// [`MemoryFootprint`]: struct.MemoryFootprint.html
+    #[cfg(not(feature = "correlation"))]
+    pub fn memory_footprint(&self) -> MemoryFootprint {
+        self.static_footprint()
+    }
+
+    /// Track the throughput, detection latency and matches of the
+    /// collection, see [`telemetry`]
+    ///
+    /// tracking costs a few atomic operations per event, and is shared
+    /// by clones of the collection
+    ///
+    /// [`telemetry`]: #method.telemetry
+    pub fn track_telemetry(mut self, track: bool) -> Self {
+        self.telemetry = track.then(Arc::default);
+        self
+    }
+
+    /// The telemetry tracked since [`track_telemetry`] was enabled,
+    /// `None` if it is not
+    ///
+    /// ```rust
+    /// # use serde_json::json;
+    /// # use sigmars::{Event, SigmaCollection};
+    /// # static RULES: &str = r#"
+    /// # title: test rule
+    /// # id: test-rule
+    /// # logsource:
+    /// #   category: test
+    /// # detection:
+    /// #   selection:
+    /// #     foo: bar
+    /// #   condition: selection
+    /// # "#;
+    /// let rules = RULES.parse::<SigmaCollection>().unwrap().track_telemetry(true);
+    /// rules.get_detection_matches(&Event::new(json!({"foo": "bar"})));
+    /// rules.get_detection_matches(&Event::new(json!({"foo": "baz"})));
+    ///
+    /// let telemetry = rules.telemetry().unwrap();
+    /// assert_eq!(telemetry.events, 2);
+    /// assert_eq!(telemetry.top_rules(10), &[("test-rule".into(), 1)]);
+    /// println!("{:.0} events/s, p99 {:?}", telemetry.events_per_second, telemetry.latency_p99);
+    /// ```
+    ///
+    /// [`track_telemetry`]: #method.track_telemetry
+    pub fn telemetry(&self) -> Option<TelemetrySnapshot> {
+        self.telemetry.as_ref().map(|telemetry| telemetry.snapshot())
+    }
+
+    /// the memory used by the rules and their indexes
+    fn static_footprint(&self) -> MemoryFootprint {
+        let rules = footprint::entries::<RuleId, Arc<SigmaRule>>(self.rules.len())
+            + self
+                .rules
+                .values()
+                .map(|rule| std::mem::size_of::<SigmaRule>() + rule.heap_size())
+                .sum::<usize>();
+        let named = footprint::strings(&self.named);
+        MemoryFootprint {
+            rules,
+            indexes: self.filters.heap_size()
+                + self.engines.heap_size()
+                + self.dependencies_heap_size()
+                + named,
+            correlation_state: None,
+        }
+    }
+
+    /// the non-fatal issues found while parsing the rules, by rule ID
+    ///
+    /// ```rust
+    /// # use sigmars::{RuleWarning, SigmaCollection};
+    /// let rules: SigmaCollection = r#"
+    /// title: test rule
+    /// id: test-rule
+    /// custom: value
+    /// logsource:
+    ///   category: test
+    /// detection:
+    ///   selection:
+    ///     foo: b*r
+    ///   condition: selection
+    /// "#.parse().unwrap();
+    /// let warnings = rules.warnings();
+    /// assert_eq!(warnings.len(), 2);
+    /// assert_eq!(warnings[0].0.id, "test-rule".into());
+    /// assert_eq!(
+    ///     warnings[1].1.to_string(),
+    ///     "unknown field: custom"
+    /// );
+    /// ```
+    pub fn warnings(&self) -> Vec<(&SigmaRule, &RuleWarning)> {
+        let mut rules = self.rules.values().collect::<Vec<_>>();
+        rules.sort_by(|a, b| a.id.cmp(&b.id));
+        rules
+            .into_iter()
+            .flat_map(|rule| {
+                let lineage = self
+                    .lineage
+                    .iter()
+                    .filter(move |(id, _)| *id == rule.id)
+                    .map(|(_, w)| w);
+                rule.warnings.iter().chain(lineage).map(move |w| (rule.as_ref(), w))
+            })
+            .collect()
+    }
+
+    /// retrieve the rules related to a Sigma rule (via its `related` field)
+    /// that are present in the collection
+    pub fn related(&self, id: &str) -> Vec<(&Related, &SigmaRule)> {
+        self.rules
+            .get(id)
+            .and_then(|rule| rule.related.as_ref())
+            .map(|related| {
+                related
+                    .iter()
+                    .filter_map(|r| self.get(&r.id).map(|rule| (r, rule)))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// list `(rule, obsoleted)` ID pairs where both a rule and a rule it
+    /// obsoletes are loaded in the collection
+    ///
+    /// the obsoleted rules are also reported by [`warnings`] with a
+    /// [`RuleWarning::Obsoleted`]
+    ///
+    /// [`warnings`]: #method.warnings
+    /// [`RuleWarning::Obsoleted`]: enum.RuleWarning.html#variant.Obsoleted
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use sigmars::SigmaCollection;
+    /// static RULES: &str = r#"
+    /// title: new rule
+    /// id: new-rule
+    /// related:
+    ///   - id: old-rule
+    ///     type: obsoletes
+    /// logsource:
+    ///   category: test
+    /// detection:
+    ///   selection:
+    ///     foo: bar
+    ///   condition: selection
+    /// ---
+    /// title: old rule
+    /// id: old-rule
+    /// logsource:
+    ///   category: test
+    /// detection:
+    ///   selection:
+    ///     foo: bar
+    ///   condition: selection
+    /// "#;
+    ///
+    /// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    /// let rules: SigmaCollection = RULES.parse()?;
+    /// assert_eq!(rules.obsoleted(), vec![("new-rule".into(), "old-rule".into())]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn obsoleted(&self) -> Vec<(RuleId, RuleId)> {
+        self.rules
+            .keys()
+            .flat_map(|id| {
+                self.related(id)
+                    .into_iter()
+                    .filter(|(r, _)| r.relation_type == RelationType::Obsoletes)
+                    .map(|(_, rule)| (id.clone(), rule.id.clone()))
+            })
+            .collect()
+    }
+
+    fn drop_raw(rule: &mut SigmaRule) {
+        if let RuleType::Detection(ref mut detection) = rule.rule {
+            detection.drop_raw();
+        }
+    }
+
+    /// apply collection options to a rule before it is shared
+    pub(crate) fn prepare(&self, mut rule: SigmaRule) -> Arc<SigmaRule> {
+        for pipeline in &self.pipelines {
+            pipeline.apply(&mut rule);
+        }
+        if self.drop_raw_detections {
+            Self::drop_raw(&mut rule);
+        }
+        Arc::new(rule)
+    }
+
+    /// Add a shared Sigma rule to the collection
+    pub(crate) fn add_shared(&mut self, rule: Arc<SigmaRule>) -> Result<(), CollectionError> {
+        let id = rule.id.clone();
+        let previous = self.rules.get(&id).cloned();
+        self.insert_shared(rule);
+        self.solve().inspect_err(|_| {
+            self.rules.remove(&id);
+            self.filters.remove(&id);
+            if let Some(previous) = previous {
+                self.insert_shared(previous);
+            }
+        })
+    }
+
+    /// whether a rule read from a file or string is loaded, see
+    /// [`load_filter`](#method.load_filter) and [`overrides`](#method.overrides)
+    fn loads(&self, rule: &SigmaRule) -> bool {
+        self.load_filter.allows(rule) && self.overrides.enables(rule)
+    }
+
+    fn insert(&mut self, mut rule: SigmaRule) {
+        self.overrides.apply(&mut rule);
+        #[cfg(feature = "correlation")]
+        for rule in correlation::legacy::expand(rule) {
+            let rule = self.prepare(rule);
+            self.insert_shared(rule);
+        }
+        #[cfg(not(feature = "correlation"))]
+        {
+            let rule = self.prepare(rule);
+            self.insert_shared(rule);
+        }
+    }
+
+    fn insert_shared(&mut self, rule: Arc<SigmaRule>) {
+        if let Some(name) = rule.name.clone() {
+            self.named.insert(name, rule.id.clone());
+        }
+        if let RuleType::Detection(ref detection) = rule.rule {
+            self.options.logsource_fields |= detection.reads_logsource();
+        }
+        self.filters.add(&rule);
+        self.engines.clear();
+        self.rules.insert(rule.id.clone(), rule);
+    }
+
+    /// resolve the dependencies of correlation rules and sort them
+    #[cfg(feature = "correlation")]
+    fn solve(&mut self) -> Result<(), CollectionError> {
+        let mut graph = DependencyGraph::default();
+        self.rules.iter().map(|(id, rule)| -> Result<_, CollectionError> {
+            if let RuleType::Correlation(ref corr) = rule.rule {
+                let _ = corr
+                    .rules()
+                    .iter()
+                    .filter_map(|dep| {
+                        let dep = self.named.get(dep).map_or(dep.as_str(), |id| id);
+                        match self.rules.get_key_value(dep) {
+                            Some((id, _)) => Some(Ok(id)),
+                            None if self.allow_missing_dependencies => None,
+                            None => Some(Err(CollectionError::DependencyMissing(
+                                id.to_string(),
+                                dep.to_string(),
+                            ))),
+                        }
+                    })
+                    .collect::<Result<Vec<_>, _>>()?
+                    .into_iter()
+                    .map(|dep| graph.add_edge(dep, id))
+                    .collect::<Result<Vec<_>, _>>()?;
+            };
+            Ok(())
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+        graph.sort()?;
+        graph.reach();
+        self.deps = graph;
+        self.resolve_lineage();
+        Ok(())
+    }
+
+    /// without correlation rules there are no dependencies to resolve
+    #[cfg(not(feature = "correlation"))]
+    fn solve(&mut self) -> Result<(), CollectionError> {
+        self.resolve_lineage();
+        Ok(())
+    }
+
+    /// warn about the rules loaded with the rules that obsolete them
+    fn resolve_lineage(&mut self) {
+        let mut obsoleted = self.obsoleted();
+        obsoleted.sort();
+        self.lineage = obsoleted
+            .into_iter()
+            .map(|(by, id)| (id, RuleWarning::Obsoleted { by: by.to_string() }))
+            .collect();
+    }
+
+    #[cfg(feature = "correlation")]
+    fn dependencies_heap_size(&self) -> usize {
+        self.deps.heap_size()
+    }
+
+    #[cfg(not(feature = "correlation"))]
+    fn dependencies_heap_size(&self) -> usize {
+        0
+    }
+}
+
+#[cfg(feature = "embedded_rules")]
+impl SigmaCollection {
+    /// Create a new `SigmaCollection` from the SigmaHQ core ruleset
+    /// embedded at build time
+    ///
+    /// The ruleset is read from `rules/core` (or the directory in the
+    /// `SIGMARS_CORE_RULES` environment variable) when the crate is built
+    /// with the `embedded_rules` feature, see `rules/core/README.md`
+    pub fn builtin_core() -> Result<Self, CollectionError> {
+        let mut collection = Self::default();
+        collection.load_builtin_core()?;
+        Ok(collection)
+    }
+
+    /// Load and add the embedded SigmaHQ core ruleset, see [`builtin_core`]
+    ///
+    /// the collection's [`ParseLimits`] and [`LoadFilter`] apply
+    ///
+    /// [`builtin_core`]: #method.builtin_core
+    /// [`ParseLimits`]: struct.ParseLimits.html
+    /// [`LoadFilter`]: struct.LoadFilter.html
+    pub fn load_builtin_core(&mut self) -> Result<u32, CollectionError> {
+        let newrules = self.parsing(|| {
+            crate::embedded::CORE_RULES
+                .iter()
+                .map(|(path, s)| {
+                    let mut rules = parse_rules(s)
+                        .map_err(|e| CollectionError::ParseError(format!("{}: {}", path, e)))?;
+                    rules
+                        .iter_mut()
+                        .for_each(|rule| rule.source = Source::Embedded(path));
+                    Ok(rules)
+                })
+                .collect::<Result<Vec<_>, CollectionError>>()
+        })?
+        .into_iter()
+        .flatten()
+        .filter(|rule| self.loads(rule))
+        .collect::<Vec<_>>();
+
+        let count = newrules.len() as u32;
+        newrules.into_iter().for_each(|rule| self.insert(rule));
+        self.solve()?;
+        self.engines.compile(&self.filters, &self.rules);
+
+        Ok(count)
+    }
+}
+
+#[cfg(feature = "correlation")]
+impl SigmaCollection {
+    /// Parse correlation rules of type `name` with `handler`,
+    /// see [`CorrelationHandler`]
+    ///
+    /// only applies to rules loaded after it is set
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use async_trait::async_trait;
+    /// # use serde_json::Value;
+    /// # use sigmars::correlation::state::GroupBy;
+    /// # use sigmars::{CorrelationHandler, CustomCorrelation, Event, RuleState, SigmaCollection};
+    /// /// matches every event of its rules
+    /// struct Always;
+    ///
+    /// #[async_trait]
+    /// impl CustomCorrelation for Always {
+    ///     async fn is_match(
+    ///         &self,
+    ///         _event: &Event,
+    ///         matched: &[&str],
+    ///         _group_by: GroupBy,
+    ///         _state: &dyn RuleState,
+    ///     ) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    ///         Ok(!matched.is_empty())
+    ///     }
+    /// }
+    ///
+    /// static RULES: &str = r#"
+    /// title: always
+    /// id: always
+    /// correlation:
+    ///   type: always
+    ///   rules:
+    ///     - test-rule
+    ///   group-by:
+    ///     - user
+    ///   timespan: 10m
+    /// "#;
+    ///
+    /// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    /// let mut rules = SigmaCollection::new().allow_missing_dependencies(true);
+    /// assert!(rules.load_from_str(RULES).is_err());
+    ///
+    /// let handler = |_: &Value| Ok(Box::new(Always) as Box<dyn CustomCorrelation>);
+    /// let mut rules = rules.correlation_type("always", handler);
+    /// assert_eq!(rules.load_from_str(RULES)?, 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`CorrelationHandler`]: trait.CorrelationHandler.html
+    pub fn correlation_type(
+        mut self,
+        name: &str,
+        handler: impl correlation::custom::CorrelationHandler + 'static,
+    ) -> Self {
+        self.correlation_types.insert(name, Arc::new(handler));
+        self
+    }
+
+    /// Load correlation rules of unknown types instead of failing,
+    /// they never match and are listed by [`unknown_correlation_types`]
+    ///
+    /// [`unknown_correlation_types`]: #method.unknown_correlation_types
+    pub fn allow_unknown_correlation_types(mut self, allow: bool) -> Self {
+        self.correlation_types.allow_unknown(allow);
+        self
+    }
+
+    /// the correlation rules of unknown types, with their type
+    pub fn unknown_correlation_types(&self) -> Vec<(RuleId, String)> {
+        let mut unknown = self
+            .rules
+            .values()
+            .filter_map(|rule| match rule.rule {
+                RuleType::Correlation(ref corr) => {
+                    Some((rule.id.clone(), corr.unknown_type()?.to_string()))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        unknown.sort();
+        unknown
+    }
+
+    /// Read the correlation group-by field `group_by` from `field`
+    /// in events matching the rule with ID or name `rule`
+    ///
+    /// correlations can then group events from sources that name the
+    /// same value differently, without changing the rules
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use sigmars::SigmaCollection;
+    /// let rules = SigmaCollection::new()
+    ///     .map_group_by_field("linux-failed-login", "user", "username")
+    ///     .map_group_by_field("windows-failed-login", "user", "TargetUserName");
+    /// ```
+    pub fn map_group_by_field(mut self, rule: &str, group_by: &str, field: &str) -> Self {
+        self.group_by_fields
+            .entry(rule.to_string())
+            .or_default()
+            .insert(group_by.to_string(), field.to_string());
+        self
+    }
+
+    /// Let at most `max` matches of a rule through in any `per` period,
+    /// see [`Throttle`]
+    ///
+    /// throttles protect downstream alerting from rule storms: matches
+    /// exceeding a throttle are dropped from the results of [`evaluate`]
+    /// and the other correlation-aware methods, listed in
+    /// [`Evaluation::throttled`] and not passed to observers. They still
+    /// count towards correlation rules depending on the rule. The state
+    /// of throttles is kept by the backend passed to [`init`], throttles
+    /// do not apply to [`get_detection_matches`]
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use std::time::Duration;
+    /// # use serde_json::json;
+    /// # use sigmars::{Event, MemBackend, SigmaCollection, Throttle};
+    /// # static RULES: &str = r#"
+    /// # title: test rule
+    /// # id: test-rule
+    /// # logsource:
+    /// #   category: test
+    /// # detection:
+    /// #   selection:
+    /// #     foo: bar
+    /// #   condition: selection
+    /// # "#;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    /// let mut rules = SigmaCollection::builder()
+    ///     .throttle(Throttle::new(2, Duration::from_secs(60)).group_by(["host"]))
+    ///     .build_from_str(RULES)?;
+    /// rules.init(&mut MemBackend::new().await).await;
+    ///
+    /// let event = Event::new(json!({"foo": "bar", "host": "a"}));
+    /// assert_eq!(rules.get_matches(&event).await?.len(), 1);
+    /// assert_eq!(rules.get_matches(&event).await?.len(), 1);
+    /// let evaluation = rules.evaluate(&event).await?;
+    /// assert!(evaluation.matches.is_empty());
+    /// assert_eq!(evaluation.throttled.len(), 1);
+    ///
+    /// let event = Event::new(json!({"foo": "bar", "host": "b"}));
+    /// assert_eq!(rules.get_matches(&event).await?.len(), 1);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Throttle`]: correlation/throttle/struct.Throttle.html
+    /// [`evaluate`]: #method.evaluate
+    /// [`Evaluation::throttled`]: struct.Evaluation.html#structfield.throttled
+    /// [`init`]: #method.init
+    /// [`get_detection_matches`]: #method.get_detection_matches
+    pub fn throttle(mut self, throttle: correlation::throttle::Throttle) -> Self {
+        let n = self.throttles.len();
+        self.throttles.push(Arc::new(correlation::throttle::Throttled::new(throttle, n)));
+        self
+    }
+
+    /// the group-by field mappings of the detection rules in `prior`
+    fn group_by_fields(&self, prior: &[RuleId]) -> HashMap<&str, &str> {
+        if self.group_by_fields.is_empty() {
+            return HashMap::new();
+        }
+        prior
+            .iter()
+            .filter_map(|id| self.rules.get(id))
+            .flat_map(|rule| {
+                [Some(&*rule.id), rule.name.as_deref()]
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|key| self.group_by_fields.get(key))
+            })
+            .flatten()
+            .map(|(group_by, field)| (group_by.as_str(), field.as_str()))
+            .collect()
+    }
+
+    /// report how the rules referenced by correlation rules resolve
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use sigmars::SigmaCollection;
+    /// static RULES: &str = r#"
+    /// title: failed login
+    /// id: failed-login
+    /// name: failed_login
     /// logsource:
-    ///   category: nomatch
+    ///   category: test
     /// detection:
     ///   selection:
-    ///     foo: bar
+    ///     event: failed
     ///   condition: selection
+    /// ---
+    /// title: brute force
+    /// id: brute-force
+    /// correlation:
+    ///   type: event_count
+    ///   rules:
+    ///     - failed_login
+    ///     - successful_login
+    ///   group-by:
+    ///     - user
+    ///   timespan: 10m
+    ///   condition:
+    ///     gte: 10
     /// "#;
-    /// 
-    /// # fn main() -> Result<(), Box<dyn Error>> {
-    /// let rules: SigmaCollection = RULES.parse()?;
-    /// let event = Event::new(json!({"foo": "bar"}))
-    ///            .logsource(LogSource::default().category("test"));
-    /// let res = rules.get_detection_matches(&event);
-    /// assert!(res.len() == 1);
-    /// assert_eq!(res[0], "test-rule");
+    ///
+    /// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    /// let mut rules = SigmaCollection::new().allow_missing_dependencies(true);
+    /// rules.load_from_str(RULES)?;
+    /// let report = rules.dependency_report();
+    /// assert_eq!(report.unresolved, vec![("brute-force".into(), "successful_login".to_string())]);
+    /// assert_eq!(
+    ///     report.by_name,
+    ///     vec![("brute-force".into(), "failed_login".to_string(), "failed-login".into())]
+    /// );
+    /// assert_eq!(report.orphaned, vec!["brute-force".into()]);
     /// # Ok(())
     /// # }
-    /// 
-    pub fn get_detection_matches(&self, event: &Event) -> Vec<String> {
-        self.filters
-            .filter(&event.logsource)
-            .iter()
-            .filter_map(|id| self.rules.get(id))
-            .filter(|rule| {
-                if let RuleType::Detection(ref d) = rule.rule {
-                    d.is_match(&event.data)
+    /// ```
+    pub fn dependency_report(&self) -> DependencyReport {
+        let mut report = DependencyReport::default();
+        for (id, rule) in &self.rules {
+            let RuleType::Correlation(ref corr) = rule.rule else {
+                continue;
+            };
+            let mut orphaned = false;
+            for dep in corr.rules() {
+                if let Some(resolved) = self.named.get(dep) {
+                    report
+                        .by_name
+                        .push((id.clone(), dep.clone(), resolved.clone()));
+                } else if let Some((resolved, _)) = self.rules.get_key_value(dep.as_str()) {
+                    report.by_id.push((id.clone(), resolved.clone()));
                 } else {
-                    false
+                    report.unresolved.push((id.clone(), dep.clone()));
+                    orphaned = true;
                 }
+            }
+            if orphaned {
+                report.orphaned.push(id.clone());
+            }
+        }
+        report.unresolved.sort();
+        report.by_name.sort();
+        report.by_id.sort();
+        report.orphaned.sort();
+        report
+    }
+
+    /// the rules referenced by each correlation rule, with references
+    /// by name resolved to rule IDs
+    ///
+    /// references that don't resolve are kept as written
+    pub fn dependency_graph(&self) -> BTreeMap<RuleId, Vec<RuleId>> {
+        self.rules
+            .iter()
+            .filter_map(|(id, rule)| match rule.rule {
+                RuleType::Correlation(ref corr) => Some((id, corr)),
+                _ => None,
+            })
+            .map(|(id, corr)| {
+                let mut deps = corr
+                    .rules()
+                    .iter()
+                    .map(|dep| match self.named.get(dep) {
+                        Some(resolved) => resolved.clone(),
+                        None => self
+                            .rules
+                            .get_key_value(dep.as_str())
+                            .map_or_else(|| dep.as_str().into(), |(id, _)| id.clone()),
+                    })
+                    .collect::<Vec<_>>();
+                deps.sort();
+                (id.clone(), deps)
             })
-            .map(|rule| rule.id.clone())
             .collect()
     }
 
-    /// apply all Sigma rules to an `Event`, returning a list of rule IDs
-    /// that match, without filtering by `LogSource`
-    /// 
+    /// add the group-by and `value_count` fields of each correlation
+    /// rule to the fields of the detection rules it depends on, through
+    /// other correlation rules
+    fn correlation_fields(&self, fields: &mut HashMap<&RuleId, (&LogSource, ReferencedFields)>) {
+        let graph = self.dependency_graph();
+        for (id, deps) in &graph {
+            let Some(RuleType::Correlation(ref corr)) = self.rules.get(id).map(|r| &r.rule) else {
+                continue;
+            };
+            let mut pending = deps.iter().collect::<Vec<_>>();
+            let mut seen = BTreeSet::new();
+            while let Some(dep) = pending.pop() {
+                if !seen.insert(dep) {
+                    continue;
+                }
+                if let Some(deps) = graph.get(dep) {
+                    pending.extend(deps);
+                    continue;
+                }
+                let mapped = self.group_by_fields(std::slice::from_ref(dep));
+                let Some((_, referenced)) = fields.get_mut(dep) else {
+                    continue;
+                };
+                referenced.fields.extend(
+                    corr.group_by()
+                        .iter()
+                        .map(|g| mapped.get(g.as_str()).copied().unwrap_or(g).to_string()),
+                );
+                referenced
+                    .fields
+                    .extend(corr.value_field().map(String::from));
+            }
+        }
+    }
+
+    /// the [`dependency_graph`] as a [DOT](https://graphviz.org/doc/info/lang.html)
+    /// digraph, with edges from each rule to the correlation rules
+    /// referencing it
+    ///
+    /// correlation rules are drawn as boxes, references to rules not
+    /// in the collection as dashed nodes
+    ///
     /// ```rust
     /// # use std::error::Error;
-    /// # use serde_json::json;
-    /// # use sigmars::event::{Event, LogSource};
     /// # use sigmars::SigmaCollection;
     /// static RULES: &str = r#"
-    /// title: test rule
-    /// id: test-rule
+    /// title: failed login
+    /// id: failed-login
     /// logsource:
     ///   category: test
     /// detection:
     ///   selection:
-    ///     foo: bar
+    ///     event: failed
     ///   condition: selection
     /// ---
-    /// title: test rule 2
-    /// id: test-rule-2
-    /// logsource:
-    ///   category: nomatch
-    /// detection:
-    ///   selection:
-    ///     foo: bar
-    ///   condition: selection
+    /// title: brute force
+    /// id: brute-force
+    /// correlation:
+    ///   type: event_count
+    ///   rules:
+    ///     - failed-login
+    ///   group-by:
+    ///     - user
+    ///   timespan: 10m
+    ///   condition:
+    ///     gte: 10
     /// "#;
-    /// 
-    /// # fn main() -> Result<(), Box<dyn Error>> {
+    ///
+    /// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     /// let rules: SigmaCollection = RULES.parse()?;
-    /// let event = Event::new(json!({"foo": "bar"}))
-    ///            .logsource(LogSource::default().category("test"));
-    /// let res = rules.get_detection_matches_unfiltered(&event);
-    /// assert!(res.len() == 2);
+    /// assert!(rules.dependency_dot().contains(r#""failed-login" -> "brute-force";"#));
     /// # Ok(())
     /// # }
+    /// ```
     ///
-    pub fn get_detection_matches_unfiltered(&self, event: &Event) -> Vec<String> {
-        self.rules
-            .values()
-            .filter(|rule| {
-                if let RuleType::Detection(ref d) = rule.rule {
-                    d.is_match(&event.data)
-                } else {
-                    false
-                }
-            })
-            .map(|rule| rule.id.clone())
-            .collect()
-    }
-
-
-    /// Add a Sigma rule to the collection
-    pub fn add(&mut self, rule: SigmaRule) -> Result<(), CollectionError> {
-        self.insert(rule);
-        self.solve()
-    }
-
-    pub fn len(&self) -> usize {
-        self.rules.len()
-    }
-
-    // retrieve a Sigma rule by ID
-    pub fn get(&self, id: &str) -> Option<&SigmaRule> {
-        self.rules.get(id)
-    }
+    /// [`dependency_graph`]: #method.dependency_graph
+    pub fn dependency_dot(&self) -> String {
+        let escape = |s: &str| s.replace('\\', "\\\\").replace('"', "\\\"");
+        let quote = |s: &str| format!("\"{}\"", escape(s));
+        let graph = self.dependency_graph();
 
-    fn insert(&mut self, rule: SigmaRule) {
-        if let Some(name) = rule.name.clone() {
-            self.named.insert(name, rule.id.clone());
-        }
-        self.filters.add(&rule);
-        self.rules.insert(rule.id.clone(), rule);
-    }
+        let nodes = graph
+            .iter()
+            .flat_map(|(id, deps)| std::iter::once(id).chain(deps))
+            .collect::<BTreeSet<_>>();
 
-    fn solve(&mut self) -> Result<(), CollectionError> {
-        let mut graph = DependencyGraph::default();
-        self.rules.iter().map(|(id, rule)| -> Result<_, CollectionError> {
-            if let RuleType::Correlation(ref corr) = rule.rule {
-                let _ = corr
-                    .rules()
-                    .iter()
-                    .map(|dep| {
-                        let dep = match self.named.get(dep) {
-                            Some(id) => id,
-                            None => dep,
-                        };
-                        if self.rules.contains_key(dep) {
-                            Ok(dep)
-                        } else {
-                            Err(CollectionError::DependencyMissing(id.clone(), dep.clone()))
-                        }
-                    })
-                    .collect::<Result<Vec<_>, _>>()?
-                    .into_iter()
-                    .map(|dep| graph.add_edge(dep, id))
-                    .collect::<Result<Vec<_>, _>>()?;
+        let mut dot = String::from("digraph dependencies {\n");
+        for id in nodes {
+            let attrs = match self.rules.get(id) {
+                Some(rule) => format!(
+                    "label=\"{}\\n{}\"{}",
+                    escape(&rule.title),
+                    escape(id),
+                    match rule.rule {
+                        RuleType::Correlation(_) => ", shape=box",
+                        _ => "",
+                    }
+                ),
+                None => "style=dashed".to_string(),
             };
-            Ok(())
-        })
-        .collect::<Result<Vec<_>, _>>()?;
-
-        graph.sort()?;
-        self.deps = graph;
-        Ok(())
+            dot.push_str(&format!("    {} [{}];\n", quote(id), attrs));
+        }
+        for (id, deps) in &graph {
+            for dep in deps {
+                dot.push_str(&format!("    {} -> {};\n", quote(dep), quote(id)));
+            }
+        }
+        dot.push_str("}\n");
+        dot
     }
-}
 
-#[cfg(feature = "correlation")]
-impl SigmaCollection {
     /// Initialize a `SigmaCollection` correlation rule backend
     /// ``` rust
     /// # use std::error::Error;
@@ -302,7 +2516,7 @@ impl SigmaCollection {
     /// #   condition: selection
     /// # "#;
     /// # #[tokio::main]
-    /// # async fn main() -> Result<(), Box<dyn Error>> {
+    /// # async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
     /// let mut rules: SigmaCollection = RULES.parse()?;
     /// let mut backend = MemBackend::new().await;
     /// rules.init(&mut backend).await;
@@ -310,11 +2524,19 @@ impl SigmaCollection {
     /// # }
     /// 
     pub async fn init(&mut self, backend: &mut impl correlation::Backend) {
-        for rule in self.rules.values_mut() {
-            if let RuleType::Correlation(ref mut corr) = rule.rule {
+        for rule in self.rules.values() {
+            if let RuleType::Correlation(ref corr) = rule.rule {
+                if corr.is_registered() {
+                    continue;
+                }
                 backend.register(corr).await.unwrap();
             }
         }
+        for throttle in &self.throttles {
+            if !throttle.rule().is_registered() {
+                backend.register(throttle.rule()).await.unwrap();
+            }
+        }
     }
 
 
@@ -330,10 +2552,56 @@ impl SigmaCollection {
     pub async fn get_matches(
         &self,
         event: &Event,
-    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-        let mut prior = self.get_detection_matches(event);
-        self.push_correlation_matches(event, &mut prior).await?;
-        Ok(prior)
+    ) -> Result<Matches, Box<dyn std::error::Error + Send + Sync>> {
+        self.get_matches_for(event, &event.logsource).await
+    }
+
+    /// apply Sigma rules to an [`Event`] as if it came from `logsource`,
+    /// similar to [`get_detection_matches_for`], but also evaluates
+    /// correlation rules
+    ///
+    /// [`get_detection_matches_for`]: #method.get_detection_matches_for
+    /// [`Event`]: event/struct.Event.html
+    pub async fn get_matches_for(
+        &self,
+        event: &Event,
+        logsource: &LogSource,
+    ) -> Result<Matches, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.to_matches(self.evaluate_for(event, logsource).await?.matches))
+    }
+
+    /// apply Sigma rules to an [`Event`] like [`get_matches`], also
+    /// returning the rules skipped after the [`timeout`]
+    ///
+    /// the timeout covers both detection and correlation rules
+    ///
+    /// [`Event`]: event/struct.Event.html
+    /// [`get_matches`]: #method.get_matches
+    /// [`timeout`]: #method.timeout
+    pub async fn evaluate(
+        &self,
+        event: &Event,
+    ) -> Result<Evaluation, Box<dyn std::error::Error + Send + Sync>> {
+        self.evaluate_for(event, &event.logsource).await
+    }
+
+    /// apply Sigma rules to an [`Event`] as if it came from `logsource`,
+    /// see [`evaluate`]
+    ///
+    /// [`Event`]: event/struct.Event.html
+    /// [`evaluate`]: #method.evaluate
+    pub async fn evaluate_for(
+        &self,
+        event: &Event,
+        logsource: &LogSource,
+    ) -> Result<Evaluation, Box<dyn std::error::Error + Send + Sync>> {
+        let start = Instant::now();
+        let engine = self.engines.get(&self.filters, &self.rules, logsource);
+        let mut evaluation = self.detect(event, logsource, engine.candidates(&event.data), start);
+        self.throttle_detections(event, &mut evaluation).await?;
+        self.notify_detections(event, &evaluation);
+        self.correlate(event, &mut evaluation, start).await?;
+        Ok(evaluation)
     }
 
     /// apply all Sigma rules to an event, returning a list of rule IDs
@@ -344,10 +2612,13 @@ impl SigmaCollection {
     pub async fn get_matches_unfiltered(
         &self,
         event: &Event,
-    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
-        let mut prior = self.get_detection_matches_unfiltered(event);
-        self.push_correlation_matches(event, &mut prior).await?;
-        Ok(prior)
+    ) -> Result<Matches, Box<dyn std::error::Error + Send + Sync>> {
+        let start = Instant::now();
+        let mut evaluation = self.detect(event, &event.logsource, self.rules.values(), start);
+        self.throttle_detections(event, &mut evaluation).await?;
+        self.notify_detections(event, &evaluation);
+        self.correlate(event, &mut evaluation, start).await?;
+        Ok(self.to_matches(evaluation.matches))
     }
 
     /// apply correlation rules to an event and a list of matching detection rule IDs
@@ -355,63 +2626,512 @@ impl SigmaCollection {
     pub async fn push_correlation_matches(
         &self,
         event: &Event,
-        prior: &mut Vec<String>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+        prior: &mut Vec<RuleId>,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut evaluation = Evaluation {
+            matches: std::mem::take(prior),
+            ..Default::default()
+        };
+        let result = self.correlate(event, &mut evaluation, Instant::now()).await;
+        *prior = evaluation.matches;
+        result
+    }
+
+    /// apply correlation rules to an event and the detection
+    /// rules in `evaluation`, until the deadline
+    async fn correlate(
+        &self,
+        event: &Event,
+        evaluation: &mut Evaluation,
+        start: Instant,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let deadline = self.timeout.map(|timeout| start + timeout);
+        let correlation_start = Instant::now();
+        let skipped = evaluation.not_evaluated.len();
+        let prior = &mut evaluation.matches;
+        let detections = prior.len();
+        let fields = self.group_by_fields(prior);
+        let reachable = self.deps.reachable_from(prior);
         let rules = self
             .deps
             .sorted
             .iter()
-            .filter_map(|idx| {
-                if prior.iter().filter_map(|r| self.deps.idx.get(r)).any(|n| {
-                    petgraph::algo::has_path_connecting(&self.deps.graph, *n, *idx, None)
-                        || n == idx
-                }) {
-                    Some(self.rules.get(&self.deps.graph[*idx])?)
-                } else {
-                    None
-                }
-            })
+            .filter(|idx| reachable.contains(*idx))
+            .filter_map(|idx| self.rules.get(&self.deps.graph[*idx]))
             .collect::<Vec<_>>();
 
         for rule in rules {
+            if self.is_done(prior) {
+                break;
+            }
+            if !self.is_scheduled(rule, event) {
+                continue;
+            }
             if let RuleType::Correlation(ref correlation) = rule.rule {
-                if correlation.is_match(event, prior).await? {
+                if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    evaluation.not_evaluated.push(rule.id.clone());
+                } else if let Some(values) = correlation
+                    .matches(event, &self.with_names(prior), &fields)
+                    .await?
+                {
+                    if self.suppressions.suppresses(rule, event) {
+                        continue;
+                    }
+                    if self.is_duplicate(rule, event) {
+                        evaluation.duplicates.push(rule.id.clone());
+                    } else if self.is_throttled(rule, event).await? {
+                        evaluation.throttled.push(rule.id.clone());
+                    } else {
+                        self.observers.notify(rule, event, true, &values);
+                    }
                     prior.push(rule.id.clone());
+                    if !values.is_empty() {
+                        evaluation.values.insert(rule.id.clone(), values);
+                    }
                 }
             }
         }
+        metrics::correlation_evaluated(correlation_start, &prior[detections..]);
+        if let Some(ref telemetry) = self.telemetry {
+            telemetry.rules_matched(&prior[detections..]);
+        }
+        metrics::rules_not_evaluated(&evaluation.not_evaluated[skipped..]);
+        evaluation.drop_duplicates();
+        evaluation.drop_throttled();
+        Ok(())
+    }
+
+    /// whether the match of `rule` for `event` exceeds one of the
+    /// throttles of the collection
+    async fn is_throttled(
+        &self,
+        rule: &SigmaRule,
+        event: &Event,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        for throttle in &self.throttles {
+            if throttle.throttles(rule, event).await? {
+                metrics::match_throttled(&rule.id);
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// record the detection rules in `evaluation` whose matches exceed
+    /// a throttle, they are dropped once correlation rules are evaluated
+    async fn throttle_detections(
+        &self,
+        event: &Event,
+        evaluation: &mut Evaluation,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        for id in &evaluation.matches {
+            let Some(rule) = self.rules.get(id) else {
+                continue;
+            };
+            if evaluation.duplicates.contains(id) {
+                continue;
+            }
+            if self.is_throttled(rule, event).await? {
+                evaluation.throttled.push(id.clone());
+            }
+        }
         Ok(())
     }
+
+    /// `matches` as [`Matches`], the detection rules being followed
+    /// by the correlation rules
+    fn to_matches(&self, matches: Vec<RuleId>) -> Matches {
+        let detections = matches
+            .iter()
+            .take_while(|id| {
+                self.rules
+                    .get(*id)
+                    .is_some_and(|rule| matches!(rule.rule, RuleType::Detection(_)))
+            })
+            .count();
+        Matches::new(matches, detections)
+    }
+
+    /// `prior` with the names of the matching rules, which correlation
+    /// rules can refer to instead of their IDs
+    fn with_names<'a>(&self, prior: &'a [RuleId]) -> Cow<'a, [RuleId]> {
+        if self.named.is_empty() {
+            return Cow::Borrowed(prior);
+        }
+        let names = prior
+            .iter()
+            .filter_map(|id| self.rules.get(id)?.name.as_deref())
+            .map(RuleId::from)
+            .collect::<Vec<_>>();
+        match names.is_empty() {
+            true => Cow::Borrowed(prior),
+            false => Cow::Owned([prior, &names].concat()),
+        }
+    }
+
+    /// whether the rule at `idx` is one of the matching rules in `prior`
+    /// or depends on one of them
+    fn depends_on(&self, prior: &[RuleId], idx: graph::NodeIndex) -> bool {
+        prior
+            .iter()
+            .filter_map(|r| self.deps.idx.get(r))
+            .any(|n| self.deps.reachable[n.index()].contains(&idx))
+    }
+
+    /// apply Sigma rules to a batch of [`Event`]s, returning the rule IDs
+    /// matching each event like [`get_matches`]
+    ///
+    /// each correlation rule is evaluated for the whole batch at once,
+    /// so that backends can increment its state in a single operation
+    /// (see [`RuleState::incr_many`]): matches are the same as when
+    /// evaluating the events one after the other, observers are
+    /// notified of the matches of one rule after the other
+    ///
+    /// [`Event`]: event/struct.Event.html
+    /// [`get_matches`]: #method.get_matches
+    /// [`RuleState::incr_many`]: correlation/state/trait.RuleState.html#method.incr_many
+    pub async fn get_matches_batch(
+        &self,
+        events: &[Event],
+    ) -> Result<Vec<Matches>, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self
+            .evaluate_batch(events)
+            .await?
+            .into_iter()
+            .map(|evaluation| self.to_matches(evaluation.matches))
+            .collect())
+    }
+
+    /// apply Sigma rules to a batch of [`Event`]s like [`get_matches_batch`],
+    /// returning the [`Evaluation`] of each event
+    ///
+    /// [`Event`]: event/struct.Event.html
+    /// [`get_matches_batch`]: #method.get_matches_batch
+    /// [`Evaluation`]: struct.Evaluation.html
+    pub async fn evaluate_batch(
+        &self,
+        events: &[Event],
+    ) -> Result<Vec<Evaluation>, Box<dyn std::error::Error + Send + Sync>> {
+        let mut starts = Vec::with_capacity(events.len());
+        let mut evaluations = Vec::with_capacity(events.len());
+        for event in events {
+            let start = Instant::now();
+            let engine = self.engines.get(&self.filters, &self.rules, &event.logsource);
+            let mut evaluation = self.detect(event, &event.logsource, engine.candidates(&event.data), start);
+            self.throttle_detections(event, &mut evaluation).await?;
+            self.notify_detections(event, &evaluation);
+            evaluations.push(evaluation);
+            starts.push(start);
+        }
+        let correlation_start = Instant::now();
+        let detections = evaluations
+            .iter()
+            .map(|e| (e.matches.len(), e.not_evaluated.len()))
+            .collect::<Vec<_>>();
+        let fields = evaluations
+            .iter()
+            .map(|e| self.group_by_fields(&e.matches))
+            .collect::<Vec<_>>();
+
+        for idx in &self.deps.sorted {
+            let Some(rule) = self.rules.get(&self.deps.graph[*idx]) else {
+                continue;
+            };
+            let RuleType::Correlation(ref correlation) = rule.rule else {
+                continue;
+            };
+            let mut batch = vec![];
+            for (i, evaluation) in evaluations.iter_mut().enumerate() {
+                let matches = &evaluation.matches;
+                if self.is_done(matches)
+                    || !self.depends_on(matches, *idx)
+                    || !self.is_scheduled(rule, &events[i])
+                {
+                    continue;
+                }
+                let deadline = self.timeout.map(|timeout| starts[i] + timeout);
+                if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                    evaluation.not_evaluated.push(rule.id.clone());
+                } else {
+                    batch.push(i);
+                }
+            }
+            if batch.is_empty() {
+                continue;
+            }
+
+            let prior = batch
+                .iter()
+                .map(|i| self.with_names(&evaluations[*i].matches))
+                .collect::<Vec<_>>();
+            let matches = correlation
+                .matches_many(
+                    &batch
+                        .iter()
+                        .zip(&prior)
+                        .map(|(i, prior)| (&events[*i], &prior[..], fields[*i].clone()))
+                        .collect::<Vec<_>>(),
+                )
+                .await?;
+            drop(prior);
+            for (i, values) in batch.into_iter().zip(matches) {
+                let Some(values) = values else {
+                    continue;
+                };
+                if self.suppressions.suppresses(rule, &events[i]) {
+                    continue;
+                }
+                if self.is_duplicate(rule, &events[i]) {
+                    evaluations[i].duplicates.push(rule.id.clone());
+                } else if self.is_throttled(rule, &events[i]).await? {
+                    evaluations[i].throttled.push(rule.id.clone());
+                } else {
+                    self.observers.notify(rule, &events[i], true, &values);
+                }
+                evaluations[i].matches.push(rule.id.clone());
+                if !values.is_empty() {
+                    evaluations[i].values.insert(rule.id.clone(), values);
+                }
+            }
+        }
+
+        for (evaluation, (matches, skipped)) in evaluations.iter_mut().zip(detections) {
+            metrics::correlation_evaluated(correlation_start, &evaluation.matches[matches..]);
+            if let Some(ref telemetry) = self.telemetry {
+                telemetry.rules_matched(&evaluation.matches[matches..]);
+            }
+            metrics::rules_not_evaluated(&evaluation.not_evaluated[skipped..]);
+            evaluation.drop_duplicates();
+            evaluation.drop_throttled();
+        }
+        Ok(evaluations)
+    }
+
+    /// replay recent historical events through the detection and
+    /// correlation rules without emitting matches, returning the
+    /// number of events replayed
+    ///
+    /// this rebuilds the state of correlation rules after a restart, so
+    /// that correlations whose windows started before the restart still
+    /// match. Events are replayed oldest first, and events older than the
+    /// longest timespan of the correlation rules are skipped (events
+    /// without a timestamp, see [`Event::timestamp`], are replayed
+    /// first). Observers are not notified, and deduplication, throttles,
+    /// telemetry and the timeout are ignored
+    ///
+    /// backends keep time themselves, so the windows of replayed events
+    /// start when they are replayed: replay only the events of the last
+    /// timespan to avoid extending windows
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use serde_json::json;
+    /// # use sigmars::event::{Event, LogSource};
+    /// # use sigmars::{MemBackend, SigmaCollection};
+    /// static RULES: &str = r#"
+    /// title: login
+    /// id: login
+    /// name: login
+    /// logsource:
+    ///   category: test
+    /// detection:
+    ///   selection:
+    ///     action: login
+    ///   condition: selection
+    /// ---
+    /// title: many logins
+    /// id: many-logins
+    /// correlation:
+    ///   type: event_count
+    ///   rules:
+    ///     - login
+    ///   group-by:
+    ///     - user
+    ///   timespan: 1h
+    ///   condition:
+    ///     gte: 3
+    /// "#;
+    ///
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    /// let mut rules: SigmaCollection = RULES.parse()?;
+    /// let mut backend = MemBackend::new().await;
+    /// rules.init(&mut backend).await;
+    ///
+    /// let login = Event::new(json!({"action": "login", "user": "bob"}))
+    ///     .logsource(LogSource::default().category("test"));
+    /// assert_eq!(rules.warm_up(&[login.clone(), login.clone()]).await?, 2);
+    /// let matches = rules.get_matches(&login).await?;
+    /// assert_eq!(matches, vec!["login".into(), "many-logins".into()]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Event::timestamp`]: event/struct.Event.html#method.timestamp
+    pub async fn warm_up(
+        &self,
+        events: &[Event],
+    ) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+        let timespan = self
+            .rules
+            .values()
+            .filter_map(|rule| match rule.rule {
+                RuleType::Correlation(ref c) => Some(c.timespan()),
+                _ => None,
+            })
+            .max();
+        let Some(timespan) = timespan else {
+            return Ok(0);
+        };
+        let since = SystemTime::now()
+            .checked_sub(timespan)
+            .and_then(|since| since.duration_since(std::time::UNIX_EPOCH).ok())
+            .map_or(i64::MIN, |since| since.as_millis() as i64);
+        let mut events = events
+            .iter()
+            .map(|event| (crate::event::timestamp_millis(&event.data), event))
+            .filter(|(millis, _)| millis.is_none_or(|millis| millis >= since))
+            .collect::<Vec<_>>();
+        events.sort_by_key(|(millis, _)| *millis);
+
+        // the rules and their correlation state are shared with the copy
+        let quiet = SigmaCollection {
+            observers: Observers::default(),
+            dedup: None,
+            telemetry: None,
+            throttles: Vec::new(),
+            timeout: None,
+            ..self.clone()
+        };
+        for (_, event) in &events {
+            let start = Instant::now();
+            let engine = quiet.engines.get(&quiet.filters, &quiet.rules, &event.logsource);
+            let mut evaluation = quiet.detect(event, &event.logsource, engine.candidates(&event.data), start);
+            quiet.correlate(event, &mut evaluation, start).await?;
+        }
+        Ok(events.len())
+    }
 }
 
 impl TryFrom<Vec<SigmaRule>> for SigmaCollection {
-    type Error = Box<dyn std::error::Error>;
+    type Error = Box<dyn std::error::Error + Send + Sync>;
 
     fn try_from(rules: Vec<SigmaRule>) -> Result<Self, Self::Error> {
-        let mut ruleset = Self::default();
-        rules.into_iter().for_each(|rule| ruleset.insert(rule));
-        ruleset.solve()?;
-        Ok(ruleset)
+        Ok(rules.into_iter().collect::<Result<Self, _>>()?)
+    }
+}
+
+/// Collect rules into a collection, solving dependencies once all
+/// rules are added
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use serde::Deserialize;
+/// # use sigmars::rule::{SigmaRule, Status};
+/// # use sigmars::SigmaCollection;
+/// static RULES: &str = r#"
+/// title: stable rule
+/// id: stable-rule
+/// status: stable
+/// logsource:
+///   category: test
+/// detection:
+///   selection:
+///     foo: bar
+///   condition: selection
+/// ---
+/// title: experimental rule
+/// id: experimental-rule
+/// status: experimental
+/// logsource:
+///   category: test
+/// detection:
+///   selection:
+///     foo: baz
+///   condition: selection
+/// "#;
+///
+/// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+/// let rules = serde_yml::Deserializer::from_str(RULES)
+///     .map(SigmaRule::deserialize)
+///     .collect::<Result<Vec<_>, _>>()?;
+/// let mut stable = rules
+///     .into_iter()
+///     .filter(|rule| rule.status == Some(Status::Stable))
+///     .collect::<Result<SigmaCollection, _>>()?;
+/// assert_eq!(stable.len(), 1);
+///
+/// let more: SigmaRule = serde_yml::from_str(r#"
+/// title: another stable rule
+/// id: another-stable-rule
+/// logsource:
+///   category: test
+/// detection:
+///   selection:
+///     foo: quux
+///   condition: selection
+/// "#)?;
+/// stable.try_extend([more])?;
+/// assert_eq!(stable.len(), 2);
+/// # Ok(())
+/// # }
+/// ```
+impl FromIterator<SigmaRule> for Result<SigmaCollection, CollectionError> {
+    fn from_iter<I: IntoIterator<Item = SigmaRule>>(rules: I) -> Self {
+        let mut collection = SigmaCollection::default();
+        rules.into_iter().for_each(|rule| collection.insert(rule));
+        collection.solve()?;
+        collection.engines.compile(&collection.filters, &collection.rules);
+        Ok(collection)
     }
 }
 
-impl Into<Vec<SigmaRule>> for SigmaCollection {
-    fn into(self) -> Vec<SigmaRule> {
-        self.rules.into_values().collect()
+impl From<SigmaCollection> for Vec<Arc<SigmaRule>> {
+    fn from(collection: SigmaCollection) -> Self {
+        collection.rules.into_values().collect()
     }
 }
 
+/// Parse a collection from a (multi-document) YAML string
+///
+/// malformed input fails with an error and never panics, see
+/// [`ParseLimits`] to also bound the resources used by untrusted rules
+///
+/// [`ParseLimits`]: struct.ParseLimits.html
 impl FromStr for SigmaCollection {
-    type Err = Box<dyn std::error::Error>;
+    type Err = Box<dyn std::error::Error + Send + Sync>;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        serde_yml::Deserializer::from_str(&s)
-            .map(|de| SigmaRule::deserialize(de).map_err(|e| e.into()))
-            .collect::<Result<Vec<_>, Self::Err>>()?
-            .try_into()
+        parse_rules(s)?.try_into()
     }
 }
 
+/// the category, product and service of a log source, without
+/// fields such as its `definition`
+fn logsource_key(logsource: &LogSource) -> LogSource {
+    LogSource {
+        category: logsource.category.clone(),
+        product: logsource.product.clone(),
+        service: logsource.service.clone(),
+        extra: HashMap::new(),
+    }
+}
+
+/// order log sources by category, product and service
+fn logsource_order(a: &LogSource, b: &LogSource) -> std::cmp::Ordering {
+    (&a.category, &a.product, &a.service).cmp(&(&b.category, &b.product, &b.service))
+}
+
+/// parse a (multi-document) YAML string into Sigma rules
+/// without resolving dependencies
+fn parse_rules(s: &str) -> Result<Vec<SigmaRule>, serde_yml::Error> {
+    limits::catch_panics(|| {
+        serde_yml::Deserializer::from_str(s)
+            .map(SigmaRule::deserialize)
+            .collect()
+    })
+}
+
 impl ToString for SigmaCollection {
     fn to_string(&self) -> String {
         self.rules
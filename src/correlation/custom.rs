@@ -0,0 +1,174 @@
+//! Correlation types beyond the Sigma specification
+//!
+//! A [`CorrelationHandler`] registered with
+//! [`SigmaCollection::correlation_type`] parses the `correlation`
+//! section of rules with its `type` into a [`CustomCorrelation`],
+//! which is evaluated against the rule's [`RuleState`]
+//!
+//! [`SigmaCollection::correlation_type`]: ../../struct.SigmaCollection.html#method.correlation_type
+//! [`RuleState`]: ../state/trait.RuleState.html
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+
+use super::serde::{Baseline, EventCount, ValueCount};
+use super::state::{GroupBy, RuleState};
+use crate::event::Event;
+
+/// Parses the configuration of a custom correlation type
+pub trait CorrelationHandler: Send + Sync {
+    /// parse the `correlation` section of a rule, without its
+    /// `rules`, `group-by` and `timespan`
+    fn parse(&self, config: &Value) -> Result<Box<dyn CustomCorrelation>, String>;
+}
+
+impl<F> CorrelationHandler for F
+where
+    F: Fn(&Value) -> Result<Box<dyn CustomCorrelation>, String> + Send + Sync,
+{
+    fn parse(&self, config: &Value) -> Result<Box<dyn CustomCorrelation>, String> {
+        self(config)
+    }
+}
+
+/// A correlation rule of a custom type
+#[async_trait]
+pub trait CustomCorrelation: Send + Sync {
+    /// whether the correlation matches `event`
+    ///
+    /// `matched` are the rules referenced by the correlation that
+    /// match the event and `group_by` the values of its group-by fields
+    async fn is_match(
+        &self,
+        event: &Event,
+        matched: &[&str],
+        group_by: GroupBy,
+        state: &dyn RuleState,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>>;
+}
+
+/// the handlers for custom correlation types of a collection
+#[derive(Clone, Default)]
+pub(crate) struct CorrelationTypes {
+    handlers: HashMap<String, Arc<dyn CorrelationHandler>>,
+    allow_unknown: bool,
+}
+
+impl fmt::Debug for CorrelationTypes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CorrelationTypes")
+            .field("handlers", &self.handlers.keys().collect::<Vec<_>>())
+            .field("allow_unknown", &self.allow_unknown)
+            .finish()
+    }
+}
+
+impl CorrelationTypes {
+    pub(crate) fn insert(&mut self, name: &str, handler: Arc<dyn CorrelationHandler>) {
+        self.handlers.insert(name.to_string(), handler);
+    }
+
+    pub(crate) fn allow_unknown(&mut self, allow: bool) {
+        self.allow_unknown = allow;
+    }
+}
+
+thread_local! {
+    static TYPES: RefCell<CorrelationTypes> = RefCell::default();
+}
+
+/// run `f` with the custom correlation types `types` available
+/// to rules parsed on this thread
+pub(crate) fn with<T>(types: &CorrelationTypes, f: impl FnOnce() -> T) -> T {
+    let previous = TYPES.with(|t| t.replace(types.clone()));
+    let result = f();
+    TYPES.with(|t| t.replace(previous));
+    result
+}
+
+/// why the configuration of a type defined by the Sigma specification
+/// or this crate did not parse, `None` for other types
+fn builtin_error(name: &str, config: &Value) -> Option<String> {
+    let result = match name {
+        "event_count" => EventCount::deserialize(config).map(drop),
+        "value_count" => ValueCount::deserialize(config).map(drop),
+        "baseline" => Baseline::deserialize(config).map(drop),
+        _ => return None,
+    };
+    result.err().map(|e| e.to_string())
+}
+
+/// a correlation of a custom type, or of an unknown type
+/// if unknown types are allowed
+///
+/// errors are kept in `error` rather than failing deserialization, as
+/// serde replaces them with a generic message for untagged variants
+pub struct Custom {
+    pub(crate) name: String,
+    config: Value,
+    pub(crate) correlation: Option<Box<dyn CustomCorrelation>>,
+    pub(crate) error: Option<String>,
+}
+
+impl fmt::Debug for Custom {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Custom")
+            .field("name", &self.name)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl Serialize for Custom {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.config.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Custom {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let config = Value::deserialize(deserializer)?;
+        let Some(name) = config
+            .get("type")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+        else {
+            return Err(de::Error::missing_field("type"));
+        };
+        let mut custom = Custom {
+            name,
+            config,
+            correlation: None,
+            error: None,
+        };
+        if let Some(e) = builtin_error(&custom.name, &custom.config) {
+            custom.error = Some(format!("invalid {} correlation: {}", custom.name, e));
+            return Ok(custom);
+        }
+
+        let (handler, allow_unknown) = TYPES.with(|t| {
+            let types = t.borrow();
+            (
+                types.handlers.get(&custom.name).cloned(),
+                types.allow_unknown,
+            )
+        });
+        match handler {
+            Some(handler) => match handler.parse(&custom.config) {
+                Ok(correlation) => custom.correlation = Some(correlation),
+                Err(e) => {
+                    custom.error = Some(format!("invalid {} correlation: {}", custom.name, e))
+                }
+            },
+            None if allow_unknown => {}
+            None => custom.error = Some(format!("unknown correlation type: {}", custom.name)),
+        }
+        Ok(custom)
+    }
+}
@@ -0,0 +1,259 @@
+//! translation of legacy (pre Sigma 2.0) aggregation conditions
+//! into correlation rules
+//!
+//! `condition: selection | count(field) by group > n` paired with
+//! `detection.timeframe` becomes a detection rule for `selection`
+//! and an `event_count` or `value_count` correlation rule over it
+//!
+//! `condition: selection | near a and b` becomes detection rules for
+//! `selection`, `a` and `b` and a `temporal` correlation rule over them
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use std::time::Duration;
+
+use super::serde::{
+    parse_timespan, Condition, ConditionOrList, Correlation, CorrelationRule, CorrelationType,
+    EventCount, ValueCondition, ValueCount,
+};
+use crate::detection::DetectionRule;
+use crate::event::LogSource;
+use crate::rule::{Related, RelationType, RuleId, RuleType, SigmaRule, Source};
+
+lazy_static! {
+    static ref COUNT: Regex =
+        Regex::new(r"^count\(\s*([\w.]*)\s*\)\s*(?:by\s+([\w.]+)\s*)?(==|=|<=|>=|<|>)\s*(\d+)$")
+            .unwrap();
+    static ref NEAR: Regex = Regex::new(r"^near\s+(.+)$").unwrap();
+}
+
+/// a legacy aggregation, split from a detection's condition
+#[derive(Debug)]
+pub(crate) struct Aggregation {
+    correlation_type: CorrelationType,
+    group_by: Vec<String>,
+    timespan: Duration,
+    /// detections for the searches of a `near` aggregation
+    near: Vec<(String, DetectionRule)>,
+}
+
+impl Aggregation {
+    /// parse the aggregation expression following the `|` in a condition
+    ///
+    /// `logsource` and `detection` are used to compile the searches
+    /// of a `near` aggregation
+    pub(crate) fn new(
+        expr: &str,
+        timeframe: Option<&str>,
+        logsource: &LogSource,
+        detection: &serde_yml::Value,
+    ) -> Result<Self, String> {
+        let expr = expr.trim();
+        let timespan = parse_timespan(timeframe.ok_or("aggregation requires a timeframe")?)?;
+
+        if let Some(captures) = NEAR.captures(expr) {
+            return Ok(Aggregation {
+                correlation_type: CorrelationType::Temporal,
+                group_by: vec![],
+                timespan,
+                near: Self::near(&captures[1], logsource, detection)?,
+            });
+        }
+
+        let captures = COUNT
+            .captures(expr)
+            .ok_or_else(|| format!("unsupported aggregation: {}", expr))?;
+
+        let n = captures[4].parse::<i64>().map_err(|e| e.to_string())?;
+        let condition = match &captures[3] {
+            ">" => Condition::Gt(n),
+            ">=" => Condition::Gte(n),
+            "<" => Condition::Lt(n),
+            "<=" => Condition::Lte(n),
+            _ => Condition::Eq(n),
+        };
+
+        let correlation_type = match captures.get(1).map(|m| m.as_str()) {
+            Some(field) if !field.is_empty() => CorrelationType::ValueCount(ValueCount {
+                condition: ValueCondition {
+                    condition,
+                    field: field.to_string(),
+                },
+            }),
+            _ => CorrelationType::EventCount(EventCount {
+                condition: ConditionOrList::Condition(condition),
+            }),
+        };
+
+        Ok(Aggregation {
+            correlation_type,
+            group_by: captures
+                .get(2)
+                .map(|m| vec![m.as_str().to_string()])
+                .unwrap_or_default(),
+            timespan,
+            near: vec![],
+        })
+    }
+
+    /// compile each search of `near a and b` into its own detection
+    fn near(
+        searches: &str,
+        logsource: &LogSource,
+        detection: &serde_yml::Value,
+    ) -> Result<Vec<(String, DetectionRule)>, String> {
+        searches
+            .split(" and ")
+            .map(str::trim)
+            .map(|search| {
+                if search.starts_with("not ") {
+                    return Err(format!("unsupported negated near search: {}", search));
+                }
+                if detection.get(search).is_none() {
+                    return Err(format!("unknown near search: {}", search));
+                }
+                let mut detection = detection.clone();
+                detection["condition"] = search.into();
+                let rule =
+                    DetectionRule::new(logsource.clone(), detection).map_err(|e| e.to_string())?;
+                Ok((search.to_string(), rule))
+            })
+            .collect()
+    }
+}
+
+/// split a rule with a legacy aggregation into detection rules
+/// and a correlation rule over them
+///
+/// the correlation rule keeps the rule's ID, name and metadata,
+/// the detection rule is given the ID `<id>-detection` and the
+/// detection for each `near` search the ID `<id>-<search>`
+pub(crate) fn expand(mut rule: SigmaRule) -> Vec<SigmaRule> {
+    let RuleType::Detection(ref mut detection) = rule.rule else {
+        return vec![rule];
+    };
+    let Some(aggregation) = detection.aggregation.take() else {
+        return vec![rule];
+    };
+
+    let detection_id: RuleId = format!("{}-detection", rule.id).into();
+    let near = aggregation
+        .near
+        .into_iter()
+        .map(|(search, detection)| (format!("{}-{}", rule.id, search).into(), detection))
+        .collect::<Vec<(RuleId, _)>>();
+
+    let correlation = CorrelationRule {
+        inner: Correlation {
+            correlation_type: aggregation.correlation_type,
+            rules: std::iter::once(&detection_id)
+                .chain(near.iter().map(|(id, _)| id))
+                .map(|id| id.to_string())
+                .collect(),
+            timespan: aggregation.timespan,
+            group_by: aggregation.group_by,
+            id: rule.id.to_string(),
+            state: OnceLock::new(),
+        },
+        extra: HashMap::new(),
+    };
+
+    let SigmaRule {
+        title,
+        id,
+        name,
+        related,
+        description,
+        references,
+        author,
+        date,
+        modified,
+        status,
+        license,
+        tags,
+        scope,
+        fields,
+        falsepositives,
+        level,
+        rule: detection,
+        source,
+        generated_id,
+        warnings,
+        owner,
+        extra,
+    } = rule;
+
+    let mut rules = std::iter::once((detection_id, detection))
+        .chain(
+            near.into_iter()
+                .map(|(id, detection)| (id, RuleType::Detection(detection))),
+        )
+        .map(|(detection_id, detection)| derived(&title, detection_id, &id, &source, detection))
+        .collect::<Vec<_>>();
+
+    rules.push(SigmaRule {
+        title,
+        id,
+        name,
+        related,
+        description,
+        references,
+        author,
+        date,
+        modified,
+        status,
+        license,
+        tags,
+        scope,
+        fields,
+        falsepositives,
+        level,
+        rule: RuleType::Correlation(correlation),
+        source,
+        generated_id,
+        warnings,
+        owner,
+        extra,
+    });
+    rules
+}
+
+/// a generated detection rule, related to the rule it was derived from
+fn derived(
+    title: &str,
+    id: RuleId,
+    original: &RuleId,
+    source: &Source,
+    rule: RuleType,
+) -> SigmaRule {
+    SigmaRule {
+        title: title.to_string(),
+        id,
+        name: None,
+        related: Some(vec![Related {
+            id: original.to_string(),
+            relation_type: RelationType::Derived,
+        }]),
+        description: None,
+        references: None,
+        author: None,
+        date: None,
+        modified: None,
+        status: None,
+        license: None,
+        tags: None,
+        scope: None,
+        fields: None,
+        falsepositives: None,
+        level: None,
+        rule,
+        source: source.clone(),
+        generated_id: false,
+        warnings: Vec::new(),
+        owner: None,
+        extra: HashMap::new(),
+    }
+}
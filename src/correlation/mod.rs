@@ -1,6 +1,9 @@
+pub mod custom;
+pub(crate) mod legacy;
 pub(crate) mod serde;
 
 pub(crate) mod rule;
+pub mod throttle;
 pub mod state;
 
 pub(crate) use serde::CorrelationRule;
@@ -1,65 +1,104 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+
+use serde_json::Value;
 
 use super::{
     serde::{ConditionOrList, Correlation, CorrelationRule, CorrelationType},
     state,
 };
-use crate::event::Event;
+use crate::{event::Event, rule::RuleId};
 
 impl Correlation {
-    async fn is_match(
+    /// `Some` with the distinct values counted by a `value_count` rule
+    /// (none for other types) if the rule matches
+    ///
+    /// group-by fields are read from the event fields they map to
+    /// in `fields`, if any
+    async fn matches(
         &self,
         event: &Event,
-        prior: &Vec<String>,
-    ) -> Result<bool, Box<dyn std::error::Error>> {
-        let hashed = prior.iter().map(|r| r).collect::<HashSet<_>>();
+        prior: &[RuleId],
+        fields: &HashMap<&str, &str>,
+    ) -> Result<Option<Vec<Value>>, Box<dyn std::error::Error + Send + Sync>> {
+        let hashed = prior.iter().map(|r| &**r).collect::<HashSet<&str>>();
 
-        // The sigma sepecification does not define matching behaviour for empty group_by fields
-        // So we assume that the rule does not match if the group_by field is empty
-        let Ok(group_by) = self
-            .group_by
-            .iter()
-            .map(|k| Ok((k.clone(), event.data.get(k).ok_or_else(|| ())?.clone())))
-            .collect::<Result<Vec<_>, ()>>()
-        else {
-            return Ok(false);
+        let Some(group_by) = self.group_by(event, fields) else {
+            return Ok(None);
         };
 
         let state = self.state.get().ok_or_else(|| "state not initialized")?;
 
-        Ok(match self.correlation_type {
+        let matched = match self.correlation_type {
             CorrelationType::EventCount(ref c) => {
 
-                if !self.rules.iter().all(|d| hashed.contains(d)) {
-                    return Ok(false);
+                if !self.rules.iter().all(|d| hashed.contains(d.as_str())) {
+                    return Ok(None);
                 };
-                let count = state.incr(&state::Key::EventCount(group_by)).await as i64;
-                match &c.condition {
-                    ConditionOrList::Condition(c) => c.is_match(count),
-                    ConditionOrList::List(conditions) => conditions.iter().all(|c| c.is_match(count)),
-                }
+                let key = state::Key::EventCount(group_by);
+                let count = state.incr(&key).await as i64;
+                let count = match &c.condition {
+                    ConditionOrList::Rate(rate) => state.count_within(&key, rate.per).await as i64,
+                    _ => count,
+                };
+                c.condition.is_match(count)
             },
             CorrelationType::ValueCount(ref c) => {
 
-                if !self.rules.iter().all(|d| hashed.contains(d)) {
-                    return Ok(false);
+                if !self.rules.iter().all(|d| hashed.contains(d.as_str())) {
+                    return Ok(None);
                 };
                 if let Some(field_value) = event.data.get(&c.condition.field) {
                     let count = state.incr(
                     &state::Key::ValueCount(
-                        group_by,
+                        group_by.clone(),
                         format!("{}:{}", c.condition.field, field_value),
                     )).await as i64;
-                    c.condition.condition.is_match(count)
+                    if c.condition.condition.is_match(count) {
+                        return Ok(Some(values(&**state, group_by, &c.condition.field).await));
+                    }
+                    false
                 } else { false }
             },
+            CorrelationType::Baseline(ref b) => {
+
+                if !self.rules.iter().all(|d| hashed.contains(d.as_str())) {
+                    return Ok(None);
+                };
+                let key = state::Key::EventCount(group_by);
+                state.incr(&key).await;
+
+                // cumulative counts of the last 1..n windows
+                let window = b.condition.window;
+                let windows = (self.timespan.as_secs_f64() / window.as_secs_f64()) as u32;
+                let mut counts = Vec::with_capacity(windows as usize);
+                for i in 1..=windows {
+                    counts.push(state.count_within(&key, window * i).await);
+                }
+                let previous = counts
+                    .windows(2)
+                    .map(|w| w[1].saturating_sub(w[0]))
+                    .collect::<Vec<_>>();
+                b.condition.is_match(counts[0], &previous)
+            },
+            CorrelationType::Custom(ref c) => {
+                let Some(ref correlation) = c.correlation else {
+                    return Ok(None);
+                };
+                let matched = self
+                    .rules
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|r| hashed.contains(r))
+                    .collect::<Vec<_>>();
+                correlation.is_match(event, &matched, group_by, &**state).await?
+            },
             CorrelationType::Temporal => {
                 let mut ret = true;
                 for r in self
                 .rules
                 .iter()
                 .map(|r| async {
-                    if hashed.contains(r) {
+                    if hashed.contains(r.as_str()) {
                         state.incr(&state::Key::ValueCount(group_by.clone(), r.clone())).await
                     } else { 
                         state.count(&state::Key::ValueCount(group_by.clone(), r.clone())).await
@@ -77,7 +116,7 @@ impl Correlation {
                 .rules
                 .iter()
                 .map(|r| async {
-                    if hashed.contains(r) {
+                    if hashed.contains(r.as_str()) {
                         state.incr(&state::Key::ValueCount(group_by.clone(), r.clone())).await
                     } else { 
                         state.count(&state::Key::ValueCount(group_by.clone(), r.clone())).await
@@ -85,15 +124,97 @@ impl Correlation {
                 })
                 .collect::<Vec<_>>() {
                     if r.await == 0 {
-                        return Ok(false);
+                        return Ok(None);
                     }
                 }
                 true
             }
-        })
+        };
+        Ok(matched.then(Vec::new))
+    }
+
+    /// [`matches`](#method.matches) for a batch of events in order,
+    /// with the events matched against the rules in `prior` and the
+    /// group-by fields in `fields`
+    ///
+    /// the state of an `event_count` rule that does not depend on the
+    /// rate of events is incremented for the whole batch at once
+    async fn matches_many(
+        &self,
+        batch: &[(&Event, &[RuleId], HashMap<&str, &str>)],
+    ) -> Result<Vec<Option<Vec<Value>>>, Box<dyn std::error::Error + Send + Sync>> {
+        let condition = match self.correlation_type {
+            CorrelationType::EventCount(ref c) => Some(&c.condition),
+            _ => None,
+        }
+        .filter(|c| !matches!(c, ConditionOrList::Rate(_)));
+        let Some(condition) = condition else {
+            let mut matches = Vec::with_capacity(batch.len());
+            for (event, prior, fields) in batch {
+                matches.push(self.matches(event, prior, fields).await?);
+            }
+            return Ok(matches);
+        };
+        let state = self.state.get().ok_or("state not initialized")?;
+
+        let mut keys = vec![];
+        let incremented = batch
+            .iter()
+            .map(|(event, prior, fields)| {
+                self.rules.iter().all(|d| prior.iter().any(|r| **r == *d))
+                    && self
+                        .group_by(event, fields)
+                        .map(|group_by| keys.push(state::Key::EventCount(group_by)))
+                        .is_some()
+            })
+            .collect::<Vec<_>>();
+
+        let mut counts = state.incr_many(&keys).await.into_iter();
+        Ok(incremented
+            .into_iter()
+            .map(|incremented| {
+                let count = incremented.then(|| counts.next()).flatten()? as i64;
+                condition.is_match(count).then(Vec::new)
+            })
+            .collect())
+    }
+
+    /// the values of the group-by fields of `event`, read from the
+    /// event fields they map to in `fields`
+    fn group_by(&self, event: &Event, fields: &HashMap<&str, &str>) -> Option<state::GroupBy> {
+        // The sigma sepecification does not define matching behaviour for empty group_by fields
+        // So we assume that the rule does not match if the group_by field is empty
+        let mut group_by = self
+            .group_by
+            .iter()
+            .map(|k| {
+                let field = fields.get(k.as_str()).copied().unwrap_or(k);
+                Some((k.clone(), event.data.get(field)?.clone()))
+            })
+            .collect::<Option<Vec<_>>>()?;
+
+        if let Some(ref tenant) = event.tenant {
+            group_by.push((state::TENANT.to_string(), tenant.clone().into()));
+        }
+        Some(group_by)
     }
 }
 
+/// the distinct values of `field` counted in the group of a
+/// `value_count` rule, stored as `field:<json>`
+async fn values(state: &dyn state::RuleState, group_by: state::GroupBy, field: &str) -> Vec<Value> {
+    let prefix = format!("{}:", field);
+    state
+        .values(&state::Key::ValueCount(group_by, String::new()))
+        .await
+        .into_iter()
+        .map(|value| match value.strip_prefix(&prefix) {
+            Some(json) => serde_json::from_str(json).unwrap_or(Value::String(value)),
+            None => Value::String(value),
+        })
+        .collect()
+}
+
 impl CorrelationRule {
     pub fn id(&self) -> &String {
         &self.inner.id
@@ -103,11 +224,66 @@ impl CorrelationRule {
         &self.inner.rules
     }
 
+    /// the group-by fields of the rule
+    pub fn group_by(&self) -> &[String] {
+        &self.inner.group_by
+    }
+
+    /// the time window of the rule
+    pub(crate) fn timespan(&self) -> std::time::Duration {
+        self.inner.timespan
+    }
+
+    /// the field whose distinct values a `value_count` rule counts
+    pub(crate) fn value_field(&self) -> Option<&str> {
+        match self.inner.correlation_type {
+            CorrelationType::ValueCount(ref c) => Some(&c.condition.field),
+            _ => None,
+        }
+    }
+
+    /// the type of the rule if it is a custom type without a handler
+    pub(crate) fn unknown_type(&self) -> Option<&str> {
+        match self.inner.correlation_type {
+            CorrelationType::Custom(ref c) if c.correlation.is_none() => Some(&c.name),
+            _ => None,
+        }
+    }
+
+    /// the memory used by the state of the rule, if the backend reports it
+    pub(crate) async fn memory_usage(&self) -> Option<usize> {
+        self.inner.state.get()?.memory_usage().await
+    }
+
+    /// whether the rule has been registered with a backend
+    pub(crate) fn is_registered(&self) -> bool {
+        self.inner.state.get().is_some()
+    }
+
     pub async fn is_match(
         &self,
         event: &Event,
-        prior: &Vec<String>,
-    ) -> Result<bool, Box<dyn std::error::Error>> {
-        self.inner.is_match(event, prior).await
+        prior: &[RuleId],
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(self.inner.matches(event, prior, &HashMap::new()).await?.is_some())
+    }
+
+    /// like [`is_match`](#method.is_match), returning the distinct
+    /// values counted by a `value_count` rule when it matches
+    pub(crate) async fn matches(
+        &self,
+        event: &Event,
+        prior: &[RuleId],
+        fields: &HashMap<&str, &str>,
+    ) -> Result<Option<Vec<Value>>, Box<dyn std::error::Error + Send + Sync>> {
+        self.inner.matches(event, prior, fields).await
+    }
+
+    /// like [`matches`](#method.matches) for a batch of events
+    pub(crate) async fn matches_many(
+        &self,
+        batch: &[(&Event, &[RuleId], HashMap<&str, &str>)],
+    ) -> Result<Vec<Option<Vec<Value>>>, Box<dyn std::error::Error + Send + Sync>> {
+        self.inner.matches_many(batch).await
     }
 }
@@ -1,10 +1,11 @@
+use super::custom::Custom;
 use super::state;
 use serde::{de, Deserializer, Serializer};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fmt;
 use std::sync::OnceLock;
-use tokio::time::Duration;
+use std::time::Duration;
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -28,13 +29,39 @@ impl Condition {
     }
 }
 
+/// a condition on the number of events in the last `per`,
+/// e.g. `{gte: 100, per: 1m}`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RateCondition {
+    #[serde(with = "serde_yml::with::singleton_map_recursive", flatten)]
+    pub condition: Condition,
+    #[serde(
+        serialize_with = "serialize_timespan",
+        deserialize_with = "deserialize_timespan"
+    )]
+    pub per: Duration,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum ConditionOrList {
+    Rate(RateCondition),
     Condition(Condition),
     List(Vec<Condition>),
 }
 
+impl ConditionOrList {
+    /// whether `count` satisfies the condition, the count of a rate
+    /// condition being that of its `per` window
+    pub(super) fn is_match(&self, count: i64) -> bool {
+        match self {
+            ConditionOrList::Rate(rate) => rate.condition.is_match(count),
+            ConditionOrList::Condition(c) => c.is_match(count),
+            ConditionOrList::List(conditions) => conditions.iter().all(|c| c.is_match(count)),
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct EventCount {
     #[serde(with = "serde_yml::with::singleton_map_recursive")]
@@ -53,6 +80,45 @@ pub struct ValueCount {
     pub condition: ValueCondition,
 }
 
+/// an unusual number of events in the last `window` compared to the
+/// previous windows of the timespan
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BaselineCondition {
+    #[serde(
+        serialize_with = "serialize_timespan",
+        deserialize_with = "deserialize_timespan"
+    )]
+    pub window: Duration,
+    /// standard deviations above the mean of the previous windows
+    pub deviation: f64,
+    /// events in the last window required to match
+    #[serde(default = "default_minimum")]
+    pub minimum: u64,
+}
+
+fn default_minimum() -> u64 {
+    1
+}
+
+impl BaselineCondition {
+    pub(super) fn is_match(&self, current: u64, previous: &[u64]) -> bool {
+        let n = previous.len() as f64;
+        let mean = previous.iter().sum::<u64>() as f64 / n;
+        let variance = previous
+            .iter()
+            .map(|count| (*count as f64 - mean).powi(2))
+            .sum::<f64>()
+            / n;
+        current >= self.minimum && current as f64 > mean + self.deviation * variance.sqrt()
+    }
+}
+
+/// extension: a rolling per-group baseline of event counts
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Baseline {
+    pub condition: BaselineCondition,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum CorrelationType {
@@ -60,6 +126,9 @@ pub enum CorrelationType {
     ValueCount(ValueCount),
     Temporal,
     TemporalOrdered,
+    Baseline(Baseline),
+    #[serde(untagged)]
+    Custom(Custom),
 }
 
 #[derive(Serialize)]
@@ -106,6 +175,34 @@ impl<'de> Deserialize<'de> for Correlation {
         let rule = CorrelationHelper::deserialize(deserializer)?;
         let timespan = rule.timespan;
 
+        if let CorrelationType::EventCount(EventCount {
+            condition: ConditionOrList::Rate(ref rate),
+        }) = rule.correlation_type
+        {
+            if rate.per > timespan {
+                return Err(de::Error::custom(format!(
+                    "rate period {}s exceeds the timespan {}s",
+                    rate.per.as_secs(),
+                    timespan.as_secs()
+                )));
+            }
+        }
+        if let CorrelationType::Custom(ref custom) = rule.correlation_type {
+            if let Some(ref e) = custom.error {
+                return Err(de::Error::custom(e));
+            }
+        }
+        if let CorrelationType::Baseline(ref baseline) = rule.correlation_type {
+            let window = baseline.condition.window;
+            if window.is_zero() || window * 2 > timespan {
+                return Err(de::Error::custom(format!(
+                    "baseline window {}s must fit at least twice in the timespan {}s",
+                    window.as_secs(),
+                    timespan.as_secs()
+                )));
+            }
+        }
+
         Ok(Correlation {
             correlation_type: rule.correlation_type,
             rules: rule.rules,
@@ -138,16 +235,24 @@ impl<'de> de::Visitor<'de> for TimespanVisitor {
     where
         E: de::Error,
     {
-        let n = value[..value.len() - 1]
-            .parse::<u64>()
-            .map_err(de::Error::custom)?;
-        match &value[value.len() - 1..] {
-            "s" => Ok(Duration::from_secs(n)),
-            "m" => Ok(Duration::from_secs(n * 60)),
-            "h" => Ok(Duration::from_secs(n * 3600)),
-            "d" => Ok(Duration::from_secs(n * 86400)),
-            other => Err(de::Error::custom(format!("invalid format: {:?}", other))),
-        }
+        parse_timespan(value).map_err(de::Error::custom)
+    }
+}
+
+/// parse a timespan as a number followed by a unit (s, m, h, d)
+pub(crate) fn parse_timespan(value: &str) -> Result<Duration, String> {
+    let Some(unit) = value.len().checked_sub(1) else {
+        return Err("empty timespan".to_string());
+    };
+    let n = value[..unit]
+        .parse::<u64>()
+        .map_err(|e| e.to_string())?;
+    match &value[unit..] {
+        "s" => Ok(Duration::from_secs(n)),
+        "m" => Ok(Duration::from_secs(n * 60)),
+        "h" => Ok(Duration::from_secs(n * 3600)),
+        "d" => Ok(Duration::from_secs(n * 86400)),
+        other => Err(format!("invalid format: {:?}", other)),
     }
 }
 
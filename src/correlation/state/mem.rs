@@ -1,121 +1,637 @@
 use super::Key;
+use super::wal::{Record, Wal};
+use crate::footprint;
+use crate::metrics;
 use super::{Backend, BackendError, CorrelationRule, RuleState};
 use async_trait::async_trait;
-use futures_util::StreamExt;
-use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime};
 use std::{collections::HashMap, sync::Arc};
-use tokio::sync::{
-    RwLock,
-    mpsc::{self, Receiver, Sender}
-};
+use tokio::sync::RwLock;
 
-use tokio_util::time::delay_queue::DelayQueue;
+/// how long increments may be recorded in a shard before its
+/// expired increments are dropped
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
 
-type BackendMap = Arc<RwLock<HashMap<String, HashMap<String, HashMap<Option<String>, u64>>>>>;
+/// the number of independently locked shards of the increments,
+/// groupings are assigned to a shard by their rule and group-by
+const SHARDS: usize = 16;
+
+/// the expiry times of the increments of a value, oldest first
+type Expiries = VecDeque<Instant>;
+
+/// the values of a group-by of a rule
+type Grouping = HashMap<Option<String>, Expiries>;
+
+/// the increments of a shard by rule and group-by, expired increments
+/// are ignored when read and dropped when their grouping is incremented
+/// or the shard is swept
+struct Increments {
+    rules: HashMap<String, HashMap<String, Grouping>>,
+    swept: Instant,
+}
+
+impl Increments {
+    fn new() -> Self {
+        Increments {
+            rules: HashMap::new(),
+            swept: Instant::now(),
+        }
+    }
+
+    fn get(&self, rule_id: &String, group_by: &String) -> Option<&Grouping> {
+        self.rules.get(rule_id)?.get(group_by)
+    }
+
+    /// increment the count of `entry` until `expires`, returning the
+    /// count of the entry and the size of the grouping it belongs to
+    fn add(&mut self, entry: Entry, now: Instant, expires: Instant) -> (u64, u64) {
+        let (rule_id, group_by, value) = entry;
+        let grouping = self.rules
+            .entry(rule_id)
+            .or_default()
+            .entry(group_by)
+            .or_default();
+        expire(grouping, now);
+        let expiries = grouping
+            .entry(value)
+            .or_default();
+
+        expiries.push_back(expires);
+        if expiries.len() == 1 {
+            metrics::state_keys_added(1);
+        }
+        (expiries.len() as u64, grouping.len() as u64)
+    }
+
+    /// drop the expired increments of every rule
+    fn sweep(&mut self, now: Instant) {
+        for groups in self.rules.values_mut() {
+            groups.retain(|_, grouping| {
+                expire(grouping, now);
+                !grouping.is_empty()
+            });
+        }
+        self.rules.retain(|_, groups| !groups.is_empty());
+        self.swept = now;
+    }
+}
+
+/// drop the expired increments of a grouping
+fn expire(grouping: &mut Grouping, now: Instant) {
+    grouping.retain(|_, expiries| {
+        let expired = live_from(expiries, now);
+        expiries.drain(..expired);
+        if expiries.is_empty() {
+            metrics::state_keys_removed(1);
+        }
+        !expiries.is_empty()
+    });
+}
+
+/// the index of the first increment still live at `at`
+fn live_from(expiries: &Expiries, at: Instant) -> usize {
+    expiries.partition_point(|expires| *expires <= at)
+}
+
+/// an increment of a rule's state: `(rule ID, group-by, value)`
+type Entry = (String, String, Option<String>);
 
 pub struct MemBackendImpl {
-    map: BackendMap,
-    tx: Sender<(String, Key, Duration)>,
-    task: tokio::task::JoinHandle<()>
+    shards: Vec<RwLock<Increments>>,
+    wal: Option<Wal>,
+    /// held to increment, and exclusively to snapshot or restore every
+    /// shard at once
+    gate: RwLock<()>,
+    /// the increments recorded since the backend started, or since the
+    /// epoch of the snapshot it was restored from
+    epoch: AtomicU64,
+    /// the salt keys are hashed with, if any
+    salt: Option<Vec<u8>>,
+    /// the nanoseconds the clock was advanced by, see
+    /// [`MemBackend::advance`]
+    #[cfg(feature = "mock_clock")]
+    advanced: AtomicU64,
 }
 
 impl MemBackendImpl {
-    async fn new() -> Self {
-        let map = BackendMap::default();
-        let (tx, rx) = mpsc::channel::<(String, Key, Duration)>(16);
-        let task = Self::start(rx, &map).await;
-
+    fn new() -> Self {
         MemBackendImpl {
-            map,
-            tx,
-            task
+            shards: (0..SHARDS).map(|_| RwLock::new(Increments::new())).collect(),
+            wal: None,
+            gate: RwLock::new(()),
+            epoch: AtomicU64::new(0),
+            salt: None,
+            #[cfg(feature = "mock_clock")]
+            advanced: AtomicU64::new(0),
         }
     }
 
-    pub async fn count(&self, rule_id: &String, key: &Key) -> u64 {
-        let (group_by, value) = key.into();
-
-        self.map.read().await
-            .get(rule_id)
-            .map(|m| {
-                m.get(&group_by)
-                    .map(|v| v.get(&value).unwrap_or_else(|| &0))
-                    .copied()
-                    .unwrap_or(0)
-            })
-            .unwrap_or_else(|| 0) as u64
+    /// the current time of the backend's clock
+    #[cfg(not(feature = "mock_clock"))]
+    fn now(&self) -> Instant {
+        Instant::now()
     }
 
-    pub async fn incr(&self, rule_id: &String, timeout: Duration, key: &Key) -> u64 {
-        let (group_by, value) = key.into();
-        let mut map = self.map.write().await;
-        let grouping = map
-            .entry(rule_id.to_string())
-            .or_insert(HashMap::new())
-            .entry(group_by)
-            .or_insert(HashMap::new());
-        let count = grouping
-            .entry(value)
-            .or_insert(0);
-
-        *count += 1;
-
-        self.tx.send((rule_id.clone(), key.clone(), timeout)).await.unwrap();
-
-        match key {
-            Key::EventCount(_) => *count as u64,
-            Key::ValueCount(_, _) => grouping.len() as u64,
-        }
-    }
-
-    async fn start(mut rx: Receiver<(String, Key, Duration)>, map: &BackendMap) -> tokio::task::JoinHandle<()> {
-        let map = map.clone();
-        tokio::spawn(async move {
-            let mut queue  = DelayQueue::<(String, Key)>::new();
-            loop {
-                tokio::select! {
-                    Some((rule_id, key, timeout)) = rx.recv() => {
-                        queue.insert((rule_id, key), timeout);
-                    },
-                    Some(expired) = queue.next() => {
-                        let (rule_id, key) = expired.into_inner();
-                        let mut map = map.write().await;
-
-                        map.entry(rule_id)
-                        .and_modify(|r| {
-                            let (group_by, value) = (&key).into();
-                            if let Some(e) = r.get_mut(&group_by) {
-                                match e.get_mut(&value) {
-                                    Some(c) => {
-                                        *c -= 1;
-                                        if *c <= 0 {
-                                            e.remove(&value);
-                                            if e.len() == 0 {
-                                                r.remove(&group_by);
-                                            }
-                                        }
-                                    },
-                                    None => {
-                                        r.remove(&group_by);
-                                    }
-                                }
-                            }
-                        });
+    #[cfg(feature = "mock_clock")]
+    fn now(&self) -> Instant {
+        Instant::now() + Duration::from_nanos(self.advanced.load(Ordering::Relaxed))
+    }
+
+    /// the group-by and value `key` is stored under, hashed if a
+    /// salt is set
+    fn entry(&self, key: &Key) -> (String, Option<String>) {
+        match self.salt {
+            Some(ref salt) => (&key.hashed(salt)).into(),
+            None => key.into(),
+        }
+    }
+
+    /// the index of the shard of the grouping of `rule_id` and `group_by`
+    fn shard_of(rule_id: &str, group_by: &str) -> usize {
+        let mut hasher = DefaultHasher::new();
+        (rule_id, group_by).hash(&mut hasher);
+        hasher.finish() as usize % SHARDS
+    }
+
+    fn shard(&self, rule_id: &str, group_by: &str) -> &RwLock<Increments> {
+        &self.shards[Self::shard_of(rule_id, group_by)]
+    }
+
+    /// restore the increments of a write-ahead log
+    async fn with_wal(path: &Path) -> Result<Self, BackendError> {
+        let mut backend = Self::new();
+        let (wal, records) = Wal::open(path)?;
+        let entries = records
+            .into_iter()
+            .filter_map(|record| {
+                let timeout = record.remaining()?;
+                Some(((record.rule, record.group, record.value), timeout))
+            });
+        backend.insert(entries).await;
+        backend.wal = Some(wal);
+        Ok(backend)
+    }
+
+    /// log each entry to the write-ahead log and increment its count
+    /// until `timeout` has elapsed, locking each shard once, returning
+    /// the count of each entry and the size of the grouping it belongs to
+    ///
+    /// the gate is held from the append to the increment, so a snapshot
+    /// or restore sees the entries in both the log and memory or in
+    /// neither
+    async fn add(&self, entries: Vec<Entry>, timeout: Duration) -> Vec<(u64, u64)> {
+        let _gate = self.gate.read().await;
+        if let Some(ref wal) = self.wal {
+            let records = entries
+                .iter()
+                .map(|(rule_id, group_by, value)| Record::new(rule_id, group_by, value, timeout))
+                .collect::<Vec<_>>();
+            wal.append(&records);
+        }
+        let counts = self
+            .insert(entries.into_iter().map(|entry| (entry, timeout)))
+            .await;
+        self.epoch.fetch_add(counts.len() as u64, Ordering::Relaxed);
+        counts
+    }
+
+    /// [`add`](#method.add) without holding the gate or counting the
+    /// increments in the epoch
+    async fn insert(
+        &self,
+        entries: impl IntoIterator<Item = (Entry, Duration)>,
+    ) -> Vec<(u64, u64)> {
+        let now = self.now();
+        let mut sharded = (0..SHARDS).map(|_| vec![]).collect::<Vec<_>>();
+        let mut n = 0;
+        for (entry, timeout) in entries {
+            sharded[Self::shard_of(&entry.0, &entry.1)].push((n, entry, timeout));
+            n += 1;
+        }
+
+        let mut counts = vec![(0, 0); n];
+        for (shard, entries) in self.shards.iter().zip(sharded) {
+            if entries.is_empty() {
+                continue;
+            }
+            let mut map = shard.write().await;
+            if now.duration_since(map.swept) >= SWEEP_INTERVAL {
+                map.sweep(now);
+            }
+            for (i, entry, timeout) in entries {
+                counts[i] = map.add(entry, now, now + timeout);
+            }
+        }
+        counts
+    }
+
+    /// the live increments of every rule at once
+    async fn snapshot(&self) -> StateSnapshot {
+        let _gate = self.gate.write().await;
+        let (now, taken_at) = (self.now(), SystemTime::now());
+        let mut records = vec![];
+        for shard in &self.shards {
+            for (rule_id, groupings) in &shard.read().await.rules {
+                for (group_by, grouping) in groupings {
+                    for (value, expiries) in grouping {
+                        records.extend(expiries.range(live_from(expiries, now)..).map(|expires| {
+                            let expires = taken_at + expires.duration_since(now);
+                            Record::expiring(rule_id, group_by, value, expires)
+                        }));
                     }
                 }
             }
-        })
+        }
+        StateSnapshot {
+            epoch: self.epoch.load(Ordering::Relaxed),
+            taken_at,
+            records,
+        }
+    }
+
+    /// replace the increments of every rule with those of `snapshot`
+    async fn restore(&self, snapshot: &StateSnapshot) -> Result<(), BackendError> {
+        let _gate = self.gate.write().await;
+        if let Some(ref wal) = self.wal {
+            wal.rewrite(&snapshot.records)?;
+        }
+        for shard in &self.shards {
+            let mut shard = shard.write().await;
+            let keys = shard
+                .rules
+                .values()
+                .flat_map(HashMap::values)
+                .map(HashMap::len)
+                .sum::<usize>();
+            metrics::state_keys_removed(keys);
+            *shard = Increments::new();
+        }
+        let entries = snapshot.records.iter().filter_map(|record| {
+            let timeout = record.remaining()?;
+            Some(((record.rule.clone(), record.group.clone(), record.value.clone()), timeout))
+        });
+        self.insert(entries).await;
+        self.epoch.store(snapshot.epoch, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// drop the expired increments of every rule
+    async fn sweep(&self) {
+        let now = self.now();
+        for shard in &self.shards {
+            shard.write().await.sweep(now);
+        }
+    }
+
+    pub async fn count(&self, rule_id: &String, key: &Key) -> u64 {
+        let (group_by, value) = self.entry(key);
+        let now = self.now();
+
+        self.shard(rule_id, &group_by).read().await
+            .get(rule_id, &group_by)
+            .and_then(|v| v.get(&value))
+            .map_or(0, |expiries| (expiries.len() - live_from(expiries, now)) as u64)
+    }
+
+    /// the increments of `key` made in the last `window`, for a rule
+    /// whose increments expire after `timespan`
+    pub async fn count_within(
+        &self,
+        rule_id: &String,
+        key: &Key,
+        timespan: Duration,
+        window: Duration,
+    ) -> u64 {
+        let (group_by, value) = self.entry(key);
+        let Some(after) = timespan.checked_sub(window).map(|d| self.now() + d) else {
+            return self.count(rule_id, key).await;
+        };
+
+        self.shard(rule_id, &group_by).read().await
+            .get(rule_id, &group_by)
+            .and_then(|v| v.get(&value))
+            .map_or(0, |expiries| (expiries.len() - live_from(expiries, after)) as u64)
+    }
+
+    pub async fn ttl(&self, rule_id: &String, key: &Key) -> Option<Duration> {
+        let (group_by, value) = self.entry(key);
+        let now = self.now();
+
+        self.shard(rule_id, &group_by).read().await
+            .get(rule_id, &group_by)?
+            .get(&value)?
+            .iter()
+            .find(|expires| **expires > now)
+            .map(|expires| expires.duration_since(now))
+    }
+
+    pub async fn values(&self, rule_id: &String, key: &Key) -> Vec<String> {
+        let (group_by, _) = self.entry(key);
+        let now = self.now();
+
+        self.shard(rule_id, &group_by).read().await
+            .get(rule_id, &group_by)
+            .map(|v| {
+                v.iter()
+                    .filter(|(_, expiries)| expiries.back().is_some_and(|expires| *expires > now))
+                    .filter_map(|(value, _)| value.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// the approximate memory used by the increments of a rule,
+    /// including those expired but not yet swept
+    pub async fn memory_usage(&self, rule_id: &String) -> usize {
+        let mut bytes = 0;
+        for shard in &self.shards {
+            let shard = shard.read().await;
+            let Some(groupings) = shard.rules.get(rule_id) else {
+                continue;
+            };
+            bytes += footprint::strings(groupings);
+            for grouping in groupings.values() {
+                bytes += footprint::entries::<Option<String>, Expiries>(grouping.len());
+                bytes += grouping
+                    .iter()
+                    .map(|(value, expiries)| {
+                        value.as_ref().map_or(0, String::len)
+                            + expiries.capacity() * std::mem::size_of::<Instant>()
+                    })
+                    .sum::<usize>();
+            }
+        }
+        bytes
+    }
+
+    /// increment `key` unless it is already counted `max` times, under
+    /// the lock of its shard, see [`RuleState::incr_below`]
+    ///
+    /// [`RuleState::incr_below`]: ../trait.RuleState.html#method.incr_below
+    pub async fn incr_below(
+        &self,
+        rule_id: &str,
+        timeout: Duration,
+        key: &Key,
+        max: u64,
+    ) -> Option<u64> {
+        let (group_by, value) = self.entry(key);
+        let rule_id = rule_id.to_string();
+        let _gate = self.gate.read().await;
+        let now = self.now();
+        let count = {
+            let mut shard = self.shard(&rule_id, &group_by).write().await;
+            let count = shard
+                .get(&rule_id, &group_by)
+                .and_then(|grouping| grouping.get(&value))
+                .map_or(0, |expiries| (expiries.len() - live_from(expiries, now)) as u64);
+            if count >= max {
+                return None;
+            }
+            let entry = (rule_id.clone(), group_by.clone(), value.clone());
+            shard.add(entry, now, now + timeout).0
+        };
+        if let Some(ref wal) = self.wal {
+            wal.append(&[Record::new(&rule_id, &group_by, &value, timeout)]);
+        }
+        self.epoch.fetch_add(1, Ordering::Relaxed);
+        Some(count)
+    }
+
+    pub async fn incr(&self, rule_id: &String, timeout: Duration, key: &Key) -> u64 {
+        self.incr_many(rule_id, timeout, std::slice::from_ref(key)).await[0]
+    }
+
+    /// increment `keys` in order under a single lock and
+    /// a single write to the write-ahead log
+    pub async fn incr_many(&self, rule_id: &str, timeout: Duration, keys: &[Key]) -> Vec<u64> {
+        let entries = keys
+            .iter()
+            .map(|key| {
+                let (group_by, value) = self.entry(key);
+                (rule_id.to_string(), group_by, value)
+            })
+            .collect::<Vec<Entry>>();
+        let counts = self.add(entries, timeout).await;
+
+        keys.iter()
+            .zip(counts)
+            .map(|(key, (count, grouping))| match key {
+                Key::EventCount(_) => count,
+                Key::ValueCount(_, _) => grouping,
+            })
+            .collect()
     }
 }
 
 pub type MemBackendType = Arc<MemBackendImpl>;
 
 /// An in-memory backend for correlation rules
+///
+/// increments expire without a background task or timer, so the
+/// backend runs on any async runtime: expired increments are ignored
+/// when read and released as new increments are recorded, or by
+/// [`expire`] while no events are evaluated
+///
+/// [`expire`]: #method.expire
 pub struct MemBackend(MemBackendType);
 
 impl MemBackend {
     pub async fn new() -> Self {
-        MemBackend(Arc::new(MemBackendImpl::new().await))
+        MemBackend(Arc::new(MemBackendImpl::new()))
+    }
+
+    /// An in-memory backend logging its state to an append-only
+    /// write-ahead log at `path`, restoring the state in the log
+    /// if it exists
+    ///
+    /// the log grows with every increment until [`checkpoint`] is called,
+    /// writes are not synced to disk so the latest increments may be lost
+    /// if the host fails
+    ///
+    /// [`checkpoint`]: #method.checkpoint
+    pub async fn with_wal(path: impl AsRef<Path>) -> Result<Self, BackendError> {
+        Ok(MemBackend(Arc::new(MemBackendImpl::with_wal(path.as_ref()).await?)))
+    }
+
+    /// store the group-by values of correlation keys, and the values
+    /// counted by `value_count` rules, as salted hashes, see
+    /// [`Key::hashed`], so neither the state nor its write-ahead log
+    /// and snapshots retain raw identifiers such as user names or IP
+    /// addresses
+    ///
+    /// events are grouped and counted as before, but `value_count`
+    /// matches report the hashes of the values they counted. The salt
+    /// must be kept to restore a write-ahead log or snapshot taken
+    /// with it
+    ///
+    /// fails once a correlation rule was registered with the backend,
+    /// as its state may not be hashed
+    ///
+    /// ```rust
+    /// # use sigmars::MemBackend;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    /// let backend = MemBackend::new().await.hash_keys("a secret salt")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`Key::hashed`]: ../enum.Key.html#method.hashed
+    pub fn hash_keys(mut self, salt: impl AsRef<[u8]>) -> Result<Self, BackendError> {
+        let backend = Arc::get_mut(&mut self.0).ok_or_else(|| {
+            BackendError::StateError("keys must be hashed before registering rules".to_string())
+        })?;
+        backend.salt = Some(salt.as_ref().to_vec());
+        Ok(self)
+    }
+
+    /// the time left before the oldest increment of `key` for the
+    /// correlation rule `rule_id` expires
+    pub async fn ttl(&self, rule_id: &str, key: &Key) -> Option<Duration> {
+        self.0.ttl(&rule_id.to_string(), key).await
+    }
+
+    /// when the oldest increment of `key` for the correlation rule
+    /// `rule_id` expires
+    pub async fn expires_at(&self, rule_id: &str, key: &Key) -> Option<SystemTime> {
+        Some(SystemTime::now() + self.ttl(rule_id, key).await?)
+    }
+
+    /// the live increments of `key` for the correlation rule `rule_id`
+    pub async fn count(&self, rule_id: &str, key: &Key) -> u64 {
+        self.0.count(&rule_id.to_string(), key).await
+    }
+
+    /// move the clock of the backend forward by `duration`, expiring
+    /// the increments whose timespan elapses without waiting for it
+    ///
+    /// increments are still logged and snapshotted against the system
+    /// clock, see [`testing::assert_expires`] to test the correlation
+    /// windows of rules
+    ///
+    /// [`testing::assert_expires`]: ../../../testing/fn.assert_expires.html
+    #[cfg(feature = "mock_clock")]
+    pub fn advance(&self, duration: Duration) {
+        self.0.advanced.fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// A consistent snapshot of the increments of every correlation
+    /// rule, to restore on another backend with [`restore`]
+    ///
+    /// increments are blocked while the snapshot is taken, so no
+    /// [`RuleState::incr_many`] is included in part. An active instance
+    /// can ship snapshots to a passive one, which restores the latest
+    /// (highest [`epoch`](struct.StateSnapshot.html#structfield.epoch))
+    /// when it takes over, keeping correlation windows intact
+    ///
+    /// ```rust
+    /// # use sigmars::MemBackend;
+    /// # #[tokio::main]
+    /// # async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    /// let active = MemBackend::new().await;
+    /// let snapshot = serde_json::to_string(&active.snapshot_all().await)?;
+    ///
+    /// let passive = MemBackend::new().await;
+    /// passive.restore(&serde_json::from_str(&snapshot)?).await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// [`restore`]: #method.restore
+    /// [`RuleState::incr_many`]: ../trait.RuleState.html#method.incr_many
+    pub async fn snapshot_all(&self) -> StateSnapshot {
+        self.0.snapshot().await
+    }
+
+    /// replace the state of every correlation rule with `snapshot`,
+    /// increments that expired since it was taken are dropped
+    ///
+    /// the epoch of the backend continues from the epoch of the
+    /// snapshot, and the write-ahead log, if any, is rewritten with
+    /// the increments of the snapshot
+    pub async fn restore(&self, snapshot: &StateSnapshot) -> Result<(), BackendError> {
+        self.0.restore(snapshot).await
+    }
+
+    /// the number of increments recorded since the backend started,
+    /// or since the epoch of the snapshot it was restored from
+    pub fn epoch(&self) -> u64 {
+        self.0.epoch.load(Ordering::Relaxed)
+    }
+
+    /// release the memory of the expired increments of every rule
+    pub async fn expire(&self) {
+        self.0.sweep().await
+    }
+
+    /// compact the write-ahead log, dropping expired increments
+    ///
+    /// failures to append to the log are reported here, as increments
+    /// cannot fail
+    pub async fn checkpoint(&self) -> Result<(), BackendError> {
+        match self.0.wal {
+            Some(ref wal) => Ok(wal.compact()?),
+            None => Ok(()),
+        }
+    }
+
+    /// whether the backend can still persist its state: fails if an
+    /// append to the write-ahead log failed since the last
+    /// [`checkpoint`], which reports and clears the failure
+    ///
+    /// increments are counted in memory regardless, and expire as
+    /// they are read, so there is no background task to fail
+    ///
+    /// [`checkpoint`]: #method.checkpoint
+    pub async fn health(&self) -> Result<(), BackendError> {
+        match self.0.wal.as_ref().and_then(Wal::failure) {
+            Some(e) => Err(e.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// write the write-ahead log through to disk before the process
+    /// exits, so no increment is lost, reporting failed appends like
+    /// [`health`]
+    ///
+    /// [`health`]: #method.health
+    pub async fn shutdown(&self) -> Result<(), BackendError> {
+        if let Some(ref wal) = self.0.wal {
+            wal.sync()?;
+        }
+        self.health().await
+    }
+}
+
+/// The increments of every correlation rule of a [`MemBackend`],
+/// see [`MemBackend::snapshot_all`]
+///
+/// [`MemBackend`]: struct.MemBackend.html
+/// [`MemBackend::snapshot_all`]: struct.MemBackend.html#method.snapshot_all
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    /// the [`epoch`](struct.MemBackend.html#method.epoch) of the backend
+    /// when the snapshot was taken, higher for later snapshots
+    pub epoch: u64,
+    pub taken_at: SystemTime,
+    records: Vec<Record>,
+}
+
+impl StateSnapshot {
+    /// the number of live increments in the snapshot
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
     }
 }
 
@@ -144,14 +660,38 @@ impl RuleState for MemState {
     async fn count(&self, key: &Key) -> u64 {
         self.backend.count(&self.rule_id, key).await
     }
+
+    async fn incr_many(&self, keys: &[Key]) -> Vec<u64> {
+        self.backend.incr_many(&self.rule_id, self.timespan, keys).await
+    }
+
+    async fn incr_below(&self, key: &Key, max: u64) -> Option<u64> {
+        self.backend.incr_below(&self.rule_id, self.timespan, key, max).await
+    }
+
+    async fn values(&self, key: &Key) -> Vec<String> {
+        self.backend.values(&self.rule_id, key).await
+    }
+
+    async fn count_within(&self, key: &Key, window: Duration) -> u64 {
+        self.backend.count_within(&self.rule_id, key, self.timespan, window).await
+    }
+
+    async fn ttl(&self, key: &Key) -> Option<Duration> {
+        self.backend.ttl(&self.rule_id, key).await
+    }
+
+    async fn memory_usage(&self) -> Option<usize> {
+        Some(self.backend.memory_usage(&self.rule_id).await)
+    }
 }
 
 #[async_trait]
 impl Backend for MemBackend {
     async fn register(
         &mut self,
-        rule: &mut CorrelationRule,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+        rule: &CorrelationRule,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 
         let state = MemState::new(&rule.inner.id, &rule.inner.timespan, self.0.clone()).await?;
 
@@ -164,9 +704,3 @@ impl Backend for MemBackend {
         Ok(())
     }
 }
-
-impl Drop for MemBackendImpl {
-    fn drop(&mut self) {
-        self.task.abort();
-    }
-}
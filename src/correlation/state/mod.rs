@@ -1,22 +1,67 @@
 use std::collections::BinaryHeap;
+use std::time::{Duration, SystemTime};
 
 use async_trait::async_trait;
 use serde_json::Value;
+use sha2::{Digest, Sha256};
 use thiserror::Error;
 
 use super::CorrelationRule;
 
 #[cfg(feature = "mem_backend")]
 pub mod mem;
+#[cfg(feature = "mem_backend")]
+mod wal;
 
 pub type GroupBy = Vec<(String, Value)>;
 
+/// the group-by entry added to the [`Key`] of events with a tenant,
+/// so that backends keep separate state for each tenant
+///
+/// [`Key`]: enum.Key.html
+pub const TENANT: &str = "@tenant";
+
 #[derive(Debug, Clone)]
 pub enum Key {
     EventCount(GroupBy),
     ValueCount(GroupBy, String),
 }
 
+impl Key {
+    /// the key with each group-by value, and the value counted by a
+    /// [`Key::ValueCount`], replaced by the hex SHA-256 of `salt` and
+    /// the value, so state stores keep no raw identifiers while equal
+    /// values still share a key
+    ///
+    /// [`Key::ValueCount`]: enum.Key.html#variant.ValueCount
+    pub fn hashed(&self, salt: &[u8]) -> Key {
+        let group_by = |group_by: &GroupBy| {
+            group_by
+                .iter()
+                .map(|(k, v)| (k.clone(), Value::String(hash(salt, k, &v.to_string()))))
+                .collect()
+        };
+        match self {
+            Key::EventCount(k) => Key::EventCount(group_by(k)),
+            Key::ValueCount(k, v) => Key::ValueCount(group_by(k), hash(salt, "", v)),
+        }
+    }
+}
+
+/// the hex SHA-256 of `salt`, the name of a value and the value
+fn hash(salt: &[u8], name: &str, value: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt);
+    hasher.update(name.as_bytes());
+    hasher.update([0]);
+    hasher.update(value.as_bytes());
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
 impl Into<(String, Option<String>)> for &Key {
     fn into(self) -> (String, Option<String>) {
         let key = match self {
@@ -47,9 +92,74 @@ impl Into<(String, Option<String>)> for &Key {
 /// `RuleState` is a property of the individual rule and the `RuleState` trait
 /// implementation becomes an attribute of the `CorrelationRule`
 #[async_trait]
-pub trait RuleState: Send {
+pub trait RuleState: Send + Sync {
     async fn incr(&self, _: &Key) -> u64;
     async fn count(&self, _: &Key) -> u64;
+
+    /// increment each of `keys` in order, returning what [`incr`]
+    /// returns for each of them
+    ///
+    /// backends should override it to increment the keys in a single
+    /// operation
+    ///
+    /// [`incr`]: #tymethod.incr
+    async fn incr_many(&self, keys: &[Key]) -> Vec<u64> {
+        let mut counts = Vec::with_capacity(keys.len());
+        for key in keys {
+            counts.push(self.incr(key).await);
+        }
+        counts
+    }
+
+    /// increment `key` unless it is already counted `max` times,
+    /// returning the count after the increment, `None` if it was not
+    /// incremented
+    ///
+    /// backends should override it to check the count and increment
+    /// it in a single operation, so that concurrent increments cannot
+    /// exceed `max`
+    async fn incr_below(&self, key: &Key, max: u64) -> Option<u64> {
+        if self.count(key).await >= max {
+            return None;
+        }
+        Some(self.incr(key).await)
+    }
+
+    /// the distinct values counted in the group of a [`Key::ValueCount`],
+    /// backends that do not keep them return none
+    ///
+    /// [`Key::ValueCount`]: enum.Key.html#variant.ValueCount
+    async fn values(&self, _: &Key) -> Vec<String> {
+        vec![]
+    }
+
+    /// the increments of `key` in the last `window`, backends that do
+    /// not keep their times return all increments
+    async fn count_within(&self, key: &Key, _window: Duration) -> u64 {
+        self.count(key).await
+    }
+
+    /// the time left before the oldest increment of `key` expires,
+    /// `None` if there is none or the backend does not track it
+    async fn ttl(&self, _: &Key) -> Option<Duration> {
+        None
+    }
+
+    /// the approximate memory used by the state of the rule in bytes,
+    /// `None` if the backend does not track it, see
+    /// [`SigmaCollection::memory_footprint`]
+    ///
+    /// [`SigmaCollection::memory_footprint`]: struct.SigmaCollection.html#method.memory_footprint
+    async fn memory_usage(&self) -> Option<usize> {
+        None
+    }
+
+    /// when the oldest increment of `key` expires, see [`ttl`]
+    ///
+    /// [`ttl`]: #method.ttl
+    async fn expires_at(&self, key: &Key) -> Option<SystemTime> {
+        Some(SystemTime::now() + self.ttl(key).await?)
+    }
 }
 
 /// A backend for [`RuleState`]
@@ -62,12 +172,14 @@ pub trait RuleState: Send {
 #[async_trait]
 pub trait Backend: Send {
     /// Register a correlation rule with the backend
-    async fn register(&mut self, _: &mut CorrelationRule)
-        -> Result<(), Box<dyn std::error::Error>>;
+    async fn register(&mut self, _: &CorrelationRule)
+        -> Result<(), Box<dyn std::error::Error + Send + Sync>>;
 }
 
 #[derive(Error, Debug)]
 pub enum BackendError {
     #[error("state error: {0}")]
     StateError(String),
+    #[error("write-ahead log error: {0}")]
+    WalError(#[from] std::io::Error),
 }
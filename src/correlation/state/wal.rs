@@ -0,0 +1,180 @@
+//! append-only write-ahead log of [`MemBackend`] increments
+//!
+//! each increment is logged as a JSON line with the time it expires,
+//! so expiries need not be logged: replaying the log restores the
+//! increments that have not expired yet
+//!
+//! [`MemBackend`]: ../mem/struct.MemBackend.html
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+/// a logged increment
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Record {
+    pub(crate) rule: String,
+    pub(crate) group: String,
+    pub(crate) value: Option<String>,
+    /// milliseconds since the Unix epoch
+    expires: u64,
+}
+
+impl Record {
+    pub(crate) fn new(rule: &str, group: &str, value: &Option<String>, timeout: Duration) -> Self {
+        Self::expiring(rule, group, value, SystemTime::now() + timeout)
+    }
+
+    /// an increment expiring at `expires`
+    pub(crate) fn expiring(
+        rule: &str,
+        group: &str,
+        value: &Option<String>,
+        expires: SystemTime,
+    ) -> Self {
+        Record {
+            rule: rule.to_string(),
+            group: group.to_string(),
+            value: value.clone(),
+            expires: millis(expires),
+        }
+    }
+
+    /// the time left before the increment expires, if any
+    pub(crate) fn remaining(&self) -> Option<Duration> {
+        let remaining = self.expires.checked_sub(millis(SystemTime::now()))?;
+        (remaining > 0).then(|| Duration::from_millis(remaining))
+    }
+}
+
+fn millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+#[derive(Debug)]
+pub(crate) struct Wal {
+    path: PathBuf,
+    file: Mutex<File>,
+    /// the first failed append since the last compaction,
+    /// as increments cannot fail
+    error: Mutex<Option<io::Error>>,
+}
+
+impl Wal {
+    /// open the log at `path`, returning it with the increments
+    /// that have not expired
+    pub(crate) fn open(path: &Path) -> io::Result<(Self, Vec<Record>)> {
+        let records = Self::read(path)?;
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let wal = Wal {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+            error: Mutex::new(None),
+        };
+        Ok((wal, records))
+    }
+
+    /// the live records of the log at `path`, lines left incomplete
+    /// by a crash are skipped
+    fn read(path: &Path) -> io::Result<Vec<Record>> {
+        let file = match File::open(path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        let mut records = vec![];
+        for line in BufReader::new(file).lines() {
+            if let Ok(record) = serde_json::from_str::<Record>(&line?) {
+                if record.remaining().is_some() {
+                    records.push(record);
+                }
+            }
+        }
+        Ok(records)
+    }
+
+    /// append `records` in a single write
+    pub(crate) fn append(&self, records: &[Record]) {
+        let mut lines = vec![];
+        let result = records
+            .iter()
+            .try_for_each(|record| {
+                serde_json::to_writer(&mut lines, record)?;
+                lines.push(b'\n');
+                Ok::<_, serde_json::Error>(())
+            })
+            .map_err(io::Error::from)
+            .and_then(|()| {
+                self.file
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .write_all(&lines)
+            });
+        if let Err(e) = result {
+            self.error
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .get_or_insert(e);
+        }
+    }
+
+    /// the first failed append since the last compaction, if any
+    pub(crate) fn failure(&self) -> Option<io::Error> {
+        self.error
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .map(|e| io::Error::new(e.kind(), e.to_string()))
+    }
+
+    /// write the appended records through to disk
+    pub(crate) fn sync(&self) -> io::Result<()> {
+        self.file
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .sync_all()
+    }
+
+    /// rewrite the log with only the increments that have not expired,
+    /// failing if an append failed since the last compaction
+    pub(crate) fn compact(&self) -> io::Result<()> {
+        let file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+        let records = Self::read(&self.path)?;
+        self.write(file, &records)
+    }
+
+    /// replace the log with `records`, failing if an append failed
+    /// since the last compaction
+    pub(crate) fn rewrite(&self, records: &[Record]) -> io::Result<()> {
+        let file = self.file.lock().unwrap_or_else(PoisonError::into_inner);
+        self.write(file, records)
+    }
+
+    fn write(&self, mut file: MutexGuard<File>, records: &[Record]) -> io::Result<()> {
+        let tmp = self.path.with_extension("tmp");
+        let mut out = io::BufWriter::new(File::create(&tmp)?);
+        for record in records {
+            serde_json::to_writer(&mut out, record)?;
+            out.write_all(b"\n")?;
+        }
+        out.into_inner()?.sync_all()?;
+        fs::rename(&tmp, &self.path)?;
+
+        *file = OpenOptions::new().append(true).open(&self.path)?;
+        match self
+            .error
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+        {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+}
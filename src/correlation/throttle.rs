@@ -0,0 +1,143 @@
+//! Throttling of rule matches
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use serde_json::Value;
+
+use super::serde::{
+    Condition, ConditionOrList, Correlation, CorrelationRule, CorrelationType, EventCount,
+};
+use super::state;
+use crate::event::Event;
+use crate::rule::SigmaRule;
+
+/// the group-by entry identifying the throttled rule in the [`Key`]
+/// of a throttle
+///
+/// [`Key`]: state/enum.Key.html
+const RULE: &str = "@rule";
+
+/// At most `max` matches of a rule in any `per` period, see
+/// [`SigmaCollection::throttle`]
+///
+/// A throttle applies to every rule unless restricted to some rules by
+/// ID or name, and counts the matches of each rule separately. With
+/// group-by fields, matches are also counted separately for each of
+/// their values, an event without a field being grouped with the other
+/// events without it
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use sigmars::Throttle;
+/// // at most 10 matches of each rule per host every 5 minutes
+/// let throttle = Throttle::new(10, Duration::from_secs(300)).group_by(["host"]);
+/// ```
+///
+/// [`SigmaCollection::throttle`]: ../struct.SigmaCollection.html#method.throttle
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Throttle {
+    max: u64,
+    per: Duration,
+    rules: Vec<String>,
+    group_by: Vec<String>,
+}
+
+impl Throttle {
+    pub fn new(max: u64, per: Duration) -> Self {
+        Throttle {
+            max,
+            per,
+            rules: Vec::new(),
+            group_by: Vec::new(),
+        }
+    }
+
+    /// only throttle the rules with these IDs or names
+    pub fn rules<S: Into<String>>(mut self, rules: impl IntoIterator<Item = S>) -> Self {
+        self.rules = rules.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// count matches separately for each value of these fields
+    pub fn group_by<S: Into<String>>(mut self, fields: impl IntoIterator<Item = S>) -> Self {
+        self.group_by = fields.into_iter().map(Into::into).collect();
+        self
+    }
+}
+
+/// A [`Throttle`] with its state, an `event_count` correlation rule
+/// registered with the backend of the collection
+#[derive(Debug)]
+pub(crate) struct Throttled {
+    throttle: Throttle,
+    rule: CorrelationRule,
+}
+
+impl Throttled {
+    /// the `n`th throttle of a collection
+    pub(crate) fn new(throttle: Throttle, n: usize) -> Self {
+        let rule = CorrelationRule {
+            inner: Correlation {
+                correlation_type: CorrelationType::EventCount(EventCount {
+                    condition: ConditionOrList::Condition(Condition::Lte(throttle.max as i64)),
+                }),
+                rules: throttle.rules.clone(),
+                timespan: throttle.per,
+                group_by: throttle.group_by.clone(),
+                id: format!("@throttle/{}", n),
+                state: OnceLock::new(),
+            },
+            extra: HashMap::new(),
+        };
+        Throttled { throttle, rule }
+    }
+
+    /// the correlation rule holding the state of the throttle
+    pub(crate) fn rule(&self) -> &CorrelationRule {
+        &self.rule
+    }
+
+    /// whether the match of `rule` for `event` exceeds the throttle,
+    /// counting it if it does not
+    pub(crate) async fn throttles(
+        &self,
+        rule: &SigmaRule,
+        event: &Event,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let throttle = &self.throttle;
+        if !throttle.rules.is_empty()
+            && !throttle
+                .rules
+                .iter()
+                .any(|r| *r == *rule.id || rule.name.as_deref() == Some(r.as_str()))
+        {
+            return Ok(false);
+        }
+        let state = self
+            .rule
+            .inner
+            .state
+            .get()
+            .ok_or("throttle state not initialized")?;
+
+        let mut group_by = throttle
+            .group_by
+            .iter()
+            .map(|field| {
+                let value = event.data.get(field).cloned().unwrap_or(Value::Null);
+                (field.clone(), value)
+            })
+            .collect::<state::GroupBy>();
+        group_by.push((RULE.to_string(), rule.id.to_string().into()));
+        if let Some(ref tenant) = event.tenant {
+            group_by.push((state::TENANT.to_string(), tenant.clone().into()));
+        }
+        let key = state::Key::EventCount(group_by);
+
+        // throttled matches are not counted, so that matches are let
+        // through again as soon as earlier ones expire
+        Ok(state.incr_below(&key, throttle.max).await.is_none())
+    }
+}
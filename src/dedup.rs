@@ -0,0 +1,107 @@
+//! Deduplication of rule matches
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::detection::get_terminal_from_dotted_path;
+use crate::event::{self, Event};
+use crate::rule::SigmaRule;
+
+/// At most one match of a rule per key and time bucket, see
+/// [`SigmaCollection::dedup`]
+///
+/// The key of a match is the ID of the rule, the tenant of the event and
+/// the values of the dedup fields (missing fields having the same value),
+/// and its time bucket the `window`-long period its event falls in: the
+/// event timestamp (see [`Event::timestamp`]) or the current time for
+/// events without one. Matches are deduplicated across evaluations, and
+/// the state is shared between clones of the collection
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use sigmars::Dedup;
+/// // one match of each rule per host and user every 10 minutes
+/// let dedup = Dedup::new(Duration::from_secs(600)).fields(["host", "user"]);
+/// ```
+///
+/// [`SigmaCollection::dedup`]: struct.SigmaCollection.html#method.dedup
+/// [`Event::timestamp`]: event/struct.Event.html#method.timestamp
+#[derive(Debug, Clone)]
+pub struct Dedup {
+    window: Duration,
+    fields: Vec<String>,
+    rules: Vec<String>,
+    seen: Arc<Mutex<Seen>>,
+}
+
+/// the keys of the current and previous time buckets
+#[derive(Debug, Default)]
+struct Seen {
+    keys: HashMap<u64, i64>,
+    latest: i64,
+}
+
+impl Dedup {
+    pub fn new(window: Duration) -> Self {
+        Dedup {
+            window,
+            fields: Vec::new(),
+            rules: Vec::new(),
+            seen: Arc::default(),
+        }
+    }
+
+    /// tell matches apart by the values of these fields
+    pub fn fields<S: Into<String>>(mut self, fields: impl IntoIterator<Item = S>) -> Self {
+        self.fields = fields.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// only deduplicate the matches of the rules with these IDs or names
+    pub fn rules<S: Into<String>>(mut self, rules: impl IntoIterator<Item = S>) -> Self {
+        self.rules = rules.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// whether the match of `rule` for `event` repeats a match with the
+    /// same key in its time bucket, recording it otherwise
+    pub(crate) fn is_duplicate(&self, rule: &SigmaRule, event: &Event) -> bool {
+        if !self.rules.is_empty()
+            && !self
+                .rules
+                .iter()
+                .any(|r| *r == *rule.id || rule.name.as_deref() == Some(r.as_str()))
+        {
+            return false;
+        }
+
+        let millis = event::timestamp_millis(&event.data).unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |now| now.as_millis() as i64)
+        });
+        let bucket = millis.div_euclid((self.window.as_millis() as i64).max(1));
+
+        let mut hasher = DefaultHasher::new();
+        rule.id.hash(&mut hasher);
+        event.tenant.hash(&mut hasher);
+        for field in &self.fields {
+            get_terminal_from_dotted_path(field, &event.data)
+                .map(|value| value.to_string())
+                .hash(&mut hasher);
+        }
+        bucket.hash(&mut hasher);
+        let key = hasher.finish();
+
+        let mut seen = self.seen.lock().unwrap_or_else(PoisonError::into_inner);
+        if bucket > seen.latest {
+            // keep the previous bucket for events arriving out of order
+            seen.latest = bucket;
+            seen.keys.retain(|_, b| *b >= bucket - 1);
+        }
+        seen.keys.insert(key, bucket).is_some()
+    }
+}
@@ -1,8 +1,11 @@
 //! This module provides the `Condition` struct and related implementations for parsing and evaluating conditions in Sigma rules.
 
-use std::collections::HashMap;
+use std::borrow::Borrow;
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+use std::str::FromStr;
+use std::sync::Arc;
 
-use glob;
 
 use pest::iterators::Pairs;
 use pest::pratt_parser::PrattParser;
@@ -27,12 +30,18 @@ lazy_static::lazy_static! {
     };
 }
 
+/// operators and parenthesis levels beyond which a condition is rejected,
+/// the parser and the evaluation of the tree being recursive
+const MAX_DEPTH: usize = 512;
+
 /// Represents a node in the condition abstract syntax tree (AST).
 #[derive(Debug, PartialEq, Clone)]
 enum ConditionNode {
     Identifier(String),
     Not(Box<ConditionNode>),
-    XOf(XOfType, Box<ConditionNode>),
+    /// `None` for operands other than a pattern of selection names,
+    /// which never match
+    XOf(XOfType, Option<Box<NamePattern>>),
     BoolOp {
         lhs: Box<ConditionNode>,
         op: BoolOp,
@@ -54,14 +63,65 @@ pub enum XOfType {
     AllOf(),
 }
 
+/// an upper bound of the depth of the tree of a condition: its
+/// parenthesis nesting plus its operators
+fn max_depth(input: &str) -> usize {
+    let parens = input
+        .chars()
+        .scan(0usize, |depth, c| {
+            match c {
+                '(' => *depth += 1,
+                ')' => *depth = depth.saturating_sub(1),
+                _ => (),
+            }
+            Some(*depth)
+        })
+        .max()
+        .unwrap_or(0);
+    let operators = input
+        .split(|c: char| !(c.is_ascii_alphanumeric() || "_*?".contains(c)))
+        .filter(|word| matches!(*word, "and" | "or" | "not" | "of"))
+        .count();
+    parens + operators
+}
+
 impl ConditionNode {
+    /// the approximate heap size of the node and its children
+    fn heap_size(&self) -> usize {
+        match self {
+            ConditionNode::Identifier(id) => id.len(),
+            ConditionNode::Not(node) => std::mem::size_of::<ConditionNode>() + node.heap_size(),
+            ConditionNode::XOf(_, pattern) => pattern.as_ref().map_or(0, |pattern| {
+                std::mem::size_of::<NamePattern>() + pattern.heap_size()
+            }),
+            ConditionNode::BoolOp { lhs, rhs, .. } => {
+                2 * std::mem::size_of::<ConditionNode>() + lhs.heap_size() + rhs.heap_size()
+            }
+        }
+    }
+
     /// Parses a condition string into a `ConditionNode`.
-    pub fn from_str(input: &str) -> Result<ConditionNode, Box<dyn std::error::Error>> {
-        let parsed = ConditionParser::parse(Rule::expr, input)?;
-        ConditionNode::parse(parsed)
+    pub fn from_str(
+        input: &str,
+    ) -> Result<ConditionNode, Box<dyn std::error::Error + Send + Sync>> {
+        let depth = max_depth(input);
+        if depth > MAX_DEPTH {
+            return Err(format!(
+                "condition too complex: depth {} (limit {})",
+                depth, MAX_DEPTH
+            )
+            .into());
+        }
+        let program = ConditionParser::parse(Rule::program, input)?
+            .next()
+            .and_then(|program| program.into_inner().next())
+            .ok_or("empty condition")?;
+        ConditionNode::parse(program.into_inner())
     }
 
-    fn parse(pairs: Pairs<Rule>) -> Result<ConditionNode, Box<dyn std::error::Error>> {
+    fn parse(
+        pairs: Pairs<Rule>,
+    ) -> Result<ConditionNode, Box<dyn std::error::Error + Send + Sync>> {
         PRATT_PARSER
             .map_primary(|primary| match primary.as_rule() {
                 Rule::identifier => Ok(ConditionNode::Identifier(
@@ -85,7 +145,11 @@ impl ConditionNode {
                             Some(rule) => XOfType::NOf(rule.as_str().parse()?),
                             None => XOfType::AllOf(),
                         };
-                        Ok(ConditionNode::XOf(count, Box::new(rhs)))
+                        let pattern = match rhs {
+                            ConditionNode::Identifier(id) => Some(Box::new(NamePattern::new(id))),
+                            _ => None,
+                        };
+                        Ok(ConditionNode::XOf(count, pattern))
                     }
                     _ => Err(
                         format!("Condition::parse expected prefix, found rule {:?}", rhs).into(),
@@ -113,45 +177,112 @@ impl ConditionNode {
     }
 }
 
+/// The pattern of selection names of an `x of` condition, `*` matching
+/// any characters and `?` any single character, compiled once
+#[derive(Debug, PartialEq, Clone)]
+struct NamePattern {
+    pattern: String,
+    /// the parts of the pattern between `*`, `None` standing for `?`
+    parts: Vec<Vec<Option<char>>>,
+    /// the selections of the detection matching the pattern, once
+    /// bound to them
+    names: Option<Vec<String>>,
+}
+
+impl NamePattern {
+    fn new(pattern: String) -> Self {
+        let parts = pattern
+            .split('*')
+            .map(|part| part.chars().map(|c| (c != '?').then_some(c)).collect())
+            .collect();
+        NamePattern {
+            pattern,
+            parts,
+            names: None,
+        }
+    }
+
+    /// the approximate heap size of the pattern
+    fn heap_size(&self) -> usize {
+        let part = std::mem::size_of::<Vec<Option<char>>>();
+        let c = std::mem::size_of::<Option<char>>();
+        self.pattern.len()
+            + self
+                .parts
+                .iter()
+                .map(|p| part + p.len() * c)
+                .sum::<usize>()
+            + self
+                .names
+                .iter()
+                .flatten()
+                .map(|name| std::mem::size_of::<String>() + name.len())
+                .sum::<usize>()
+    }
+
+    /// whether a selection name matches the pattern, the parts between
+    /// `*` being matched leftmost without backtracking
+    fn matches(&self, name: &str) -> bool {
+        let Some((first, rest)) = self.parts.split_first() else {
+            return name.is_empty();
+        };
+        let Some(mut name) = strip_part(first, name) else {
+            return false;
+        };
+        let Some((last, middle)) = rest.split_last() else {
+            return name.is_empty();
+        };
+        for part in middle {
+            let found = name
+                .char_indices()
+                .map(|(i, _)| i)
+                .chain([name.len()])
+                .find_map(|i| strip_part(part, &name[i..]));
+            match found {
+                Some(rest) => name = rest,
+                None => return false,
+            }
+        }
+        let Some(skip) = name.chars().count().checked_sub(last.len()) else {
+            return false;
+        };
+        let start = name.char_indices().nth(skip).map_or(name.len(), |(i, _)| i);
+        strip_part(last, &name[start..]).is_some()
+    }
+}
+
+/// `name` without a prefix matching `part`
+fn strip_part<'a>(part: &[Option<char>], name: &'a str) -> Option<&'a str> {
+    let mut chars = name.chars();
+    for p in part {
+        let c = chars.next()?;
+        if p.is_some_and(|p| p != c) {
+            return None;
+        }
+    }
+    Some(chars.as_str())
+}
+
 /// Evaluates a condition node against a statement.
-fn is_match(statement: &HashMap<&String, bool>, begin: &ConditionNode) -> bool {
+fn is_match<K>(statement: &HashMap<K, bool>, begin: &ConditionNode) -> bool
+where
+    K: Borrow<str> + Eq + Hash,
+{
     match begin {
-        ConditionNode::Identifier(id) => *(statement.get(id).unwrap_or(&false)),
+        ConditionNode::Identifier(id) => *(statement.get(id.as_str()).unwrap_or(&false)),
         ConditionNode::Not(inner) => !is_match(statement, inner),
-        ConditionNode::XOf(xoftype, inner) => match xoftype {
-            XOfType::NOf(n) => {
-                if let ConditionNode::Identifier(id) = inner.as_ref() {
-                    glob::Pattern::new(id)
-                        .and_then(|pattern| {
-                            Ok(statement
-                                .keys()
-                                .filter(|k| {
-                                    pattern.matches(*k)
-                                        && statement.get(*k).copied().unwrap_or(false)
-                                })
-                                .count() as i64
-                                >= *n)
-                        })
-                        .unwrap_or(false)
-                } else {
-                    false
-                }
-            }
-            XOfType::AllOf() => {
-                if let ConditionNode::Identifier(id) = inner.as_ref() {
-                    glob::Pattern::new(id)
-                        .and_then(|pattern| {
-                            Ok(statement
-                                .keys()
-                                .filter(|k| pattern.matches(*k))
-                                .all(|k| statement.get(k).copied().unwrap_or(false)))
-                        })
-                        .unwrap_or(false)
-                } else {
-                    false
+        ConditionNode::XOf(xoftype, pattern) => {
+            let Some(pattern) = pattern else {
+                return false;
+            };
+            match pattern.names {
+                Some(ref names) => x_of(xoftype, names.iter().map(String::as_str), statement),
+                None => {
+                    let keys = statement.keys().map(|k| k.borrow());
+                    x_of(xoftype, keys.filter(|k| pattern.matches(k)), statement)
                 }
             }
-        },
+        }
         ConditionNode::BoolOp { lhs, op, rhs } => match op {
             BoolOp::Or => is_match(statement, lhs) || is_match(statement, rhs),
             BoolOp::And => is_match(statement, lhs) && is_match(statement, rhs),
@@ -159,21 +290,219 @@ fn is_match(statement: &HashMap<&String, bool>, begin: &ConditionNode) -> bool {
     }
 }
 
+/// whether the selections covered by an `x of` condition satisfy it
+fn x_of<'a, K>(
+    xoftype: &XOfType,
+    mut covered: impl Iterator<Item = &'a str>,
+    statement: &HashMap<K, bool>,
+) -> bool
+where
+    K: Borrow<str> + Eq + Hash,
+{
+    let matched = |name: &str| statement.get(name).copied().unwrap_or(false);
+    match xoftype {
+        XOfType::NOf(n) => covered.filter(|name| matched(name)).count() as i64 >= *n,
+        XOfType::AllOf() => covered.all(matched),
+    }
+}
+
+/// Computes the fields that must be present for a condition node to match,
+/// given the required fields of each selection.
+fn required_fields(
+    selections: &HashMap<&String, HashSet<Arc<str>>>,
+    begin: &ConditionNode,
+) -> HashSet<Arc<str>> {
+    let matching = |pattern: &NamePattern| -> Vec<&HashSet<Arc<str>>> {
+        selections
+            .iter()
+            .filter(|(k, _)| pattern.matches(k))
+            .map(|(_, v)| v)
+            .collect()
+    };
+    let intersection = |sets: Vec<HashSet<Arc<str>>>| {
+        sets.into_iter()
+            .reduce(|acc, set| acc.intersection(&set).cloned().collect())
+            .unwrap_or_default()
+    };
+
+    match begin {
+        ConditionNode::Identifier(id) => selections.get(id).cloned().unwrap_or_default(),
+        // a negated condition can match without any field present
+        ConditionNode::Not(_) => HashSet::new(),
+        ConditionNode::XOf(xoftype, pattern) => match (xoftype, pattern) {
+            // at least one of the selections must match
+            (XOfType::NOf(n), Some(pattern)) if *n > 0 => {
+                intersection(matching(pattern).into_iter().cloned().collect())
+            }
+            // all of the selections must match
+            (XOfType::AllOf(), Some(pattern)) => {
+                matching(pattern).into_iter().flatten().cloned().collect()
+            }
+            _ => HashSet::new(),
+        },
+        ConditionNode::BoolOp { lhs, op, rhs } => {
+            let lhs = required_fields(selections, lhs);
+            let rhs = required_fields(selections, rhs);
+            match op {
+                BoolOp::Or => intersection(vec![lhs, rhs]),
+                BoolOp::And => lhs.union(&rhs).cloned().collect(),
+            }
+        }
+    }
+}
+
+/// bind the `x of` patterns of a condition node to the selections
+/// in `names`
+fn bind(node: &mut ConditionNode, names: &[&str]) {
+    match node {
+        ConditionNode::Identifier(_) => {}
+        ConditionNode::Not(inner) => bind(inner, names),
+        ConditionNode::XOf(_, pattern) => {
+            if let Some(pattern) = pattern {
+                let matching = names.iter().filter(|name| pattern.matches(name));
+                pattern.names = Some(matching.map(|name| name.to_string()).collect());
+            }
+        }
+        ConditionNode::BoolOp { lhs, rhs, .. } => {
+            bind(lhs, names);
+            bind(rhs, names);
+        }
+    }
+}
+
+/// a condition as a disjunction of conjunctions of selections,
+/// `(name, false)` standing for a negated selection
+pub(crate) type Dnf = Vec<Vec<(String, bool)>>;
+
+/// the disjunctive normal form of a condition node, or of its negation,
+/// over the selections in `names`, `None` beyond `limit` conjunctions or
+/// for `N of` with N other than 1
+fn dnf(node: &ConditionNode, names: &[&str], negated: bool, limit: usize) -> Option<Dnf> {
+    let or = |a: Dnf, b: Dnf| Some([a, b].concat()).filter(|dnf| dnf.len() <= limit);
+    let and = |a: Dnf, b: Dnf| {
+        if a.len() * b.len() > limit {
+            return None;
+        }
+        let mut dnf = Vec::with_capacity(a.len() * b.len());
+        for lhs in &a {
+            for rhs in &b {
+                // contradictions never match
+                if rhs.iter().any(|(name, positive)| lhs.contains(&(name.clone(), !positive))) {
+                    continue;
+                }
+                let mut conjunction = lhs.clone();
+                conjunction.extend(rhs.iter().filter(|literal| !lhs.contains(literal)).cloned());
+                dnf.push(conjunction);
+            }
+        }
+        Some(dnf)
+    };
+
+    match node {
+        ConditionNode::Identifier(id) => Some(vec![vec![(id.clone(), !negated)]]),
+        ConditionNode::Not(inner) => dnf(inner, names, !negated, limit),
+        ConditionNode::XOf(xoftype, pattern) => {
+            let pattern = pattern.as_ref()?;
+            let literals = names
+                .iter()
+                .filter(|name| pattern.matches(name))
+                .map(|name| vec![vec![(name.to_string(), !negated)]]);
+            // `1 of` is a disjunction, `all of` a conjunction
+            let any = match xoftype {
+                XOfType::NOf(1) => !negated,
+                XOfType::AllOf() => negated,
+                XOfType::NOf(_) => return None,
+            };
+            match any {
+                true => literals.into_iter().try_fold(vec![], or),
+                false => literals.into_iter().try_fold(vec![vec![]], and),
+            }
+        }
+        ConditionNode::BoolOp { lhs, op, rhs } => {
+            let lhs = dnf(lhs, names, negated, limit)?;
+            let rhs = dnf(rhs, names, negated, limit)?;
+            match (op, negated) {
+                (BoolOp::Or, false) | (BoolOp::And, true) => or(lhs, rhs),
+                (BoolOp::And, false) | (BoolOp::Or, true) => and(lhs, rhs),
+            }
+        }
+    }
+}
+
 /// Represents a condition in a Sigma rule.
-#[derive(Debug)]
+///
+/// conditions are parsed with the grammar used for rules, and evaluated
+/// against the result of each selection (search identifier), so that
+/// tools can check conditions without compiling a rule. Selections
+/// missing from the results do not match, and `1 of`/`all of` patterns
+/// only cover the selections in the results
+///
+/// ```rust
+/// # use std::collections::HashMap;
+/// # use sigmars::Condition;
+/// let condition: Condition = "selection and not 1 of filter_*".parse().unwrap();
+/// let results = HashMap::from([
+///     ("selection", true),
+///     ("filter_admin", false),
+///     ("filter_system", true),
+/// ]);
+/// assert!(!condition.is_match(&results));
+/// assert!(condition.is_match(&HashMap::from([("selection", true)])));
+///
+/// assert!("selection and".parse::<Condition>().is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq)]
 pub struct Condition {
     ast: ConditionNode,
 }
 
 impl Condition {
     /// Creates a new `Condition` from a string input.
-    pub fn new(input: &str) -> Result<Condition, Box<dyn std::error::Error>> {
+    pub fn new(input: &str) -> Result<Condition, Box<dyn std::error::Error + Send + Sync>> {
         let parsed = ConditionNode::from_str(input)?;
         Ok(Condition { ast: parsed })
     }
 
-    /// Evaluates the condition against a statement.
-    pub fn is_match(&self, statement: &HashMap<&String, bool>) -> bool {
+    /// resolve the `x of` patterns of the condition to the selections
+    /// in `names` they match, so events are evaluated without matching
+    /// patterns
+    pub(crate) fn bind(&mut self, names: &[&str]) {
+        bind(&mut self.ast, names)
+    }
+
+    /// the approximate heap size of the condition
+    pub(crate) fn heap_size(&self) -> usize {
+        self.ast.heap_size()
+    }
+
+    /// Computes the fields that must be present for the condition to match,
+    /// given the required fields of each selection.
+    pub(crate) fn required_fields(
+        &self,
+        selections: &HashMap<&String, HashSet<Arc<str>>>,
+    ) -> HashSet<Arc<str>> {
+        required_fields(selections, &self.ast)
+    }
+
+    /// the condition in disjunctive normal form over the selections in
+    /// `names`, see [`dnf`]
+    pub(crate) fn dnf(&self, names: &[&str], limit: usize) -> Option<Dnf> {
+        dnf(&self.ast, names, false, limit)
+    }
+
+    /// Evaluates the condition against the result of each selection.
+    pub fn is_match<K>(&self, statement: &HashMap<K, bool>) -> bool
+    where
+        K: Borrow<str> + Eq + Hash,
+    {
         is_match(statement, &self.ast)
     }
 }
+
+impl FromStr for Condition {
+    type Err = Box<dyn std::error::Error + Send + Sync>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Condition::new(s)
+    }
+}
@@ -1,15 +1,24 @@
-use super::condition::Condition;
+use super::condition::{Condition, Dnf};
+use super::options::MatchOptions;
+use crate::fields::ReferencedFields;
+use crate::footprint;
+use crate::limits;
 use super::selection;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
 
 #[derive(Debug)]
 pub struct Detection {
     selections: HashMap<String, selection::Selection>,
     condition: Condition,
+    /// top-level fields that must be present in an event for it to match
+    required: Vec<Arc<str>>,
 }
 
 impl Detection {
-    pub fn new(detection: &serde_yml::Value) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn new(
+        detection: &serde_yml::Value,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         let mut detection = detection.clone();
         let rules = detection
             .as_mapping_mut()
@@ -22,22 +31,174 @@ impl Detection {
             .ok_or_else(|| "invalid detection")?
             .to_string();
 
+        limits::check_condition(&condition)?;
+        limits::check_selections(rules.len())?;
+
         let selections: HashMap<String, selection::Selection> = rules
             .iter()
             .map(|(key, value)| {
-                let key = key.as_str().ok_or_else(|| "invalid detection")?.to_string();
-                let selection = selection::Selection::new(value)?;
-                Ok((key, selection))
+                let key = key.as_str().ok_or("invalid detection")?;
+                let (key, selection) = match key.split_once('|') {
+                    Some((key, modifiers)) => {
+                        (key, selection::Selection::new_keywords(modifiers, value)?)
+                    }
+                    None => (key, selection::Selection::new(value)?),
+                };
+                Ok((key.to_string(), selection))
             })
-            .collect::<Result<HashMap<String, selection::Selection>, Box<dyn std::error::Error>>>(
-            )?;
+            .collect::<Result<
+                HashMap<String, selection::Selection>,
+                Box<dyn std::error::Error + Send + Sync>,
+            >>()?;
+
+        let mut condition = Condition::new(&condition)?;
+        condition.bind(&selections.keys().map(String::as_str).collect::<Vec<_>>());
+        let required = Self::required(&condition, &selections);
 
         Ok(Detection {
             selections,
-            condition: Condition::new(&condition)?,
+            condition,
+            required,
         })
     }
 
+    fn required(
+        condition: &Condition,
+        selections: &HashMap<String, selection::Selection>,
+    ) -> Vec<Arc<str>> {
+        condition
+            .required_fields(
+                &selections
+                    .iter()
+                    .map(|(key, selection)| (key, selection.required_fields()))
+                    .collect(),
+            )
+            .into_iter()
+            .collect()
+    }
+
+    /// rename the fields read by the detection to those returned by `map`
+    pub(crate) fn map_fields(&mut self, map: &dyn Fn(&str) -> Option<String>) {
+        for selection in self.selections.values_mut() {
+            selection.map_fields(map);
+        }
+        self.required = Self::required(&self.condition, &self.selections);
+    }
+
+    /// the approximate heap size of the compiled detection
+    pub(crate) fn heap_size(&self) -> usize {
+        footprint::entries::<String, selection::Selection>(self.selections.len())
+            + self
+                .selections
+                .iter()
+                .map(|(key, selection)| key.len() + selection.heap_size())
+                .sum::<usize>()
+            + self.condition.heap_size()
+            + self.required.len() * std::mem::size_of::<Arc<str>>()
+    }
+
+    /// top-level fields that must be present in an event for it to match
+    pub(crate) fn required_fields(&self) -> &[Arc<str>] {
+        &self.required
+    }
+
+    /// the selections of the detection by name
+    pub(crate) fn selections(&self) -> &HashMap<String, selection::Selection> {
+        &self.selections
+    }
+
+    /// the condition in disjunctive normal form, `None` beyond `limit`
+    /// conjunctions
+    pub(crate) fn dnf(&self, limit: usize) -> Option<Dnf> {
+        let names = self.selections.keys().map(String::as_str).collect::<Vec<_>>();
+        self.condition.dnf(&names, limit)
+    }
+
+    /// fields of events read by the detection, including those
+    /// referenced with `fieldref`, `@logsource.*` fields excluded
+    pub(crate) fn fields(&self) -> ReferencedFields {
+        let mut fields = self.all_fields();
+        fields.fields.retain(|name| !is_logsource_field(name));
+        fields
+    }
+
+    /// whether the detection reads `@logsource.*` fields
+    pub(crate) fn reads_logsource(&self) -> bool {
+        self.all_fields().fields.iter().any(|name| is_logsource_field(name))
+    }
+
+    /// fields read by the detection, including those referenced with
+    /// `fieldref`
+    fn all_fields(&self) -> ReferencedFields {
+        let mut fields = ReferencedFields::default();
+        for item in self.selections.values().flat_map(|s| s.items()) {
+            let selection::SelectionItem::Field { path, modifiers, values } = item else {
+                fields.keywords = true;
+                continue;
+            };
+            fields.fields.insert(path.join("."));
+            if modifiers.iter().any(|m| m == "fieldref") {
+                fields
+                    .fields
+                    .extend(values.iter().filter_map(|value| Some(value.as_str()?.to_string())));
+            }
+        }
+        fields
+    }
+
+    /// top-level fields read by the detection, including those
+    /// referenced with `fieldref`, `None` if it has keywords
+    /// (matched against every field)
+    #[cfg(feature = "arrow")]
+    pub(crate) fn referenced_fields(&self) -> Option<std::collections::HashSet<Arc<str>>> {
+        let fields = self.fields();
+        if fields.keywords {
+            return None;
+        }
+        Some(
+            fields
+                .fields
+                .iter()
+                .filter_map(|field| field.split('.').next())
+                .map(Arc::from)
+                .collect(),
+        )
+    }
+
+    /// the result of each selection for a log event, and the required
+    /// fields missing from it
+    pub(crate) fn explain(
+        &self,
+        data: &serde_json::Value,
+        options: &MatchOptions,
+    ) -> (BTreeMap<String, bool>, Vec<Arc<str>>) {
+        let missing = self
+            .required
+            .iter()
+            .filter(|field| data.get(&***field).is_none())
+            .cloned()
+            .collect();
+        let results = self
+            .selections
+            .iter()
+            .map(|(key, selection)| {
+                let result = match &options.matcher {
+                    Some(matcher) => matcher.is_match(selection, data),
+                    None => selection.is_match_with(data, options),
+                };
+                (key.clone(), result)
+            })
+            .collect();
+        (results, missing)
+    }
+
+    /// whether the selection results of [`explain`](#method.explain)
+    /// satisfy the condition
+    pub(crate) fn condition_matches(&self, results: &BTreeMap<String, bool>) -> bool {
+        self.condition
+            .is_match(&results.iter().map(|(k, v)| (k.as_str(), *v)).collect())
+    }
+
     /// Evaluates the detection against a log event.
     ///
     /// # Arguments
@@ -47,12 +208,39 @@ impl Detection {
     /// # Returns
     ///
     /// Returns `true` if the log event matches the detection criteria, otherwise `false`.
+    #[cfg(test)]
     pub fn is_match(&self, data: &serde_json::Value) -> bool {
+        self.is_match_with(data, &MatchOptions::default())
+    }
+
+    /// Evaluates the detection against a log event with collection options
+    pub(crate) fn is_match_with(&self, data: &serde_json::Value, options: &MatchOptions) -> bool {
+        if !self
+            .required
+            .iter()
+            .all(|field| data.get(&**field).is_some())
+        {
+            return false;
+        }
+
         let results = self
             .selections
             .iter()
-            .map(|(key, selection)| (key, selection.is_match(data)))
-            .collect::<HashMap<&String, bool>>();
+            .map(|(key, selection)| {
+                (
+                    key.as_str(),
+                    match &options.matcher {
+                        Some(matcher) => matcher.is_match(selection, data),
+                        None => selection.is_match_with(data, options),
+                    },
+                )
+            })
+            .collect::<HashMap<&str, bool>>();
         self.condition.is_match(&results)
     }
 }
+
+/// whether `name` is read from the log source of events
+fn is_logsource_field(name: &str) -> bool {
+    name.split('.').next() == Some(selection::LOGSOURCE_FIELD)
+}
@@ -0,0 +1,155 @@
+//! detection rules compiled per log source
+//!
+//! Rather than filtering the whole collection by log source for each
+//! event, the rules selected by a log source are compiled into an
+//! [`Engine`] once and events are dispatched to it
+
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::sync::{Arc, PoisonError, RwLock};
+
+use serde_json::Value;
+
+use super::filter::{Axis, Filter};
+use crate::event::LogSource;
+use crate::footprint;
+use crate::rule::{RuleId, RuleType, SigmaRule};
+
+/// the detection rules selected by a log source
+///
+/// rules are indexed by one of their required top-level fields
+/// so that events without the field skip them entirely
+#[derive(Debug, Default)]
+pub(crate) struct Engine {
+    /// rules without required fields
+    always: Vec<Arc<SigmaRule>>,
+    by_field: HashMap<Arc<str>, Vec<Arc<SigmaRule>>>,
+}
+
+impl Engine {
+    fn new<'a>(rules: impl IntoIterator<Item = &'a Arc<SigmaRule>>) -> Self {
+        let mut engine = Engine::default();
+        for rule in rules {
+            let RuleType::Detection(ref detection) = rule.rule else {
+                continue;
+            };
+            match detection.required_fields().iter().min() {
+                Some(field) => engine
+                    .by_field
+                    .entry(field.clone())
+                    .or_default()
+                    .push(rule.clone()),
+                None => engine.always.push(rule.clone()),
+            }
+        }
+        engine
+    }
+
+    /// all rules of the engine
+    #[cfg(feature = "arrow")]
+    pub(crate) fn rules(&self) -> impl Iterator<Item = &Arc<SigmaRule>> {
+        self.always.iter().chain(self.by_field.values().flatten())
+    }
+
+    /// the rules that may match an event
+    pub(crate) fn candidates<'a>(
+        &'a self,
+        data: &'a Value,
+    ) -> impl Iterator<Item = &'a Arc<SigmaRule>> + 'a {
+        let indexed = data
+            .as_object()
+            .into_iter()
+            .flat_map(|fields| fields.keys())
+            .filter_map(|field| self.by_field.get(field.as_str()))
+            .flatten();
+        self.always.iter().chain(indexed)
+    }
+}
+
+/// engines for the log sources seen so far
+#[derive(Debug, Default)]
+pub(crate) struct Engines {
+    engines: RwLock<HashMap<[Axis; 3], Arc<Engine>>>,
+}
+
+/// clones do not share engines, as their rules may diverge
+impl Clone for Engines {
+    fn clone(&self) -> Self {
+        let engines = self
+            .engines
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        Engines {
+            engines: RwLock::new(engines),
+        }
+    }
+}
+
+impl Engines {
+    /// the engine for `target`, compiled on first use
+    pub(crate) fn get(
+        &self,
+        filter: &Filter,
+        rules: &HashMap<RuleId, Arc<SigmaRule>>,
+        target: &LogSource,
+    ) -> Arc<Engine> {
+        let key = filter.key(target);
+        if let Some(engine) = self
+            .engines
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(&key)
+        {
+            return engine.clone();
+        }
+
+        let engine = Arc::new(Engine::new(
+            filter.filter(target).iter().filter_map(|id| rules.get(id)),
+        ));
+        self.engines
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(key, engine.clone());
+        engine
+    }
+
+    /// compile the engines for the log sources of `rules`
+    /// and for events without a log source
+    pub(crate) fn compile(&self, filter: &Filter, rules: &HashMap<RuleId, Arc<SigmaRule>>) {
+        self.get(filter, rules, &LogSource::default());
+        for rule in rules.values() {
+            if let RuleType::Detection(ref detection) = rule.rule {
+                self.get(filter, rules, &detection.logsource);
+            }
+        }
+    }
+
+    /// the approximate heap size of the engines, rules are shared
+    /// with the collection and not counted
+    pub(crate) fn heap_size(&self) -> usize {
+        let engines = self.engines.read().unwrap_or_else(PoisonError::into_inner);
+        footprint::entries::<[Axis; 3], Arc<Engine>>(engines.len())
+            + engines
+                .values()
+                .map(|engine| {
+                    size_of::<Engine>()
+                        + engine.always.len() * size_of::<Arc<SigmaRule>>()
+                        + footprint::entries::<Arc<str>, Vec<Arc<SigmaRule>>>(engine.by_field.len())
+                        + engine
+                            .by_field
+                            .values()
+                            .map(|rules| rules.len() * size_of::<Arc<SigmaRule>>())
+                            .sum::<usize>()
+                })
+                .sum::<usize>()
+    }
+
+    /// drop all engines after the rules have changed
+    pub(crate) fn clear(&mut self) {
+        self.engines
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+}
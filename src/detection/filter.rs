@@ -1,14 +1,23 @@
 use std::collections::{HashMap, HashSet};
 
-use crate::{event::LogSource, rule::{RuleType, SigmaRule}};
+use crate::{event::LogSource, footprint, rule::{RuleId, RuleType, SigmaRule}};
 
-#[derive(Debug, Default)]
+/// a log source field of an event, values that no rule uses
+/// select the same rules and are collapsed into `Other`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Axis {
+    Any,
+    Value(String),
+    Other,
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct Filter {
-    category: HashMap<Option<String>, HashSet<String>>,
-    product: HashMap<Option<String>, HashSet<String>>,
-    service: HashMap<Option<String>, HashSet<String>>,
+    category: HashMap<Option<String>, HashSet<RuleId>>,
+    product: HashMap<Option<String>, HashSet<RuleId>>,
+    service: HashMap<Option<String>, HashSet<RuleId>>,
 
-    all: HashSet<String>,
+    all: HashSet<RuleId>,
 }
 
 impl Filter {
@@ -35,7 +44,50 @@ impl Filter {
         self.all.insert(rule.id.clone());
     }
 
-    pub fn filter(&self, target: &LogSource) -> Vec<String> {
+    /// the approximate heap size of the filter, rule IDs are shared
+    /// with the collection and not counted
+    pub(crate) fn heap_size(&self) -> usize {
+        [&self.category, &self.product, &self.service]
+            .into_iter()
+            .flat_map(|axis| axis.iter())
+            .map(|(value, ids)| {
+                footprint::entries::<Option<String>, HashSet<RuleId>>(1)
+                    + value.as_ref().map_or(0, String::len)
+                    + footprint::entries::<RuleId, ()>(ids.len())
+            })
+            .sum::<usize>()
+            + footprint::entries::<RuleId, ()>(self.all.len())
+    }
+
+    pub fn remove(&mut self, id: &RuleId) {
+        self.category
+            .values_mut()
+            .chain(self.product.values_mut())
+            .chain(self.service.values_mut())
+            .for_each(|ids| {
+                ids.remove(id);
+            });
+        self.all.remove(id);
+    }
+
+    /// the key of the rules selected by `target`, targets with
+    /// the same key select the same rules
+    pub fn key(&self, target: &LogSource) -> [Axis; 3] {
+        let axis = |rules: &HashMap<Option<String>, HashSet<RuleId>>, value: &Option<String>| {
+            match value {
+                None => Axis::Any,
+                Some(v) if rules.contains_key(value) => Axis::Value(v.clone()),
+                Some(_) => Axis::Other,
+            }
+        };
+        [
+            axis(&self.category, &target.category),
+            axis(&self.product, &target.product),
+            axis(&self.service, &target.service),
+        ]
+    }
+
+    pub fn filter(&self, target: &LogSource) -> Vec<RuleId> {
         let empty = HashSet::new();
         let all = self.all.iter().collect::<HashSet<_>>();
 
@@ -0,0 +1,108 @@
+//! Integers of any size, for values beyond `i128`
+
+use std::cmp::Ordering;
+
+use serde_json::Value;
+
+/// hexadecimal digits beyond which a value is not an integer, the
+/// conversion to decimal being quadratic
+const MAX_HEX_DIGITS: usize = 256;
+
+/// An integer of any size, parsed from a JSON number or a decimal or
+/// `0x`-prefixed hexadecimal string
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Integer {
+    negative: bool,
+    /// decimal digits, most significant first, without leading zeros
+    digits: Vec<u8>,
+}
+
+impl Integer {
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let (negative, s) = match s.strip_prefix('-') {
+            Some(s) => (true, s),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        let digits = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+            Some(hex) => from_hex(hex)?,
+            None if !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()) => {
+                s.bytes().map(|b| b - b'0').collect()
+            }
+            None => return None,
+        };
+        let zeros = digits.iter().take_while(|d| **d == 0).count();
+        let digits = digits[zeros..].to_vec();
+        Some(Integer {
+            negative: negative && !digits.is_empty(),
+            digits,
+        })
+    }
+
+    /// the integer value of a JSON number or numeric string
+    ///
+    /// numbers beyond `u64` are only exact with the `arbitrary_precision`
+    /// feature of `serde_json`, and are compared by their nearest `f64`
+    /// otherwise
+    pub(crate) fn from_json(value: &Value) -> Option<Self> {
+        match value {
+            Value::Number(n) => Integer::parse(&n.to_string()).or_else(|| {
+                let f = n.as_f64()?;
+                (f.is_finite() && f.fract() == 0.0)
+                    .then(|| format!("{:.0}", f))
+                    .and_then(|s| Integer::parse(&s))
+            }),
+            Value::String(s) => Integer::parse(s),
+            _ => None,
+        }
+    }
+
+    fn cmp_magnitude(&self, other: &Self) -> Ordering {
+        self.digits
+            .len()
+            .cmp(&other.digits.len())
+            .then_with(|| self.digits.cmp(&other.digits))
+    }
+}
+
+impl Ord for Integer {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => self.cmp_magnitude(other),
+            (true, true) => other.cmp_magnitude(self),
+        }
+    }
+}
+
+impl PartialOrd for Integer {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// the decimal digits of a hexadecimal number
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.is_empty() {
+        return None;
+    }
+    let hex = hex.trim_start_matches('0');
+    if hex.len() > MAX_HEX_DIGITS {
+        return None;
+    }
+    let mut digits = vec![0];
+    for c in hex.chars() {
+        let mut carry = c.to_digit(16)?;
+        for digit in digits.iter_mut().rev() {
+            let value = *digit as u32 * 16 + carry;
+            *digit = (value % 10) as u8;
+            carry = value / 10;
+        }
+        while carry > 0 {
+            digits.insert(0, (carry % 10) as u8);
+            carry /= 10;
+        }
+    }
+    Some(digits)
+}
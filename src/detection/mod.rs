@@ -1,8 +1,16 @@
 mod condition;
+mod integer;
+mod options;
 mod rule;
 mod selection;
 
 pub(crate) mod detection;
+pub(crate) mod engine;
 pub mod filter;
 
+pub use condition::Condition;
+pub(crate) use options::MatchOptions;
+pub use options::KeywordOptions;
 pub use rule::DetectionRule;
+pub(crate) use selection::get_terminal_from_dotted_path;
+pub use selection::{Selection, SelectionItem};
@@ -0,0 +1,217 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde_json::Value;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::event::{Event, LogSource};
+use crate::matcher::Matcher;
+
+/// collection-wide options applied when matching values
+#[derive(Debug, Default, Clone)]
+pub(crate) struct MatchOptions {
+    /// disable Sigma's default case-insensitive matching
+    pub case_sensitive: bool,
+    /// apply NFKC normalization to rule values and event strings
+    pub normalize_unicode: bool,
+    /// map look-alike Cyrillic and Greek letters to Latin
+    /// after normalization
+    pub fold_homoglyphs: bool,
+    /// evaluates selections in place of the built-in engine
+    pub matcher: Option<Arc<dyn Matcher>>,
+    /// which values of an event keywords are searched in
+    pub keywords: KeywordOptions,
+    /// the values of `%name%` placeholders expanded by the
+    /// `expand` modifier
+    pub placeholders: Arc<HashMap<String, Value>>,
+    /// the placeholder values of the event being matched, taking
+    /// precedence over `placeholders`
+    pub context: Option<Arc<HashMap<String, Value>>>,
+    /// whether rules match `@logsource.*` fields, the log source
+    /// of events is only attached to the options if they do
+    pub logsource_fields: bool,
+    /// the log source of the event being matched, as JSON
+    pub logsource: Option<Value>,
+}
+
+/// Selects the values of an event searched by keyword lists, see
+/// [`SigmaCollection::keyword_options`]
+///
+/// Like the full-text search of SIEM backends, keywords are searched
+/// in every string of the event, descending into nested objects and
+/// arrays, and in numbers and booleans written as JSON. Limiting the
+/// depth or skipping non-string values makes keyword rules cheaper on
+/// large events
+///
+/// ```rust
+/// # use sigmars::{KeywordOptions, SigmaCollection};
+/// // top-level strings only
+/// let options = KeywordOptions::new().max_depth(0).non_strings(false);
+/// let rules = SigmaCollection::new().keyword_options(options);
+/// ```
+///
+/// [`SigmaCollection::keyword_options`]: struct.SigmaCollection.html#method.keyword_options
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeywordOptions {
+    max_depth: Option<usize>,
+    non_strings: bool,
+}
+
+impl Default for KeywordOptions {
+    fn default() -> Self {
+        KeywordOptions {
+            max_depth: None,
+            non_strings: true,
+        }
+    }
+}
+
+impl KeywordOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the number of levels of nested objects and arrays searched,
+    /// `0` only searches the top-level values of the event
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// whether numbers and booleans are searched
+    pub fn non_strings(mut self, search: bool) -> Self {
+        self.non_strings = search;
+        self
+    }
+
+    /// the values of `value` searched by keywords, `value` being
+    /// at `depth`
+    pub(crate) fn texts<'a>(&self, value: &'a Value, depth: usize, texts: &mut Vec<Cow<'a, str>>) {
+        match value {
+            Value::String(s) => texts.push(Cow::Borrowed(s)),
+            Value::Number(_) | Value::Bool(_) if self.non_strings => {
+                texts.push(Cow::Owned(value.to_string()))
+            }
+            // the event itself is not a level of nesting
+            Value::Array(_) | Value::Object(_)
+                if depth > 0 && self.max_depth.is_some_and(|max| depth > max) => {}
+            Value::Array(values) => values.iter().for_each(|v| self.texts(v, depth + 1, texts)),
+            Value::Object(map) => map.values().for_each(|v| self.texts(v, depth + 1, texts)),
+            _ => {}
+        }
+    }
+}
+
+impl MatchOptions {
+    /// the options for matching `event` as if it came from `logsource`,
+    /// with its placeholder context
+    pub fn for_event(&self, event: &Event, logsource: &LogSource) -> Cow<'_, MatchOptions> {
+        let options = self.for_logsource(logsource);
+        if event.context.is_none() {
+            return options;
+        }
+        Cow::Owned(MatchOptions {
+            context: event.context.clone(),
+            ..options.into_owned()
+        })
+    }
+
+    /// the options for matching events from `logsource`
+    pub fn for_logsource(&self, logsource: &LogSource) -> Cow<'_, MatchOptions> {
+        if !self.logsource_fields {
+            return Cow::Borrowed(self);
+        }
+        Cow::Owned(MatchOptions {
+            logsource: serde_json::to_value(logsource).ok(),
+            ..self.clone()
+        })
+    }
+
+    /// the value of a placeholder, from the event context
+    /// or the collection
+    pub fn placeholder(&self, name: &str) -> Option<&Value> {
+        self.context
+            .as_ref()
+            .and_then(|context| context.get(name))
+            .or_else(|| self.placeholders.get(name))
+    }
+
+    /// normalize a string for comparison, borrowing it when unchanged
+    pub fn normalize<'a>(&self, s: &'a str) -> Cow<'a, str> {
+        if !(self.normalize_unicode || self.fold_homoglyphs) || s.is_ascii() {
+            return Cow::Borrowed(s);
+        }
+        let normalized = s.nfkc();
+        Cow::Owned(match self.fold_homoglyphs {
+            true => normalized.map(fold_homoglyph).collect(),
+            false => normalized.collect(),
+        })
+    }
+}
+
+/// the Latin letter a Cyrillic or Greek homoglyph is mistaken for
+fn fold_homoglyph(c: char) -> char {
+    match c {
+        // Cyrillic
+        'а' => 'a',
+        'в' => 'b',
+        'е' | 'ё' => 'e',
+        'і' => 'i',
+        'ј' => 'j',
+        'к' => 'k',
+        'м' => 'm',
+        'н' => 'h',
+        'о' => 'o',
+        'р' => 'p',
+        'с' => 'c',
+        'т' => 't',
+        'у' => 'y',
+        'х' => 'x',
+        'ѕ' => 's',
+        'ԁ' => 'd',
+        'һ' => 'h',
+        'ԛ' => 'q',
+        'ԝ' => 'w',
+        'А' => 'A',
+        'В' => 'B',
+        'Е' | 'Ё' => 'E',
+        'І' => 'I',
+        'Ј' => 'J',
+        'К' => 'K',
+        'М' => 'M',
+        'Н' => 'H',
+        'О' => 'O',
+        'Р' => 'P',
+        'С' => 'C',
+        'Т' => 'T',
+        'У' => 'Y',
+        'Х' => 'X',
+        'Ѕ' => 'S',
+        // Greek
+        'α' => 'a',
+        'ι' => 'i',
+        'κ' => 'k',
+        'ν' => 'v',
+        'ο' => 'o',
+        'ρ' => 'p',
+        'τ' => 't',
+        'υ' => 'u',
+        'χ' => 'x',
+        'Α' => 'A',
+        'Β' => 'B',
+        'Ε' => 'E',
+        'Ζ' => 'Z',
+        'Η' => 'H',
+        'Ι' => 'I',
+        'Κ' => 'K',
+        'Μ' => 'M',
+        'Ν' => 'N',
+        'Ο' => 'O',
+        'Ρ' => 'P',
+        'Τ' => 'T',
+        'Υ' => 'Y',
+        'Χ' => 'X',
+        c => c,
+    }
+}
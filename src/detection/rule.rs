@@ -1,23 +1,155 @@
 use serde::{self, Deserialize, Serialize};
 use serde_json::Value;
 use serde_yml;
+use std::sync::Arc;
 
 use super::detection::Detection;
+use super::MatchOptions;
 use crate::event::LogSource;
 
+#[cfg(feature = "correlation")]
+use crate::correlation::legacy::Aggregation;
+#[cfg(feature = "correlation")]
+use crate::warnings::{self, RuleWarning};
+
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub struct DetectionRule {
     /// The log source information for the detection rule.
     pub logsource: LogSource,
+    /// The raw detection, `Null` if dropped after compilation
+    #[serde(skip_serializing_if = "serde_yml::Value::is_null")]
     pub detection: serde_yml::Value,
     #[serde(skip)]
     compiled: Detection,
+    /// a legacy aggregation (`selection | count() > n`), translated
+    /// into a correlation rule when the rule is added to a collection
+    #[cfg(feature = "correlation")]
+    #[serde(skip)]
+    pub(crate) aggregation: Option<Aggregation>,
 }
 
 impl DetectionRule {
-    pub fn is_match(&self, data: &Value) -> bool {
-        self.compiled.is_match(data)
+    /// compile a detection rule without a legacy aggregation
+    #[cfg(feature = "correlation")]
+    pub(crate) fn new(
+        logsource: LogSource,
+        detection: serde_yml::Value,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let compiled = Detection::new(&detection)?;
+        Ok(DetectionRule {
+            logsource,
+            detection,
+            compiled,
+            #[cfg(feature = "correlation")]
+            aggregation: None,
+        })
+    }
+
+    /// match an event with collection options
+    pub(crate) fn is_match(&self, data: &Value, options: &MatchOptions) -> bool {
+        self.compiled.is_match_with(data, options)
+    }
+
+    /// see [`Detection::explain`]
+    pub(crate) fn explain(
+        &self,
+        data: &Value,
+        options: &MatchOptions,
+    ) -> (std::collections::BTreeMap<String, bool>, Vec<Arc<str>>) {
+        self.compiled.explain(data, options)
+    }
+
+    /// see [`Detection::condition_matches`]
+    pub(crate) fn condition_matches(
+        &self,
+        results: &std::collections::BTreeMap<String, bool>,
+    ) -> bool {
+        self.compiled.condition_matches(results)
+    }
+
+    /// top-level fields that must be present in an event for it to match
+    pub(crate) fn required_fields(&self) -> &[Arc<str>] {
+        self.compiled.required_fields()
+    }
+
+    /// the selections of the detection by name
+    pub(crate) fn selections(&self) -> &std::collections::HashMap<String, super::Selection> {
+        self.compiled.selections()
+    }
+
+    /// see [`Detection::dnf`]
+    pub(crate) fn dnf(&self, limit: usize) -> Option<super::condition::Dnf> {
+        self.compiled.dnf(limit)
+    }
+
+    /// see [`Detection::fields`]
+    pub(crate) fn fields(&self) -> crate::fields::ReferencedFields {
+        self.compiled.fields()
+    }
+
+    /// see [`Detection::reads_logsource`]
+    pub(crate) fn reads_logsource(&self) -> bool {
+        self.compiled.reads_logsource()
+    }
+
+    /// top-level fields read by the detection, see
+    /// [`Detection::referenced_fields`]
+    #[cfg(feature = "arrow")]
+    pub(crate) fn referenced_fields(&self) -> Option<std::collections::HashSet<Arc<str>>> {
+        self.compiled.referenced_fields()
+    }
+
+    /// rename the fields read by the compiled detection, the raw
+    /// detection keeps the fields as written
+    pub(crate) fn map_fields(&mut self, map: &dyn Fn(&str) -> Option<String>) {
+        self.compiled.map_fields(map);
+    }
+
+    /// add filter selections to the detection: it no longer matches
+    /// events matching any of them
+    pub(crate) fn add_filters(
+        &mut self,
+        filters: &serde_yml::Mapping,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut detection = self.detection.clone();
+        let rules = detection.as_mapping_mut().ok_or("invalid detection")?;
+        let condition = rules
+            .get("condition")
+            .and_then(serde_yml::Value::as_str)
+            .ok_or("invalid detection")?
+            .to_string();
+
+        let mut names = Vec::new();
+        for key in filters.keys() {
+            let key = key.as_str().ok_or("invalid filter")?;
+            let name = key.split('|').next().unwrap_or(key);
+            if rules
+                .keys()
+                .filter_map(serde_yml::Value::as_str)
+                .any(|k| k.split('|').next() == Some(name))
+            {
+                return Err(format!("filter {} conflicts with a selection", name).into());
+            }
+            names.push(name);
+        }
+        let condition = format!("({}) and not ({})", condition, names.join(" or "));
+        rules.insert("condition".into(), condition.into());
+        rules.extend(filters.clone());
+
+        self.compiled = Detection::new(&detection)?;
+        self.detection = detection;
+        Ok(())
+    }
+
+    /// the approximate heap size of the compiled detection
+    pub(crate) fn compiled_size(&self) -> usize {
+        self.compiled.heap_size()
+    }
+
+    /// drop the raw detection, keeping only the compiled form
+    pub(crate) fn drop_raw(&mut self) {
+        self.detection = serde_yml::Value::Null;
     }
 }
 
@@ -32,7 +164,47 @@ impl<'de> Deserialize<'de> for DetectionRule {
             detection: serde_yml::Value,
         }
         // Deserialize the detection rule from the deserializer
-        let rule = RuleHelper::deserialize(deserializer)?;
+        let mut rule = RuleHelper::deserialize(deserializer)?;
+
+        // Split legacy aggregations and their timeframe from the detection
+        let (timeframe, aggregation) = match rule.detection.as_mapping_mut() {
+            Some(detection) => {
+                let timeframe = detection.remove("timeframe");
+                let aggregation = match detection.get_mut("condition") {
+                    Some(serde_yml::Value::String(condition)) => condition.find('|').map(|idx| {
+                        let aggregation = condition.split_off(idx)[1..].to_string();
+                        condition.truncate(condition.trim_end().len());
+                        aggregation
+                    }),
+                    _ => None,
+                };
+                (timeframe, aggregation)
+            }
+            None => (None, None),
+        };
+
+        #[cfg(feature = "correlation")]
+        let aggregation = aggregation
+            .map(|aggregation| {
+                warnings::warn(RuleWarning::Deprecated {
+                    syntax: format!("aggregation in condition: {}", aggregation),
+                });
+                Aggregation::new(
+                    &aggregation,
+                    timeframe.as_ref().and_then(serde_yml::Value::as_str),
+                    &rule.logsource,
+                    &rule.detection,
+                )
+            })
+            .transpose()
+            .map_err(serde::de::Error::custom)?;
+        #[cfg(not(feature = "correlation"))]
+        if aggregation.is_some() {
+            let _ = timeframe;
+            return Err(serde::de::Error::custom(
+                "aggregations require the correlation feature",
+            ));
+        }
 
         // Compile the detection criteria
         let compiled = Detection::new(&rule.detection).map_err(serde::de::Error::custom)?;
@@ -41,6 +213,8 @@ impl<'de> Deserialize<'de> for DetectionRule {
             logsource: rule.logsource,
             detection: rule.detection,
             compiled,
+            #[cfg(feature = "correlation")]
+            aggregation,
         })
     }
 }
@@ -1,8 +1,18 @@
 use cidr;
-use regex::{Regex, RegexBuilder};
+use regex::{Regex, RegexBuilder, RegexSet, RegexSetBuilder};
 use serde_json::{json, Value as JsonValue};
 use serde_yml::Value as YamlValue;
-use std::{net::IpAddr, str::FromStr};
+use std::cmp::Ordering;
+use std::{borrow::Cow, collections::HashSet, mem::size_of, net::IpAddr, str::FromStr, sync::Arc};
+
+use super::integer::Integer;
+use super::options::MatchOptions;
+use crate::footprint;
+use crate::intern::intern;
+use crate::limits;
+use crate::warnings::{self, RuleWarning};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 
 use serde::{Deserialize, Serialize};
 
@@ -14,9 +24,7 @@ enum Modifier {
     Contains,
     Exists,
     Cased,
-    Re(Option<Regex>),
-    Base64(Option<Base64Modifier>),
-    Base64Offset,
+    Re(Option<RegexSet>),
     Lt,
     Lte,
     Gt,
@@ -27,60 +35,37 @@ enum Modifier {
 }
 
 impl Modifier {
-    fn eval(&self, key: &String, value: &JsonValue, full_log: &JsonValue) -> bool {
-        let log = get_terminal_from_dotted_path(key, full_log).unwrap_or(&JsonValue::Null);
+    fn eval(
+        &self,
+        path: &[Arc<str>],
+        value: &JsonValue,
+        full_log: &JsonValue,
+        options: &MatchOptions,
+    ) -> bool {
+        let log = field_value(path, full_log, options).unwrap_or(&JsonValue::Null);
+        let compare = |f: fn(&str, &str) -> bool| match (as_text(log), as_text(value)) {
+            (Some(log), Some(v)) => f(&options.normalize(&log), &options.normalize(&v)),
+            _ => false,
+        };
         match self {
             Modifier::All => log.as_array().map_or(false, |log| {
                 value
                     .as_array()
                     .map_or(false, |v| v.iter().all(|v| log.contains(v)))
             }),
-            Modifier::StartsWith => value.as_str().map_or(false, |v| {
-                log.as_str().map_or(false, |log| log.starts_with(v))
-            }),
-            Modifier::EndsWith => value.as_str().map_or(false, |v| {
-                log.as_str().map_or(false, |log| log.ends_with(v))
-            }),
-            Modifier::Contains => value
-                .as_str()
-                .map_or(false, |v| log.as_str().map_or(false, |log| log.contains(v))),
+            Modifier::StartsWith => compare(|log, v| log.starts_with(v)),
+            Modifier::EndsWith => compare(|log, v| log.ends_with(v)),
+            Modifier::Contains => compare(|log, v| log.contains(v)),
             Modifier::Exists => !log.is_null(),
-            Modifier::Cased => value
+            Modifier::Cased => compare(|log, v| log == v),
+            Modifier::Re(Some(re)) => log
                 .as_str()
-                .map_or(false, |v| log.as_str().map_or(false, |log| log == v)),
-            Modifier::Re(Some(re)) => log.as_str().map_or(false, |log| re.is_match(log)),
+                .is_some_and(|log| re.is_match(&options.normalize(log))),
             Modifier::Re(None) => false,
-            Modifier::Base64(b64mod) => {
-                // TODO: Implement Base64
-                match b64mod {
-                    Some(Base64Modifier::Utf16Le) => false,
-                    Some(Base64Modifier::Utf16Be) => false,
-                    Some(Base64Modifier::Utf16) => false,
-                    Some(Base64Modifier::Wide) => false,
-                    None => false,
-                }
-            }
-            Modifier::Base64Offset => false, // TODO: Implement Base64Offset
-            Modifier::Lt => value.as_i64().map_or(false, |v| {
-                log.as_i64()
-                    .or_else(|| log.as_str().and_then(|s| s.parse::<i64>().ok()))
-                    .map_or(false, |n| n < v)
-            }),
-            Modifier::Lte => value.as_i64().map_or(false, |v| {
-                log.as_i64()
-                    .or_else(|| log.as_str().and_then(|s| s.parse::<i64>().ok()))
-                    .map_or(false, |n| n <= v)
-            }),
-            Modifier::Gt => value.as_i64().map_or(false, |v| {
-                log.as_i64()
-                    .or_else(|| log.as_str().and_then(|s| s.parse::<i64>().ok()))
-                    .map_or(false, |n| n > v)
-            }),
-            Modifier::Gte => value.as_i64().map_or(false, |v| {
-                log.as_i64()
-                    .or_else(|| log.as_str().and_then(|s| s.parse::<i64>().ok()))
-                    .map_or(false, |n| n >= v)
-            }),
+            Modifier::Lt => compare_integers(log, value).is_some_and(Ordering::is_lt),
+            Modifier::Lte => compare_integers(log, value).is_some_and(Ordering::is_le),
+            Modifier::Gt => compare_integers(log, value).is_some_and(Ordering::is_gt),
+            Modifier::Gte => compare_integers(log, value).is_some_and(Ordering::is_ge),
             Modifier::Cidr => value
                 .as_str()
                 .and_then(|v| cidr::AnyIpCidr::from_str(v).ok())
@@ -100,10 +85,17 @@ impl Modifier {
                                 .unwrap_or_else(|| false),
                         })
                 }),
-            Modifier::Expand => false, // TODO: Implement Expand
+            // values are expanded before matching, see `is_expanded_match`
+            Modifier::Expand => false,
             Modifier::FieldRef => value.as_str().map_or(false, |rhs| {
-                get_terminal_from_dotted_path(rhs, full_log)
-                    .map_or(false, |rhs_value| log == rhs_value)
+                let rhs_value = match rhs.split_once('.') {
+                    Some((LOGSOURCE_FIELD, rhs)) => options
+                        .logsource
+                        .as_ref()
+                        .and_then(|logsource| get_terminal_from_dotted_path(rhs, logsource)),
+                    _ => get_terminal_from_dotted_path(rhs, full_log),
+                };
+                rhs_value == Some(log)
             }),
         }
     }
@@ -121,8 +113,6 @@ impl FromStr for Modifier {
             "exists" => Ok(Modifier::Exists),
             "cased" => Ok(Modifier::Cased),
             "re" => Ok(Modifier::Re(None)),
-            "base64" => Ok(Modifier::Base64(None)), // TODO: Add Base64Modifier
-            "base64offset" => Ok(Modifier::Base64Offset),
             "lt" => Ok(Modifier::Lt),
             "lte" => Ok(Modifier::Lte),
             "gt" => Ok(Modifier::Gt),
@@ -135,7 +125,7 @@ impl FromStr for Modifier {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 enum Base64Modifier {
     Utf16Le,
     Utf16Be,
@@ -143,94 +133,591 @@ enum Base64Modifier {
     Wide,
 }
 
+impl Base64Modifier {
+    /// the bytes of `value` in this encoding, ahead of base64 encoding
+    fn encode(&self, value: &str) -> Vec<u8> {
+        match self {
+            Base64Modifier::Utf16Le | Base64Modifier::Wide => {
+                value.encode_utf16().flat_map(u16::to_le_bytes).collect()
+            }
+            Base64Modifier::Utf16Be => value.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+            Base64Modifier::Utf16 => [0xFF, 0xFE]
+                .into_iter()
+                .chain(value.encode_utf16().flat_map(u16::to_le_bytes))
+                .collect(),
+        }
+    }
+}
+
+/// value transforms applied to rule values at parse time
+#[derive(Debug, Clone, Copy)]
+enum Base64Transform {
+    Base64,
+    Base64Offset,
+}
+
+impl Base64Transform {
+    /// encode `value`; `base64offset` yields one alternative for each
+    /// of the three possible byte offsets in the encoded stream, less
+    /// those too short to keep any character of `value`
+    fn apply(&self, value: &[u8]) -> JsonValue {
+        match self {
+            Base64Transform::Base64 => JsonValue::String(BASE64.encode(value)),
+            Base64Transform::Base64Offset => JsonValue::Array(
+                (0..3)
+                    .filter_map(|i| {
+                        let mut shifted = vec![b' '; i];
+                        shifted.extend_from_slice(value);
+                        let encoded = BASE64.encode(&shifted);
+                        let start = [0, 2, 3][i];
+                        let end = encoded.len().checked_sub([0, 3, 2][(value.len() + i) % 3])?;
+                        match encoded.get(start..end)? {
+                            "" => None,
+                            encoded => Some(JsonValue::String(encoded.to_string())),
+                        }
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 struct Field {
-    key: String,
+    /// the dotted field path, split at parse time
+    path: Vec<Arc<str>>,
     values: Vec<JsonValue>,
     modifiers: Vec<Modifier>,
+    /// the modifiers as written in the rule
+    names: Vec<String>,
 }
 
 impl Field {
-    pub fn new(key: String, value: &YamlValue) -> Result<Self, Box<dyn std::error::Error>> {
+    pub fn new(
+        key: String,
+        value: &YamlValue,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         let mut key_modifiers = key.split("|");
         let key = key_modifiers
             .next()
             .ok_or_else(|| "invalid Key")?
             .to_string();
+        let names = key_modifiers.clone().map(str::to_string).collect();
+        if let YamlValue::Sequence(seq) = value {
+            limits::check_values(seq.len())?;
+        }
 
         let mut modifiers = Vec::new();
+        let mut encoding = None;
+        let mut transform = None;
 
-        match key_modifiers.next() {
-            Some("regex") => {
-                let re = value
-                    .as_str()
-                    .map(|re| RegexBuilder::new(re))
-                    .map(|mut builder| {
-                        for modifier in key_modifiers {
-                            match modifier {
-                                "i" => builder.case_insensitive(true),
-                                "m" => builder.multi_line(true),
-                                "s" => builder.dot_matches_new_line(true),
-                                _ => {
-                                    return Err(regex::Error::Syntax(
-                                        format!("invalid modifier: {}", modifier).into(),
-                                    ))
-                                }
-                            };
-                        }
-                        builder.build()
-                    })
-                    .transpose()?
-                    .ok_or_else(|| "invalid regex")?;
-                modifiers.push(Modifier::Re(Some(re)));
+        while let Some(m) = key_modifiers.next() {
+            match m {
+                "re" | "regex" => {
+                    let patterns = match value {
+                        YamlValue::String(s) => vec![s.as_str()],
+                        YamlValue::Sequence(seq) => seq
+                            .iter()
+                            .map(|v| v.as_str().ok_or("invalid regex"))
+                            .collect::<Result<_, _>>()?,
+                        _ => Err("invalid regex")?,
+                    };
+                    // the remaining modifiers are regex flags
+                    let set = build_regex_set(&patterns, key_modifiers.by_ref())?;
+                    modifiers.push(Modifier::Re(Some(set)));
+
+                    return Ok(Field {
+                        path: key.split('.').map(intern).collect(),
+                        values: patterns.into_iter().map(JsonValue::from).collect(),
+                        modifiers,
+                        names,
+                    });
+                }
+                "base64" => transform = Some(Base64Transform::Base64),
+                "base64offset" => transform = Some(Base64Transform::Base64Offset),
+                "utf16le" => encoding = Some(Base64Modifier::Utf16Le),
+                "utf16be" => encoding = Some(Base64Modifier::Utf16Be),
+                "utf16" => encoding = Some(Base64Modifier::Utf16),
+                "wide" => encoding = Some(Base64Modifier::Wide),
+                m => modifiers
+                    .push(Modifier::from_str(m).map_err(|_| format!("invalid modifier: {}", m))?),
             }
-            Some(m) => modifiers
-                .push(Modifier::from_str(m).map_err(|_| format!("invalid modifier: {}", m))?),
-            None => (),
-        };
+        }
 
         let values: Vec<JsonValue> = match value {
             YamlValue::String(s) => vec![JsonValue::String(s.clone())],
-            YamlValue::Number(n) => vec![n.as_i64().map_or_else(
-                || n.as_f64().map_or_else(|| JsonValue::Null, |f| json!(f)),
-                |i| json!(i),
-            )],
+            YamlValue::Number(n) => vec![n
+                .as_i64()
+                .map(|i| json!(i))
+                .or_else(|| n.as_u64().map(|u| json!(u)))
+                .or_else(|| n.as_f64().map(|f| json!(f)))
+                .unwrap_or(JsonValue::Null)],
             YamlValue::Bool(b) => vec![JsonValue::Bool(*b)],
             YamlValue::Sequence(seq) => seq
                 .iter()
                 .map(|v| match v {
                     YamlValue::String(s) => Ok(JsonValue::String(s.as_str().to_string())),
-                    YamlValue::Number(n) => n.as_i64().map_or_else(
-                        || {
-                            n.as_f64().map_or_else(
-                                || Err(format!("invalid numeric value: {}", n).into()),
-                                |f| Ok(json!(f)),
-                            )
-                        },
-                        |i| Ok(json!(i)),
-                    ),
+                    YamlValue::Number(n) => n
+                        .as_i64()
+                        .map(|i| json!(i))
+                        .or_else(|| n.as_u64().map(|u| json!(u)))
+                        .or_else(|| n.as_f64().map(|f| json!(f)))
+                        .ok_or_else(|| format!("invalid numeric value: {}", n).into()),
                     YamlValue::Bool(b) => Ok(JsonValue::Bool(*b)),
                     _ => Err("invalid value type")?,
                 })
-                .collect::<Result<Vec<JsonValue>, Box<dyn std::error::Error>>>()?,
+                .collect::<Result<Vec<JsonValue>, Box<dyn std::error::Error + Send + Sync>>>()?,
             _ => Err("invalid value type")?,
         };
 
+        let values = match transform {
+            Some(transform) => {
+                // encoded values are compared exactly unless a match type is given
+                if modifiers.is_empty() {
+                    modifiers.push(Modifier::Cased);
+                }
+                values
+                    .iter()
+                    .map(|v| {
+                        let v = v.as_str().ok_or("base64 modifiers require string values")?;
+                        if v.is_empty() {
+                            Err("base64 modifiers require non-empty values")?;
+                        }
+                        let bytes = encoding.map_or_else(|| v.as_bytes().to_vec(), |e| e.encode(v));
+                        Ok(transform.apply(&bytes))
+                    })
+                    .collect::<Result<_, Box<dyn std::error::Error + Send + Sync>>>()?
+            }
+            None if encoding.is_some() => Err("utf16 modifiers require base64 or base64offset")?,
+            None => {
+                check_wildcards(&key, &modifiers, &values);
+                values
+            }
+        };
+
         Ok(Field {
-            key,
+            path: key.split('.').map(intern).collect(),
             values,
             modifiers,
+            names,
         })
     }
 }
 
+impl Field {
+    /// the approximate heap size of the field, interned path
+    /// segments are shared and not counted
+    fn heap_size(&self) -> usize {
+        let patterns = self
+            .modifiers
+            .iter()
+            .map(|m| match m {
+                Modifier::Re(Some(set)) => set.patterns().iter().map(String::len).sum(),
+                _ => 0,
+            })
+            .sum::<usize>();
+        self.path.len() * size_of::<Arc<str>>()
+            + self
+                .values
+                .iter()
+                .map(|v| size_of::<JsonValue>() + footprint::json(v))
+                .sum::<usize>()
+            + self.modifiers.len() * size_of::<Modifier>()
+            + patterns
+            + strings(&self.names)
+    }
+
+    /// match with modifiers: each value must satisfy every modifier,
+    /// and values are OR-ed unless `all` is given
+    fn is_modified_match(&self, log: &JsonValue, options: &MatchOptions) -> bool {
+        if self.modifiers.iter().any(|m| matches!(m, Modifier::Expand)) {
+            return self.is_expanded_match(log, options);
+        }
+        let all = self.modifiers.iter().any(|m| matches!(m, Modifier::All));
+        let modifiers = self
+            .modifiers
+            .iter()
+            .filter(|m| !matches!(m, Modifier::All))
+            .collect::<Vec<_>>();
+
+        if modifiers.is_empty() {
+            // a bare `all` matches arrays containing every value
+            return Modifier::All.eval(&self.path, &json!(&self.values), log, options);
+        }
+
+        // regexes are compiled from the values, so are evaluated once
+        if self.values.is_empty() || modifiers.iter().any(|m| matches!(m, Modifier::Re(_))) {
+            return modifiers
+                .iter()
+                .all(|m| m.eval(&self.path, &JsonValue::Null, log, options));
+        }
+
+        let matches = |value: &JsonValue| match value {
+            // alternative encodings from `base64offset`
+            JsonValue::Array(alternatives) => alternatives
+                .iter()
+                .any(|v| modifiers.iter().all(|m| m.eval(&self.path, v, log, options))),
+            value => modifiers
+                .iter()
+                .all(|m| m.eval(&self.path, value, log, options)),
+        };
+
+        if all {
+            self.values.iter().all(matches)
+        } else {
+            self.values.iter().any(matches)
+        }
+    }
+
+    /// match with `expand`: the placeholders of each value are replaced
+    /// by their values, see [`expand`], which are alternatives matched
+    /// with the other modifiers
+    fn is_expanded_match(&self, log: &JsonValue, options: &MatchOptions) -> bool {
+        let all = self.modifiers.iter().any(|m| matches!(m, Modifier::All));
+        let modifiers = self
+            .modifiers
+            .iter()
+            .filter(|m| !matches!(m, Modifier::All | Modifier::Expand))
+            .collect::<Vec<_>>();
+        let field = field_value(&self.path, log, options);
+
+        let matches = |value: &JsonValue| {
+            let alternatives = match value.as_str() {
+                Some(v) => expand(v, options).into_iter().map(JsonValue::from).collect(),
+                None => vec![value.clone()],
+            };
+            alternatives.iter().any(|v| match modifiers.is_empty() {
+                true => field.is_some_and(|field| value_eq(field, v, options)),
+                false => modifiers.iter().all(|m| m.eval(&self.path, v, log, options)),
+            })
+        };
+
+        if all {
+            self.values.iter().all(matches)
+        } else {
+            self.values.iter().any(matches)
+        }
+    }
+}
+
+/// a rule value with its `%name%` placeholders replaced by each of
+/// their values, none if a placeholder is not defined
+fn expand(value: &str, options: &MatchOptions) -> Vec<String> {
+    let Some((prefix, rest)) = value.split_once('%') else {
+        return vec![value.to_string()];
+    };
+    let Some((name, suffix)) = rest.split_once('%') else {
+        return vec![value.to_string()];
+    };
+    let values: Vec<String> = match options.placeholder(name) {
+        Some(JsonValue::Array(values)) => values.iter().filter_map(placeholder_text).collect(),
+        Some(value) => placeholder_text(value).into_iter().collect(),
+        None => return vec![],
+    };
+    let suffixes = expand(suffix, options);
+    values
+        .iter()
+        .flat_map(|v| suffixes.iter().map(move |s| format!("{prefix}{v}{s}")))
+        .collect()
+}
+
+/// a placeholder value as text, numbers and booleans as written
+fn placeholder_text(value: &JsonValue) -> Option<String> {
+    match value {
+        JsonValue::String(s) => Some(s.clone()),
+        JsonValue::Number(_) | JsonValue::Bool(_) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// a string, or a number as written, compared by the string modifiers
+fn as_text(value: &JsonValue) -> Option<Cow<'_, str>> {
+    match value {
+        JsonValue::String(s) => Some(Cow::Borrowed(s)),
+        JsonValue::Number(n) => Some(Cow::Owned(n.to_string())),
+        _ => None,
+    }
+}
+
+/// coerce a JSON number or numeric string (decimal, or hexadecimal
+/// with a `0x` prefix) to an integer wide enough for both i64 and u64
+fn as_integer(value: &JsonValue) -> Option<i128> {
+    match value {
+        JsonValue::Number(n) => n
+            .as_i64()
+            .map(i128::from)
+            .or_else(|| n.as_u64().map(i128::from)),
+        JsonValue::String(s) => {
+            let s = s.trim();
+            match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+                Some(hex) => i128::from_str_radix(hex, 16).ok(),
+                None => s.parse::<i128>().ok(),
+            }
+        }
+        _ => None,
+    }
+}
+
+/// compare two values numerically, see [`as_integer`], falling back
+/// to an [`Integer`] of any size for values beyond `i128`
+fn compare_integers(a: &JsonValue, b: &JsonValue) -> Option<Ordering> {
+    match (as_integer(a), as_integer(b)) {
+        (Some(a), Some(b)) => Some(a.cmp(&b)),
+        _ => Some(Integer::from_json(a)?.cmp(&Integer::from_json(b)?)),
+    }
+}
+
+/// whether two values are numerically equal, see [`compare_integers`]
+fn integer_eq(a: &JsonValue, b: &JsonValue) -> bool {
+    compare_integers(a, b).is_some_and(Ordering::is_eq)
+}
+
+/// match an event field with a rule value without modifiers, an
+/// array field matches if any of its elements does
+fn value_eq(field: &JsonValue, value: &JsonValue, options: &MatchOptions) -> bool {
+    match field {
+        /*
+         * Sigma specifies case-insensitive matching
+         * and allows wildcards
+         */
+        JsonValue::String(logvalue) => value.as_str().map_or_else(
+            || integer_eq(field, value),
+            |v| {
+                let (logvalue, v) = (options.normalize(logvalue), options.normalize(v));
+                match options.case_sensitive {
+                    true => wildcard_match(&logvalue, &v),
+                    false => wildcard_match(&logvalue.to_lowercase(), &v.to_lowercase()),
+                }
+            },
+        ),
+        JsonValue::Number(logvalue) => {
+            value.as_number().is_some_and(|v| logvalue == v) || integer_eq(field, value)
+        }
+        JsonValue::Array(fields) => fields.iter().any(|field| value_eq(field, value, options)),
+        _ => false,
+    }
+}
+
+/// warn of `*` wildcards matched literally, i.e. inside a value or
+/// with a string modifier, and of values matching anything
+fn check_wildcards(key: &str, modifiers: &[Modifier], values: &[JsonValue]) {
+    let plain = modifiers.iter().all(|m| matches!(m, Modifier::All));
+    let string = modifiers.iter().any(|m| {
+        matches!(
+            m,
+            Modifier::StartsWith | Modifier::EndsWith | Modifier::Contains | Modifier::Cased
+        )
+    });
+    for value in values.iter().filter_map(JsonValue::as_str) {
+        let inner = value.trim_matches('*');
+        if (plain && (inner.is_empty() || inner.contains('*'))) || (string && value.contains('*'))
+        {
+            warnings::warn(RuleWarning::Wildcard {
+                field: key.to_string(),
+                value: value.to_string(),
+            });
+        }
+    }
+}
+
+/// the approximate heap size of a list of strings
+fn strings(values: &[String]) -> usize {
+    values.iter().map(|v| size_of::<String>() + v.len()).sum()
+}
+
+/// match a value with leading and/or trailing `*` wildcards
+fn wildcard_match(logvalue: &str, v: &str) -> bool {
+    match (v.strip_prefix('*'), v.strip_suffix('*')) {
+        (Some(_), Some(_)) if v.len() > 1 => logvalue.contains(&v[1..v.len() - 1]),
+        (Some(suffix), _) => logvalue.ends_with(suffix),
+        (_, Some(prefix)) => logvalue.starts_with(prefix),
+        (None, None) => logvalue == v,
+    }
+}
+
+/// compile a regex with Sigma's `i`, `m` and `s` flag modifiers
+fn build_regex<'a>(
+    pattern: &str,
+    flags: impl Iterator<Item = &'a str>,
+) -> Result<Regex, regex::Error> {
+    let mut builder = RegexBuilder::new(pattern);
+    for flag in flags {
+        match flag {
+            "i" => builder.case_insensitive(true),
+            "m" => builder.multi_line(true),
+            "s" => builder.dot_matches_new_line(true),
+            _ => return Err(regex::Error::Syntax(format!("invalid modifier: {}", flag))),
+        };
+    }
+    builder.build()
+}
+
+/// compile a list of patterns into a single [`RegexSet`] matching any of
+/// them, with the same flag modifiers as [`build_regex`]
+fn build_regex_set<'a>(
+    patterns: &[&str],
+    flags: impl Iterator<Item = &'a str>,
+) -> Result<RegexSet, regex::Error> {
+    let mut builder = RegexSetBuilder::new(patterns);
+    for flag in flags {
+        match flag {
+            "i" => builder.case_insensitive(true),
+            "m" => builder.multi_line(true),
+            "s" => builder.dot_matches_new_line(true),
+            _ => return Err(regex::Error::Syntax(format!("invalid modifier: {}", flag))),
+        };
+    }
+    builder.build()
+}
+
+/// An unbound keyword list, matched against the event as a whole
+///
+/// Keywords are OR-ed unless the `all` modifier is present,
+/// and matched as case-insensitive substrings unless `cased`
+/// or `re` is present
+#[derive(Debug, Clone)]
+struct Keywords {
+    /// the keywords as written in the rule
+    values: Vec<String>,
+    /// the keywords without wildcards, empty if `re`
+    needles: Vec<String>,
+    /// lower-cased needles, empty if `cased`
+    folded: Vec<String>,
+    regexes: Vec<Regex>,
+    all: bool,
+    cased: bool,
+    /// the modifiers as written in the rule
+    names: Vec<String>,
+}
+
+impl Keywords {
+    /// the approximate heap size of the keywords
+    fn heap_size(&self) -> usize {
+        strings(&self.values)
+            + strings(&self.needles)
+            + strings(&self.folded)
+            + self
+                .regexes
+                .iter()
+                .map(|re| size_of::<Regex>() + re.as_str().len())
+                .sum::<usize>()
+            + strings(&self.names)
+    }
+
+    fn new<'a>(
+        mut modifiers: impl Iterator<Item = &'a str>,
+        value: &YamlValue,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        if let YamlValue::Sequence(seq) = value {
+            limits::check_values(seq.len())?;
+        }
+        let values = match value {
+            YamlValue::Sequence(seq) => seq
+                .iter()
+                .map(keyword)
+                .collect::<Option<Vec<_>>>()
+                .ok_or("invalid keyword")?,
+            _ => vec![keyword(value).ok_or("invalid keyword")?],
+        };
+
+        let mut keywords = Keywords {
+            values: vec![],
+            needles: vec![],
+            folded: vec![],
+            regexes: vec![],
+            all: false,
+            cased: false,
+            names: vec![],
+        };
+        let mut re = false;
+        while let Some(modifier) = modifiers.next() {
+            keywords.names.push(modifier.to_string());
+            match modifier {
+                "all" => keywords.all = true,
+                "cased" => keywords.cased = true,
+                "contains" => (),
+                "re" | "regex" => {
+                    let flags = modifiers.by_ref().collect::<Vec<_>>();
+                    keywords
+                        .names
+                        .extend(flags.iter().map(|flag| flag.to_string()));
+                    keywords.regexes = values
+                        .iter()
+                        .map(|v| build_regex(v, flags.iter().copied()))
+                        .collect::<Result<_, _>>()?;
+                    re = true;
+                }
+                m => Err(format!("invalid keyword modifier: {}", m))?,
+            }
+        }
+
+        if !re {
+            keywords.needles = values
+                .iter()
+                .map(|v| v.trim_matches('*').to_string())
+                .collect();
+            if !keywords.cased {
+                keywords.folded = keywords.needles.iter().map(|v| v.to_lowercase()).collect();
+            }
+        }
+        keywords.values = values;
+        Ok(keywords)
+    }
+
+    fn is_match(&self, log: &JsonValue, options: &MatchOptions) -> bool {
+        let mut texts = vec![];
+        options.keywords.texts(log, 0, &mut texts);
+        let fold = !(self.cased || options.case_sensitive);
+        let texts = texts
+            .iter()
+            .map(|text| match fold {
+                true => Cow::Owned(options.normalize(text).to_lowercase()),
+                false => options.normalize(text),
+            })
+            .collect::<Vec<_>>();
+        let values = match fold {
+            true => &self.folded,
+            false => &self.needles,
+        };
+
+        let mut matches = values
+            .iter()
+            .map(|v| {
+                let v = options.normalize(v);
+                texts.iter().any(|text| text.contains(v.as_ref()))
+            })
+            .chain(
+                self.regexes
+                    .iter()
+                    .map(|re| texts.iter().any(|text| re.is_match(text))),
+            );
+        match self.all {
+            true => matches.all(|m| m),
+            false => matches.any(|m| m),
+        }
+    }
+}
+
+/// a keyword as text, numbers and booleans being searched as written
+fn keyword(value: &YamlValue) -> Option<String> {
+    match value {
+        YamlValue::String(s) => Some(s.clone()),
+        YamlValue::Number(n) => Some(n.to_string()),
+        YamlValue::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
 #[derive(Debug, Clone)]
 enum MatchType {
     Field(Field),
-    Exact(String),
+    Keywords(Keywords),
 }
 
-fn get_terminal_from_dotted_path<'a>(path: &str, log: &'a JsonValue) -> Option<&'a JsonValue> {
+pub(crate) fn get_terminal_from_dotted_path<'a>(
+    path: &str,
+    log: &'a JsonValue,
+) -> Option<&'a JsonValue> {
     let mut current = log;
     for key in path.split(".") {
         current = current.get(key)?;
@@ -238,97 +725,255 @@ fn get_terminal_from_dotted_path<'a>(path: &str, log: &'a JsonValue) -> Option<&
     Some(current)
 }
 
+fn get_terminal_from_path<'a>(path: &[Arc<str>], log: &'a JsonValue) -> Option<&'a JsonValue> {
+    path.iter()
+        .try_fold(log, |current, key| current.get(&**key))
+}
+
+/// the prefix of fields read from the log source of the event
+/// rather than its data, e.g. `@logsource.service`
+pub(crate) const LOGSOURCE_FIELD: &str = "@logsource";
+
+/// the value of a field of the event, `@logsource.*` fields being
+/// read from its log source
+fn field_value<'a>(
+    path: &[Arc<str>],
+    log: &'a JsonValue,
+    options: &'a MatchOptions,
+) -> Option<&'a JsonValue> {
+    match path.split_first() {
+        Some((first, rest)) if **first == *LOGSOURCE_FIELD => {
+            get_terminal_from_path(rest, options.logsource.as_ref()?)
+        }
+        _ => get_terminal_from_path(path, log),
+    }
+}
+
+impl FromStr for Selection {
+    type Err = Box<dyn std::error::Error + Send + Sync>;
+
+    /// parse a selection from YAML, a map of fields or a list
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Selection::new(&limits::catch_panics(|| {
+            serde_yml::from_str::<YamlValue>(s)
+        })?)
+    }
+}
+
+/// A field or keyword list of a [`Selection`], as written in the rule
+#[derive(Debug, Clone, Copy)]
+pub enum SelectionItem<'a> {
+    /// a field, `path` is the dotted field name split on `.`
+    ///
+    /// values are the rule values after any encoding modifiers
+    /// (`base64offset` yields an array of alternatives per value)
+    Field {
+        path: &'a [Arc<str>],
+        modifiers: &'a [String],
+        values: &'a [JsonValue],
+    },
+    /// a list of keywords, matched against the event as a whole
+    Keywords {
+        modifiers: &'a [String],
+        values: &'a [String],
+    },
+}
+
+/// A compiled selection (search identifier) of a detection
+///
+/// selections can be parsed from YAML on their own, as written under
+/// their name in the `detection` section of a rule, and matched
+/// against events, e.g. to test rule fragments
+///
+/// ```rust
+/// # use serde_json::json;
+/// # use sigmars::Selection;
+/// let selection: Selection = r#"
+/// Image|endswith: '/curl'
+/// CommandLine|contains|all:
+///   - ' -o '
+///   - '/tmp/'
+/// "#
+/// .parse()
+/// .unwrap();
+/// assert!(selection.is_match(&json!({
+///     "Image": "/usr/bin/curl",
+///     "CommandLine": "curl -s -o /tmp/x https://example.com/x",
+/// })));
+/// assert!(!selection.is_match(&json!({"Image": "/usr/bin/wget"})));
+///
+/// // keyword lists
+/// let keywords: Selection = "['mimikatz', 'sekurlsa::']".parse().unwrap();
+/// assert!(keywords.is_match(&json!("invoke-mimikatz")));
+/// ```
 #[derive(Debug, Clone)]
 pub struct Selection {
     items: Vec<MatchType>,
 }
 
+fn field_or_keywords(
+    key: &str,
+    value: &YamlValue,
+) -> Result<MatchType, Box<dyn std::error::Error + Send + Sync>> {
+    match key.strip_prefix('|') {
+        Some(modifiers) => Ok(MatchType::Keywords(Keywords::new(
+            modifiers.split('|'),
+            value,
+        )?)),
+        None => Ok(MatchType::Field(Field::new(key.to_string(), value)?)),
+    }
+}
+
 impl Selection {
-    pub fn new(value: &YamlValue) -> Result<Self, Box<dyn std::error::Error>> {
+    /// the approximate heap size of the compiled selection
+    pub(crate) fn heap_size(&self) -> usize {
+        self.items
+            .iter()
+            .map(|item| {
+                size_of::<MatchType>()
+                    + match item {
+                        MatchType::Field(field) => field.heap_size(),
+                        MatchType::Keywords(keywords) => keywords.heap_size(),
+                    }
+            })
+            .sum()
+    }
+
+    pub fn new(value: &YamlValue) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
         let items: Vec<MatchType> = match value {
-            YamlValue::Sequence(keys) => keys
-                .iter()
-                .map(|key| match key {
-                    YamlValue::String(s) => Ok(vec![MatchType::Exact(s.to_string())]),
-                    YamlValue::Mapping(m) => m
-                        .iter()
-                        .map(|(k, v)| {
-                            let key = k.as_str().ok_or_else(|| "invalid key")?.to_string();
-                            Ok(MatchType::Field(Field::new(key, v)?))
-                        })
-                        .collect::<Result<Vec<MatchType>, Box<dyn std::error::Error>>>(),
-                    _ => Err("invalid selection".into()),
-                })
-                .collect::<Result<Vec<_>, _>>()?
-                .into_iter()
-                .flatten()
-                .collect(),
+            YamlValue::Sequence(keys) => {
+                let (keywords, maps): (Vec<_>, Vec<_>) =
+                    keys.iter().partition(|key| !key.is_mapping());
+
+                let keywords = match keywords.is_empty() {
+                    true => None,
+                    false => Some(MatchType::Keywords(Keywords::new(
+                        std::iter::empty(),
+                        &YamlValue::Sequence(keywords.into_iter().cloned().collect()),
+                    )?)),
+                };
+
+                maps.into_iter()
+                    .map(|key| match key {
+                        YamlValue::Mapping(m) => m
+                            .iter()
+                            .map(|(k, v)| field_or_keywords(k.as_str().ok_or("invalid key")?, v))
+                            .collect::<Result<
+                                Vec<MatchType>,
+                                Box<dyn std::error::Error + Send + Sync>,
+                            >>(),
+                        _ => Err("invalid selection".into()),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?
+                    .into_iter()
+                    .flatten()
+                    .chain(keywords)
+                    .collect()
+            }
 
             YamlValue::Mapping(m) => m
                 .iter()
-                .map(|(k, v)| {
-                    let key = k.as_str().ok_or_else(|| "not a string")?.to_string();
-                    Ok(MatchType::Field(Field::new(key, v)?))
-                })
-                .collect::<Result<Vec<MatchType>, Box<dyn std::error::Error>>>()?,
-            _ => panic!("invalid value type"),
+                .map(|(k, v)| field_or_keywords(k.as_str().ok_or("not a string")?, v))
+                .collect::<Result<Vec<MatchType>, Box<dyn std::error::Error + Send + Sync>>>()?,
+            YamlValue::String(_) => vec![MatchType::Keywords(Keywords::new(
+                std::iter::empty(),
+                value,
+            )?)],
+            _ => Err("invalid selection")?,
         };
         Ok(Selection { items })
     }
 
+    /// a keyword selection with modifiers taken from the selection name
+    /// (e.g. `keywords|re`)
+    pub fn new_keywords(
+        modifiers: &str,
+        value: &YamlValue,
+    ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(Selection {
+            items: vec![MatchType::Keywords(Keywords::new(
+                modifiers.split('|'),
+                value,
+            )?)],
+        })
+    }
+
+    /// the top-level fields that must be present in an event
+    /// for the selection to match
+    pub fn required_fields(&self) -> HashSet<Arc<str>> {
+        self.items
+            .iter()
+            .filter_map(|item| match item {
+                MatchType::Field(f)
+                    if !f
+                        .modifiers
+                        .iter()
+                        .any(|m| matches!(m, Modifier::Exists | Modifier::FieldRef))
+                        && f.path.first().is_some_and(|field| **field != *LOGSOURCE_FIELD) =>
+                {
+                    f.path.first().cloned()
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// rename the fields of the selection, and the fields they
+    /// reference with `fieldref`, to those returned by `map`
+    pub(crate) fn map_fields(&mut self, map: &dyn Fn(&str) -> Option<String>) {
+        for item in &mut self.items {
+            let MatchType::Field(f) = item else {
+                continue;
+            };
+            if let Some(field) = map(&f.path.join(".")) {
+                f.path = field.split('.').map(intern).collect();
+            }
+            if f.modifiers.iter().any(|m| matches!(m, Modifier::FieldRef)) {
+                for value in &mut f.values {
+                    if let Some(field) = value.as_str().and_then(map) {
+                        *value = JsonValue::String(field);
+                    }
+                }
+            }
+        }
+    }
+
+    /// the fields and keyword lists of the selection, all of which
+    /// must match for the selection to match
+    pub fn items(&self) -> impl Iterator<Item = SelectionItem<'_>> {
+        self.items.iter().map(|item| match item {
+            MatchType::Field(f) => SelectionItem::Field {
+                path: &f.path,
+                modifiers: &f.names,
+                values: &f.values,
+            },
+            MatchType::Keywords(k) => SelectionItem::Keywords {
+                modifiers: &k.names,
+                values: &k.values,
+            },
+        })
+    }
+
+    /// match an event with the built-in matching engine and
+    /// Sigma's default (case-insensitive) matching
     pub fn is_match(&self, log: &JsonValue) -> bool {
+        self.is_match_with(log, &MatchOptions::default())
+    }
+
+    /// match an event, values without the `cased` modifier are
+    /// compared case-insensitively unless `case_sensitive` is set
+    pub(crate) fn is_match_with(&self, log: &JsonValue, options: &MatchOptions) -> bool {
         self.items.iter().all(|item| match item {
-            MatchType::Exact(s) => log
-                .as_str()
-                .map(|field| field.contains(s))
-                .unwrap_or_else(|| false),
+            MatchType::Keywords(k) => k.is_match(log, options),
 
             MatchType::Field(f) => {
                 match &f.modifiers.len() {
                     0 => f.values.iter().any(|value| {
-                        match get_terminal_from_dotted_path(&f.key, log) {
-                            /*
-                             * Sigma specifies case-insensitive matching
-                             * and allows wildcards
-                             */
-                            Some(&JsonValue::String(ref logvalue)) => value.as_str().map_or_else(
-                                || false,
-                                |v| {
-                                    if v.starts_with("*") {
-                                        if v.ends_with("*") {
-                                            logvalue
-                                                .to_lowercase()
-                                                .contains(&v[1..v.len() - 1].to_lowercase())
-                                        } else {
-                                            logvalue
-                                                .to_lowercase()
-                                                .ends_with(&v[1..].to_lowercase())
-                                        }
-                                    } else if v.ends_with("*") {
-                                        logvalue
-                                            .to_lowercase()
-                                            .starts_with(&v[..v.len() - 1].to_lowercase())
-                                    } else {
-                                        logvalue.to_lowercase() == v.to_lowercase()
-                                    }
-                                },
-                            ),
-                            Some(&JsonValue::Number(ref logvalue)) => {
-                                value.as_number().map_or_else(|| false, |v| logvalue == v)
-                            }
-                            _ => false,
-                        }
+                        field_value(&f.path, log, options)
+                            .is_some_and(|field| value_eq(field, value, options))
                     }),
 
-                    _ => f.modifiers.iter().all(|modifier| match &f.values.len() {
-                        0 => false,
-                        1 => f
-                            .values
-                            .iter()
-                            .next()
-                            .map_or_else(|| false, |v| modifier.eval(&f.key, v, &log)),
-                        _ => modifier.eval(&f.key, &json!(&f.values), &log),
-                    }),
+                    _ => f.is_modified_match(log, options),
                 }
             }
         })
@@ -0,0 +1,173 @@
+//! Traversal of rule directories
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[cfg(feature = "glob")]
+use glob::{Pattern, PatternError};
+
+/// Selects the files loaded from a directory by
+/// [`SigmaCollection::load_from_dir`]
+///
+/// By default `.yml` and `.yaml` files are loaded from the directory
+/// and all its subdirectories, following symbolic links. Exclude
+/// patterns are [`glob`](https://docs.rs/glob) patterns matched
+/// against paths relative to the directory, an excluded subdirectory
+/// is not traversed. Exclude patterns require the `glob` feature
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use sigmars::{DirOptions, SigmaCollection};
+/// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+/// let options = DirOptions::new()
+///     .extensions(["yml"])
+///     .exclude("deprecated")?
+///     .exclude("**/*_test.yml")?
+///     .max_depth(2)
+///     .follow_symlinks(false);
+/// let rules = SigmaCollection::new().dir_options(options);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`SigmaCollection::load_from_dir`]: struct.SigmaCollection.html#method.load_from_dir
+#[derive(Debug, Clone)]
+pub struct DirOptions {
+    extensions: Vec<String>,
+    #[cfg(feature = "glob")]
+    exclude: Vec<Pattern>,
+    max_depth: Option<usize>,
+    follow_symlinks: bool,
+}
+
+impl Default for DirOptions {
+    fn default() -> Self {
+        DirOptions {
+            extensions: vec!["yml".to_string(), "yaml".to_string()],
+            #[cfg(feature = "glob")]
+            exclude: Vec::new(),
+            max_depth: None,
+            follow_symlinks: true,
+        }
+    }
+}
+
+impl DirOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the file extensions loaded, without the leading `.`,
+    /// compared case-insensitively
+    pub fn extensions<S: Into<String>>(mut self, extensions: impl IntoIterator<Item = S>) -> Self {
+        self.extensions = extensions
+            .into_iter()
+            .map(|ext| ext.into().to_lowercase())
+            .collect();
+        self
+    }
+
+    /// skip the files and directories matching `pattern`
+    #[cfg(feature = "glob")]
+    pub fn exclude(mut self, pattern: &str) -> Result<Self, PatternError> {
+        self.exclude.push(Pattern::new(pattern)?);
+        Ok(self)
+    }
+
+    /// the number of subdirectory levels traversed, `0` only loads
+    /// the files directly in the directory
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// whether symbolically linked files and directories are loaded,
+    /// each directory is traversed once when following links
+    pub fn follow_symlinks(mut self, follow: bool) -> Self {
+        self.follow_symlinks = follow;
+        self
+    }
+
+    /// list the rule files under `dir` with their modification times
+    pub(crate) fn files(
+        &self,
+        dir: &str,
+    ) -> Result<HashMap<PathBuf, SystemTime>, Box<dyn std::error::Error + Send + Sync>> {
+        let root = Path::new(dir);
+        let mut files = HashMap::new();
+        // a missing directory has no rules, its rules are removed on reload
+        if !root.exists() {
+            return Ok(files);
+        }
+        let mut visited = HashSet::new();
+        self.walk(root, root, 0, &mut visited, &mut files)?;
+        Ok(files)
+    }
+
+    fn walk(
+        &self,
+        root: &Path,
+        dir: &Path,
+        depth: usize,
+        visited: &mut HashSet<PathBuf>,
+        files: &mut HashMap<PathBuf, SystemTime>,
+    ) -> std::io::Result<()> {
+        // guards against symbolic link cycles
+        if !visited.insert(dir.canonicalize().map_err(|e| at(dir, e))?) {
+            return Ok(());
+        }
+        for entry in std::fs::read_dir(dir).map_err(|e| at(dir, e))? {
+            let path = entry.map_err(|e| at(dir, e))?.path();
+            let relative = path.strip_prefix(root).unwrap_or(&path);
+            if self.is_excluded(relative) {
+                continue;
+            }
+            let link = std::fs::symlink_metadata(&path)
+                .map_err(|e| at(&path, e))?
+                .file_type()
+                .is_symlink();
+            if link && !self.follow_symlinks {
+                continue;
+            }
+            let metadata = match std::fs::metadata(&path) {
+                Ok(metadata) => metadata,
+                // a dangling symbolic link
+                Err(_) if link => continue,
+                Err(e) => return Err(at(&path, e)),
+            };
+            if metadata.is_dir() {
+                if self.max_depth.is_none_or(|max| depth < max) {
+                    self.walk(root, &path, depth + 1, visited, files)?;
+                }
+            } else if self.has_extension(&path) {
+                let modified = metadata.modified().map_err(|e| at(&path, e))?;
+                files.insert(path, modified);
+            }
+        }
+        Ok(())
+    }
+
+    /// whether `path`, relative to the directory, matches an
+    /// exclude pattern
+    #[cfg(feature = "glob")]
+    fn is_excluded(&self, path: &Path) -> bool {
+        self.exclude.iter().any(|p| p.matches_path(path))
+    }
+
+    #[cfg(not(feature = "glob"))]
+    fn is_excluded(&self, _path: &Path) -> bool {
+        false
+    }
+
+    fn has_extension(&self, path: &Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| self.extensions.contains(&ext.to_lowercase()))
+    }
+}
+
+/// prefix an I/O error with the path it occurred on
+fn at(path: &Path, e: std::io::Error) -> std::io::Error {
+    std::io::Error::new(e.kind(), format!("{}: {}", path.display(), e))
+}
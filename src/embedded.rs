@@ -0,0 +1,3 @@
+//! Sigma rules embedded at build time, see `rules/core/README.md`
+
+include!(concat!(env!("OUT_DIR"), "/core_rules.rs"));
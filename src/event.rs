@@ -1,6 +1,9 @@
+#[cfg(feature = "time")]
+use chrono::{DateTime, NaiveDateTime, Utc};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 
 /// Encapsulates log source information from the Sigma
@@ -9,7 +12,12 @@ use std::collections::HashMap;
 /// implements `From<serde_json::Value>` using
 /// the `category`, `product`, and `service` top level
 /// fields with `String` values (if present)
-#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+///
+/// besides selecting rules, the log source of an event can be
+/// matched like its data with `@logsource.*` fields, e.g.
+/// `@logsource.service: sshd`, the log source an event is
+/// evaluated as (e.g. by `get_detection_matches_for`) being matched
+#[derive(Debug, Serialize, Deserialize, Clone, Default, PartialEq, Eq)]
 pub struct LogSource {
     pub category: Option<String>,
     pub product: Option<String>,
@@ -55,7 +63,7 @@ impl LogSource {
 /// # use std::error::Error;
 /// # use serde_json::{json, Value};
 /// # use sigmars::event::{Event, LogSource};
-/// # fn main() -> Result<(), Box<dyn Error>> {
+/// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
 /// #
 ///  let event: Event = Event::new(json!({"foo": "bar"}))
 ///                     .logsource(LogSource::default()); // logsource is optional
@@ -83,6 +91,12 @@ pub struct Event {
     pub data: Value,
     pub logsource: LogSource,
     pub metadata: HashMap<String, Value>,
+    /// the tenant the event belongs to, correlation state
+    /// is kept separately for each tenant
+    pub tenant: Option<String>,
+    /// placeholder values for the `expand` modifier, taking precedence
+    /// over those of the collection, see [`Event::context`]
+    pub context: Option<Arc<HashMap<String, Value>>>,
 }
 
 impl From<&Value> for LogSource {
@@ -132,6 +146,65 @@ impl Event {
         self.metadata = metadata;
         self
     }
+    pub fn tenant(mut self, tenant: &str) -> Self {
+        self.tenant = Some(tenant.to_string());
+        self
+    }
+
+    /// Set the values of `%name%` placeholders expanded by the `expand`
+    /// modifier when matching this event, e.g. per-tenant lists of
+    /// privileged users
+    ///
+    /// a placeholder is a string or a list of alternatives, and takes
+    /// precedence over one with the same name set with
+    /// [`SigmaCollection::placeholders`]
+    ///
+    /// ```rust
+    /// # use std::{collections::HashMap, sync::Arc};
+    /// # use serde_json::json;
+    /// # use sigmars::{Event, SigmaCollection};
+    /// # static RULES: &str = r#"
+    /// # title: vip logon
+    /// # id: vip-logon
+    /// # logsource:
+    /// #   category: test
+    /// # detection:
+    /// #   selection:
+    /// #     User|expand: '%vip_users%'
+    /// #   condition: selection
+    /// # "#;
+    /// let rules: SigmaCollection = RULES.parse().unwrap();
+    /// let tenant = Arc::new(HashMap::from([(
+    ///     "vip_users".to_string(),
+    ///     json!(["alice", "bob"]),
+    /// )]));
+    /// let event = Event::new(json!({"User": "bob"})).context(tenant);
+    /// assert_eq!(rules.get_detection_matches(&event).len(), 1);
+    /// assert!(rules
+    ///     .get_detection_matches(&Event::new(json!({"User": "bob"})))
+    ///     .is_empty());
+    /// ```
+    ///
+    /// [`SigmaCollection::placeholders`]: struct.SigmaCollection.html#method.placeholders
+    pub fn context(mut self, context: Arc<HashMap<String, Value>>) -> Self {
+        self.context = Some(context);
+        self
+    }
+
+    /// the time of the event, from its `@timestamp`, `timestamp`,
+    /// `time`, `ts`, `UtcTime` or `EventTime` field
+    ///
+    /// ```rust
+    /// # use serde_json::json;
+    /// # use sigmars::Event;
+    /// let event = Event::new(json!({"@timestamp": "2024-06-01T22:30:00Z"}));
+    /// assert_eq!(event.timestamp().unwrap().timestamp(), 1717281000);
+    /// assert!(Event::new(json!({"foo": "bar"})).timestamp().is_none());
+    /// ```
+    #[cfg(feature = "time")]
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        DateTime::from_timestamp_millis(timestamp_millis(&self.data)?)
+    }
 }
 
 impl From<Value> for Event {
@@ -142,3 +215,38 @@ impl From<Value> for Event {
         }
     }
 }
+
+/// fields holding the time of an event, in order of preference
+const TIMESTAMP_FIELDS: &[&str] = &[
+    "@timestamp",
+    "timestamp",
+    "time",
+    "ts",
+    "UtcTime",
+    "EventTime",
+];
+
+/// the time of an event in milliseconds since the Unix epoch, from an
+/// RFC 3339 string, a `YYYY-MM-DD hh:mm:ss` UTC string, or a number of
+/// seconds or milliseconds
+///
+/// strings are only parsed with the `time` feature
+pub(crate) fn timestamp_millis(data: &Value) -> Option<i64> {
+    let value = TIMESTAMP_FIELDS.iter().find_map(|field| data.get(field))?;
+    match value {
+        #[cfg(feature = "time")]
+        Value::String(s) => DateTime::parse_from_rfc3339(s)
+            .map(|time| time.timestamp_millis())
+            .or_else(|_| {
+                NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f")
+                    .map(|time| time.and_utc().timestamp_millis())
+            })
+            .ok(),
+        Value::Number(n) => {
+            let n = n.as_f64()?;
+            // seconds until the year 5138, milliseconds after
+            Some(if n.abs() < 1e11 { n * 1000.0 } else { n } as i64)
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,50 @@
+//! Explanations of the evaluation of detection rules
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::rule::RuleId;
+
+/// Why a detection rule did or did not match an event, see
+/// [`SigmaCollection::explain`]
+///
+/// [`SigmaCollection::explain`]: struct.SigmaCollection.html#method.explain
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RuleExplanation {
+    pub rule: RuleId,
+    pub outcome: RuleOutcome,
+    /// the result of each selection (search identifier) of the rule
+    pub selections: BTreeMap<String, bool>,
+    /// top-level fields the rule requires that the event lacks
+    pub missing_fields: Vec<Arc<str>>,
+}
+
+impl RuleExplanation {
+    /// whether the rule matched the event
+    pub fn matched(&self) -> bool {
+        self.outcome == RuleOutcome::Matched
+    }
+
+    /// the selections that did not match the event
+    pub fn failed_selections(&self) -> impl Iterator<Item = &str> {
+        self.selections
+            .iter()
+            .filter(|(_, matched)| !**matched)
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// The outcome of a detection rule for an event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum RuleOutcome {
+    Matched,
+    /// the selection results do not satisfy the condition, or the
+    /// event lacks a required field
+    NotMatched,
+    /// the event is outside the schedule of the rule
+    NotScheduled,
+    /// the match is suppressed by the suppression with this ID
+    Suppressed(u64),
+}
@@ -0,0 +1,36 @@
+//! Event fields inspected by rules
+
+use std::collections::BTreeSet;
+
+use serde::Serialize;
+
+/// The event fields inspected by rules, see
+/// [`SigmaCollection::referenced_fields`]
+///
+/// [`SigmaCollection::referenced_fields`]: struct.SigmaCollection.html#method.referenced_fields
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize)]
+#[non_exhaustive]
+pub struct ReferencedFields {
+    /// dotted field names, after field mappings
+    pub fields: BTreeSet<String>,
+    /// whether a rule has keywords, searched in every field
+    pub keywords: bool,
+}
+
+impl ReferencedFields {
+    /// whether an event field, or a field nested in it, is inspected
+    ///
+    /// every field is inspected by keywords
+    pub fn contains(&self, field: &str) -> bool {
+        self.keywords
+            || self
+                .fields
+                .iter()
+                .any(|f| f == field || (f.starts_with(field) && f[field.len()..].starts_with('.')))
+    }
+
+    pub(crate) fn extend(&mut self, other: ReferencedFields) {
+        self.fields.extend(other.fields);
+        self.keywords |= other.keywords;
+    }
+}
@@ -0,0 +1,81 @@
+//! Estimates of the memory used by a collection
+
+use std::collections::HashMap;
+use std::mem::size_of;
+
+use serde::Serialize;
+
+/// The estimated memory used by a [`SigmaCollection`], in bytes
+///
+/// estimates count the heap allocations of rules and indexes, not
+/// allocator overhead, and compiled regexes by their pattern length
+///
+/// ```rust
+/// # use sigmars::SigmaCollection;
+/// # static RULES: &str = r#"
+/// # title: test rule
+/// # id: test-rule
+/// # logsource:
+/// #   category: test
+/// # detection:
+/// #   selection:
+/// #     foo: bar
+/// #   condition: selection
+/// # "#;
+/// # #[tokio::main]
+/// # async fn main() {
+/// let rules: SigmaCollection = RULES.parse().unwrap();
+/// let footprint = rules.memory_footprint().await;
+/// assert!(footprint.rules > 0);
+/// assert_eq!(footprint.total(), footprint.rules + footprint.indexes);
+/// # }
+/// ```
+///
+/// [`SigmaCollection`]: struct.SigmaCollection.html
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MemoryFootprint {
+    /// rules, with their compiled detections and raw YAML
+    pub rules: usize,
+    /// log source filters, field indexes and the dependency graph
+    pub indexes: usize,
+    /// the state of correlation rules, `None` if their backend does
+    /// not report it, see [`RuleState::memory_usage`]
+    ///
+    /// [`RuleState::memory_usage`]: trait.RuleState.html#method.memory_usage
+    pub correlation_state: Option<usize>,
+}
+
+impl MemoryFootprint {
+    pub fn total(&self) -> usize {
+        self.rules + self.indexes + self.correlation_state.unwrap_or(0)
+    }
+}
+
+/// the approximate size of the entries of a hash map (or set, with
+/// `V = ()`), excluding the heap allocations of the keys and values
+pub(crate) fn entries<K, V>(len: usize) -> usize {
+    // hashbrown allocates a control byte per bucket
+    len * (size_of::<K>() + size_of::<V>() + 1)
+}
+
+/// the approximate size of a hash map and of the strings it holds
+pub(crate) fn strings<V>(map: &HashMap<String, V>) -> usize {
+    entries::<String, V>(map.len()) + map.keys().map(String::len).sum::<usize>()
+}
+
+/// the approximate heap size of a JSON value
+pub(crate) fn json(value: &serde_json::Value) -> usize {
+    use serde_json::Value;
+    match value {
+        Value::String(s) => s.len(),
+        Value::Array(values) => values
+            .iter()
+            .map(|v| size_of::<Value>() + json(v))
+            .sum(),
+        Value::Object(map) => map
+            .iter()
+            .map(|(k, v)| size_of::<String>() + k.len() + size_of::<Value>() + json(v))
+            .sum(),
+        _ => 0,
+    }
+}
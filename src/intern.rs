@@ -0,0 +1,75 @@
+//! A global string interner for field names shared across rules
+//!
+//! strings are held weakly, so those no longer used by any rule are
+//! freed and pruned as the interner grows
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::sync::{Arc, Mutex, Weak};
+
+/// entries below which the interner is not pruned
+const MIN_PRUNE: usize = 1024;
+
+lazy_static::lazy_static! {
+    static ref INTERNED: Mutex<Interner> = Mutex::new(Interner::default());
+}
+
+/// return a shared handle for a string, allocating it only on first use
+pub(crate) fn intern(s: &str) -> Arc<str> {
+    INTERNED.lock().unwrap_or_else(|e| e.into_inner()).intern(s)
+}
+
+#[derive(Default)]
+pub(crate) struct Interner {
+    hasher: RandomState,
+    /// the strings by hash
+    strings: HashMap<u64, Vec<Weak<str>>>,
+    /// entries, including those of freed strings
+    len: usize,
+    /// entries above which freed strings are pruned
+    prune_at: usize,
+}
+
+impl Interner {
+    /// return a shared handle for a string, allocating it unless one
+    /// is still in use
+    pub(crate) fn intern(&mut self, s: &str) -> Arc<str> {
+        let hash = self.hasher.hash_one(s);
+        if let Some(existing) = self
+            .strings
+            .get(&hash)
+            .into_iter()
+            .flatten()
+            .filter_map(Weak::upgrade)
+            .find(|existing| **existing == *s)
+        {
+            return existing;
+        }
+        if self.len >= self.prune_at {
+            self.prune();
+        }
+        let new: Arc<str> = Arc::from(s);
+        self.strings.entry(hash).or_default().push(Arc::downgrade(&new));
+        self.len += 1;
+        new
+    }
+
+    /// the entries of the interner, including those of freed strings
+    /// not yet pruned
+    #[cfg(test)]
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// drop the entries of freed strings, pruning again once the
+    /// remaining entries have doubled
+    fn prune(&mut self) {
+        self.strings.retain(|_, strings| {
+            strings.retain(|s| s.strong_count() > 0);
+            !strings.is_empty()
+        });
+        self.len = self.strings.values().map(Vec::len).sum();
+        self.prune_at = (self.len * 2).max(MIN_PRUNE);
+    }
+}
@@ -0,0 +1,81 @@
+//! Extraction of `key=value` pairs embedded in strings
+
+use serde_json::Value;
+
+use crate::detection::get_terminal_from_dotted_path;
+use crate::event::Event;
+
+/// a `key=value` pair, with the quote of a quoted value
+pub(crate) type Pair<'a> = (&'a str, &'a str, Option<char>);
+
+/// the `key=value` pairs of `s`, separated by characters for which
+/// `delimiter` is true
+///
+/// values may be double or single quoted, text that is not part of a
+/// pair (e.g. a free-form message around the pairs) is skipped
+pub(crate) fn pairs(s: &str, delimiter: fn(char) -> bool) -> Vec<Pair<'_>> {
+    let mut pairs = vec![];
+    let mut rest = s.trim_start_matches(delimiter);
+    while !rest.is_empty() {
+        let end = rest
+            .find(|c| delimiter(c) || c == '=')
+            .unwrap_or(rest.len());
+        let (key, after) = rest.split_at(end);
+        let Some(value) = after.strip_prefix('=') else {
+            rest = after.trim_start_matches(delimiter);
+            continue;
+        };
+        let (value, quote, next) = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => match value[1..].split_once(quote) {
+                Some((value, next)) => (value, Some(quote), next),
+                None => (&value[1..], Some(quote), ""),
+            },
+            _ => {
+                let end = value.find(delimiter).unwrap_or(value.len());
+                (&value[..end], None, &value[end..])
+            }
+        };
+        if !key.is_empty() {
+            pairs.push((key, value, quote));
+        }
+        rest = next.trim_start_matches(delimiter);
+    }
+    pairs
+}
+
+impl Event {
+    /// Add the `key=value` pairs of the string field `field` to the
+    /// event data, so rules can read them as fields
+    ///
+    /// pairs are separated by whitespace, `,` or `;` and values may be
+    /// quoted. Fields already in the event are not replaced, so a
+    /// message cannot override them
+    ///
+    /// ```rust
+    /// # use serde_json::json;
+    /// # use sigmars::Event;
+    /// let event = Event::new(json!({
+    ///     "message": "login failed user=admin src=\"10.0.0.1\", reason='bad password'",
+    ///     "host": "web-1",
+    /// }))
+    /// .extract_kv("message");
+    /// assert_eq!(event.data["user"], "admin");
+    /// assert_eq!(event.data["src"], "10.0.0.1");
+    /// assert_eq!(event.data["reason"], "bad password");
+    /// ```
+    pub fn extract_kv(mut self, field: &str) -> Self {
+        let Some(Value::String(s)) = get_terminal_from_dotted_path(field, &self.data) else {
+            return self;
+        };
+        let pairs = pairs(s, |c| c.is_whitespace() || c == ',' || c == ';')
+            .into_iter()
+            .map(|(key, value, _)| (key.to_string(), Value::from(value)))
+            .collect::<Vec<_>>();
+        if let Value::Object(ref mut data) = self.data {
+            for (key, value) in pairs {
+                data.entry(key).or_insert(value);
+            }
+        }
+        self
+    }
+}
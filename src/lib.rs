@@ -5,26 +5,106 @@
 //!
 //! [`Sigma`]: https://sigmahq.io/
 //!
+#[cfg(feature = "arrow")]
+mod arrow;
+mod auditd;
+mod builder;
+mod cloudtrail;
 mod collection;
+mod dedup;
 mod detection;
+mod dir_options;
+#[cfg(feature = "embedded_rules")]
+mod embedded;
+mod explain;
+mod fields;
+mod footprint;
+mod intern;
+mod kv;
+mod limits;
+mod load_filter;
+mod matches;
+mod metrics;
+mod network;
+mod observer;
+#[cfg(feature = "otel")]
+mod otel;
+mod overlap;
+mod overrides;
+mod pipeline;
+#[cfg(feature = "python")]
+mod python;
+mod replay;
+#[cfg(feature = "time")]
+mod schedule;
+mod schema;
+mod shared;
+#[cfg(feature = "signing")]
+mod signing;
+mod suppression;
+mod telemetry;
+mod template;
+mod warnings;
 
+#[cfg(feature = "blocking")]
+pub mod blocking;
 pub mod event;
+pub mod matcher;
 pub mod rule;
+pub mod rule_tests;
+#[cfg(any(feature = "webhook", feature = "kafka", feature = "syslog"))]
+pub mod sinks;
+pub mod testing;
+#[cfg(feature = "updater")]
+pub mod updater;
 
 #[doc(hidden)]
 #[cfg(feature = "correlation")]
 pub mod correlation;
 
-pub use collection::SigmaCollection;
+pub use auditd::AuditdParser;
+pub use builder::SigmaCollectionBuilder;
+pub use collection::{CollectionError, Evaluation, SigmaCollection};
+pub use dedup::Dedup;
+pub use detection::{Condition, KeywordOptions, Selection};
+pub use dir_options::DirOptions;
 pub use event::Event;
+pub use explain::{RuleExplanation, RuleOutcome};
+pub use fields::ReferencedFields;
+pub use footprint::MemoryFootprint;
+pub use limits::ParseLimits;
+pub use load_filter::LoadFilter;
+pub use matches::Matches;
+pub use observer::Match;
+pub use overlap::{OverlapKind, RuleOverlap};
+pub use overrides::Overrides;
+pub use pipeline::Pipeline;
+pub use replay::LineMatch;
 pub use rule::SigmaRule;
+pub use rule_tests::RuleTestResult;
+#[cfg(feature = "time")]
+pub use schedule::Schedule;
+pub use shared::SharedSigmaCollection;
+pub use suppression::{Suppression, SuppressionEntry, SuppressionStore};
+pub use telemetry::TelemetrySnapshot;
+pub use template::{AlertTemplate, TemplateError};
+pub use warnings::RuleWarning;
 
+#[cfg(feature = "signing")]
+pub use minisign_verify::PublicKey;
+
+#[cfg(feature = "correlation")]
+pub use collection::DependencyReport;
 #[cfg(feature = "correlation")]
 pub use correlation::Backend;
 #[cfg(feature = "correlation")]
+pub use correlation::custom::{CorrelationHandler, CustomCorrelation};
+#[cfg(feature = "correlation")]
 pub use correlation::RuleState;
+#[cfg(feature = "correlation")]
+pub use correlation::throttle::Throttle;
 #[cfg(feature = "mem_backend")]
-pub use correlation::state::mem::MemBackend;
+pub use correlation::state::mem::{MemBackend, StateSnapshot};
 
 #[cfg(test)]
 mod tests;
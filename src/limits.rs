@@ -0,0 +1,150 @@
+//! Resource limits enforced while parsing untrusted rules
+
+use std::cell::Cell;
+
+use thiserror::Error;
+
+/// Limits on the size of parsed rules
+///
+/// The default is unlimited, [`ParseLimits::untrusted`] is a
+/// conservative preset for services that accept user-supplied rules
+///
+/// ```rust
+/// # use sigmars::{ParseLimits, SigmaCollection};
+/// let mut rules = SigmaCollection::new().limits(ParseLimits {
+///     max_selections: 2,
+///     ..ParseLimits::untrusted()
+/// });
+/// let err = rules.load_from_str(r#"
+/// title: too many selections
+/// id: too-many-selections
+/// logsource:
+///   category: test
+/// detection:
+///   a:
+///     foo: bar
+///   b:
+///     foo: baz
+///   c:
+///     foo: quux
+///   condition: a or b or c
+/// "#);
+/// assert!(err.unwrap_err().to_string().contains("too many selections"));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseLimits {
+    /// selections (search identifiers) per detection
+    pub max_selections: usize,
+    /// values per field or keyword list
+    pub max_values: usize,
+    /// length of a condition in bytes
+    pub max_condition_length: usize,
+    /// parenthesis nesting depth of a condition
+    pub max_nesting_depth: usize,
+}
+
+impl ParseLimits {
+    /// no limits
+    pub const UNLIMITED: ParseLimits = ParseLimits {
+        max_selections: usize::MAX,
+        max_values: usize::MAX,
+        max_condition_length: usize::MAX,
+        max_nesting_depth: usize::MAX,
+    };
+
+    /// limits generous enough for the SigmaHQ ruleset
+    pub fn untrusted() -> Self {
+        ParseLimits {
+            max_selections: 64,
+            max_values: 1024,
+            max_condition_length: 4096,
+            max_nesting_depth: 32,
+        }
+    }
+}
+
+impl Default for ParseLimits {
+    fn default() -> Self {
+        Self::UNLIMITED
+    }
+}
+
+/// a rule exceeding a [`ParseLimits`] limit
+#[derive(Error, Debug)]
+pub(crate) enum LimitExceeded {
+    #[error("too many selections: {0} (limit {1})")]
+    Selections(usize, usize),
+    #[error("too many values: {0} (limit {1})")]
+    Values(usize, usize),
+    #[error("condition too long: {0} bytes (limit {1})")]
+    ConditionLength(usize, usize),
+    #[error("condition nested too deeply: {0} levels (limit {1})")]
+    NestingDepth(usize, usize),
+}
+
+thread_local! {
+    static LIMITS: Cell<ParseLimits> = const { Cell::new(ParseLimits::UNLIMITED) };
+}
+
+/// run `f` with `limits` applied to rules parsed on this thread
+pub(crate) fn with<T>(limits: ParseLimits, f: impl FnOnce() -> T) -> T {
+    let previous = LIMITS.with(|l| l.replace(limits));
+    let result = f();
+    LIMITS.with(|l| l.set(previous));
+    result
+}
+
+/// run `f`, turning a panic into an error
+///
+/// the YAML scanner panics on some malformed input instead of failing,
+/// rules parsed from untrusted strings must not bring down the caller
+pub(crate) fn catch_panics<T, E: serde::de::Error>(
+    f: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E> {
+    std::panic::catch_unwind(std::panic::AssertUnwindSafe(f))
+        .unwrap_or_else(|_| Err(E::custom("invalid YAML")))
+}
+
+pub(crate) fn check_selections(n: usize) -> Result<(), LimitExceeded> {
+    let max = LIMITS.with(|l| l.get().max_selections);
+    match n > max {
+        true => Err(LimitExceeded::Selections(n, max)),
+        false => Ok(()),
+    }
+}
+
+pub(crate) fn check_values(n: usize) -> Result<(), LimitExceeded> {
+    let max = LIMITS.with(|l| l.get().max_values);
+    match n > max {
+        true => Err(LimitExceeded::Values(n, max)),
+        false => Ok(()),
+    }
+}
+
+/// check the length and nesting depth of a condition before parsing it
+pub(crate) fn check_condition(condition: &str) -> Result<(), LimitExceeded> {
+    let limits = LIMITS.with(|l| l.get());
+    if condition.len() > limits.max_condition_length {
+        return Err(LimitExceeded::ConditionLength(
+            condition.len(),
+            limits.max_condition_length,
+        ));
+    }
+
+    let depth = condition
+        .chars()
+        .scan(0usize, |depth, c| {
+            match c {
+                '(' => *depth += 1,
+                ')' => *depth = depth.saturating_sub(1),
+                _ => (),
+            }
+            Some(*depth)
+        })
+        .max()
+        .unwrap_or(0);
+    match depth > limits.max_nesting_depth {
+        true => Err(LimitExceeded::NestingDepth(depth, limits.max_nesting_depth)),
+        false => Ok(()),
+    }
+}
@@ -0,0 +1,169 @@
+//! Selection of the rules to load from large rule repositories
+
+use std::collections::HashSet;
+
+#[cfg(feature = "glob")]
+use glob::{Pattern, PatternError};
+
+use crate::rule::{SigmaRule, Source, Status};
+
+/// Selects the rules loaded into a collection by ID, tag, file path
+/// or status
+///
+/// If any `include_*` criteria are set, a rule must match at least one
+/// of them to be loaded. A rule matching any `exclude_*` criterion is
+/// not loaded. IDs and tags are compared case-insensitively, path
+/// patterns are [`glob`](https://docs.rs/glob) patterns matched against
+/// the full path of the file a rule is loaded from, and require the
+/// `glob` feature
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use sigmars::{LoadFilter, SigmaCollection};
+/// static RULES: &str = r#"
+/// title: test rule
+/// id: test-rule
+/// status: deprecated
+/// logsource:
+///   category: test
+/// detection:
+///   selection:
+///     foo: bar
+///   condition: selection
+/// "#;
+///
+/// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+/// let filter = LoadFilter::new()
+///     .exclude_inactive()
+///     .exclude_status("experimental")
+///     .exclude_path("**/deprecated/**")?;
+/// let mut rules = SigmaCollection::new().load_filter(filter);
+/// assert_eq!(rules.load_from_str(RULES)?, 0);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct LoadFilter {
+    include_ids: HashSet<String>,
+    exclude_ids: HashSet<String>,
+    include_tags: HashSet<String>,
+    exclude_tags: HashSet<String>,
+    #[cfg(feature = "glob")]
+    include_paths: Vec<Pattern>,
+    #[cfg(feature = "glob")]
+    exclude_paths: Vec<Pattern>,
+    include_status: HashSet<Status>,
+    exclude_status: HashSet<Status>,
+}
+
+impl LoadFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn include_id(mut self, id: &str) -> Self {
+        self.include_ids.insert(id.to_lowercase());
+        self
+    }
+
+    pub fn exclude_id(mut self, id: &str) -> Self {
+        self.exclude_ids.insert(id.to_lowercase());
+        self
+    }
+
+    pub fn include_tag(mut self, tag: &str) -> Self {
+        self.include_tags.insert(tag.to_lowercase());
+        self
+    }
+
+    pub fn exclude_tag(mut self, tag: &str) -> Self {
+        self.exclude_tags.insert(tag.to_lowercase());
+        self
+    }
+
+    #[cfg(feature = "glob")]
+    pub fn include_path(mut self, pattern: &str) -> Result<Self, PatternError> {
+        self.include_paths.push(Pattern::new(pattern)?);
+        Ok(self)
+    }
+
+    #[cfg(feature = "glob")]
+    pub fn exclude_path(mut self, pattern: &str) -> Result<Self, PatternError> {
+        self.exclude_paths.push(Pattern::new(pattern)?);
+        Ok(self)
+    }
+
+    pub fn include_status(mut self, status: impl Into<Status>) -> Self {
+        self.include_status.insert(status.into());
+        self
+    }
+
+    pub fn exclude_status(mut self, status: impl Into<Status>) -> Self {
+        self.exclude_status.insert(status.into());
+        self
+    }
+
+    /// exclude `deprecated` and `unsupported` rules, which are rarely
+    /// wanted in production
+    pub fn exclude_inactive(self) -> Self {
+        self.exclude_status(Status::Deprecated)
+            .exclude_status(Status::Unsupported)
+    }
+
+    fn includes(&self) -> bool {
+        !(self.include_ids.is_empty()
+            && self.include_tags.is_empty()
+            && self.paths(true).is_empty()
+            && self.include_status.is_empty())
+    }
+
+    /// the include or exclude path patterns
+    #[cfg(feature = "glob")]
+    fn paths(&self, include: bool) -> &[Pattern] {
+        match include {
+            true => &self.include_paths,
+            false => &self.exclude_paths,
+        }
+    }
+
+    #[cfg(not(feature = "glob"))]
+    fn paths(&self, _include: bool) -> &[Pattern] {
+        &[]
+    }
+
+    /// whether `rule` matches any of the include or exclude ID, tag,
+    /// path or status criteria
+    fn matches(&self, rule: &SigmaRule, include: bool) -> bool {
+        let (ids, tags, status) = match include {
+            true => (&self.include_ids, &self.include_tags, &self.include_status),
+            false => (&self.exclude_ids, &self.exclude_tags, &self.exclude_status),
+        };
+        ids.contains(&rule.id.to_lowercase())
+            || rule
+                .tags
+                .iter()
+                .flatten()
+                .any(|tag| tags.contains(&tag.as_str().to_lowercase()))
+            || match rule.source {
+                Source::File(ref path) => self.paths(include).iter().any(|p| p.matches_path(path)),
+                _ => false,
+            }
+            || rule.status.as_ref().is_some_and(|s| status.contains(s))
+    }
+
+    /// whether `rule` should be loaded
+    pub(crate) fn allows(&self, rule: &SigmaRule) -> bool {
+        (!self.includes() || self.matches(rule, true)) && !self.matches(rule, false)
+    }
+}
+
+/// path patterns require the `glob` feature
+#[cfg(not(feature = "glob"))]
+enum Pattern {}
+
+#[cfg(not(feature = "glob"))]
+impl Pattern {
+    fn matches_path(&self, _path: &std::path::Path) -> bool {
+        match *self {}
+    }
+}
@@ -0,0 +1,57 @@
+//! Pluggable selection evaluation
+//!
+//! Rules are parsed and their conditions evaluated by sigmars, while
+//! each selection (search identifier) can be evaluated by a custom
+//! [`Matcher`], e.g. a vectorized matcher or one backed by an
+//! external index
+//!
+//! ```rust
+//! # use std::error::Error;
+//! # use std::sync::Arc;
+//! # use serde_json::{json, Value};
+//! # use sigmars::{Event, SigmaCollection};
+//! use sigmars::matcher::{Matcher, Selection};
+//!
+//! /// counts evaluations, delegating to the built-in engine
+//! #[derive(Debug, Default)]
+//! struct Counting(std::sync::atomic::AtomicUsize);
+//!
+//! impl Matcher for Counting {
+//!     fn is_match(&self, selection: &Selection, event: &Value) -> bool {
+//!         self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+//!         selection.is_match(event)
+//!     }
+//! }
+//!
+//! # static RULES: &str = r#"
+//! # title: test rule
+//! # id: test-rule
+//! # logsource:
+//! #   category: test
+//! # detection:
+//! #   selection:
+//! #     foo: bar
+//! #   condition: selection
+//! # "#;
+//! # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+//! let matcher = Arc::new(Counting::default());
+//! let rules = RULES.parse::<SigmaCollection>()?.matcher(matcher.clone());
+//! assert!(rules.get_detection_matches(&Event::new(json!({"foo": "bar"}))).len() == 1);
+//! assert!(matcher.0.load(std::sync::atomic::Ordering::Relaxed) == 1);
+//! # Ok(())
+//! # }
+//! ```
+use std::fmt::Debug;
+
+use serde_json::Value;
+
+pub use crate::detection::{Selection, SelectionItem};
+
+/// Evaluates a compiled [`Selection`] against an event
+///
+/// The structure of the selection is available through
+/// [`Selection::items`], and [`Selection::is_match`] evaluates
+/// it with the built-in engine
+pub trait Matcher: Debug + Send + Sync {
+    fn is_match(&self, selection: &Selection, event: &Value) -> bool;
+}
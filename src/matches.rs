@@ -0,0 +1,159 @@
+//! The rules matching an event
+
+use std::ops::Index;
+
+use serde::{Serialize, Serializer};
+
+use crate::rule::RuleId;
+
+/// The IDs of the rules matching an event, detection rules first,
+/// then correlation rules in dependency order
+///
+/// serialized as a list of rule IDs
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use serde_json::json;
+/// # use sigmars::{Event, MemBackend, SigmaCollection};
+/// static RULES: &str = r#"
+/// title: test rule
+/// id: test-rule
+/// logsource:
+///   category: test
+/// detection:
+///   selection:
+///     foo: bar
+///   condition: selection
+/// ---
+/// title: repeated test rule
+/// id: repeated-test-rule
+/// correlation:
+///   type: event_count
+///   rules:
+///     - test-rule
+///   group-by:
+///     - user
+///   timespan: 1m
+///   condition:
+///     gte: 2
+/// "#;
+///
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+/// let mut rules: SigmaCollection = RULES.parse()?;
+/// let mut backend = MemBackend::new().await;
+/// rules.init(&mut backend).await;
+///
+/// let event = Event::new(json!({"foo": "bar", "user": "root"}));
+/// rules.get_matches(&event).await?;
+/// let matches = rules.get_matches(&event).await?;
+/// assert_eq!(matches.len(), 2);
+/// assert!(matches.contains("repeated-test-rule"));
+/// assert_eq!(matches.detections(), ["test-rule".into()]);
+/// assert_eq!(matches.correlations(), ["repeated-test-rule".into()]);
+/// assert_eq!(
+///     serde_json::to_value(&matches)?,
+///     json!(["test-rule", "repeated-test-rule"])
+/// );
+/// for id in matches {
+///     println!("{}", id);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Matches {
+    rules: Vec<RuleId>,
+    /// the number of detection rules at the start of `rules`
+    detections: usize,
+}
+
+impl Matches {
+    /// `rules`, of which the first `detections` are detection rules
+    pub(crate) fn new(rules: Vec<RuleId>, detections: usize) -> Self {
+        Matches {
+            detections: detections.min(rules.len()),
+            rules,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// whether the rule with ID `rule_id` matches
+    pub fn contains(&self, rule_id: &str) -> bool {
+        self.rules.iter().any(|id| &**id == rule_id)
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, RuleId> {
+        self.rules.iter()
+    }
+
+    /// the matching detection rules
+    pub fn detections(&self) -> &[RuleId] {
+        &self.rules[..self.detections]
+    }
+
+    /// the matching correlation rules
+    pub fn correlations(&self) -> &[RuleId] {
+        &self.rules[self.detections..]
+    }
+
+    /// the IDs of all matching rules
+    pub fn as_slice(&self) -> &[RuleId] {
+        &self.rules
+    }
+
+    pub fn into_vec(self) -> Vec<RuleId> {
+        self.rules
+    }
+}
+
+impl IntoIterator for Matches {
+    type Item = RuleId;
+    type IntoIter = std::vec::IntoIter<RuleId>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.rules.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Matches {
+    type Item = &'a RuleId;
+    type IntoIter = std::slice::Iter<'a, RuleId>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.rules.iter()
+    }
+}
+
+impl Index<usize> for Matches {
+    type Output = RuleId;
+
+    fn index(&self, index: usize) -> &RuleId {
+        &self.rules[index]
+    }
+}
+
+impl From<Matches> for Vec<RuleId> {
+    fn from(matches: Matches) -> Self {
+        matches.rules
+    }
+}
+
+impl PartialEq<Vec<RuleId>> for Matches {
+    fn eq(&self, other: &Vec<RuleId>) -> bool {
+        &self.rules == other
+    }
+}
+
+impl Serialize for Matches {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.rules.serialize(serializer)
+    }
+}
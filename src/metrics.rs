@@ -0,0 +1,102 @@
+//! Metrics reported through the [`metrics`](https://crates.io/crates/metrics)
+//! facade when the `metrics` feature is enabled
+//!
+//! Install any `metrics` recorder (e.g. `metrics-exporter-prometheus`)
+//! in the host application to export them:
+//!
+//! - `sigmars_events_evaluated_total` (counter): events evaluated
+//! - `sigmars_rule_matches_total` (counter, `rule_id` label): matches per rule
+//! - `sigmars_detection_duration_seconds` (histogram): detection evaluation latency
+//! - `sigmars_correlation_duration_seconds` (histogram): correlation evaluation latency
+//! - `sigmars_rules_not_evaluated_total` (counter): rules skipped after an evaluation timeout
+//! - `sigmars_matches_suppressed_total` (counter, `rule_id` label): matches suppressed
+//! - `sigmars_matches_deduplicated_total` (counter, `rule_id` label): duplicate matches dropped
+//! - `sigmars_matches_throttled_total` (counter, `rule_id` label): matches throttled
+//! - `sigmars_correlation_state_keys` (gauge): keys held by the in-memory backend
+//!
+//! Without the feature these are no-ops
+
+use std::time::Instant;
+
+use crate::rule::RuleId;
+
+#[cfg(feature = "metrics")]
+pub(crate) fn detection_evaluated(start: Instant, matches: &[RuleId]) {
+    ::metrics::counter!("sigmars_events_evaluated_total").increment(1);
+    ::metrics::histogram!("sigmars_detection_duration_seconds")
+        .record(start.elapsed().as_secs_f64());
+    rule_matched(matches);
+}
+
+#[cfg(all(feature = "metrics", feature = "correlation"))]
+pub(crate) fn correlation_evaluated(start: Instant, matches: &[RuleId]) {
+    ::metrics::histogram!("sigmars_correlation_duration_seconds")
+        .record(start.elapsed().as_secs_f64());
+    rule_matched(matches);
+}
+
+#[cfg(feature = "metrics")]
+fn rule_matched(matches: &[RuleId]) {
+    matches.iter().for_each(|id| {
+        ::metrics::counter!("sigmars_rule_matches_total", "rule_id" => id.to_string()).increment(1)
+    });
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn rules_not_evaluated(skipped: &[RuleId]) {
+    if !skipped.is_empty() {
+        ::metrics::counter!("sigmars_rules_not_evaluated_total").increment(skipped.len() as u64);
+    }
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn match_suppressed(rule_id: &RuleId) {
+    ::metrics::counter!("sigmars_matches_suppressed_total", "rule_id" => rule_id.to_string())
+        .increment(1);
+}
+
+#[cfg(feature = "metrics")]
+pub(crate) fn match_deduplicated(rule_id: &RuleId) {
+    ::metrics::counter!("sigmars_matches_deduplicated_total", "rule_id" => rule_id.to_string())
+        .increment(1);
+}
+
+#[cfg(all(feature = "metrics", feature = "correlation"))]
+pub(crate) fn match_throttled(rule_id: &RuleId) {
+    ::metrics::counter!("sigmars_matches_throttled_total", "rule_id" => rule_id.to_string())
+        .increment(1);
+}
+
+#[cfg(all(feature = "metrics", feature = "mem_backend"))]
+pub(crate) fn state_keys_added(n: usize) {
+    ::metrics::gauge!("sigmars_correlation_state_keys").increment(n as f64);
+}
+
+#[cfg(all(feature = "metrics", feature = "mem_backend"))]
+pub(crate) fn state_keys_removed(n: usize) {
+    ::metrics::gauge!("sigmars_correlation_state_keys").decrement(n as f64);
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn detection_evaluated(_: Instant, _: &[RuleId]) {}
+
+#[cfg(all(not(feature = "metrics"), feature = "correlation"))]
+pub(crate) fn correlation_evaluated(_: Instant, _: &[RuleId]) {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn rules_not_evaluated(_: &[RuleId]) {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn match_suppressed(_: &RuleId) {}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn match_deduplicated(_: &RuleId) {}
+
+#[cfg(all(not(feature = "metrics"), feature = "correlation"))]
+pub(crate) fn match_throttled(_: &RuleId) {}
+
+#[cfg(all(not(feature = "metrics"), feature = "mem_backend"))]
+pub(crate) fn state_keys_added(_: usize) {}
+
+#[cfg(all(not(feature = "metrics"), feature = "mem_backend"))]
+pub(crate) fn state_keys_removed(_: usize) {}
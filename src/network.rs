@@ -0,0 +1,101 @@
+//! Conversion of network sensor output (Suricata EVE, Zeek JSON logs)
+//! into events, see [`Pipeline::suricata_eve`] and [`Pipeline::zeek`]
+//!
+//! [`Pipeline::suricata_eve`]: ../struct.Pipeline.html#method.suricata_eve
+//! [`Pipeline::zeek`]: ../struct.Pipeline.html#method.zeek
+
+use serde_json::{Map, Value};
+
+use crate::event::{Event, LogSource};
+
+/// Sigma categories of Suricata EVE event types
+const SURICATA_EVENT_TYPES: &[(&str, &str)] = &[
+    ("dns", "dns"),
+    ("http", "proxy"),
+    ("flow", "firewall"),
+    ("netflow", "firewall"),
+];
+
+/// Sigma categories of Zeek logs
+const ZEEK_LOGS: &[(&str, &str)] = &[("dns", "dns"), ("http", "proxy"), ("conn", "firewall")];
+
+impl Event {
+    /// An event from a Suricata EVE record, with the `suricata`
+    /// product and the Sigma category of its `event_type`
+    /// (`dns`, `proxy` for `http`, `firewall` for `flow` and `netflow`)
+    ///
+    /// ```rust
+    /// # use serde_json::json;
+    /// # use sigmars::Event;
+    /// let event = Event::from_suricata_eve(json!({
+    ///     "event_type": "dns",
+    ///     "dns": {"type": "query", "rrname": "example.com", "rrtype": "A"},
+    /// }));
+    /// assert_eq!(event.logsource.category.as_deref(), Some("dns"));
+    /// ```
+    pub fn from_suricata_eve(data: Value) -> Self {
+        let category = category(&data, "event_type", SURICATA_EVENT_TYPES);
+        let logsource = LogSource::new(category, Some("suricata".to_string()), None);
+        Event::new(data).logsource(logsource)
+    }
+
+    /// An event from a record of a Zeek JSON log, with the `zeek`
+    /// product, the log (`_path`) as service, and its Sigma category
+    /// (`dns`, `proxy` for `http`, `firewall` for `conn`)
+    ///
+    /// Zeek writes fields such as `id.orig_h` with dotted names, they
+    /// are nested (`{"id": {"orig_h": ..}}`) to be read as dotted fields
+    ///
+    /// ```rust
+    /// # use serde_json::json;
+    /// # use sigmars::Event;
+    /// let event = Event::from_zeek(json!({"_path": "conn", "id.orig_h": "10.0.0.1"}));
+    /// assert_eq!(event.data["id"]["orig_h"], "10.0.0.1");
+    /// assert_eq!(event.logsource.category.as_deref(), Some("firewall"));
+    /// ```
+    pub fn from_zeek(data: Value) -> Self {
+        let category = category(&data, "_path", ZEEK_LOGS);
+        let service = data
+            .get("_path")
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        let data = match data {
+            Value::Object(fields) => Value::Object(nest(fields)),
+            data => data,
+        };
+        let logsource = LogSource::new(category, Some("zeek".to_string()), service);
+        Event::new(data).logsource(logsource)
+    }
+}
+
+/// the Sigma category of the `field` type of `data`
+fn category(data: &Value, field: &str, categories: &[(&str, &str)]) -> Option<String> {
+    let kind = data.get(field)?.as_str()?;
+    categories
+        .iter()
+        .find(|(name, _)| *name == kind)
+        .map(|(_, category)| category.to_string())
+}
+
+/// nest dotted keys, keeping a key as is if a prefix is not an object
+fn nest(fields: Map<String, Value>) -> Map<String, Value> {
+    let mut nested = Map::new();
+    for (key, value) in fields {
+        let mut path = key.split('.');
+        let last = path.next_back().unwrap_or_default().to_string();
+        let parent = path.try_fold(&mut nested, |object, part| {
+            match object
+                .entry(part)
+                .or_insert_with(|| Value::Object(Map::new()))
+            {
+                Value::Object(object) => Some(object),
+                _ => None,
+            }
+        });
+        match parent {
+            Some(parent) => parent.insert(last, value),
+            None => nested.insert(key, value),
+        };
+    }
+    nested
+}
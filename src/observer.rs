@@ -0,0 +1,71 @@
+//! Callbacks invoked for every match during evaluation
+
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use serde_json::{Map, Value};
+
+use crate::event::Event;
+use crate::rule::SigmaRule;
+
+/// A rule matching an event, passed to observers registered with
+/// [`SigmaCollection::on_match`]
+///
+/// [`SigmaCollection::on_match`]: struct.SigmaCollection.html#method.on_match
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct Match {
+    /// whether the rule is a correlation rule
+    pub correlation: bool,
+    /// the values of the rule's `fields` in the event,
+    /// see [`SigmaRule::project_fields`]
+    ///
+    /// [`SigmaRule::project_fields`]: rule/struct.SigmaRule.html#method.project_fields
+    pub fields: Map<String, Value>,
+    /// the distinct values counted by a `value_count` correlation rule,
+    /// if the backend keeps them
+    pub values: Vec<Value>,
+}
+
+type Observer = dyn FnMut(&SigmaRule, &Event, &Match) + Send;
+
+/// the observers of a collection, shared between its clones
+#[derive(Default, Clone)]
+pub(crate) struct Observers(Vec<Arc<Mutex<Box<Observer>>>>);
+
+impl fmt::Debug for Observers {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Observers({})", self.0.len())
+    }
+}
+
+impl Observers {
+    pub(crate) fn push(
+        &mut self,
+        observer: impl FnMut(&SigmaRule, &Event, &Match) + Send + 'static,
+    ) {
+        self.0.push(Arc::new(Mutex::new(Box::new(observer))));
+    }
+
+    /// invoke each observer for a matching rule
+    pub(crate) fn notify(
+        &self,
+        rule: &SigmaRule,
+        event: &Event,
+        correlation: bool,
+        values: &[Value],
+    ) {
+        if self.0.is_empty() {
+            return;
+        }
+        let m = Match {
+            correlation,
+            fields: rule.project_fields(event),
+            values: values.to_vec(),
+        };
+        for observer in &self.0 {
+            let mut observer = observer.lock().unwrap_or_else(PoisonError::into_inner);
+            observer(rule, event, &m);
+        }
+    }
+}
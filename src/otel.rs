@@ -0,0 +1,115 @@
+//! Conversion of OpenTelemetry (OTLP) log records into events
+
+use std::collections::HashMap;
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use opentelemetry_proto::tonic::common::v1::{any_value, AnyValue, KeyValue};
+use opentelemetry_proto::tonic::logs::v1::LogRecord;
+use opentelemetry_proto::tonic::resource::v1::Resource;
+use serde_json::{Map, Value};
+
+use crate::event::{Event, LogSource};
+
+/// Sigma services of well-known Windows event log channels
+const CHANNELS: &[(&str, &str)] = &[
+    ("Security", "security"),
+    ("System", "system"),
+    ("Application", "application"),
+    ("Microsoft-Windows-Sysmon/Operational", "sysmon"),
+    ("Microsoft-Windows-PowerShell/Operational", "powershell"),
+    ("Windows PowerShell", "powershell-classic"),
+    (
+        "Microsoft-Windows-TaskScheduler/Operational",
+        "taskscheduler",
+    ),
+    (
+        "Microsoft-Windows-Windows Defender/Operational",
+        "windefend",
+    ),
+];
+
+impl Event {
+    /// An event from an OTLP log record and the resource it was
+    /// emitted by
+    ///
+    /// the fields of a structured (key/value list) body and the record
+    /// attributes make up the event data, attributes taking precedence;
+    /// any other body is kept in a `body` field. Resource attributes
+    /// are added to the event metadata
+    ///
+    /// the log source product is read from the `os.type` resource
+    /// attribute, records with a Windows event log `channel` are mapped
+    /// to the `windows` product and the channel's Sigma service
+    ///
+    /// ```rust
+    /// # use opentelemetry_proto::tonic::common::v1::{any_value, AnyValue, KeyValue};
+    /// # use opentelemetry_proto::tonic::logs::v1::LogRecord;
+    /// # use serde_json::json;
+    /// # use sigmars::Event;
+    /// let string = |s: &str| AnyValue { value: Some(any_value::Value::StringValue(s.into())) };
+    /// let record = LogRecord {
+    ///     body: Some(string("Accepted password for root")),
+    ///     attributes: vec![KeyValue { key: "user".into(), value: Some(string("root")) }],
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let event = Event::from_otlp_log_record(&record, None);
+    /// assert_eq!(event.data, json!({"body": "Accepted password for root", "user": "root"}));
+    /// ```
+    pub fn from_otlp_log_record(record: &LogRecord, resource: Option<&Resource>) -> Self {
+        let mut data = match record.body.as_ref().map(any_value) {
+            Some(Value::Object(fields)) => fields,
+            Some(body) => Map::from_iter([("body".to_string(), body)]),
+            None => Map::new(),
+        };
+        data.extend(attributes(&record.attributes));
+
+        let metadata = resource
+            .map(|resource| attributes(&resource.attributes).collect::<HashMap<_, _>>())
+            .unwrap_or_default();
+
+        let mut product = metadata
+            .get("os.type")
+            .and_then(Value::as_str)
+            .map(|os| match os {
+                "darwin" => "macos".to_string(),
+                os => os.to_string(),
+            });
+        let mut service = None;
+        if let Some(channel) = data.get("channel").and_then(Value::as_str) {
+            product = Some("windows".to_string());
+            service = CHANNELS
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(channel))
+                .map(|(_, service)| service.to_string());
+        }
+
+        Event::new(Value::Object(data))
+            .logsource(LogSource::new(None, product, service))
+            .metadata(metadata)
+    }
+}
+
+fn attributes(attributes: &[KeyValue]) -> impl Iterator<Item = (String, Value)> + '_ {
+    attributes.iter().map(|kv| {
+        let value = kv.value.as_ref().map_or(Value::Null, any_value);
+        (kv.key.clone(), value)
+    })
+}
+
+fn any_value(value: &AnyValue) -> Value {
+    match value.value {
+        Some(any_value::Value::StringValue(ref s)) => Value::String(s.clone()),
+        Some(any_value::Value::BoolValue(b)) => Value::Bool(b),
+        Some(any_value::Value::IntValue(i)) => Value::from(i),
+        Some(any_value::Value::DoubleValue(d)) => Value::from(d),
+        Some(any_value::Value::ArrayValue(ref array)) => {
+            Value::Array(array.values.iter().map(any_value).collect())
+        }
+        Some(any_value::Value::KvlistValue(ref kvlist)) => {
+            Value::Object(attributes(&kvlist.values).collect())
+        }
+        Some(any_value::Value::BytesValue(ref bytes)) => Value::String(BASE64.encode(bytes)),
+        None => Value::Null,
+    }
+}
@@ -0,0 +1,271 @@
+//! Overlaps between the detections of a collection
+
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+use serde_json::Value;
+
+use crate::detection::{DetectionRule, Selection, SelectionItem};
+use crate::event::LogSource;
+use crate::rule::RuleId;
+
+/// conjunctions beyond which the condition of a rule is not analyzed
+const MAX_CONJUNCTIONS: usize = 64;
+
+/// A pair of detection rules matching the same events, or one matching
+/// every event the other does, see [`SigmaCollection::overlaps`]
+///
+/// [`SigmaCollection::overlaps`]: struct.SigmaCollection.html#method.overlaps
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RuleOverlap {
+    /// the rule matching every event `specific` matches
+    pub general: RuleId,
+    pub specific: RuleId,
+    pub kind: OverlapKind,
+}
+
+/// How two detection rules overlap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum OverlapKind {
+    /// the rules match the same events
+    Identical,
+    /// the general rule also matches events the specific one does not
+    Subsumes,
+}
+
+/// a value of a field or keyword list, as matched
+#[derive(Debug, PartialEq)]
+enum Pattern {
+    /// case-insensitive text, matched as a whole or in part
+    Text(Match, String),
+    /// any other value, with its modifiers
+    Exact(Vec<String>, Value),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Match {
+    Equals,
+    StartsWith,
+    EndsWith,
+    Contains,
+}
+
+impl Pattern {
+    /// whether every value matched by `other` is matched by the pattern
+    fn covers(&self, other: &Pattern) -> bool {
+        match (self, other) {
+            (Pattern::Text(this, text), Pattern::Text(that, other)) => match this {
+                Match::Equals => *that == Match::Equals && text == other,
+                Match::StartsWith => {
+                    matches!(that, Match::Equals | Match::StartsWith) && other.starts_with(text)
+                }
+                Match::EndsWith => {
+                    matches!(that, Match::Equals | Match::EndsWith) && other.ends_with(text)
+                }
+                Match::Contains => other.contains(text.as_str()),
+            },
+            (this, other) => this == other,
+        }
+    }
+}
+
+/// a field or keyword list of a selection
+#[derive(Debug)]
+struct Constraint {
+    /// `None` for keywords
+    path: Option<Vec<Arc<str>>>,
+    patterns: Vec<Pattern>,
+    all: bool,
+}
+
+impl Constraint {
+    fn new(item: SelectionItem) -> Self {
+        let (path, modifiers, values) = match item {
+            SelectionItem::Field {
+                path,
+                modifiers,
+                values,
+            } => (Some(path.to_vec()), modifiers, values.to_vec()),
+            SelectionItem::Keywords { modifiers, values } => {
+                let values = values.iter().map(|v| Value::from(v.as_str())).collect();
+                (None, modifiers, values)
+            }
+        };
+        let all = modifiers.iter().any(|m| m == "all");
+        let modifiers = modifiers
+            .iter()
+            .filter(|m| *m != "all")
+            .cloned()
+            .collect::<Vec<_>>();
+        let text = match (path.is_some(), modifiers.as_slice()) {
+            // keywords are matched as substrings
+            (false, []) => Some(Match::Contains),
+            (false, [m]) if m == "contains" => Some(Match::Contains),
+            (true, []) => Some(Match::Equals),
+            (true, [m]) if m == "contains" => Some(Match::Contains),
+            (true, [m]) if m == "startswith" => Some(Match::StartsWith),
+            (true, [m]) if m == "endswith" => Some(Match::EndsWith),
+            _ => None,
+        };
+        let patterns = values
+            .into_iter()
+            .map(|value| match (text, value.as_str()) {
+                (Some(Match::Equals), Some(s)) => {
+                    let s = s.to_lowercase();
+                    match (s.strip_prefix('*'), s.strip_suffix('*')) {
+                        (Some(_), Some(_)) if s.len() > 1 => {
+                            Pattern::Text(Match::Contains, s[1..s.len() - 1].to_string())
+                        }
+                        (Some(suffix), _) => Pattern::Text(Match::EndsWith, suffix.to_string()),
+                        (_, Some(prefix)) => Pattern::Text(Match::StartsWith, prefix.to_string()),
+                        (None, None) => Pattern::Text(Match::Equals, s),
+                    }
+                }
+                (Some(m), Some(s)) if !s.contains('*') => Pattern::Text(m, s.to_lowercase()),
+                _ => Pattern::Exact(modifiers.clone(), value),
+            })
+            .collect();
+        Constraint {
+            path,
+            patterns,
+            all,
+        }
+    }
+
+    /// whether every event satisfying `other` satisfies the constraint
+    fn covers(&self, other: &Constraint) -> bool {
+        if self.path != other.path {
+            return false;
+        }
+        // the values `other` may be satisfied with
+        let alternatives = match other.all {
+            true => vec![other.patterns.iter().collect::<Vec<_>>()],
+            false => other.patterns.iter().map(|p| vec![p]).collect(),
+        };
+        alternatives.iter().all(|alternative| {
+            let implied = |pattern: &Pattern| alternative.iter().any(|p| pattern.covers(p));
+            match self.all {
+                true => self.patterns.iter().all(implied),
+                false => self.patterns.iter().any(implied),
+            }
+        })
+    }
+}
+
+/// whether every event satisfying all of `other` satisfies all of `this`
+fn implies(this: &[Constraint], other: &[Constraint]) -> bool {
+    this.iter().all(|c| other.iter().any(|o| c.covers(o)))
+}
+
+/// a conjunction of the condition of a detection
+#[derive(Debug)]
+struct Conjunction {
+    /// the constraints of the selections that must match
+    matched: Vec<Constraint>,
+    /// the constraints of each selection that must not match
+    excluded: Vec<Vec<Constraint>>,
+}
+
+impl Conjunction {
+    /// whether every event matching `other` matches the conjunction
+    fn covers(&self, other: &Conjunction) -> bool {
+        implies(&self.matched, &other.matched)
+            && self
+                .excluded
+                .iter()
+                .all(|excluded| other.excluded.iter().any(|o| implies(o, excluded)))
+    }
+}
+
+/// a detection rule prepared for comparison
+#[derive(Debug)]
+pub(crate) struct Normalized {
+    id: RuleId,
+    logsource: LogSource,
+    conjunctions: Vec<Conjunction>,
+}
+
+impl Normalized {
+    /// `None` if the condition is too complex to compare
+    pub(crate) fn new(id: &RuleId, rule: &DetectionRule) -> Option<Self> {
+        let selections = rule.selections();
+        let constraints = |name: &str| {
+            selections
+                .get(name)
+                .map(Selection::items)
+                .into_iter()
+                .flatten()
+                .map(Constraint::new)
+        };
+        let conjunctions = rule
+            .dnf(MAX_CONJUNCTIONS)?
+            .into_iter()
+            .map(|literals| {
+                let mut conjunction = Conjunction {
+                    matched: vec![],
+                    excluded: vec![],
+                };
+                for (name, matched) in literals {
+                    match matched {
+                        true => conjunction.matched.extend(constraints(&name)),
+                        false => conjunction.excluded.push(constraints(&name).collect()),
+                    }
+                }
+                conjunction
+            })
+            .collect();
+        Some(Normalized {
+            id: id.clone(),
+            logsource: rule.logsource.clone(),
+            conjunctions,
+        })
+    }
+
+    /// whether every event matching `other` matches the rule
+    fn covers(&self, other: &Normalized) -> bool {
+        logsource_covers(&self.logsource, &other.logsource)
+            && other
+                .conjunctions
+                .iter()
+                .all(|o| self.conjunctions.iter().any(|c| c.covers(o)))
+    }
+
+    /// how the rules overlap, if they do
+    pub(crate) fn overlap(&self, other: &Normalized) -> Option<RuleOverlap> {
+        let (general, specific, kind) = match (self.covers(other), other.covers(self)) {
+            (true, true) => {
+                let (a, b) = match self.id.cmp(&other.id) {
+                    Ordering::Greater => (other, self),
+                    _ => (self, other),
+                };
+                (a, b, OverlapKind::Identical)
+            }
+            (true, false) => (self, other, OverlapKind::Subsumes),
+            (false, true) => (other, self, OverlapKind::Subsumes),
+            (false, false) => return None,
+        };
+        Some(RuleOverlap {
+            general: general.id.clone(),
+            specific: specific.id.clone(),
+            kind,
+        })
+    }
+}
+
+/// whether every event from `other` is from `this`
+fn logsource_covers(this: &LogSource, other: &LogSource) -> bool {
+    let field = |this: &Option<String>, other: &Option<String>| match (this, other) {
+        (None, _) => true,
+        (Some(this), Some(other)) => this.eq_ignore_ascii_case(other),
+        (Some(_), None) => false,
+    };
+    field(&this.category, &other.category)
+        && field(&this.product, &other.product)
+        && field(&this.service, &other.service)
+        && this
+            .extra
+            .iter()
+            .all(|(k, v)| other.extra.get(k) == Some(v))
+}
@@ -0,0 +1,235 @@
+//! Local tuning of upstream rules
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::detection::Selection;
+use crate::dir_options::DirOptions;
+use crate::limits;
+use crate::rule::{RuleType, SigmaRule, Tag};
+use crate::warnings::RuleWarning;
+
+/// Local changes to rules, applied when they are loaded into a
+/// collection so that they survive updates of the upstream rules,
+/// see [`SigmaCollection::overrides`]
+///
+/// Each YAML document references a rule by its `id` with `rule` and
+/// may set its `level`, replace its `tags`, set its `owner`, disable
+/// it with `enabled: false`, or add `filter` selections: the rule no
+/// longer matches events matching any of them. Overrides can also be
+/// added with [`level`](#method.level), [`tags`](#method.tags) and
+/// [`owner`](#method.owner). Overrides of the same rule apply in the
+/// order they were added. An override that cannot be applied leaves
+/// the rule unchanged, with a [`RuleWarning::Override`]
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use serde_json::json;
+/// # use sigmars::{Event, Overrides, SigmaCollection};
+/// static RULES: &str = r#"
+/// title: whoami
+/// id: whoami
+/// level: high
+/// logsource:
+///   category: process_creation
+/// detection:
+///   selection:
+///     CommandLine|contains: whoami
+///   condition: selection
+/// "#;
+///
+/// static OVERRIDES: &str = r#"
+/// rule: whoami
+/// level: low
+/// tags:
+///   - attack.discovery
+/// filter:
+///   filter_admin:
+///     User: admin
+/// "#;
+///
+/// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+/// let overrides = OVERRIDES.parse::<Overrides>()?.owner("whoami", "soc-team");
+/// let rules = SigmaCollection::builder()
+///     .overrides(overrides)
+///     .build_from_str(RULES)?;
+/// let rule = rules.get("whoami").unwrap();
+/// assert_eq!(rule.level.as_deref(), Some("low"));
+/// assert_eq!(rule.tags.as_ref().unwrap()[0].as_str(), "attack.discovery");
+/// assert_eq!(rule.owner.as_deref(), Some("soc-team"));
+///
+/// let event = Event::new(json!({"CommandLine": "whoami", "User": "admin"}));
+/// assert!(rules.get_detection_matches(&event).is_empty());
+/// let event = Event::new(json!({"CommandLine": "whoami", "User": "guest"}));
+/// assert_eq!(rules.get_detection_matches(&event).len(), 1);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`SigmaCollection::overrides`]: struct.SigmaCollection.html#method.overrides
+/// [`RuleWarning::Override`]: enum.RuleWarning.html#variant.Override
+#[derive(Debug, Default, Clone)]
+pub struct Overrides {
+    /// rule ID -> overrides of the rule, in load order
+    rules: HashMap<String, Vec<Override>>,
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Override {
+    rule: String,
+    level: Option<String>,
+    tags: Option<Vec<Tag>>,
+    owner: Option<String>,
+    enabled: Option<bool>,
+    #[serde(default)]
+    filter: serde_yml::Mapping,
+}
+
+impl Overrides {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// load the overrides of the `.yml` and `.yaml` files under a directory
+    pub fn from_dir(path: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        let mut files = DirOptions::default()
+            .files(path)?
+            .into_keys()
+            .collect::<Vec<_>>();
+        // files are applied in a stable order
+        files.sort();
+        let mut overrides = Self::default();
+        for file in files {
+            let s =
+                std::fs::read_to_string(&file).map_err(|e| format!("{}: {}", file.display(), e))?;
+            overrides
+                .load_from_str(&s)
+                .map_err(|e| format!("{}: {}", file.display(), e))?;
+        }
+        Ok(overrides)
+    }
+
+    /// load the overrides of a (multi-document) YAML string,
+    /// returning the number of overrides loaded
+    pub fn load_from_str(
+        &mut self,
+        s: &str,
+    ) -> Result<u32, Box<dyn std::error::Error + Send + Sync>> {
+        let overrides = limits::catch_panics(|| {
+            serde_yml::Deserializer::from_str(s)
+                .map(Override::deserialize)
+                .collect::<Result<Vec<_>, _>>()
+        })?;
+        for o in &overrides {
+            for (name, selection) in &o.filter {
+                name.as_str().ok_or("invalid filter")?;
+                Selection::new(selection)?;
+            }
+        }
+        let count = overrides.len() as u32;
+        overrides.into_iter().for_each(|o| self.push(o));
+        Ok(count)
+    }
+
+    /// set the level of the rule with ID `rule`
+    pub fn level(mut self, rule: &str, level: &str) -> Self {
+        self.push(Override {
+            level: Some(level.to_string()),
+            ..Override::new(rule)
+        });
+        self
+    }
+
+    /// replace the tags of the rule with ID `rule`
+    pub fn tags<S: AsRef<str>>(mut self, rule: &str, tags: impl IntoIterator<Item = S>) -> Self {
+        self.push(Override {
+            tags: Some(tags.into_iter().map(|tag| tag.as_ref().into()).collect()),
+            ..Override::new(rule)
+        });
+        self
+    }
+
+    /// set the owner of the rule with ID `rule`
+    pub fn owner(mut self, rule: &str, owner: &str) -> Self {
+        self.push(Override {
+            owner: Some(owner.to_string()),
+            ..Override::new(rule)
+        });
+        self
+    }
+
+    fn push(&mut self, o: Override) {
+        self.rules.entry(o.rule.clone()).or_default().push(o);
+    }
+
+    pub fn len(&self) -> usize {
+        self.rules.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// whether `rule` is not disabled by an override
+    pub(crate) fn enables(&self, rule: &SigmaRule) -> bool {
+        self.rules
+            .get(&*rule.id)
+            .into_iter()
+            .flatten()
+            .rev()
+            .find_map(|o| o.enabled)
+            .unwrap_or(true)
+    }
+
+    /// apply the overrides of `rule`
+    pub(crate) fn apply(&self, rule: &mut SigmaRule) {
+        for o in self.rules.get(&*rule.id).into_iter().flatten() {
+            if let Err(e) = o.apply(rule) {
+                rule.warnings.push(RuleWarning::Override {
+                    error: e.to_string(),
+                });
+            }
+        }
+    }
+}
+
+impl Override {
+    fn new(rule: &str) -> Self {
+        Override {
+            rule: rule.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn apply(&self, rule: &mut SigmaRule) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        if !self.filter.is_empty() {
+            let RuleType::Detection(ref mut detection) = rule.rule else {
+                return Err("filters only apply to detection rules".into());
+            };
+            detection.add_filters(&self.filter)?;
+        }
+        if let Some(ref level) = self.level {
+            rule.level = Some(level.clone());
+        }
+        if let Some(ref tags) = self.tags {
+            rule.tags = Some(tags.clone());
+        }
+        if let Some(ref owner) = self.owner {
+            rule.owner = Some(owner.clone());
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Overrides {
+    type Err = Box<dyn std::error::Error + Send + Sync>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut overrides = Self::default();
+        overrides.load_from_str(s)?;
+        Ok(overrides)
+    }
+}
@@ -0,0 +1,236 @@
+//! Processing pipelines adapting rules to the field names of a log shipper
+
+use std::collections::{HashMap, HashSet};
+
+use crate::rule::{RuleType, SigmaRule};
+
+/// Sigma Windows fields -> ECS fields of winlogbeat's Sysmon module
+const SYSMON_ECS: &[(&str, &str)] = &[
+    ("EventID", "winlog.event_id"),
+    ("Channel", "winlog.channel"),
+    ("Provider_Name", "winlog.provider_name"),
+    ("Computer", "winlog.computer_name"),
+    ("ProcessGuid", "process.entity_id"),
+    ("ProcessId", "process.pid"),
+    ("Image", "process.executable"),
+    ("FileVersion", "process.pe.file_version"),
+    ("Description", "process.pe.description"),
+    ("Product", "process.pe.product"),
+    ("Company", "process.pe.company"),
+    ("OriginalFileName", "process.pe.original_file_name"),
+    ("CommandLine", "process.command_line"),
+    ("CurrentDirectory", "process.working_directory"),
+    ("User", "user.name"),
+    ("ParentProcessGuid", "process.parent.entity_id"),
+    ("ParentProcessId", "process.parent.pid"),
+    ("ParentImage", "process.parent.executable"),
+    ("ParentCommandLine", "process.parent.command_line"),
+    ("SourceImage", "process.executable"),
+    ("TargetFilename", "file.path"),
+    ("ImageLoaded", "file.path"),
+    ("Signed", "file.code_signature.signed"),
+    ("Signature", "file.code_signature.subject_name"),
+    ("SignatureStatus", "file.code_signature.status"),
+    ("PipeName", "file.name"),
+    ("TargetObject", "registry.path"),
+    ("Protocol", "network.transport"),
+    ("SourceIp", "source.ip"),
+    ("SourcePort", "source.port"),
+    ("SourceHostname", "source.domain"),
+    ("DestinationIp", "destination.ip"),
+    ("DestinationPort", "destination.port"),
+    ("DestinationHostname", "destination.domain"),
+    ("QueryName", "dns.question.name"),
+];
+
+/// Sigma network rule categories mapped by the sensor pipelines
+const NETWORK_CATEGORIES: &[&str] = &["dns", "proxy", "firewall"];
+
+/// Sigma dns, proxy and firewall fields -> Suricata EVE fields
+const SURICATA_EVE: &[(&str, &str)] = &[
+    ("dst_ip", "dest_ip"),
+    ("dst_port", "dest_port"),
+    ("query", "dns.rrname"),
+    ("record_type", "dns.rrtype"),
+    ("c-uri", "http.url"),
+    ("cs-host", "http.hostname"),
+    ("c-useragent", "http.http_user_agent"),
+    ("cs-method", "http.http_method"),
+    ("cs-referrer", "http.http_refer"),
+    ("cs-version", "http.protocol"),
+    ("sc-status", "http.status"),
+    ("sc-bytes", "http.length"),
+];
+
+/// Sigma dns, proxy and firewall fields -> Zeek dns, http and conn
+/// log fields, `id.*` fields are nested by `Event::from_zeek`
+const ZEEK: &[(&str, &str)] = &[
+    ("src_ip", "id.orig_h"),
+    ("src_port", "id.orig_p"),
+    ("dst_ip", "id.resp_h"),
+    ("dst_port", "id.resp_p"),
+    ("record_type", "qtype_name"),
+    ("answer", "answers"),
+    ("c-uri", "uri"),
+    ("cs-host", "host"),
+    ("c-useragent", "user_agent"),
+    ("cs-method", "method"),
+    ("cs-referrer", "referrer"),
+    ("cs-version", "version"),
+    ("sc-status", "status_code"),
+    ("cs-bytes", "request_body_len"),
+    ("sc-bytes", "response_body_len"),
+];
+
+/// Maps the fields read by rules to the field names of the events
+/// they are evaluated against, see [`SigmaCollection::pipeline`]
+///
+/// A pipeline for a `product` or `category` only applies to rules with
+/// that logsource product, or one of those categories. Fields without
+/// a mapping are kept as written, or prefixed with [`prefix_unmapped`]
+/// if they are not dotted
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use serde_json::json;
+/// # use sigmars::{Event, Pipeline, SigmaCollection};
+/// static RULES: &str = r#"
+/// title: whoami
+/// id: whoami
+/// logsource:
+///   category: process_creation
+///   product: windows
+/// detection:
+///   selection:
+///     CommandLine|contains: whoami
+///   condition: selection
+/// "#;
+///
+/// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+/// let rules = SigmaCollection::builder()
+///     .pipeline(Pipeline::sysmon_ecs())
+///     .build_from_str(RULES)?;
+/// let event = Event::new(json!({"process": {"command_line": "whoami /all"}}));
+/// assert_eq!(rules.get_detection_matches(&event), vec!["whoami".into()]);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`SigmaCollection::pipeline`]: struct.SigmaCollection.html#method.pipeline
+/// [`prefix_unmapped`]: #method.prefix_unmapped
+#[derive(Debug, Default, Clone)]
+pub struct Pipeline {
+    name: String,
+    product: Option<String>,
+    categories: HashSet<String>,
+    fields: HashMap<String, String>,
+    unmapped_prefix: Option<String>,
+}
+
+impl Pipeline {
+    pub fn new(name: &str) -> Self {
+        Pipeline {
+            name: name.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Maps the fields of SigmaHQ Windows rules to the ECS fields of
+    /// events shipped by winlogbeat, fields without an ECS equivalent
+    /// are read from `winlog.event_data`
+    pub fn sysmon_ecs() -> Self {
+        SYSMON_ECS.iter().fold(
+            Self::new("sysmon_ecs")
+                .product("windows")
+                .prefix_unmapped("winlog.event_data."),
+            |pipeline, (field, mapped)| pipeline.map_field(field, mapped),
+        )
+    }
+
+    /// Maps the fields of Sigma dns, proxy and firewall rules to the
+    /// fields of Suricata EVE events, see [`Event::from_suricata_eve`]
+    ///
+    /// [`Event::from_suricata_eve`]: event/struct.Event.html#method.from_suricata_eve
+    pub fn suricata_eve() -> Self {
+        Self::network("suricata_eve", SURICATA_EVE)
+    }
+
+    /// Maps the fields of Sigma dns, proxy and firewall rules to the
+    /// fields of Zeek JSON logs, see [`Event::from_zeek`]
+    ///
+    /// [`Event::from_zeek`]: event/struct.Event.html#method.from_zeek
+    pub fn zeek() -> Self {
+        Self::network("zeek", ZEEK)
+    }
+
+    fn network(name: &str, fields: &[(&str, &str)]) -> Self {
+        let pipeline = NETWORK_CATEGORIES
+            .iter()
+            .fold(Self::new(name), |pipeline, category| {
+                pipeline.category(category)
+            });
+        fields.iter().fold(pipeline, |pipeline, (field, mapped)| {
+            pipeline.map_field(field, mapped)
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// only apply the pipeline to rules for `product`
+    pub fn product(mut self, product: &str) -> Self {
+        self.product = Some(product.to_string());
+        self
+    }
+
+    /// only apply the pipeline to rules for `category`, or any of
+    /// the categories added
+    pub fn category(mut self, category: &str) -> Self {
+        self.categories.insert(category.to_lowercase());
+        self
+    }
+
+    /// read `mapped` where rules read `field`
+    pub fn map_field(mut self, field: &str, mapped: &str) -> Self {
+        self.fields.insert(field.to_string(), mapped.to_string());
+        self
+    }
+
+    /// read undotted fields without a mapping from under `prefix`
+    /// (e.g. `winlog.event_data.`)
+    pub fn prefix_unmapped(mut self, prefix: &str) -> Self {
+        self.unmapped_prefix = Some(prefix.to_string());
+        self
+    }
+
+    fn field(&self, field: &str) -> Option<String> {
+        match (self.fields.get(field), &self.unmapped_prefix) {
+            (Some(mapped), _) => Some(mapped.clone()),
+            (None, Some(prefix)) if !field.contains('.') => Some(format!("{}{}", prefix, field)),
+            _ => None,
+        }
+    }
+
+    /// map the fields of `rule` if the pipeline applies to it
+    pub(crate) fn apply(&self, rule: &mut SigmaRule) {
+        let RuleType::Detection(ref mut detection) = rule.rule else {
+            return;
+        };
+        let logsource = &detection.logsource;
+        let product = self.product.as_ref().is_none_or(|product| {
+            logsource
+                .product
+                .as_ref()
+                .is_some_and(|p| p.eq_ignore_ascii_case(product))
+        });
+        let category = self.categories.is_empty()
+            || logsource
+                .category
+                .as_ref()
+                .is_some_and(|c| self.categories.contains(&c.to_lowercase()));
+        if product && category {
+            detection.map_fields(&|field| self.field(field));
+        }
+    }
+}
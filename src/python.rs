@@ -0,0 +1,172 @@
+//! Python bindings (feature `python`)
+//!
+//! Build with [maturin](https://www.maturin.rs) (`maturin develop`)
+//!
+//! ```python
+//! import sigmars
+//!
+//! rules = sigmars.SigmaCollection.from_dir("/path/to/sigma/rules/")
+//! event = sigmars.Event({"EventID": 4624}, product="windows")
+//! for rule_id in rules.get_detection_matches(event):
+//!     print(rules.finding(rule_id, event))
+//! ```
+use pyo3::exceptions::{PyKeyError, PyRuntimeError, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyAny;
+use serde_json::Value;
+
+use crate::event::LogSource;
+use crate::matches::Matches;
+use crate::{Event, MemBackend, SigmaCollection};
+
+fn to_value(obj: &Bound<'_, PyAny>) -> PyResult<Value> {
+    let json = obj.py().import("json")?;
+    let s: String = json.call_method1("dumps", (obj,))?.extract()?;
+    serde_json::from_str(&s).map_err(|e| PyValueError::new_err(e.to_string()))
+}
+
+fn to_py<'py>(py: Python<'py>, value: &Value) -> PyResult<Bound<'py, PyAny>> {
+    py.import("json")?
+        .call_method1("loads", (value.to_string(),))
+}
+
+fn to_strings(ids: Matches) -> Vec<String> {
+    ids.into_iter().map(|id| id.to_string()).collect()
+}
+
+/// a log event, with optional log source
+#[pyclass(name = "Event")]
+pub struct PyEvent {
+    inner: Event,
+}
+
+#[pymethods]
+impl PyEvent {
+    #[new]
+    #[pyo3(signature = (data, category=None, product=None, service=None, tenant=None))]
+    fn new(
+        data: &Bound<'_, PyAny>,
+        category: Option<String>,
+        product: Option<String>,
+        service: Option<String>,
+        tenant: Option<String>,
+    ) -> PyResult<Self> {
+        let mut inner =
+            Event::new(to_value(data)?).logsource(LogSource::new(category, product, service));
+        inner.tenant = tenant;
+        Ok(PyEvent { inner })
+    }
+
+    #[getter]
+    fn data<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        to_py(py, &self.inner.data)
+    }
+}
+
+/// a collection of Sigma rules
+///
+/// correlation rules are evaluated by `get_matches` once
+/// `init_mem_backend` has been called
+#[pyclass(name = "SigmaCollection")]
+pub struct PySigmaCollection {
+    inner: SigmaCollection,
+    runtime: Option<(tokio::runtime::Runtime, MemBackend)>,
+}
+
+impl From<SigmaCollection> for PySigmaCollection {
+    fn from(inner: SigmaCollection) -> Self {
+        PySigmaCollection {
+            inner,
+            runtime: None,
+        }
+    }
+}
+
+#[pymethods]
+impl PySigmaCollection {
+    /// parse a collection from a (multi-document) YAML string
+    #[new]
+    #[pyo3(signature = (rules=None))]
+    fn new(rules: Option<&str>) -> PyResult<Self> {
+        match rules {
+            Some(rules) => rules
+                .parse::<SigmaCollection>()
+                .map(Into::into)
+                .map_err(|e| PyValueError::new_err(e.to_string())),
+            None => Ok(SigmaCollection::new().into()),
+        }
+    }
+
+    /// load a collection from a directory of YAML rules
+    #[staticmethod]
+    fn from_dir(path: &str) -> PyResult<Self> {
+        SigmaCollection::new_from_dir(path)
+            .map(Into::into)
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    /// load and add rules from a directory of YAML rules
+    fn load_from_dir(&mut self, path: &str) -> PyResult<u32> {
+        self.inner
+            .load_from_dir(path)
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.len()
+    }
+
+    fn get_detection_matches(&self, event: &PyEvent) -> Vec<String> {
+        to_strings(self.inner.get_detection_matches(&event.inner))
+    }
+
+    fn get_detection_matches_unfiltered(&self, event: &PyEvent) -> Vec<String> {
+        to_strings(self.inner.get_detection_matches_unfiltered(&event.inner))
+    }
+
+    /// initialize correlation rules with an in-memory backend
+    fn init_mem_backend(&mut self) -> PyResult<()> {
+        let runtime =
+            tokio::runtime::Runtime::new().map_err(|e| PyRuntimeError::new_err(e.to_string()))?;
+        let backend = runtime.block_on(async {
+            let mut backend = MemBackend::new().await;
+            self.inner.init(&mut backend).await;
+            backend
+        });
+        self.runtime = Some((runtime, backend));
+        Ok(())
+    }
+
+    /// evaluate detection and correlation rules
+    fn get_matches(&self, event: &PyEvent) -> PyResult<Vec<String>> {
+        let (runtime, _) = self
+            .runtime
+            .as_ref()
+            .ok_or_else(|| PyRuntimeError::new_err("correlation backend not initialized"))?;
+        runtime
+            .block_on(self.inner.get_matches(&event.inner))
+            .map(to_strings)
+            .map_err(|e| PyRuntimeError::new_err(e.to_string()))
+    }
+
+    /// the OCSF Detection Finding for a rule and a matching event
+    fn finding<'py>(
+        &self,
+        py: Python<'py>,
+        rule_id: &str,
+        event: &PyEvent,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let rule = self
+            .inner
+            .get(rule_id)
+            .ok_or_else(|| PyKeyError::new_err(rule_id.to_string()))?;
+        to_py(py, &rule.to_finding(&event.inner))
+    }
+}
+
+#[pymodule]
+fn sigmars(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_class::<PyEvent>()?;
+    m.add_class::<PySigmaCollection>()?;
+    Ok(())
+}
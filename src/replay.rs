@@ -0,0 +1,42 @@
+//! Replay of NDJSON log files, see [`SigmaCollection::scan_ndjson`]
+//!
+//! [`SigmaCollection::scan_ndjson`]: ../struct.SigmaCollection.html#method.scan_ndjson
+
+use std::io::BufRead;
+
+use serde_json::Value;
+
+use crate::event::Event;
+use crate::matches::Matches;
+
+/// The rules matching an event of an NDJSON stream,
+/// see [`SigmaCollection::scan_ndjson`]
+///
+/// [`SigmaCollection::scan_ndjson`]: ../struct.SigmaCollection.html#method.scan_ndjson
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct LineMatch {
+    /// the line of the event, starting at 1
+    pub line: usize,
+    pub event: Event,
+    pub matches: Matches,
+}
+
+/// the events of an NDJSON stream with their line numbers, read one
+/// line at a time in stream order
+pub(crate) fn read(
+    reader: impl BufRead,
+) -> impl Iterator<Item = Result<(usize, Value), Box<dyn std::error::Error + Send + Sync>>> {
+    reader
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| match line {
+            Ok(line) if line.trim().is_empty() => None,
+            Ok(line) => Some(
+                serde_json::from_str::<Value>(&line)
+                    .map(|data| (i + 1, data))
+                    .map_err(|e| format!("line {}: {}", i + 1, e).into()),
+            ),
+            Err(e) => Some(Err(e.into())),
+        })
+}
@@ -1,25 +1,49 @@
-use std::{collections::HashMap, hash::Hash};
+use std::cell::Cell;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{collections::HashMap, hash::Hash, path::PathBuf, sync::Arc};
 
+#[cfg(feature = "time")]
 use chrono::prelude::*;
 use serde::de::{self, DeserializeSeed, Deserializer, Visitor};
 use serde::{self, Deserialize, Serialize};
-use serde_json::Value;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
 use std::fmt;
+use uuid::Uuid;
 
-use crate::detection::DetectionRule;
+use crate::detection::{get_terminal_from_dotted_path, DetectionRule, MatchOptions};
+use crate::event::Event;
+use crate::footprint;
+use crate::warnings::{self, RuleWarning};
 
 #[cfg(feature = "correlation")]
 use crate::correlation::CorrelationRule;
 
 #[doc(hidden)]
-#[derive(Debug, Serialize, Deserialize)]
-#[serde(rename_all = "lowercase")]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
 pub enum Status {
     Stable,
     Test,
     Experimental,
     Deprecated,
     Unsupported,
+    /// a status not defined by the Sigma specification, kept as written
+    Other(String),
+}
+
+impl Status {
+    /// the status as written in the rule
+    pub fn as_str(&self) -> &str {
+        match self {
+            Status::Stable => "stable",
+            Status::Test => "test",
+            Status::Experimental => "experimental",
+            Status::Deprecated => "deprecated",
+            Status::Unsupported => "unsupported",
+            Status::Other(s) => s,
+        }
+    }
 }
 
 impl From<&str> for Status {
@@ -30,26 +54,275 @@ impl From<&str> for Status {
             "experimental" => Status::Experimental,
             "deprecated" => Status::Deprecated,
             "unsupported" => Status::Unsupported,
-            _ => Status::Unsupported,
+            _ => Status::Other(s.to_string()),
         }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl From<String> for Status {
+    fn from(s: String) -> Self {
+        s.as_str().into()
+    }
+}
+
+impl From<Status> for String {
+    fn from(status: Status) -> Self {
+        match status {
+            Status::Other(s) => s,
+            status => status.as_str().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// the current time in milliseconds since the Unix epoch
+fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_millis() as i64)
+}
+
+/// A rule ID, cheaply cloneable and shared between the
+/// collection and match results
+pub type RuleId = Arc<str>;
+
+/// The relationship between a rule and a rule listed in its `related` field
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum RelationType {
+    Derived,
+    Obsoletes,
+    Merged,
+    Renamed,
+    Similar,
+    /// a relationship not defined by the Sigma specification, kept
+    /// as written
+    Other(String),
+}
+
+impl RelationType {
+    /// the relationship as written in the rule
+    pub fn as_str(&self) -> &str {
+        match self {
+            RelationType::Derived => "derived",
+            RelationType::Obsoletes => "obsoletes",
+            RelationType::Merged => "merged",
+            RelationType::Renamed => "renamed",
+            RelationType::Similar => "similar",
+            RelationType::Other(s) => s,
+        }
+    }
+}
+
+impl From<&str> for RelationType {
+    fn from(s: &str) -> Self {
+        match s {
+            "derived" => RelationType::Derived,
+            "obsoletes" => RelationType::Obsoletes,
+            "merged" => RelationType::Merged,
+            "renamed" => RelationType::Renamed,
+            "similar" => RelationType::Similar,
+            _ => RelationType::Other(s.to_string()),
+        }
+    }
+}
+
+impl From<String> for RelationType {
+    fn from(s: String) -> Self {
+        s.as_str().into()
+    }
+}
+
+impl From<RelationType> for String {
+    fn from(relation_type: RelationType) -> Self {
+        match relation_type {
+            RelationType::Other(s) => s,
+            relation_type => relation_type.as_str().to_string(),
+        }
+    }
+}
+
+impl fmt::Display for RelationType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// An entry in the `related` field of a Sigma rule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Related {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub relation_type: RelationType,
+}
+
+/// The kind of a [`Tag`], derived from its namespace
+///
+/// `attack.*` tags are further classified by the MITRE ATT&CK
+/// object they reference (the ID is upper-cased, e.g. `T1059.001`)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TagKind {
+    AttackTactic(String),
+    AttackTechnique(String),
+    AttackGroup(String),
+    AttackSoftware(String),
+    AttackCampaign(String),
+    /// a CVE identifier (e.g. `CVE-2021-44228`)
+    Cve(String),
+    Detection(String),
+    Other,
+}
+
+/// A tag from the `tags` field of a Sigma rule
+///
+/// Tags are split into a namespace and value on the first `.`,
+/// the original tag is preserved and used when serializing
+///
+/// ```rust
+/// # use sigmars::rule::{Tag, TagKind};
+/// let tag: Tag = "attack.t1059.001".into();
+/// assert_eq!(tag.namespace, "attack");
+/// assert_eq!(tag.value, "t1059.001");
+/// assert_eq!(tag.kind, TagKind::AttackTechnique("T1059.001".to_string()));
+/// assert_eq!(tag.as_str(), "attack.t1059.001");
+///
+/// let tag: Tag = "cve.2021-44228".into();
+/// assert_eq!(tag.kind, TagKind::Cve("CVE-2021-44228".to_string()));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub struct Tag {
+    pub namespace: String,
+    pub value: String,
+    pub kind: TagKind,
+    original: String,
+}
+
+impl Tag {
+    /// the tag as written in the rule
+    pub fn as_str(&self) -> &str {
+        &self.original
+    }
+}
+
+fn is_attack_id(value: &str, prefix: char) -> bool {
+    let mut parts = value.splitn(2, '.');
+    let id = parts.next().unwrap_or_default();
+    id.len() == 5
+        && id.starts_with(prefix)
+        && id[1..].chars().all(|c| c.is_ascii_digit())
+        && parts
+            .next()
+            .is_none_or(|sub| !sub.is_empty() && sub.chars().all(|c| c.is_ascii_digit()))
+}
+
+impl From<&str> for Tag {
+    fn from(s: &str) -> Self {
+        let (namespace, value) = s.split_once('.').unwrap_or(("", s));
+        let lower = value.to_lowercase();
+
+        let kind = match namespace.to_lowercase().as_str() {
+            "attack" if is_attack_id(&lower, 't') => TagKind::AttackTechnique(value.to_uppercase()),
+            "attack" if is_attack_id(&lower, 'g') => TagKind::AttackGroup(value.to_uppercase()),
+            "attack" if is_attack_id(&lower, 's') => TagKind::AttackSoftware(value.to_uppercase()),
+            "attack" if is_attack_id(&lower, 'c') => TagKind::AttackCampaign(value.to_uppercase()),
+            "attack" => TagKind::AttackTactic(lower),
+            "cve" => TagKind::Cve(format!("CVE-{}", value.to_uppercase())),
+            "detection" => TagKind::Detection(lower),
+            _ => TagKind::Other,
+        };
+
+        Tag {
+            namespace: namespace.to_string(),
+            value: value.to_string(),
+            kind,
+            original: s.to_string(),
+        }
+    }
+}
+
+impl From<String> for Tag {
+    fn from(s: String) -> Self {
+        s.as_str().into()
+    }
+}
+
+impl From<Tag> for String {
+    fn from(tag: Tag) -> Self {
+        tag.original
+    }
+}
+
+impl fmt::Display for Tag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.original)
+    }
+}
+
+#[derive(Debug, Serialize)]
 #[serde(untagged)]
 pub(crate) enum RuleType {
     Detection(DetectionRule),
     Correlation(CorrelationRule),
 }
 
+/// select the rule type by key rather than trying each in turn
+/// (as `untagged` does), so that errors in the rule are reported
+impl<'de> Deserialize<'de> for RuleType {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let rule = serde_yml::Mapping::deserialize(deserializer)?;
+        match rule.contains_key("correlation") {
+            true => CorrelationRule::deserialize(serde_yml::Value::Mapping(rule))
+                .map(RuleType::Correlation),
+            false => {
+                DetectionRule::deserialize(serde_yml::Value::Mapping(rule)).map(RuleType::Detection)
+            }
+        }
+        .map_err(de::Error::custom)
+    }
+}
+
+/// Where a rule was loaded from
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Source {
+    /// parsed from a string or constructed in code
+    #[default]
+    Programmatic,
+    /// loaded from a file
+    File(PathBuf),
+    /// embedded at build time, with the path of the rule
+    /// file in the embedded ruleset
+    Embedded(&'static str),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Source::Programmatic => f.write_str("<programmatic>"),
+            Source::File(path) => write!(f, "{}", path.display()),
+            Source::Embedded(path) => write!(f, "<embedded>/{}", path),
+        }
+    }
+}
+
 /// a single Sigma rule (detection or correlation)
 /// fields are described by the [Sigma specification](https://github.com/SigmaHQ/sigma-specification)
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "lowercase")]
 pub struct SigmaRule {
     pub title: String,
-    pub id: String,
+    pub id: RuleId,
     pub name: Option<String>,
+    pub related: Option<Vec<Related>>,
     pub description: Option<String>,
     pub references: Option<Vec<String>>,
     pub author: Option<String>,
@@ -57,13 +330,29 @@ pub struct SigmaRule {
     pub modified: Option<String>,
     pub status: Option<Status>,
     pub license: Option<String>,
-    pub tags: Option<Vec<String>>,
+    pub tags: Option<Vec<Tag>>,
     pub scope: Option<String>,
     pub fields: Option<Vec<String>>,
     pub falsepositives: Option<Vec<String>>,
     pub level: Option<String>,
     #[serde(flatten)]
     pub(crate) rule: RuleType,
+    /// where the rule was loaded from
+    #[serde(skip)]
+    pub source: Source,
+    /// whether the rule has no `id` and [`id`](#structfield.id) was
+    /// generated, see [`SigmaCollection::generate_missing_ids`]
+    ///
+    /// [`SigmaCollection::generate_missing_ids`]: struct.SigmaCollection.html#method.generate_missing_ids
+    #[serde(skip)]
+    pub generated_id: bool,
+    /// non-fatal issues found while parsing the rule
+    #[serde(skip)]
+    pub warnings: Vec<RuleWarning>,
+    /// who is responsible for the rule locally, only set by
+    /// [`Overrides`](crate::Overrides)
+    #[serde(skip)]
+    pub owner: Option<String>,
     #[doc(hidden)]
     #[serde(flatten)]
     pub extra: HashMap<String, serde_json::Value>,
@@ -73,7 +362,7 @@ pub struct SigmaRule {
 /// (as JSON)
 impl From<&SigmaRule> for Value {
     fn from(rule: &SigmaRule) -> Value {
-        let time = Utc::now().timestamp_millis();
+        let time = now_millis();
 
         let severity_id = match rule.level {
             Some(ref level) => match level.as_str() {
@@ -121,11 +410,266 @@ impl From<&SigmaRule> for Value {
             Some(ref level) => value["severity"] = level.clone().into(),
             None => {}
         };
+        if let Some(ref tags) = rule.tags {
+            value["metadata"]["labels"] = tags.iter().map(Tag::as_str).collect();
+        }
+        if let Some(ref owner) = rule.owner {
+            value["unmapped"] = serde_json::json!({ "owner": owner });
+        }
 
         value
     }
 }
 
+impl SigmaRule {
+    /// project the values of the fields listed in the rule's `fields`
+    /// attribute from an event
+    ///
+    /// fields may be dotted paths into nested objects, fields that are
+    /// not present in the event are omitted
+    pub fn project_fields(&self, event: &Event) -> Map<String, Value> {
+        self.fields
+            .iter()
+            .flatten()
+            .filter_map(|field| {
+                get_terminal_from_dotted_path(field, &event.data)
+                    .map(|value| (field.clone(), value.clone()))
+            })
+            .collect()
+    }
+
+    /// the approximate heap size of the rule, estimating its fields
+    /// and raw detection from their JSON form
+    pub(crate) fn heap_size(&self) -> usize {
+        let compiled = match self.rule {
+            RuleType::Detection(ref detection) => detection.compiled_size(),
+            RuleType::Correlation(_) => 0,
+        };
+        serde_json::to_value(self).map_or(0, |value| footprint::json(&value))
+            + self.warnings.len() * std::mem::size_of::<RuleWarning>()
+            + compiled
+    }
+
+    /// whether the rule matches an event, without a collection
+    ///
+    /// matching is case-insensitive as in Sigma, and events with a
+    /// log source only match rules for that log source, as in a
+    /// [`SigmaCollection`]. Correlation rules and rules with a legacy
+    /// aggregation (`selection | count() > 5`) need state and never match
+    ///
+    /// ```rust
+    /// # use serde_json::json;
+    /// # use sigmars::event::{Event, LogSource};
+    /// # use sigmars::SigmaRule;
+    /// let rule: SigmaRule = serde_yml::from_str(r#"
+    /// title: test rule
+    /// id: test-rule
+    /// logsource:
+    ///   category: test
+    /// detection:
+    ///   selection:
+    ///     foo: bar
+    ///   condition: selection
+    /// "#).unwrap();
+    ///
+    /// let event = Event::new(json!({"foo": "bar"}));
+    /// assert!(rule.is_match(&event));
+    /// assert!(!rule.is_match(&event.logsource(LogSource::default().category("other"))));
+    /// ```
+    ///
+    /// [`SigmaCollection`]: struct.SigmaCollection.html
+    pub fn is_match(&self, event: &Event) -> bool {
+        let RuleType::Detection(ref detection) = self.rule else {
+            return false;
+        };
+        #[cfg(feature = "correlation")]
+        if detection.aggregation.is_some() {
+            return false;
+        }
+        let selected = |rule: &Option<String>, event: &Option<String>| {
+            rule.is_none() || event.is_none() || rule == event
+        };
+        let (rule, target) = (&detection.logsource, &event.logsource);
+        let options = MatchOptions {
+            logsource_fields: true,
+            ..Default::default()
+        };
+        selected(&rule.category, &target.category)
+            && selected(&rule.product, &target.product)
+            && selected(&rule.service, &target.service)
+            && detection.is_match(&event.data, &options.for_event(event, &event.logsource))
+    }
+
+    /// convert the rule to an [OCSF](https://ocsf.io) Detection Finding
+    /// (as JSON) for a matching event
+    ///
+    /// the values of the rule's `fields` in the event are included
+    /// as evidence
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use serde_json::json;
+    /// # use sigmars::{Event, SigmaCollection};
+    /// static RULES: &str = r#"
+    /// title: test rule
+    /// id: test-rule
+    /// fields:
+    ///   - user.name
+    /// logsource:
+    ///   category: test
+    /// detection:
+    ///   selection:
+    ///     foo: bar
+    ///   condition: selection
+    /// "#;
+    ///
+    /// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    /// let rules: SigmaCollection = RULES.parse()?;
+    /// let event = Event::new(json!({"foo": "bar", "user": {"name": "root"}}));
+    /// let finding = rules.get("test-rule").unwrap().to_finding(&event);
+    /// assert_eq!(finding["evidences"][0]["data"], json!({"user.name": "root"}));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn to_finding(&self, event: &Event) -> Value {
+        let mut value: Value = self.into();
+        let fields = self.project_fields(event);
+        if !fields.is_empty() {
+            value["evidences"] = serde_json::json!([{ "data": fields }]);
+        }
+        value
+    }
+
+    /// An [ECS](https://www.elastic.co/guide/en/ecs/current/index.html)
+    /// alert for a match of the rule against `event`, with the event
+    /// data in `event.original`
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use serde_json::json;
+    /// # use sigmars::{Event, SigmaCollection};
+    /// static RULES: &str = r#"
+    /// title: test rule
+    /// id: test-rule
+    /// level: high
+    /// logsource:
+    ///   category: test
+    /// detection:
+    ///   selection:
+    ///     foo: bar
+    ///   condition: selection
+    /// "#;
+    ///
+    /// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    /// let rules: SigmaCollection = RULES.parse()?;
+    /// let event = Event::new(json!({"foo": "bar"}));
+    /// let alert = rules.get("test-rule").unwrap().to_ecs_alert(&event);
+    /// assert_eq!(alert["rule"]["id"], "test-rule");
+    /// assert_eq!(alert["event"]["severity"], 73);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[cfg(feature = "time")]
+    pub fn to_ecs_alert(&self, event: &Event) -> Value {
+        // the risk scores Elastic uses for Sigma levels
+        let severity = match self.level.as_deref() {
+            Some("informational") => 1,
+            Some("low") => 21,
+            Some("medium") => 47,
+            Some("high") => 73,
+            Some("critical") => 99,
+            _ => 0,
+        };
+        let mut value = serde_json::json!({
+            "@timestamp": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            "message": self.title,
+            "event": {
+                "kind": "alert",
+                "severity": severity,
+                "original": event.data.to_string(),
+            },
+            "rule": {
+                "id": self.id,
+                "name": self.title,
+                "ruleset": "sigma",
+            },
+        });
+        if let Some(ref description) = self.description {
+            value["rule"]["description"] = description.clone().into();
+        }
+        if let Some(ref author) = self.author {
+            value["rule"]["author"] = author.clone().into();
+        }
+        if let Some(ref tags) = self.tags {
+            value["tags"] = serde_json::json!(tags);
+        }
+        value
+    }
+
+    /// An ArcSight CEF record for a match of the rule against `event`,
+    /// with the event data in `cs2` and the rule tags in `cs1`
+    ///
+    /// ```rust
+    /// # use std::error::Error;
+    /// # use serde_json::json;
+    /// # use sigmars::{Event, SigmaCollection};
+    /// static RULES: &str = r#"
+    /// title: test | rule
+    /// id: test-rule
+    /// level: high
+    /// logsource:
+    ///   category: test
+    /// detection:
+    ///   selection:
+    ///     foo: bar
+    ///   condition: selection
+    /// "#;
+    ///
+    /// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    /// let rules: SigmaCollection = RULES.parse()?;
+    /// let event = Event::new(json!({"foo": "bar"}));
+    /// let cef = rules.get("test-rule").unwrap().to_cef(&event);
+    /// assert!(cef.starts_with("CEF:0|crowdalert|sigmars|"));
+    /// assert!(cef.contains(r#"|test-rule|test \| rule|8|"#));
+    /// assert!(cef.ends_with(r#"cs2Label=event cs2={"foo":"bar"}"#));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn to_cef(&self, event: &Event) -> String {
+        let header = |s: &str| s.replace('\\', "\\\\").replace('|', "\\|");
+        let extension = |s: &str| {
+            s.replace('\\', "\\\\")
+                .replace('=', "\\=")
+                .replace('\r', "\\r")
+                .replace('\n', "\\n")
+        };
+        let severity = match self.level.as_deref() {
+            Some("informational") => 1,
+            Some("low") => 3,
+            Some("medium") => 5,
+            Some("high") => 8,
+            Some("critical") => 10,
+            _ => 0,
+        };
+        let mut cef = format!(
+            "CEF:0|crowdalert|sigmars|{}|{}|{}|{}|rt={}",
+            env!("CARGO_PKG_VERSION"),
+            header(&self.id),
+            header(&self.title),
+            severity,
+            now_millis(),
+        );
+        if let Some(ref description) = self.description {
+            cef += &format!(" msg={}", extension(description));
+        }
+        if let Some(ref tags) = self.tags {
+            let tags = tags.iter().map(Tag::to_string).collect::<Vec<_>>();
+            cef += &format!(" cs1Label=tags cs1={}", extension(&tags.join(",")));
+        }
+        cef + &format!(" cs2Label=event cs2={}", extension(&event.data.to_string()))
+    }
+}
+
 impl PartialEq for SigmaRule {
     fn eq(&self, other: &Self) -> bool {
         self.id == other.id
@@ -140,6 +684,34 @@ impl Hash for SigmaRule {
     }
 }
 
+thread_local! {
+    static GENERATE_IDS: Cell<bool> = const { Cell::new(false) };
+}
+
+/// run `f` generating the IDs of rules without one parsed on this thread
+pub(crate) fn generating_ids<T>(generate: bool, f: impl FnOnce() -> T) -> T {
+    let previous = GENERATE_IDS.with(|g| g.replace(generate));
+    let result = f();
+    GENERATE_IDS.with(|g| g.set(previous));
+    result
+}
+
+/// a stable ID for a rule without one: a UUIDv8 (RFC 9562) of the
+/// SHA-256 hash of its title and its logsource and detection (or
+/// correlation) sections
+fn generate_id(title: &str, sections: &HashMap<String, Value>) -> RuleId {
+    let content = json!([
+        title,
+        sections.get("logsource"),
+        sections.get("detection"),
+        sections.get("correlation"),
+    ]);
+    let hash = Sha256::digest(content.to_string().as_bytes());
+    let mut bytes = [0; 16];
+    bytes.copy_from_slice(&hash[..16]);
+    Uuid::new_v8(bytes).to_string().into()
+}
+
 struct SigmaRuleSeed;
 
 impl<'de> DeserializeSeed<'de> for SigmaRuleSeed {
@@ -169,8 +741,9 @@ impl<'de> Visitor<'de> for SigmaRuleVisitor {
         #[derive(Deserialize)]
         struct SigmaRuleHelper {
             pub title: String,
-            pub id: String,
+            pub id: Option<RuleId>,
             pub name: Option<String>,
+            pub related: Option<Vec<Related>>,
             pub description: Option<String>,
             pub references: Option<Vec<String>>,
             pub author: Option<String>,
@@ -178,7 +751,7 @@ impl<'de> Visitor<'de> for SigmaRuleVisitor {
             pub modified: Option<String>,
             pub status: Option<Status>,
             pub license: Option<String>,
-            pub tags: Option<Vec<String>>,
+            pub tags: Option<Vec<Tag>>,
             pub scope: Option<String>,
             pub fields: Option<Vec<String>>,
             pub falsepositives: Option<Vec<String>>,
@@ -189,17 +762,47 @@ impl<'de> Visitor<'de> for SigmaRuleVisitor {
             pub extra: HashMap<String, serde_json::Value>,
         }
 
-        let mut helper =
-            SigmaRuleHelper::deserialize(de::value::MapAccessDeserializer::new(&mut map))?;
+        let (helper, mut warnings) = warnings::collect(|| {
+            SigmaRuleHelper::deserialize(de::value::MapAccessDeserializer::new(&mut map))
+        });
+        let mut helper = helper?;
+
+        let (id, generated_id) = match helper.id.take() {
+            Some(id) => (id, false),
+            None if GENERATE_IDS.with(Cell::get) => {
+                (generate_id(&helper.title, &helper.extra), true)
+            }
+            None => return Err(de::Error::missing_field("id")),
+        };
 
         if let RuleType::Correlation(ref mut rule) = helper.rule {
-            rule.inner.id = helper.id.clone();
+            rule.inner.id = id.to_string();
         }
 
+        // flattened fields all receive the unconsumed keys, so remove
+        // the ones owned by the rule type from `extra`
+        let owned: &[&str] = match helper.rule {
+            RuleType::Detection(_) => &["logsource", "detection"],
+            RuleType::Correlation(_) => &["correlation"],
+        };
+        owned.iter().for_each(|key| {
+            helper.extra.remove(*key);
+        });
+        let mut unknown = helper
+            .extra
+            .keys()
+            .filter(|field| warnings::is_unknown_field(field))
+            .collect::<Vec<_>>();
+        unknown.sort();
+        warnings.extend(unknown.into_iter().map(|field| RuleWarning::UnknownField {
+            field: field.to_string(),
+        }));
+
         Ok(SigmaRule {
             title: helper.title,
-            id: helper.id,
+            id,
             name: helper.name,
+            related: helper.related,
             description: helper.description,
             references: helper.references,
             author: helper.author,
@@ -213,6 +816,10 @@ impl<'de> Visitor<'de> for SigmaRuleVisitor {
             falsepositives: helper.falsepositives,
             level: helper.level,
             rule: helper.rule,
+            source: Source::default(),
+            generated_id,
+            warnings,
+            owner: None,
             extra: helper.extra,
         })
     }
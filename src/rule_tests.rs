@@ -0,0 +1,102 @@
+//! Sample events embedded in rules to test them with
+//!
+//! Rules may carry positive and negative sample events in a `tests`
+//! field, which [`SigmaCollection::run_rule_tests`] evaluates
+//!
+//! ```yaml
+//! title: test rule
+//! id: test-rule
+//! logsource:
+//!   category: test
+//! detection:
+//!   selection:
+//!     foo: bar
+//!   condition: selection
+//! tests:
+//!   positive:
+//!     - foo: bar
+//!   negative:
+//!     - foo: baz
+//! ```
+//!
+//! [`SigmaCollection::run_rule_tests`]: ../struct.SigmaCollection.html#method.run_rule_tests
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::detection::MatchOptions;
+use crate::rule::{RuleId, RuleType, SigmaRule};
+
+/// the `tests` field of a rule
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleTests {
+    #[serde(default)]
+    positive: Vec<Value>,
+    #[serde(default)]
+    negative: Vec<Value>,
+}
+
+/// The outcome of the sample events of a rule,
+/// see [`SigmaCollection::run_rule_tests`]
+///
+/// [`SigmaCollection::run_rule_tests`]: ../struct.SigmaCollection.html#method.run_rule_tests
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RuleTestResult {
+    pub rule: RuleId,
+    /// indexes of the positive events the rule does not match
+    pub missed: Vec<usize>,
+    /// indexes of the negative events the rule matches
+    pub false_positives: Vec<usize>,
+    /// why the tests could not be run
+    pub error: Option<String>,
+}
+
+impl RuleTestResult {
+    /// whether the rule matches exactly its positive events
+    pub fn passed(&self) -> bool {
+        self.error.is_none() && self.missed.is_empty() && self.false_positives.is_empty()
+    }
+
+    fn error(rule: &SigmaRule, error: String) -> Self {
+        RuleTestResult {
+            rule: rule.id.clone(),
+            missed: vec![],
+            false_positives: vec![],
+            error: Some(error),
+        }
+    }
+}
+
+/// run the sample events of `rule`, if it has any
+pub(crate) fn run(rule: &SigmaRule, options: &MatchOptions) -> Option<RuleTestResult> {
+    let tests = rule.extra.get("tests")?;
+    let tests = match RuleTests::deserialize(tests) {
+        Ok(tests) => tests,
+        Err(e) => return Some(RuleTestResult::error(rule, format!("invalid tests: {}", e))),
+    };
+    let RuleType::Detection(ref detection) = rule.rule else {
+        return Some(RuleTestResult::error(
+            rule,
+            "tests are only supported for detection rules".to_string(),
+        ));
+    };
+
+    // the events come from the log source of the rule
+    let options = options.for_logsource(&detection.logsource);
+    let indexes = |events: &[Value], expected: bool| {
+        events
+            .iter()
+            .enumerate()
+            .filter(|(_, event)| detection.is_match(event, &options) != expected)
+            .map(|(i, _)| i)
+            .collect()
+    };
+    Some(RuleTestResult {
+        rule: rule.id.clone(),
+        missed: indexes(&tests.positive, true),
+        false_positives: indexes(&tests.negative, false),
+        error: None,
+    })
+}
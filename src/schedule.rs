@@ -0,0 +1,89 @@
+//! Activation schedules of rules
+
+use chrono::{DateTime, Datelike, FixedOffset, NaiveTime, ParseError, Utc, Weekday};
+
+/// When a rule is active, see [`SigmaCollection::schedule`]
+///
+/// A schedule is checked against the time of each event (see
+/// [`Event::timestamp`]), or the current time for events without one.
+/// A time window ending before it starts wraps around midnight and
+/// belongs to the day it starts on, e.g. `22:00`–`06:00` on Friday
+/// covers Saturday morning. Times are in UTC unless a
+/// [`utc_offset`](#method.utc_offset) is set
+///
+/// ```rust
+/// # use chrono::{FixedOffset, Weekday};
+/// # use sigmars::Schedule;
+/// # fn main() -> Result<(), chrono::ParseError> {
+/// // out of hours in UTC+2
+/// let nights = Schedule::new()
+///     .between("22:00", "06:00")?
+///     .utc_offset(FixedOffset::east_opt(2 * 3600).unwrap());
+/// let weekends = Schedule::new().days([Weekday::Sat, Weekday::Sun]);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`SigmaCollection::schedule`]: struct.SigmaCollection.html#method.schedule
+/// [`Event::timestamp`]: event/struct.Event.html#method.timestamp
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    days: Option<Vec<Weekday>>,
+    window: Option<(NaiveTime, NaiveTime)>,
+    offset: FixedOffset,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Schedule {
+            days: None,
+            window: None,
+            offset: FixedOffset::east_opt(0).unwrap(),
+        }
+    }
+}
+
+impl Schedule {
+    /// a schedule active at all times, until restricted
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// only active from `start` until `end`, as `hh:mm` or `hh:mm:ss`
+    pub fn between(mut self, start: &str, end: &str) -> Result<Self, ParseError> {
+        self.window = Some((parse_time(start)?, parse_time(end)?));
+        Ok(self)
+    }
+
+    /// only active on `days`
+    pub fn days(mut self, days: impl IntoIterator<Item = Weekday>) -> Self {
+        self.days = Some(days.into_iter().collect());
+        self
+    }
+
+    /// the offset from UTC of the times and days of the schedule
+    pub fn utc_offset(mut self, offset: FixedOffset) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// whether the schedule is active at `time`
+    pub fn is_active(&self, time: DateTime<Utc>) -> bool {
+        let time = time.with_timezone(&self.offset);
+        let (mut day, now) = (time.weekday(), time.time());
+        if let Some((start, end)) = self.window {
+            match start <= end {
+                true if now < start || now >= end => return false,
+                // the window started the day before
+                false if now < end => day = day.pred(),
+                false if now < start => return false,
+                _ => {}
+            }
+        }
+        self.days.as_ref().is_none_or(|days| days.contains(&day))
+    }
+}
+
+fn parse_time(s: &str) -> Result<NaiveTime, ParseError> {
+    NaiveTime::parse_from_str(s, "%H:%M:%S").or_else(|_| NaiveTime::parse_from_str(s, "%H:%M"))
+}
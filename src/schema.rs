@@ -0,0 +1,224 @@
+//! JSON Schema of the rule format accepted by this crate
+
+use serde_json::{json, Value};
+
+use crate::rule::SigmaRule;
+
+/// the value modifiers of fields, in addition to the `re` flags
+pub(crate) const MODIFIERS: &[&str] = &[
+    "all",
+    "startswith",
+    "endswith",
+    "contains",
+    "exists",
+    "cased",
+    "lt",
+    "lte",
+    "gt",
+    "gte",
+    "cidr",
+    "expand",
+    "fieldref",
+    "base64",
+    "base64offset",
+    "utf16le",
+    "utf16be",
+    "utf16",
+    "wide",
+];
+
+/// the flags following the `re` modifier
+const REGEX_FLAGS: &[&str] = &["i", "m", "s"];
+
+/// the correlation types defined by the Sigma specification and
+/// this crate
+#[cfg(feature = "correlation")]
+pub(crate) const CORRELATION_TYPES: &[&str] = &[
+    "event_count",
+    "value_count",
+    "temporal",
+    "temporal_ordered",
+    "baseline",
+];
+
+/// a timespan, e.g. `5m`
+const TIMESPAN: &str = "^[0-9]+[smhd]$";
+
+impl SigmaRule {
+    /// A [JSON Schema](https://json-schema.org) (draft 2020-12) of
+    /// the rules this crate parses, for editors and CI validation
+    ///
+    /// field names are checked for supported modifiers, and correlation
+    /// sections for supported types (without the custom types of
+    /// [`SigmaCollection::correlation_type`]) and their conditions.
+    /// Conditions and regular expressions are not checked
+    ///
+    /// ```rust
+    /// # use sigmars::SigmaRule;
+    /// let schema = SigmaRule::json_schema();
+    /// assert_eq!(schema["required"], serde_json::json!(["title", "id"]));
+    /// println!("{}", serde_json::to_string_pretty(&schema).unwrap());
+    /// ```
+    ///
+    /// [`SigmaCollection::correlation_type`]: ../struct.SigmaCollection.html#method.correlation_type
+    pub fn json_schema() -> Value {
+        let strings = json!({"type": "array", "items": {"type": "string"}});
+        #[cfg_attr(not(feature = "correlation"), allow(unused_mut))]
+        let mut schema = json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "title": "Sigma rule",
+            "type": "object",
+            "required": ["title", "id"],
+            "properties": {
+                "title": {"type": "string"},
+                "id": {"type": "string"},
+                "name": {"type": "string"},
+                "related": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "required": ["id", "type"],
+                        "properties": {
+                            "id": {"type": "string"},
+                            "type": {
+                                "enum": ["derived", "obsoletes", "merged", "renamed", "similar"],
+                            },
+                        },
+                    },
+                },
+                "description": {"type": "string"},
+                "references": strings,
+                "author": {"type": "string"},
+                "date": {"type": "string"},
+                "modified": {"type": "string"},
+                "status": {
+                    "enum": ["stable", "test", "experimental", "deprecated", "unsupported"],
+                },
+                "license": {"type": "string"},
+                "tags": strings,
+                "scope": {"type": "string"},
+                "fields": strings,
+                "falsepositives": strings,
+                "level": {
+                    "type": "string",
+                    "examples": ["informational", "low", "medium", "high", "critical"],
+                },
+                "logsource": {
+                    "type": "object",
+                    "properties": {
+                        "category": {"type": "string"},
+                        "product": {"type": "string"},
+                        "service": {"type": "string"},
+                    },
+                    "additionalProperties": {"type": "string"},
+                },
+                "detection": detection(),
+            },
+            "oneOf": [
+                {"required": ["logsource", "detection"], "not": {"required": ["correlation"]}},
+                {"required": ["correlation"], "not": {"required": ["detection"]}},
+            ],
+        });
+        #[cfg(feature = "correlation")]
+        {
+            schema["properties"]["correlation"] = correlation();
+        }
+        schema
+    }
+}
+
+/// the `detection` section, its selections and their field names
+fn detection() -> Value {
+    let modifiers = format!(
+        r"(\|({}))*(\|(re|regex)(\|({}))*)?",
+        MODIFIERS.join("|"),
+        REGEX_FLAGS.join("|")
+    );
+    let value = json!({"type": ["string", "number", "boolean"]});
+    let values = json!({"anyOf": [value, {"type": "array", "items": value}]});
+    let fields = json!({
+        "type": "object",
+        "propertyNames": {"pattern": format!("^[^|]*{}$", modifiers)},
+        "additionalProperties": values,
+    });
+    let keywords = json!({"anyOf": [
+        {"type": "string"},
+        {"type": "array", "items": {"type": "string"}},
+    ]});
+    let mut detection = json!({
+        "type": "object",
+        "required": ["condition"],
+        "properties": {
+            "condition": {"type": "string"},
+            "timeframe": {"type": "string", "pattern": TIMESPAN},
+        },
+        "patternProperties": {},
+        "additionalProperties": {"anyOf": [
+            fields,
+            keywords,
+            {"type": "array", "items": {"anyOf": [{"type": "string"}, fields]}},
+        ]},
+    });
+    // keyword lists with modifiers, e.g. `keywords|all`
+    detection["patternProperties"][format!("^[^|]+{}$", modifiers)] = keywords;
+    detection
+}
+
+/// the `correlation` section, with the condition of each type
+#[cfg(feature = "correlation")]
+fn correlation() -> Value {
+    // a single comparison, with the property of the condition type
+    let comparison = |property: Option<(&str, Value)>| {
+        let comparisons = ["gt", "gte", "lt", "lte", "eq"];
+        let mut properties = comparisons
+            .iter()
+            .map(|c| (c.to_string(), json!({"type": "integer"})))
+            .collect::<serde_json::Map<_, _>>();
+        if let Some((property, value)) = property {
+            properties.insert(property.to_string(), value);
+        }
+        json!({
+            "type": "object",
+            "properties": properties,
+            "additionalProperties": false,
+            "oneOf": comparisons.map(|c| json!({"required": [c]})),
+        })
+    };
+    let condition = |kind: &str, condition: Value| {
+        json!({
+            "if": {"properties": {"type": {"const": kind}}},
+            "then": {"required": ["condition"], "properties": {"condition": condition}},
+        })
+    };
+    let timespan = json!({"type": "string", "pattern": TIMESPAN});
+    json!({
+        "type": "object",
+        "required": ["type", "rules", "timespan", "group-by"],
+        "properties": {
+            "type": {"enum": CORRELATION_TYPES},
+            "rules": {"type": "array", "items": {"type": "string"}},
+            "timespan": timespan,
+            "group-by": {"type": "array", "items": {"type": "string"}},
+        },
+        "allOf": [
+            condition("event_count", json!({"anyOf": [
+                comparison(Some(("per", timespan.clone()))),
+                {"type": "array", "items": comparison(None)},
+            ]})),
+            condition("value_count", {
+                let mut value_count = comparison(Some(("field", json!({"type": "string"}))));
+                value_count["required"] = json!(["field"]);
+                value_count
+            }),
+            condition("baseline", json!({
+                "type": "object",
+                "required": ["window", "deviation"],
+                "properties": {
+                    "window": timespan,
+                    "deviation": {"type": "number"},
+                    "minimum": {"type": "integer", "minimum": 0},
+                },
+            })),
+        ],
+    })
+}
@@ -0,0 +1,237 @@
+use std::sync::Arc;
+
+use arc_swap::ArcSwap;
+
+use crate::collection::{CollectionError, Evaluation, SigmaCollection};
+use crate::event::{Event, LogSource};
+use crate::matches::Matches;
+use crate::rule::SigmaRule;
+
+#[cfg(feature = "correlation")]
+use crate::correlation;
+
+/// A [`SigmaCollection`] that can be replaced while in use
+///
+/// Readers evaluate events against an immutable snapshot of the
+/// collection without locking, writers build a new collection and
+/// atomically swap it in. Evaluations in flight when a collection is
+/// replaced complete against the snapshot they started with.
+///
+/// Individual rules can be added and removed concurrently with
+/// evaluation, each update clones the current collection (sharing
+/// its compiled rules) and swaps in the updated clone.
+///
+/// [`SigmaCollection`]: struct.SigmaCollection.html
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use serde_json::json;
+/// # use sigmars::{Event, SharedSigmaCollection, SigmaCollection};
+/// static RULES: &str = r#"
+/// title: test rule
+/// id: test-rule
+/// logsource:
+///   category: test
+/// detection:
+///   selection:
+///     foo: bar
+///   condition: selection
+/// "#;
+///
+/// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+/// let shared = SharedSigmaCollection::new(SigmaCollection::new());
+/// let event = Event::new(json!({"foo": "bar"}));
+/// assert!(shared.get_detection_matches(&event).is_empty());
+///
+/// shared.store(RULES.parse()?);
+/// assert!(shared.get_detection_matches(&event).len() == 1);
+/// # Ok(())
+/// # }
+/// ```
+pub struct SharedSigmaCollection {
+    inner: ArcSwap<SigmaCollection>,
+}
+
+impl SharedSigmaCollection {
+    pub fn new(collection: SigmaCollection) -> Self {
+        SharedSigmaCollection {
+            inner: ArcSwap::from_pointee(collection),
+        }
+    }
+
+    /// a snapshot of the current collection
+    pub fn load(&self) -> Arc<SigmaCollection> {
+        self.inner.load_full()
+    }
+
+    /// replace the current collection, returning the previous one
+    ///
+    /// correlation rules in `collection` must already be initialized,
+    /// see [`replace`](#method.replace)
+    pub fn store(&self, collection: SigmaCollection) -> Arc<SigmaCollection> {
+        self.inner.swap(Arc::new(collection))
+    }
+
+    /// add a Sigma rule
+    ///
+    /// correlation rules must be added with
+    /// [`add_with_backend`](#method.add_with_backend)
+    pub fn add(&self, rule: SigmaRule) -> Result<(), CollectionError> {
+        let rule = self.inner.load().prepare(rule);
+        let mut result = Ok(());
+        self.inner.rcu(|current| {
+            let mut next = SigmaCollection::clone(current);
+            result = next.add_shared(rule.clone());
+            match result {
+                Ok(_) => Arc::new(next),
+                Err(_) => current.clone(),
+            }
+        });
+        result
+    }
+
+    /// remove a Sigma rule, see [`SigmaCollection::remove`]
+    ///
+    /// [`SigmaCollection::remove`]: struct.SigmaCollection.html#method.remove
+    pub fn remove(&self, id: &str) -> Result<Option<Arc<SigmaRule>>, CollectionError> {
+        let mut result = Ok(None);
+        self.inner.rcu(|current| {
+            let mut next = SigmaCollection::clone(current);
+            result = next.remove(id);
+            match result {
+                Ok(Some(_)) => Arc::new(next),
+                _ => current.clone(),
+            }
+        });
+        result
+    }
+
+    /// see [`SigmaCollection::get_detection_matches`]
+    ///
+    /// [`SigmaCollection::get_detection_matches`]: struct.SigmaCollection.html#method.get_detection_matches
+    pub fn get_detection_matches(&self, event: &Event) -> Matches {
+        self.inner.load().get_detection_matches(event)
+    }
+
+    /// see [`SigmaCollection::get_detection_matches_for`]
+    ///
+    /// [`SigmaCollection::get_detection_matches_for`]: struct.SigmaCollection.html#method.get_detection_matches_for
+    pub fn get_detection_matches_for(&self, event: &Event, logsource: &LogSource) -> Matches {
+        self.inner.load().get_detection_matches_for(event, logsource)
+    }
+
+    /// see [`SigmaCollection::evaluate_detections`]
+    ///
+    /// [`SigmaCollection::evaluate_detections`]: struct.SigmaCollection.html#method.evaluate_detections
+    pub fn evaluate_detections(&self, event: &Event) -> Evaluation {
+        self.inner.load().evaluate_detections(event)
+    }
+
+    /// see [`SigmaCollection::get_detection_matches_unfiltered`]
+    ///
+    /// [`SigmaCollection::get_detection_matches_unfiltered`]: struct.SigmaCollection.html#method.get_detection_matches_unfiltered
+    pub fn get_detection_matches_unfiltered(&self, event: &Event) -> Matches {
+        self.inner.load().get_detection_matches_unfiltered(event)
+    }
+}
+
+#[cfg(feature = "correlation")]
+impl SharedSigmaCollection {
+    /// initialize the correlation rules of `collection` with `backend`
+    /// and replace the current collection, returning the previous one
+    ///
+    /// correlation state is owned by the backend and keyed by rule ID,
+    /// so correlation rules present in both collections keep their state
+    /// when registered with the backend used by the previous collection
+    pub async fn replace(
+        &self,
+        mut collection: SigmaCollection,
+        backend: &mut impl correlation::Backend,
+    ) -> Arc<SigmaCollection> {
+        collection.init(backend).await;
+        self.store(collection)
+    }
+
+    /// add a Sigma rule, registering it with `backend` if it is
+    /// a correlation rule
+    pub async fn add_with_backend(
+        &self,
+        rule: SigmaRule,
+        backend: &mut impl correlation::Backend,
+    ) -> Result<(), CollectionError> {
+        if let crate::rule::RuleType::Correlation(ref corr) = rule.rule {
+            backend
+                .register(corr)
+                .await
+                .map_err(|e| CollectionError::BackendError(e.to_string()))?;
+        }
+        self.add(rule)
+    }
+
+    /// see [`SigmaCollection::get_matches`]
+    ///
+    /// [`SigmaCollection::get_matches`]: struct.SigmaCollection.html#method.get_matches
+    pub async fn get_matches(
+        &self,
+        event: &Event,
+    ) -> Result<Matches, Box<dyn std::error::Error + Send + Sync>> {
+        self.load().get_matches(event).await
+    }
+
+    /// see [`SigmaCollection::get_matches_for`]
+    ///
+    /// [`SigmaCollection::get_matches_for`]: struct.SigmaCollection.html#method.get_matches_for
+    pub async fn get_matches_for(
+        &self,
+        event: &Event,
+        logsource: &LogSource,
+    ) -> Result<Matches, Box<dyn std::error::Error + Send + Sync>> {
+        self.load().get_matches_for(event, logsource).await
+    }
+
+    /// see [`SigmaCollection::evaluate`]
+    ///
+    /// [`SigmaCollection::evaluate`]: struct.SigmaCollection.html#method.evaluate
+    pub async fn evaluate(
+        &self,
+        event: &Event,
+    ) -> Result<Evaluation, Box<dyn std::error::Error + Send + Sync>> {
+        self.load().evaluate(event).await
+    }
+
+    /// see [`SigmaCollection::get_matches_batch`]
+    ///
+    /// [`SigmaCollection::get_matches_batch`]: struct.SigmaCollection.html#method.get_matches_batch
+    pub async fn get_matches_batch(
+        &self,
+        events: &[Event],
+    ) -> Result<Vec<Matches>, Box<dyn std::error::Error + Send + Sync>> {
+        self.load().get_matches_batch(events).await
+    }
+
+    /// see [`SigmaCollection::get_matches_unfiltered`]
+    ///
+    /// [`SigmaCollection::get_matches_unfiltered`]: struct.SigmaCollection.html#method.get_matches_unfiltered
+    pub async fn get_matches_unfiltered(
+        &self,
+        event: &Event,
+    ) -> Result<Matches, Box<dyn std::error::Error + Send + Sync>> {
+        self.load().get_matches_unfiltered(event).await
+    }
+
+    /// see [`SigmaCollection::warm_up`]
+    ///
+    /// [`SigmaCollection::warm_up`]: struct.SigmaCollection.html#method.warm_up
+    pub async fn warm_up(
+        &self,
+        events: &[Event],
+    ) -> Result<usize, Box<dyn std::error::Error + Send + Sync>> {
+        self.load().warm_up(events).await
+    }
+}
+
+impl From<SigmaCollection> for SharedSigmaCollection {
+    fn from(collection: SigmaCollection) -> Self {
+        Self::new(collection)
+    }
+}
@@ -0,0 +1,24 @@
+//! Verification of [minisign](https://jedisct1.github.io/minisign/)
+//! signatures on rule files
+
+use std::path::Path;
+
+use minisign_verify::{PublicKey, Signature};
+
+/// verify `data` against a minisign signature made by any of `keys`
+pub(crate) fn verify(keys: &[PublicKey], data: &[u8], signature: &str) -> Result<(), String> {
+    let signature = Signature::decode(signature).map_err(|e| e.to_string())?;
+    keys.iter()
+        .find(|key| key.verify(data, &signature, false).is_ok())
+        .map(|_| ())
+        .ok_or_else(|| "no trusted key signed this file".to_string())
+}
+
+/// verify a rule file against its detached `<file>.minisig` signature
+pub(crate) fn verify_file(keys: &[PublicKey], path: &Path, data: &[u8]) -> Result<(), String> {
+    let mut signature_path = path.as_os_str().to_owned();
+    signature_path.push(".minisig");
+    let signature = std::fs::read_to_string(&signature_path)
+        .map_err(|e| format!("{}: {}", Path::new(&signature_path).display(), e))?;
+    verify(keys, data, &signature)
+}
@@ -0,0 +1,237 @@
+//! Delivery of matches to Kafka topics
+
+use std::time::Duration;
+
+use rdkafka::config::ClientConfig;
+use rdkafka::error::{KafkaError, RDKafkaErrorCode};
+use rdkafka::producer::{FutureProducer, FutureRecord};
+use serde_json::Value;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::{JoinError, JoinHandle, JoinSet};
+
+use super::{AlertFormat, DeliveryReport};
+use crate::event::Event;
+use crate::observer::Match;
+use crate::rule::{RuleType, SigmaRule};
+
+/// The key of the messages published by a [`KafkaSink`], which
+/// selects their partition
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MessageKey {
+    /// the ID of the matching rule
+    #[default]
+    RuleId,
+    /// the values of the group-by fields of correlation rules joined
+    /// with `|`, keeping the matches of a group (e.g. a user) in order
+    /// on one partition; the rule ID for other rules
+    GroupBy,
+}
+
+/// Publishes matches to a Kafka topic, one alert per message
+///
+/// the producer waits for all in-sync replicas to acknowledge each
+/// message and retries failures without duplicating messages
+/// (`acks=all`, `enable.idempotence=true`), messages that are not
+/// delivered within librdkafka's `message.timeout.ms` are dropped
+///
+/// ```rust,no_run
+/// # use std::error::Error;
+/// # use serde_json::json;
+/// # use sigmars::sinks::{KafkaSink, MessageKey};
+/// # use sigmars::{Event, SigmaCollection};
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+/// let sink = KafkaSink::new("localhost:9092", "sigma-alerts")
+///     .key(MessageKey::GroupBy)
+///     .set("compression.type", "zstd")
+///     .spawn()?;
+///
+/// let rules = SigmaCollection::builder()
+///     .on_match(sink.observer())
+///     .build_from_dir("/path/to/rules")?;
+/// rules.get_detection_matches(&Event::new(json!({"foo": "bar"})));
+///
+/// let report = sink.close().await;
+/// println!("{} alerts delivered, {} dropped", report.delivered, report.dropped);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct KafkaSink {
+    config: ClientConfig,
+    topic: String,
+    format: AlertFormat,
+    key: MessageKey,
+}
+
+impl KafkaSink {
+    /// a sink publishing to `topic` on the comma-separated `brokers`
+    pub fn new(brokers: &str, topic: &str) -> Self {
+        let mut config = ClientConfig::new();
+        config
+            .set("bootstrap.servers", brokers)
+            .set("acks", "all")
+            .set("enable.idempotence", "true");
+        KafkaSink {
+            config,
+            topic: topic.to_string(),
+            format: AlertFormat::default(),
+            key: MessageKey::default(),
+        }
+    }
+
+    pub fn format(mut self, format: AlertFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn key(mut self, key: MessageKey) -> Self {
+        self.key = key;
+        self
+    }
+
+    /// set a librdkafka producer option (e.g. `sasl.mechanism`)
+    pub fn set(mut self, option: &str, value: &str) -> Self {
+        self.config.set(option, value);
+        self
+    }
+
+    /// create the producer and start publishing on a background task
+    ///
+    /// must be called from within a tokio runtime
+    pub fn spawn(self) -> Result<KafkaHandle, KafkaError> {
+        let producer = self.config.create::<FutureProducer>()?;
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let (close, closed) = oneshot::channel();
+        Ok(KafkaHandle {
+            sender,
+            format: self.format,
+            key: self.key,
+            close,
+            task: tokio::spawn(run(producer, self.topic, receiver, closed)),
+        })
+    }
+}
+
+async fn run(
+    producer: FutureProducer,
+    topic: String,
+    mut receiver: mpsc::UnboundedReceiver<(String, String)>,
+    mut closed: oneshot::Receiver<()>,
+) -> DeliveryReport {
+    let mut report = DeliveryReport::default();
+    let mut pending = JoinSet::new();
+    loop {
+        tokio::select! {
+            Some((key, payload)) = receiver.recv() => {
+                publish(&producer, &topic, &key, &payload, &mut pending, &mut report).await;
+            }
+            _ = &mut closed => break,
+        }
+        while let Some(delivery) = pending.try_join_next() {
+            count(delivery, &mut report);
+        }
+    }
+    receiver.close();
+    while let Ok((key, payload)) = receiver.try_recv() {
+        publish(&producer, &topic, &key, &payload, &mut pending, &mut report).await;
+    }
+    while let Some(delivery) = pending.join_next().await {
+        count(delivery, &mut report);
+    }
+    report
+}
+
+/// enqueue a message, waiting while the producer queue is full
+async fn publish(
+    producer: &FutureProducer,
+    topic: &str,
+    key: &str,
+    payload: &str,
+    pending: &mut JoinSet<bool>,
+    report: &mut DeliveryReport,
+) {
+    loop {
+        let record = FutureRecord::to(topic).key(key).payload(payload);
+        match producer.send_result(record) {
+            Ok(delivery) => {
+                pending.spawn(async move { matches!(delivery.await, Ok(Ok(_))) });
+                return;
+            }
+            Err((KafkaError::MessageProduction(RDKafkaErrorCode::QueueFull), _)) => {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            Err(_) => {
+                report.dropped += 1;
+                return;
+            }
+        }
+    }
+}
+
+/// count a delivery, whether the message was acknowledged by the brokers
+fn count(delivery: Result<bool, JoinError>, report: &mut DeliveryReport) {
+    match delivery {
+        Ok(true) => report.delivered += 1,
+        _ => report.dropped += 1,
+    }
+}
+
+/// the message key of a match of `rule` against `event`
+fn message_key(key: MessageKey, rule: &SigmaRule, event: &Event) -> String {
+    match (key, &rule.rule) {
+        (MessageKey::GroupBy, RuleType::Correlation(correlation))
+            if !correlation.group_by().is_empty() =>
+        {
+            correlation
+                .group_by()
+                .iter()
+                .map(|field| match event.data.get(field) {
+                    Some(Value::String(value)) => value.clone(),
+                    Some(value) => value.to_string(),
+                    None => String::new(),
+                })
+                .collect::<Vec<_>>()
+                .join("|")
+        }
+        _ => rule.id.to_string(),
+    }
+}
+
+/// A running [`KafkaSink`]
+#[derive(Debug)]
+pub struct KafkaHandle {
+    sender: mpsc::UnboundedSender<(String, String)>,
+    format: AlertFormat,
+    key: MessageKey,
+    close: oneshot::Sender<()>,
+    task: JoinHandle<DeliveryReport>,
+}
+
+impl KafkaHandle {
+    /// an observer queueing matches for publication, see
+    /// [`SigmaCollection::on_match`]
+    ///
+    /// [`SigmaCollection::on_match`]: ../struct.SigmaCollection.html#method.on_match
+    pub fn observer(&self) -> impl FnMut(&SigmaRule, &Event, &Match) + Send + 'static {
+        let sender = self.sender.clone();
+        let (format, key) = (self.format, self.key);
+        move |rule, event, _| {
+            let message = (
+                message_key(key, rule, event),
+                format.alert(rule, event).to_string(),
+            );
+            // the sink is closed, matches are no longer published
+            let _ = sender.send(message);
+        }
+    }
+
+    /// publish the pending alerts, wait for their delivery and stop
+    ///
+    /// matches of observers still registered with a collection
+    /// are no longer published
+    pub async fn close(self) -> DeliveryReport {
+        let _ = self.close.send(());
+        self.task.await.unwrap_or_default()
+    }
+}
@@ -0,0 +1,62 @@
+//! Delivery of matches to external systems
+//!
+//! Sinks are fed by an observer registered with
+//! [`SigmaCollection::on_match`] and deliver matches as OCSF Detection
+//! Findings or ECS alerts from a background task:
+//!
+//! - [`WebhookSink`] posts them to an HTTP endpoint (feature `webhook`)
+//! - [`KafkaSink`] publishes them to a Kafka topic (feature `kafka`)
+//! - [`SyslogSink`] sends them to a syslog collector, optionally as CEF
+//!   records (feature `syslog`)
+//!
+//! [`SigmaCollection::on_match`]: ../struct.SigmaCollection.html#method.on_match
+
+use serde_json::Value;
+
+use crate::event::Event;
+use crate::rule::SigmaRule;
+
+#[cfg(feature = "kafka")]
+mod kafka;
+#[cfg(feature = "syslog")]
+mod syslog;
+#[cfg(feature = "webhook")]
+mod webhook;
+
+#[cfg(feature = "kafka")]
+pub use kafka::{KafkaHandle, KafkaSink, MessageKey};
+#[cfg(feature = "syslog")]
+pub use syslog::{SyslogHandle, SyslogSink};
+#[cfg(feature = "webhook")]
+pub use webhook::{WebhookHandle, WebhookSink};
+
+/// The JSON format of delivered matches
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AlertFormat {
+    /// OCSF Detection Findings, see [`SigmaRule::to_finding`]
+    ///
+    /// [`SigmaRule::to_finding`]: ../rule/struct.SigmaRule.html#method.to_finding
+    #[default]
+    Ocsf,
+    /// ECS alerts, see [`SigmaRule::to_ecs_alert`]
+    ///
+    /// [`SigmaRule::to_ecs_alert`]: ../rule/struct.SigmaRule.html#method.to_ecs_alert
+    Ecs,
+}
+
+impl AlertFormat {
+    fn alert(self, rule: &SigmaRule, event: &Event) -> Value {
+        match self {
+            AlertFormat::Ocsf => rule.to_finding(event),
+            AlertFormat::Ecs => rule.to_ecs_alert(event),
+        }
+    }
+}
+
+/// The counts of alerts delivered and dropped by a sink
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DeliveryReport {
+    pub delivered: usize,
+    pub dropped: usize,
+}
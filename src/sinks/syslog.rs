@@ -0,0 +1,332 @@
+//! Delivery of matches to syslog collectors
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use chrono::{SecondsFormat, Utc};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use tokio_rustls::rustls::pki_types::{CertificateDer, ServerName};
+use tokio_rustls::rustls::{crypto, ClientConfig, RootCertStore};
+use tokio_rustls::TlsConnector;
+
+use super::{AlertFormat, DeliveryReport};
+use crate::event::Event;
+use crate::observer::Match;
+use crate::rule::SigmaRule;
+
+/// The transport of a [`SyslogSink`]
+#[derive(Debug, Clone)]
+enum Transport {
+    Udp,
+    Tcp,
+    Tls {
+        server_name: String,
+        roots: Vec<Vec<u8>>,
+    },
+}
+
+/// Sends matches to a syslog collector as RFC 5424 messages
+///
+/// messages carry a JSON alert, or an ArcSight CEF record (see
+/// [`SigmaRule::to_cef`]), with a severity following the rule level.
+/// They are sent one per datagram over UDP, and framed with their
+/// length (RFC 6587 octet counting) over TCP and TLS, reconnecting once
+/// when the connection fails; messages that cannot be sent are dropped
+///
+/// ```rust,no_run
+/// # use std::error::Error;
+/// # use serde_json::json;
+/// # use sigmars::sinks::SyslogSink;
+/// # use sigmars::{Event, SigmaCollection};
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+/// let sink = SyslogSink::tls("siem.example.com:6514", "siem.example.com")
+///     .cef()
+///     .hostname("detection-1")
+///     .spawn()?;
+///
+/// let rules = SigmaCollection::builder()
+///     .on_match(sink.observer())
+///     .build_from_dir("/path/to/rules")?;
+/// rules.get_detection_matches(&Event::new(json!({"foo": "bar"})));
+///
+/// let report = sink.close().await;
+/// println!("{} alerts delivered, {} dropped", report.delivered, report.dropped);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`SigmaRule::to_cef`]: ../rule/struct.SigmaRule.html#method.to_cef
+#[derive(Debug, Clone)]
+pub struct SyslogSink {
+    address: String,
+    transport: Transport,
+    format: AlertFormat,
+    cef: bool,
+    facility: u8,
+    hostname: String,
+    app_name: String,
+}
+
+impl SyslogSink {
+    fn new(address: &str, transport: Transport) -> Self {
+        SyslogSink {
+            address: address.to_string(),
+            transport,
+            format: AlertFormat::default(),
+            cef: false,
+            facility: 16,
+            hostname: "-".to_string(),
+            app_name: "sigmars".to_string(),
+        }
+    }
+
+    /// a sink sending datagrams to `address` (e.g. `siem:514`)
+    pub fn udp(address: &str) -> Self {
+        Self::new(address, Transport::Udp)
+    }
+
+    /// a sink sending to `address` over TCP
+    pub fn tcp(address: &str) -> Self {
+        Self::new(address, Transport::Tcp)
+    }
+
+    /// a sink sending to `address` over TLS, verifying that the
+    /// collector's certificate is valid for `server_name`
+    pub fn tls(address: &str, server_name: &str) -> Self {
+        let transport = Transport::Tls {
+            server_name: server_name.to_string(),
+            roots: vec![],
+        };
+        Self::new(address, transport)
+    }
+
+    /// trust a DER-encoded CA certificate for TLS, in addition to the
+    /// Mozilla root certificates (e.g. for an internal CA)
+    pub fn root_certificate(mut self, der: &[u8]) -> Self {
+        if let Transport::Tls { ref mut roots, .. } = self.transport {
+            roots.push(der.to_vec());
+        }
+        self
+    }
+
+    pub fn format(mut self, format: AlertFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// send CEF records rather than JSON alerts
+    pub fn cef(mut self) -> Self {
+        self.cef = true;
+        self
+    }
+
+    /// the syslog facility of the messages, 16 (`local0`) by default
+    pub fn facility(mut self, facility: u8) -> Self {
+        self.facility = facility.min(23);
+        self
+    }
+
+    /// the `HOSTNAME` of the messages, `-` (unknown) by default
+    pub fn hostname(mut self, hostname: &str) -> Self {
+        self.hostname = hostname.to_string();
+        self
+    }
+
+    /// the `APP-NAME` of the messages, `sigmars` by default
+    pub fn app_name(mut self, app_name: &str) -> Self {
+        self.app_name = app_name.to_string();
+        self
+    }
+
+    /// start sending messages on a background task
+    ///
+    /// fails if the TLS server name or a root certificate is invalid,
+    /// must be called from within a tokio runtime
+    pub fn spawn(self) -> io::Result<SyslogHandle> {
+        let connector = match self.transport {
+            Transport::Tls {
+                ref server_name,
+                ref roots,
+            } => Some(connector(server_name, roots)?),
+            _ => None,
+        };
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let (close, closed) = oneshot::channel();
+        Ok(SyslogHandle {
+            sender,
+            sink: self.clone(),
+            close,
+            task: tokio::spawn(self.run(connector, receiver, closed)),
+        })
+    }
+
+    /// an RFC 5424 message for a match of `rule` against `event`
+    fn message(&self, rule: &SigmaRule, event: &Event) -> String {
+        let severity = match rule.level.as_deref() {
+            Some("critical") => 2,
+            Some("high") => 3,
+            Some("medium") => 4,
+            Some("informational") => 6,
+            _ => 5,
+        };
+        let msg = match self.cef {
+            true => rule.to_cef(event),
+            false => self.format.alert(rule, event).to_string(),
+        };
+        format!(
+            "<{}>1 {} {} {} {} sigma - {}",
+            self.facility as u32 * 8 + severity,
+            Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
+            self.hostname,
+            self.app_name,
+            std::process::id(),
+            msg,
+        )
+    }
+
+    async fn run(
+        self,
+        connector: Option<Tls>,
+        mut receiver: mpsc::UnboundedReceiver<String>,
+        mut closed: oneshot::Receiver<()>,
+    ) -> DeliveryReport {
+        let mut report = DeliveryReport::default();
+        let mut connection = None;
+        loop {
+            let message = tokio::select! {
+                Some(message) = receiver.recv() => message,
+                _ = &mut closed => break,
+            };
+            self.deliver(&connector, &mut connection, &message, &mut report)
+                .await;
+        }
+        receiver.close();
+        while let Ok(message) = receiver.try_recv() {
+            self.deliver(&connector, &mut connection, &message, &mut report)
+                .await;
+        }
+        if let Some(Connection::Stream(mut stream)) = connection {
+            let _ = stream.shutdown().await;
+        }
+        report
+    }
+
+    /// send `message`, connecting again once if the connection fails
+    async fn deliver(
+        &self,
+        connector: &Option<Tls>,
+        connection: &mut Option<Connection>,
+        message: &str,
+        report: &mut DeliveryReport,
+    ) {
+        for _ in 0..2 {
+            if connection.is_none() {
+                *connection = self.connect(connector).await.ok();
+            }
+            let result = match connection {
+                Some(Connection::Udp(socket)) => socket.send(message.as_bytes()).await.map(|_| ()),
+                Some(Connection::Stream(stream)) => {
+                    let frame = format!("{} {}", message.len(), message);
+                    stream.write_all(frame.as_bytes()).await
+                }
+                None => continue,
+            };
+            match result {
+                Ok(()) => {
+                    report.delivered += 1;
+                    return;
+                }
+                Err(_) => *connection = None,
+            }
+        }
+        report.dropped += 1;
+    }
+
+    async fn connect(&self, connector: &Option<Tls>) -> io::Result<Connection> {
+        if let Transport::Udp = self.transport {
+            let address = tokio::net::lookup_host(&self.address)
+                .await?
+                .next()
+                .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+            let local = match address {
+                SocketAddr::V4(_) => "0.0.0.0:0",
+                SocketAddr::V6(_) => "[::]:0",
+            };
+            let socket = UdpSocket::bind(local).await?;
+            socket.connect(address).await?;
+            return Ok(Connection::Udp(socket));
+        }
+        let stream = TcpStream::connect(&self.address).await?;
+        Ok(Connection::Stream(match connector {
+            Some((connector, server_name)) => {
+                Box::new(connector.connect(server_name.clone(), stream).await?)
+            }
+            None => Box::new(stream),
+        }))
+    }
+}
+
+/// a TLS connector and the name of the collector
+type Tls = (TlsConnector, ServerName<'static>);
+
+/// a TLS connector trusting the Mozilla and `roots` certificates
+fn connector(server_name: &str, roots: &[Vec<u8>]) -> io::Result<Tls> {
+    let invalid = |e| io::Error::new(io::ErrorKind::InvalidInput, e);
+    let mut store = RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    for root in roots {
+        store
+            .add(CertificateDer::from(root.clone()))
+            .map_err(|e| invalid(e.to_string()))?;
+    }
+    let config = ClientConfig::builder_with_provider(Arc::new(crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .map_err(|e| invalid(e.to_string()))?
+        .with_root_certificates(store)
+        .with_no_client_auth();
+    let server_name =
+        ServerName::try_from(server_name.to_string()).map_err(|e| invalid(e.to_string()))?;
+    Ok((TlsConnector::from(Arc::new(config)), server_name))
+}
+
+enum Connection {
+    Udp(UdpSocket),
+    Stream(Box<dyn AsyncWrite + Send + Unpin>),
+}
+
+/// A running [`SyslogSink`]
+#[derive(Debug)]
+pub struct SyslogHandle {
+    sender: mpsc::UnboundedSender<String>,
+    sink: SyslogSink,
+    close: oneshot::Sender<()>,
+    task: JoinHandle<DeliveryReport>,
+}
+
+impl SyslogHandle {
+    /// an observer queueing matches for sending, see
+    /// [`SigmaCollection::on_match`]
+    ///
+    /// [`SigmaCollection::on_match`]: ../struct.SigmaCollection.html#method.on_match
+    pub fn observer(&self) -> impl FnMut(&SigmaRule, &Event, &Match) + Send + 'static {
+        let sender = self.sender.clone();
+        let sink = self.sink.clone();
+        move |rule, event, _| {
+            // the sink is closed, matches are no longer sent
+            let _ = sender.send(sink.message(rule, event));
+        }
+    }
+
+    /// send the pending messages and stop
+    ///
+    /// matches of observers still registered with a collection
+    /// are no longer sent
+    pub async fn close(self) -> DeliveryReport {
+        let _ = self.close.send(());
+        self.task.await.unwrap_or_default()
+    }
+}
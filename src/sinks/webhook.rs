@@ -0,0 +1,264 @@
+//! Delivery of matches to HTTP endpoints
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::Value;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+
+use super::{AlertFormat, DeliveryReport};
+use crate::event::Event;
+use crate::observer::Match;
+use crate::rule::SigmaRule;
+
+/// Posts matches to an HTTP endpoint as JSON arrays of alerts
+///
+/// alerts are sent once `batch_size` are pending or `flush_interval`
+/// has elapsed. Deliveries failing with a connection error, a `429` or
+/// a `5xx` status are retried up to `max_retries` times, waiting
+/// `backoff` and twice as long after each further failure; batches that
+/// still fail, or fail with another status, are dropped
+///
+/// up to `queue_size` alerts wait for delivery. Matches are never held
+/// up by a slow or unavailable endpoint: those arriving while the queue
+/// is full are dropped, and counted in the [`DeliveryReport`]
+///
+/// ```rust,no_run
+/// # use std::error::Error;
+/// # use std::time::Duration;
+/// # use serde_json::json;
+/// # use sigmars::sinks::{AlertFormat, WebhookSink};
+/// # use sigmars::{Event, SigmaCollection};
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+/// let sink = WebhookSink::new("https://alerts.example.com/sigma")
+///     .format(AlertFormat::Ecs)
+///     .header("Authorization", "Bearer token")
+///     .batch_size(100)
+///     .flush_interval(Duration::from_secs(5))
+///     .spawn();
+///
+/// let rules = SigmaCollection::builder()
+///     .on_match(sink.observer())
+///     .build_from_dir("/path/to/rules")?;
+/// rules.get_detection_matches(&Event::new(json!({"foo": "bar"})));
+///
+/// let report = sink.close().await;
+/// println!("{} alerts delivered, {} dropped", report.delivered, report.dropped);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct WebhookSink {
+    url: String,
+    format: AlertFormat,
+    headers: Vec<(String, String)>,
+    batch_size: usize,
+    flush_interval: Duration,
+    max_retries: u32,
+    backoff: Duration,
+    timeout: Duration,
+    queue_size: usize,
+}
+
+impl WebhookSink {
+    pub fn new(url: &str) -> Self {
+        WebhookSink {
+            url: url.to_string(),
+            format: AlertFormat::default(),
+            headers: vec![],
+            batch_size: 100,
+            flush_interval: Duration::from_secs(1),
+            max_retries: 5,
+            backoff: Duration::from_millis(500),
+            timeout: Duration::from_secs(10),
+            queue_size: 10_000,
+        }
+    }
+
+    pub fn format(mut self, format: AlertFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// add a header to every request (e.g. `Authorization`)
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    /// send once `size` alerts are pending, 100 by default
+    pub fn batch_size(mut self, size: usize) -> Self {
+        self.batch_size = size.max(1);
+        self
+    }
+
+    /// send pending alerts at least this often, every second by default
+    pub fn flush_interval(mut self, interval: Duration) -> Self {
+        self.flush_interval = interval;
+        self
+    }
+
+    /// retry a failed delivery up to `retries` times, 5 by default
+    pub fn max_retries(mut self, retries: u32) -> Self {
+        self.max_retries = retries;
+        self
+    }
+
+    /// wait `backoff` before the first retry, doubling for each
+    /// further retry, 500ms by default
+    pub fn backoff(mut self, backoff: Duration) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// give up on a request after `timeout`, 10s by default
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// queue up to `size` alerts for delivery, dropping further matches
+    /// until the queue drains, 10,000 by default
+    pub fn queue_size(mut self, size: usize) -> Self {
+        self.queue_size = size.max(1);
+        self
+    }
+
+    /// start delivering alerts on a background task
+    ///
+    /// must be called from within a tokio runtime
+    pub fn spawn(self) -> WebhookHandle {
+        let (sender, receiver) = mpsc::channel(self.queue_size);
+        let (close, closed) = oneshot::channel();
+        let format = self.format;
+        WebhookHandle {
+            sender,
+            format,
+            dropped: Arc::new(AtomicUsize::new(0)),
+            close,
+            task: tokio::spawn(self.run(receiver, closed)),
+        }
+    }
+
+    async fn run(
+        self,
+        mut receiver: mpsc::Receiver<Value>,
+        mut closed: oneshot::Receiver<()>,
+    ) -> DeliveryReport {
+        let client = reqwest::Client::new();
+        let mut report = DeliveryReport::default();
+        let mut batch = vec![];
+        let mut interval = tokio::time::interval(self.flush_interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                Some(alert) = receiver.recv() => {
+                    batch.push(alert);
+                    if batch.len() < self.batch_size {
+                        continue;
+                    }
+                }
+                _ = interval.tick() => {}
+                _ = &mut closed => {
+                    receiver.close();
+                    while let Ok(alert) = receiver.try_recv() {
+                        batch.push(alert);
+                    }
+                    while !batch.is_empty() {
+                        let rest = batch.split_off(batch.len().min(self.batch_size));
+                        self.deliver(&client, &mut batch, &mut report).await;
+                        batch = rest;
+                    }
+                    return report;
+                }
+            }
+            self.deliver(&client, &mut batch, &mut report).await;
+        }
+    }
+
+    /// post `batch`, retrying transient failures, and empty it
+    async fn deliver(
+        &self,
+        client: &reqwest::Client,
+        batch: &mut Vec<Value>,
+        report: &mut DeliveryReport,
+    ) {
+        if batch.is_empty() {
+            return;
+        }
+        let count = batch.len();
+        let body = Value::Array(std::mem::take(batch)).to_string();
+        let mut backoff = self.backoff;
+        for attempt in 0..=self.max_retries {
+            if attempt > 0 {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            let request = self
+                .headers
+                .iter()
+                .fold(client.post(&self.url), |request, (name, value)| {
+                    request.header(name, value)
+                })
+                .header("Content-Type", "application/json")
+                .timeout(self.timeout)
+                .body(body.clone());
+            match request.send().await.map(|response| response.status()) {
+                Ok(status) if status.is_success() => {
+                    report.delivered += count;
+                    return;
+                }
+                Ok(status) if status.as_u16() != 429 && !status.is_server_error() => break,
+                _ => {}
+            }
+        }
+        report.dropped += count;
+    }
+}
+
+/// A running [`WebhookSink`]
+#[derive(Debug)]
+pub struct WebhookHandle {
+    sender: mpsc::Sender<Value>,
+    format: AlertFormat,
+    /// matches dropped while the queue was full
+    dropped: Arc<AtomicUsize>,
+    close: oneshot::Sender<()>,
+    task: JoinHandle<DeliveryReport>,
+}
+
+impl WebhookHandle {
+    /// an observer queueing matches for delivery, see
+    /// [`SigmaCollection::on_match`]
+    ///
+    /// [`SigmaCollection::on_match`]: ../struct.SigmaCollection.html#method.on_match
+    pub fn observer(&self) -> impl FnMut(&SigmaRule, &Event, &Match) + Send + 'static {
+        let sender = self.sender.clone();
+        let format = self.format;
+        let dropped = self.dropped.clone();
+        move |rule, event, _| {
+            let alert = format.alert(rule, event);
+            match sender.try_send(alert) {
+                Err(mpsc::error::TrySendError::Full(_)) => {
+                    dropped.fetch_add(1, Ordering::Relaxed);
+                }
+                // the sink is closed, matches are no longer delivered
+                Err(mpsc::error::TrySendError::Closed(_)) | Ok(()) => {}
+            }
+        }
+    }
+
+    /// deliver the pending alerts and stop
+    ///
+    /// matches of observers still registered with a collection
+    /// are no longer delivered
+    pub async fn close(self) -> DeliveryReport {
+        let _ = self.close.send(());
+        let mut report = self.task.await.unwrap_or_default();
+        report.dropped += self.dropped.load(Ordering::Relaxed);
+        report
+    }
+}
@@ -0,0 +1,248 @@
+//! Suppression of known false positives
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::{Duration, SystemTime};
+
+use serde_json::Value;
+
+use crate::detection::get_terminal_from_dotted_path;
+use crate::event::Event;
+use crate::metrics;
+use crate::rule::SigmaRule;
+
+/// A known false positive: the matches of a rule, by ID or name, for
+/// events with the given field values
+///
+/// fields are dotted paths into the event, and strings are compared
+/// case-insensitively. An array field has a value if one of its
+/// elements does
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suppression {
+    pub rule: String,
+    pub fields: BTreeMap<String, Value>,
+    /// when the suppression stops applying, `None` if it does not expire
+    pub expires: Option<SystemTime>,
+    /// why the matches are suppressed, e.g. a ticket reference
+    pub reason: Option<String>,
+}
+
+impl Suppression {
+    /// suppress every match of `rule`
+    pub fn new(rule: &str) -> Self {
+        Suppression {
+            rule: rule.to_string(),
+            fields: BTreeMap::new(),
+            expires: None,
+            reason: None,
+        }
+    }
+
+    /// only suppress matches for events where `field` has `value`
+    pub fn field(mut self, field: &str, value: impl Into<Value>) -> Self {
+        self.fields.insert(field.to_string(), value.into());
+        self
+    }
+
+    pub fn expires_at(mut self, expires: SystemTime) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    pub fn expires_in(self, duration: Duration) -> Self {
+        self.expires_at(SystemTime::now() + duration)
+    }
+
+    pub fn reason(mut self, reason: &str) -> Self {
+        self.reason = Some(reason.to_string());
+        self
+    }
+
+    fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    fn applies(&self, rule: &SigmaRule, event: &Event) -> bool {
+        (*rule.id == *self.rule || rule.name.as_deref() == Some(self.rule.as_str()))
+            && self.fields.iter().all(|(field, value)| {
+                get_terminal_from_dotted_path(field, &event.data)
+                    .is_some_and(|field| value_eq(field, value))
+            })
+    }
+}
+
+fn value_eq(field: &Value, value: &Value) -> bool {
+    match (field, value) {
+        (Value::String(field), Value::String(value)) => {
+            field.to_lowercase() == value.to_lowercase()
+        }
+        (Value::Array(fields), _) if !value.is_array() => {
+            fields.iter().any(|field| value_eq(field, value))
+        }
+        _ => field == value,
+    }
+}
+
+/// A registered [`Suppression`], with the number of matches it suppressed
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct SuppressionEntry {
+    pub id: u64,
+    pub suppression: Suppression,
+    pub suppressed: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    id: u64,
+    suppression: Suppression,
+    suppressed: AtomicU64,
+}
+
+impl From<&Entry> for SuppressionEntry {
+    fn from(entry: &Entry) -> Self {
+        SuppressionEntry {
+            id: entry.id,
+            suppression: entry.suppression.clone(),
+            suppressed: entry.suppressed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    next_id: u64,
+    entries: Vec<Entry>,
+}
+
+/// The suppressions of a collection, see [`SigmaCollection::suppressions`]
+///
+/// Matches of a rule meeting an unexpired suppression are dropped from
+/// the results, are not passed to observers and do not count towards
+/// correlation rules depending on the rule. The store is shared between
+/// clones of a collection and can be updated while events are evaluated
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use serde_json::json;
+/// # use sigmars::{Event, SigmaCollection, Suppression};
+/// # static RULES: &str = r#"
+/// # title: test rule
+/// # id: test-rule
+/// # logsource:
+/// #   category: test
+/// # detection:
+/// #   selection:
+/// #     foo: bar
+/// #   condition: selection
+/// # "#;
+/// let rules: SigmaCollection = RULES.parse().unwrap();
+/// let id = rules.suppressions().add(
+///     Suppression::new("test-rule")
+///         .field("host.name", "build-01")
+///         .expires_in(Duration::from_secs(86400))
+///         .reason("nightly builds"),
+/// );
+///
+/// let event = Event::new(json!({"foo": "bar", "host": {"name": "BUILD-01"}}));
+/// assert!(rules.get_detection_matches(&event).is_empty());
+/// assert_eq!(rules.suppressions().list()[0].suppressed, 1);
+///
+/// rules.suppressions().remove(id);
+/// assert_eq!(rules.get_detection_matches(&event).len(), 1);
+/// ```
+///
+/// [`SigmaCollection::suppressions`]: struct.SigmaCollection.html#method.suppressions
+#[derive(Debug, Default, Clone)]
+pub struct SuppressionStore(Arc<RwLock<Entries>>);
+
+impl SuppressionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// register a suppression, returning its ID
+    pub fn add(&self, suppression: Suppression) -> u64 {
+        let mut entries = self.0.write().unwrap_or_else(PoisonError::into_inner);
+        entries.next_id += 1;
+        let id = entries.next_id;
+        entries.entries.push(Entry {
+            id,
+            suppression,
+            suppressed: AtomicU64::new(0),
+        });
+        id
+    }
+
+    /// unregister a suppression, returning it if it was registered
+    pub fn remove(&self, id: u64) -> Option<Suppression> {
+        let mut entries = self.0.write().unwrap_or_else(PoisonError::into_inner);
+        let idx = entries.entries.iter().position(|entry| entry.id == id)?;
+        Some(entries.entries.remove(idx).suppression)
+    }
+
+    /// the registered suppressions, including expired ones until
+    /// they are removed with [`expire`](#method.expire)
+    pub fn list(&self) -> Vec<SuppressionEntry> {
+        self.0
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entries
+            .iter()
+            .map(SuppressionEntry::from)
+            .collect()
+    }
+
+    /// unregister the expired suppressions, returning them
+    pub fn expire(&self) -> Vec<SuppressionEntry> {
+        let now = SystemTime::now();
+        let mut entries = self.0.write().unwrap_or_else(PoisonError::into_inner);
+        let (expired, live): (Vec<_>, _) = std::mem::take(&mut entries.entries)
+            .into_iter()
+            .partition(|entry| entry.suppression.is_expired(now));
+        entries.entries = live;
+        expired.iter().map(SuppressionEntry::from).collect()
+    }
+
+    /// the number of matches suppressed by the registered suppressions
+    pub fn suppressed(&self) -> u64 {
+        self.0
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entries
+            .iter()
+            .map(|entry| entry.suppressed.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// the ID of the suppression of the match of `rule` for `event`,
+    /// without counting it
+    pub(crate) fn find(&self, rule: &SigmaRule, event: &Event) -> Option<u64> {
+        let now = SystemTime::now();
+        self.0
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entries
+            .iter()
+            .find(|entry| {
+                !entry.suppression.is_expired(now) && entry.suppression.applies(rule, event)
+            })
+            .map(|entry| entry.id)
+    }
+
+    /// whether the match of `rule` for `event` is suppressed,
+    /// counting it if it is
+    pub(crate) fn suppresses(&self, rule: &SigmaRule, event: &Event) -> bool {
+        let now = SystemTime::now();
+        let entries = self.0.read().unwrap_or_else(PoisonError::into_inner);
+        let Some(entry) = entries.entries.iter().find(|entry| {
+            !entry.suppression.is_expired(now) && entry.suppression.applies(rule, event)
+        }) else {
+            return false;
+        };
+        entry.suppressed.fetch_add(1, Ordering::Relaxed);
+        metrics::match_suppressed(&rule.id);
+        true
+    }
+}
@@ -0,0 +1,203 @@
+//! Throughput, latency and match counts tracked by a collection
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{PoisonError, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::rule::RuleId;
+
+/// seconds over which the event rate is averaged
+const RATE_WINDOW: u64 = 60;
+
+/// buckets per power of two of the latency histogram, for percentiles
+/// within 20% of the exact latency
+const SUB_BUCKETS: usize = 4;
+
+/// latencies are tracked from 1µs to 2^32µs (over an hour)
+const LATENCY_BUCKETS: usize = 32 * SUB_BUCKETS;
+
+/// The counters of a collection with telemetry enabled, shared between
+/// its clones
+#[derive(Debug)]
+pub(crate) struct Telemetry {
+    start: Instant,
+    events: AtomicU64,
+    /// (second since `start`, events evaluated in that second)
+    seconds: [(AtomicU64, AtomicU64); RATE_WINDOW as usize + 1],
+    /// detection latencies in microseconds
+    latencies: [AtomicU64; LATENCY_BUCKETS],
+    matches: RwLock<HashMap<RuleId, AtomicU64>>,
+}
+
+impl Default for Telemetry {
+    fn default() -> Self {
+        Telemetry {
+            start: Instant::now(),
+            events: AtomicU64::new(0),
+            seconds: std::array::from_fn(|_| (AtomicU64::new(u64::MAX), AtomicU64::new(0))),
+            latencies: std::array::from_fn(|_| AtomicU64::new(0)),
+            matches: RwLock::default(),
+        }
+    }
+}
+
+impl Telemetry {
+    /// record the detection evaluation of an event, started at `start`
+    pub(crate) fn detection_evaluated(&self, start: Instant, matches: &[RuleId]) {
+        let now = Instant::now();
+        self.events.fetch_add(1, Ordering::Relaxed);
+
+        let second = now.duration_since(self.start).as_secs();
+        let (slot, count) = &self.seconds[(second % self.seconds.len() as u64) as usize];
+        let previous = slot.load(Ordering::Relaxed);
+        if previous != second
+            && slot
+                .compare_exchange(previous, second, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            count.store(0, Ordering::Relaxed);
+        }
+        count.fetch_add(1, Ordering::Relaxed);
+
+        let micros = now.duration_since(start).as_micros() as u64;
+        self.latencies[bucket(micros)].fetch_add(1, Ordering::Relaxed);
+        self.rules_matched(matches);
+    }
+
+    /// record matches of rules
+    pub(crate) fn rules_matched(&self, matches: &[RuleId]) {
+        if matches.is_empty() {
+            return;
+        }
+        let counts = self.matches.read().unwrap_or_else(PoisonError::into_inner);
+        let mut missing = vec![];
+        for id in matches {
+            match counts.get(id) {
+                Some(count) => {
+                    count.fetch_add(1, Ordering::Relaxed);
+                }
+                None => missing.push(id),
+            }
+        }
+        drop(counts);
+        if !missing.is_empty() {
+            let mut counts = self.matches.write().unwrap_or_else(PoisonError::into_inner);
+            for id in missing {
+                counts
+                    .entry(id.clone())
+                    .or_default()
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> TelemetrySnapshot {
+        let elapsed = self.start.elapsed().as_secs();
+        // the current second is incomplete
+        let window = elapsed.min(RATE_WINDOW);
+        let recent = self
+            .seconds
+            .iter()
+            .filter(|(second, _)| {
+                let second = second.load(Ordering::Relaxed);
+                second < elapsed && second + window >= elapsed
+            })
+            .map(|(_, count)| count.load(Ordering::Relaxed))
+            .sum::<u64>();
+
+        let latencies = self
+            .latencies
+            .iter()
+            .map(|count| count.load(Ordering::Relaxed))
+            .collect::<Vec<_>>();
+        let mut rule_matches = self
+            .matches
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .map(|(id, count)| (id.clone(), count.load(Ordering::Relaxed)))
+            .collect::<Vec<_>>();
+        rule_matches.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+        TelemetrySnapshot {
+            uptime: self.start.elapsed(),
+            events: self.events.load(Ordering::Relaxed),
+            events_per_second: match window {
+                0 => 0.0,
+                window => recent as f64 / window as f64,
+            },
+            latency_p50: percentile(&latencies, 0.5),
+            latency_p90: percentile(&latencies, 0.9),
+            latency_p99: percentile(&latencies, 0.99),
+            rule_matches,
+        }
+    }
+}
+
+/// the histogram bucket of a latency
+fn bucket(micros: u64) -> usize {
+    if micros < 2 {
+        return 0;
+    }
+    let power = 63 - micros.leading_zeros() as usize;
+    // the next bits after the leading one select the sub-bucket
+    let sub = match power >= 2 {
+        true => (micros >> (power - 2)) as usize & (SUB_BUCKETS - 1),
+        false => ((micros << (2 - power)) as usize) & (SUB_BUCKETS - 1),
+    };
+    (power * SUB_BUCKETS + sub).min(LATENCY_BUCKETS - 1)
+}
+
+/// the upper bound of the latencies in a histogram bucket
+fn upper_bound(bucket: usize) -> Duration {
+    let (power, sub) = (bucket / SUB_BUCKETS, bucket % SUB_BUCKETS);
+    let micros = (1u64 << power) as f64 * (1.0 + (sub + 1) as f64 / SUB_BUCKETS as f64);
+    Duration::from_micros(micros as u64)
+}
+
+/// the latency below which a fraction `q` of the latencies fall
+fn percentile(latencies: &[u64], q: f64) -> Duration {
+    let total = latencies.iter().sum::<u64>();
+    if total == 0 {
+        return Duration::ZERO;
+    }
+    let rank = ((total as f64 * q).ceil() as u64).max(1);
+    let mut seen = 0;
+    for (bucket, count) in latencies.iter().enumerate() {
+        seen += count;
+        if seen >= rank {
+            return upper_bound(bucket);
+        }
+    }
+    upper_bound(latencies.len() - 1)
+}
+
+/// The telemetry of a collection, see [`SigmaCollection::telemetry`]
+///
+/// latencies are those of the detection rules of an event, estimated
+/// within 20%
+///
+/// [`SigmaCollection::telemetry`]: struct.SigmaCollection.html#method.telemetry
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct TelemetrySnapshot {
+    /// the time since telemetry was enabled
+    pub uptime: Duration,
+    /// events evaluated
+    pub events: u64,
+    /// events evaluated per second over the last minute
+    pub events_per_second: f64,
+    pub latency_p50: Duration,
+    pub latency_p90: Duration,
+    pub latency_p99: Duration,
+    /// the matches of each rule, most matching first
+    pub rule_matches: Vec<(RuleId, u64)>,
+}
+
+impl TelemetrySnapshot {
+    /// the `n` rules with the most matches
+    pub fn top_rules(&self, n: usize) -> &[(RuleId, u64)] {
+        &self.rule_matches[..n.min(self.rule_matches.len())]
+    }
+}
@@ -0,0 +1,280 @@
+//! Rendering of matches as text with user-supplied templates
+
+use std::str::FromStr;
+
+use serde_json::{json, Value};
+use thiserror::Error;
+
+use crate::event::Event;
+use crate::observer::Match;
+use crate::rule::SigmaRule;
+
+/// An error in the syntax of an [`AlertTemplate`]
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum TemplateError {
+    #[error("unclosed tag at offset {0}")]
+    UnclosedTag(usize),
+    #[error("invalid tag {{{{{0}}}}} at offset {1}")]
+    InvalidTag(String, usize),
+    #[error("{{{{#{0}}}}} is not closed")]
+    UnclosedSection(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    Value(String),
+    If {
+        path: String,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    Each {
+        path: String,
+        body: Vec<Node>,
+    },
+}
+
+/// A template rendering matches as text, e.g. the body of a chat
+/// message, an email or a ticket
+///
+/// templates use a subset of the Handlebars syntax, with the rule (as
+/// in its YAML file), the event data and the match as `rule`, `event`
+/// and `match`:
+///
+/// - `{{rule.title}}` is replaced by a value, strings as is and lists
+///   joined with `, `; missing values are empty. Field names containing
+///   dots (e.g. `{{match.fields.user.name}}`) are looked up as is
+/// - `{{#if rule.description}}...{{else}}...{{/if}}` renders its content
+///   if the value is present and not `false`, `0` or empty
+/// - `{{#each rule.tags}}...{{/each}}` renders its content for every
+///   element of a list, which is `{{this}}`
+///
+/// values are not escaped
+///
+/// ```rust
+/// # use std::error::Error;
+/// # use std::sync::mpsc;
+/// # use serde_json::json;
+/// # use sigmars::{AlertTemplate, Event, SigmaCollection};
+/// static RULES: &str = r#"
+/// title: test rule
+/// id: test-rule
+/// level: high
+/// tags:
+///   - attack.execution
+///   - attack.t1059
+/// fields:
+///   - user.name
+/// logsource:
+///   category: test
+/// detection:
+///   selection:
+///     foo: bar
+///   condition: selection
+/// "#;
+///
+/// # fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+/// let template: AlertTemplate = concat!(
+///     "*{{rule.title}}* ({{rule.level}}) by {{match.fields.user.name}}",
+///     "{{#each rule.tags}} #{{this}}{{/each}}",
+/// )
+/// .parse()?;
+///
+/// let (sender, receiver) = mpsc::channel();
+/// let rules = RULES
+///     .parse::<SigmaCollection>()?
+///     .on_match(template.observer(move |text| sender.send(text).unwrap()));
+/// rules.get_detection_matches(&Event::new(json!({"foo": "bar", "user": {"name": "root"}})));
+/// assert_eq!(
+///     receiver.recv()?,
+///     "*test rule* (high) by root #attack.execution #attack.t1059"
+/// );
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertTemplate {
+    nodes: Vec<Node>,
+}
+
+impl FromStr for AlertTemplate {
+    type Err = TemplateError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // the open sections, with their tag and the nodes before them
+        let mut sections: Vec<(String, String, Vec<Node>)> = vec![];
+        // the `then` nodes of `if` sections with an `else`
+        let mut then: Vec<Option<Vec<Node>>> = vec![];
+        let mut nodes = vec![];
+        let mut rest = s;
+        while !rest.is_empty() {
+            let Some(start) = rest.find("{{") else {
+                nodes.push(Node::Text(rest.to_string()));
+                break;
+            };
+            if start > 0 {
+                nodes.push(Node::Text(rest[..start].to_string()));
+            }
+            let offset = s.len() - rest.len() + start;
+            let end = rest[start..]
+                .find("}}")
+                .ok_or(TemplateError::UnclosedTag(offset))?;
+            let tag = rest[start + 2..start + end].trim();
+            rest = &rest[start + end + 2..];
+            let invalid = || TemplateError::InvalidTag(tag.to_string(), offset);
+
+            if let Some(section) = tag.strip_prefix('#') {
+                let (name, path) = section
+                    .split_once(char::is_whitespace)
+                    .ok_or_else(invalid)?;
+                if !matches!(name, "if" | "each") || path.trim().is_empty() {
+                    return Err(invalid());
+                }
+                let outer = std::mem::take(&mut nodes);
+                sections.push((name.to_string(), path.trim().to_string(), outer));
+                then.push(None);
+            } else if tag == "else" {
+                match (sections.last(), then.last_mut()) {
+                    (Some((name, _, _)), Some(then @ None)) if name == "if" => {
+                        *then = Some(std::mem::take(&mut nodes));
+                    }
+                    _ => return Err(invalid()),
+                }
+            } else if let Some(name) = tag.strip_prefix('/') {
+                let (section, path, outer) = match sections.pop() {
+                    Some(section) if section.0 == name.trim() => section,
+                    _ => return Err(invalid()),
+                };
+                let body = std::mem::replace(&mut nodes, outer);
+                nodes.push(match (section.as_str(), then.pop().flatten()) {
+                    ("if", Some(then)) => Node::If {
+                        path,
+                        then,
+                        otherwise: body,
+                    },
+                    ("if", None) => Node::If {
+                        path,
+                        then: body,
+                        otherwise: vec![],
+                    },
+                    _ => Node::Each { path, body },
+                });
+            } else if tag.is_empty() || tag.contains(char::is_whitespace) {
+                return Err(invalid());
+            } else {
+                nodes.push(Node::Value(tag.to_string()));
+            }
+        }
+        match sections.pop() {
+            Some((name, _, _)) => Err(TemplateError::UnclosedSection(name)),
+            None => Ok(AlertTemplate { nodes }),
+        }
+    }
+}
+
+impl AlertTemplate {
+    /// render the template for a match of `rule` against `event`
+    pub fn render(&self, rule: &SigmaRule, event: &Event, m: &Match) -> String {
+        let context = json!({
+            "rule": serde_json::to_value(rule).unwrap_or_default(),
+            "event": event.data,
+            "match": {
+                "correlation": m.correlation,
+                "fields": m.fields,
+                "values": m.values,
+            },
+        });
+        let mut output = String::new();
+        render(&self.nodes, &context, &Value::Null, &mut output);
+        output
+    }
+
+    /// an observer passing the rendered text of every match to `f`,
+    /// see [`SigmaCollection::on_match`]
+    ///
+    /// [`SigmaCollection::on_match`]: struct.SigmaCollection.html#method.on_match
+    pub fn observer<F>(self, mut f: F) -> impl FnMut(&SigmaRule, &Event, &Match) + Send + 'static
+    where
+        F: FnMut(String) + Send + 'static,
+    {
+        move |rule, event, m| f(self.render(rule, event, m))
+    }
+}
+
+fn render(nodes: &[Node], context: &Value, this: &Value, output: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => output.push_str(text),
+            Node::Value(path) => {
+                if let Some(value) = lookup(path, context, this) {
+                    display(value, output);
+                }
+            }
+            Node::If {
+                path,
+                then,
+                otherwise,
+            } => match lookup(path, context, this).is_some_and(truthy) {
+                true => render(then, context, this, output),
+                false => render(otherwise, context, this, output),
+            },
+            Node::Each { path, body } => {
+                if let Some(Value::Array(values)) = lookup(path, context, this) {
+                    for value in values {
+                        render(body, context, value, output);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// the value at a dotted `path`, relative to the element of the
+/// enclosing `each` if it starts with `this`
+fn lookup<'a>(path: &str, context: &'a Value, this: &'a Value) -> Option<&'a Value> {
+    let segments = path.split('.').collect::<Vec<_>>();
+    match segments.split_first() {
+        Some((&"this", rest)) => get(this, rest),
+        _ => get(context, &segments),
+    }
+}
+
+/// the value at `segments`, preferring the longest keys so that
+/// dotted field names are found
+fn get<'a>(value: &'a Value, segments: &[&str]) -> Option<&'a Value> {
+    if segments.is_empty() {
+        return Some(value);
+    }
+    (1..=segments.len()).rev().find_map(|i| {
+        let next = value.get(segments[..i].join("."))?;
+        get(next, &segments[i..])
+    })
+}
+
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64() != Some(0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(values) => !values.is_empty(),
+        Value::Object(map) => !map.is_empty(),
+    }
+}
+
+fn display(value: &Value, output: &mut String) {
+    match value {
+        Value::Null => {}
+        Value::String(s) => output.push_str(s),
+        Value::Array(values) => {
+            for (i, value) in values.iter().enumerate() {
+                if i > 0 {
+                    output.push_str(", ");
+                }
+                display(value, output);
+            }
+        }
+        value => output.push_str(&value.to_string()),
+    }
+}
@@ -0,0 +1,498 @@
+//! Sample events synthesized from the detection of a rule
+//!
+//! [`matching_event`] and [`non_matching_event`] bootstrap the `tests`
+//! of a rule (see [`rule_tests`]) and events to verify a pipeline end
+//! to end. Values are chosen from the rule: wildcards are dropped,
+//! numbers are picked on the right side of comparisons, networks yield
+//! their first address and regular expressions their shortest simple
+//! match. Every sample is checked against the rule, so rules whose
+//! values cannot be synthesized yield none
+//!
+//! ```rust
+//! # use serde_json::json;
+//! # use sigmars::rule::SigmaRule;
+//! # use sigmars::testing::{matching_event, non_matching_event};
+//! let rule: SigmaRule = serde_yml::from_str(r#"
+//! title: curl to tmp
+//! id: curl-to-tmp
+//! logsource:
+//!   category: process_creation
+//! detection:
+//!   selection:
+//!     Image|endswith: '/curl'
+//!     CommandLine|re: ' -o /tmp/\w+$'
+//!   filter:
+//!     User: root
+//!   condition: selection and not filter
+//! "#).unwrap();
+//!
+//! let event = matching_event(&rule).unwrap();
+//! assert_eq!(event["Image"], json!("/curl"));
+//! assert_eq!(event["CommandLine"], json!(" -o /tmp/a"));
+//! // a near miss: filtered out
+//! assert_eq!(non_matching_event(&rule).unwrap()["User"], json!("root"));
+//! ```
+//!
+//! With the `mock_clock` feature, [`assert_expires`] checks the
+//! correlation windows of rules against a [`MemBackend`] whose clock is
+//! advanced instead of waiting for the timespan to elapse
+//!
+//! [`rule_tests`]: ../rule_tests/index.html
+//! [`MemBackend`]: ../struct.MemBackend.html
+
+use std::sync::Arc;
+#[cfg(feature = "mock_clock")]
+use std::time::Duration;
+
+use regex_syntax::hir::{Class, Hir, HirKind};
+use serde_json::{json, Map, Value};
+
+#[cfg(feature = "mock_clock")]
+use crate::correlation::state::{mem::MemBackend, Key};
+use crate::detection::{get_terminal_from_dotted_path, MatchOptions, Selection, SelectionItem};
+use crate::rule::{RuleType, SigmaRule};
+
+/// selections beyond which only the assignments with at most one
+/// selection matching, or at most one not matching, are tried
+const MAX_SELECTIONS: usize = 12;
+
+/// the value of fields whose value is not constrained by the rule
+const PLACEHOLDER: &str = "sample";
+
+/// a value not matching string, numeric or network comparisons
+const MISMATCH: &str = "sigmars-no-match";
+
+/// how long before the end of its timespan a key must still be
+/// counted, leaving time for the test to run
+#[cfg(feature = "mock_clock")]
+const EXPIRY_MARGIN: Duration = Duration::from_secs(1);
+
+/// An event matching the detection of `rule`, `None` for correlation
+/// rules, detections reading `@logsource.*` fields and detections
+/// whose values cannot be synthesized
+///
+/// the event matches as few selections as the condition allows
+pub fn matching_event(rule: &SigmaRule) -> Option<Value> {
+    sample(rule, true)
+}
+
+/// An event not matching the detection of `rule`, `None` for
+/// correlation rules, detections reading `@logsource.*` fields and
+/// detections whose values cannot be synthesized
+///
+/// the event is a near miss, matching as many selections as the
+/// condition allows, e.g. a filtered event
+pub fn non_matching_event(rule: &SigmaRule) -> Option<Value> {
+    sample(rule, false)
+}
+
+/// Assert that `key` is counted by the correlation rule `rule_id`
+/// until shortly (a second) before `timespan` has elapsed, and that
+/// its count drops to zero once it has, advancing the clock of
+/// `backend`
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use serde_json::json;
+/// # use sigmars::correlation::state::Key;
+/// # use sigmars::{Event, MemBackend, SigmaCollection};
+/// # #[tokio::main]
+/// # async fn main() {
+/// let mut rules: SigmaCollection = r#"
+/// title: failed login
+/// id: failed-login
+/// logsource:
+///   category: authentication
+/// detection:
+///   selection:
+///     outcome: failure
+///   condition: selection
+/// ---
+/// title: brute force
+/// id: brute-force
+/// correlation:
+///   type: event_count
+///   rules:
+///     - failed-login
+///   group-by:
+///     - user
+///   timespan: 5m
+///   condition:
+///     gte: 10
+/// "#.parse().unwrap();
+/// let mut backend = MemBackend::new().await;
+/// rules.init(&mut backend).await;
+///
+/// let event = Event::new(json!({"outcome": "failure", "user": "alice"}));
+/// rules.get_matches(&event).await.unwrap();
+///
+/// let key = Key::EventCount(vec![("user".to_string(), json!("alice"))]);
+/// sigmars::testing::assert_expires(&backend, "brute-force", &key, Duration::from_secs(300)).await;
+/// # }
+/// ```
+///
+/// # Panics
+///
+/// if `key` is not counted, expires a second or more before
+/// `timespan`, or is still counted after it
+#[cfg(feature = "mock_clock")]
+pub async fn assert_expires(backend: &MemBackend, rule_id: &str, key: &Key, timespan: Duration) {
+    let count = backend.count(rule_id, key).await;
+    assert!(count > 0, "{:?} is not counted by {}", key, rule_id);
+    let early = timespan.saturating_sub(EXPIRY_MARGIN);
+    backend.advance(early);
+    assert!(
+        backend.count(rule_id, key).await > 0,
+        "{:?} expired from {} before {:?}",
+        key,
+        rule_id,
+        early
+    );
+    backend.advance(timespan - early);
+    let count = backend.count(rule_id, key).await;
+    assert!(
+        count == 0,
+        "{:?} is still counted {} times by {} after {:?}",
+        key,
+        count,
+        rule_id,
+        timespan
+    );
+}
+
+fn sample(rule: &SigmaRule, matching: bool) -> Option<Value> {
+    let RuleType::Detection(ref detection) = rule.rule else {
+        return None;
+    };
+    // whether the detection matches depends on the log source of the
+    // event, not only on the sample
+    if detection.reads_logsource() {
+        return None;
+    }
+    let mut selections = detection.selections().iter().collect::<Vec<_>>();
+    selections.sort_by(|a, b| a.0.cmp(b.0));
+    let samples = selections
+        .iter()
+        .map(|(_, selection)| {
+            let positive = positive(selection);
+            let negative = negative(selection, positive.clone());
+            (positive, negative)
+        })
+        .collect::<Vec<_>>();
+
+    let options = MatchOptions::default();
+    for assignment in assignments(selections.len(), matching) {
+        let results = selections
+            .iter()
+            .zip(&assignment)
+            .map(|((name, _), matched)| ((*name).clone(), *matched))
+            .collect();
+        if detection.condition_matches(&results) != matching {
+            continue;
+        }
+        let positives = assignment
+            .iter()
+            .zip(&samples)
+            .filter(|(matched, _)| **matched)
+            .map(|(_, (positive, _))| positive.as_ref())
+            .collect::<Option<Vec<_>>>();
+        let Some(positives) = positives else {
+            continue;
+        };
+        let mut event = json!({});
+        for positive in positives {
+            merge(&mut event, positive);
+        }
+        // near misses of the selections that must not match, only if
+        // they do for matching events, the samples of matching
+        // selections taking precedence
+        let mut negatives = assignment.iter().zip(&selections).zip(&samples);
+        let feasible = negatives.all(|((matched, (_, selection)), (_, negative))| {
+            let needed = selection.is_match(&event);
+            match negative {
+                _ if *matched => true,
+                Some(negative) if needed || !matching => {
+                    let mut negative = negative.clone();
+                    merge(&mut negative, &event);
+                    event = negative;
+                    true
+                }
+                _ => !needed,
+            }
+        });
+        if !feasible {
+            continue;
+        }
+        if detection.is_match(&event, &options) == matching {
+            return Some(event);
+        }
+    }
+    None
+}
+
+/// the selection results to try, with as few (`matching`) or as many
+/// selections matching as possible first
+fn assignments(n: usize, matching: bool) -> Vec<Vec<bool>> {
+    let mut masks = match n <= MAX_SELECTIONS {
+        true => (0..1u64 << n).collect::<Vec<_>>(),
+        false => {
+            let all = (1u64 << n.min(63)) - 1;
+            let singles = (0..n.min(63)).map(|i| 1u64 << i);
+            [0, all]
+                .into_iter()
+                .chain(singles.clone())
+                .chain(singles.map(|single| all & !single))
+                .collect()
+        }
+    };
+    masks.sort_by_key(|mask| match matching {
+        true => mask.count_ones(),
+        false => u32::MAX - mask.count_ones(),
+    });
+    masks.dedup();
+    masks
+        .into_iter()
+        .map(|mask| (0..n).map(|i| i < 64 && mask & (1 << i) != 0).collect())
+        .collect()
+}
+
+/// an event matching `selection`
+fn positive(selection: &Selection) -> Option<Value> {
+    let mut event = json!({});
+    for item in selection.items() {
+        match item {
+            SelectionItem::Field {
+                path,
+                modifiers,
+                values,
+            } => {
+                let value = field_value(&event, modifiers, values)?;
+                insert(&mut event, path, value.clone());
+                if let Some(path) = fieldref(modifiers, values) {
+                    insert(&mut event, &path, value);
+                }
+            }
+            SelectionItem::Keywords { modifiers, values } => {
+                let has = |m: &str| modifiers.iter().any(|modifier| modifier == m);
+                let texts = match has("re") || has("regex") {
+                    true => values
+                        .iter()
+                        .map(|v| regex_sample(v))
+                        .collect::<Option<Vec<_>>>()?,
+                    false => values
+                        .iter()
+                        .map(|v| v.trim_matches('*').to_string())
+                        .collect(),
+                };
+                let text = match has("all") {
+                    true => texts.join(" "),
+                    false => texts.into_iter().next()?,
+                };
+                let message = match event.get("message").and_then(Value::as_str) {
+                    Some(message) => format!("{} {}", message, text),
+                    None => text,
+                };
+                event["message"] = Value::String(message);
+            }
+        }
+    }
+    selection.is_match(&event).then_some(event)
+}
+
+/// an event not matching `selection`, changing one of the values of
+/// its `positive` event if any
+fn negative(selection: &Selection, positive: Option<Value>) -> Option<Value> {
+    if let Some(positive) = positive {
+        for item in selection.items() {
+            let path = match item {
+                SelectionItem::Field { path, .. } => path.to_vec(),
+                SelectionItem::Keywords { .. } => vec![Arc::from("message")],
+            };
+            let mut mismatched = positive.clone();
+            insert(&mut mismatched, &path, Value::String(MISMATCH.to_string()));
+            if !selection.is_match(&mismatched) {
+                return Some(mismatched);
+            }
+            let mut missing = positive.clone();
+            remove(&mut missing, &path);
+            if !selection.is_match(&missing) {
+                return Some(missing);
+            }
+        }
+    }
+    let empty = json!({});
+    (!selection.is_match(&empty)).then_some(empty)
+}
+
+/// the value of a field matching its `values` with `modifiers`, `None`
+/// if it cannot be synthesized
+fn field_value(event: &Value, modifiers: &[String], values: &[Value]) -> Option<Value> {
+    let has = |m: &str| modifiers.iter().any(|modifier| modifier == m);
+    // alternatives from `base64offset`
+    let first = |value: &Value| match value {
+        Value::Array(alternatives) => alternatives.first().cloned(),
+        value => Some(value.clone()),
+    };
+    let value = first(values.first()?)?;
+
+    if has("exists") {
+        return Some(Value::from(PLACEHOLDER));
+    }
+    if has("fieldref") {
+        let value = get_terminal_from_dotted_path(value.as_str()?, event);
+        return Some(value.cloned().unwrap_or(Value::from(PLACEHOLDER)));
+    }
+    if has("re") || has("regex") {
+        return Some(Value::String(regex_sample(value.as_str()?)?));
+    }
+    if has("cidr") {
+        let cidr = value.as_str()?.parse::<cidr::AnyIpCidr>().ok()?;
+        return Some(Value::String(cidr.first_address()?.to_string()));
+    }
+    if has("expand") {
+        return None;
+    }
+    for (modifier, offset) in [("lt", -1), ("lte", 0), ("gt", 1), ("gte", 0)] {
+        if has(modifier) {
+            let number = match value {
+                Value::String(ref s) => s.trim().parse::<i64>().ok()?,
+                ref value => value.as_i64()?,
+            };
+            return Some(json!(number.checked_add(offset)?));
+        }
+    }
+
+    let string = ["contains", "startswith", "endswith", "cased"]
+        .iter()
+        .any(|m| has(m));
+    let text = |value: Value| match value {
+        Value::String(s) if !string => match s.trim_matches('*') {
+            "" => Value::from(PLACEHOLDER),
+            inner => Value::from(inner),
+        },
+        value => value,
+    };
+    if !has("all") {
+        return Some(text(value));
+    }
+    let values = values.iter().map(first).collect::<Option<Vec<_>>>()?;
+    match string {
+        true => {
+            let values = values
+                .iter()
+                .map(|v| v.as_str())
+                .collect::<Option<Vec<_>>>()?;
+            Some(Value::String(values.join(" ")))
+        }
+        false => Some(Value::Array(values.into_iter().map(text).collect())),
+    }
+}
+
+/// the field a `fieldref` field refers to
+fn fieldref(modifiers: &[String], values: &[Value]) -> Option<Vec<Arc<str>>> {
+    if !modifiers.iter().any(|m| m == "fieldref") {
+        return None;
+    }
+    Some(
+        values
+            .first()?
+            .as_str()?
+            .split('.')
+            .map(Arc::from)
+            .collect(),
+    )
+}
+
+/// a short string matching `pattern`, `None` for patterns with
+/// features other than literals, classes, groups, repetitions,
+/// alternations and assertions
+fn regex_sample(pattern: &str) -> Option<String> {
+    let hir = regex_syntax::Parser::new().parse(pattern).ok()?;
+    let mut sample = String::new();
+    write_sample(&hir, &mut sample)?;
+    Some(sample)
+}
+
+fn write_sample(hir: &Hir, sample: &mut String) -> Option<()> {
+    match hir.kind() {
+        HirKind::Empty | HirKind::Look(_) => {}
+        HirKind::Literal(literal) => sample.push_str(std::str::from_utf8(&literal.0).ok()?),
+        HirKind::Class(Class::Unicode(class)) => {
+            let contains = |c: char| {
+                class
+                    .ranges()
+                    .iter()
+                    .any(|r| r.start() <= c && c <= r.end())
+            };
+            let c = ['a', '0', 'A', '_', '-', '.', ' ']
+                .into_iter()
+                .find(|c| contains(*c))
+                .or_else(|| Some(class.ranges().first()?.start()))?;
+            sample.push(c);
+        }
+        HirKind::Class(Class::Bytes(class)) => {
+            let byte = class.ranges().first()?.start();
+            sample.push(char::from(byte.is_ascii().then_some(byte)?));
+        }
+        HirKind::Repetition(repetition) => {
+            for _ in 0..repetition.min {
+                write_sample(&repetition.sub, sample)?;
+            }
+        }
+        HirKind::Capture(capture) => write_sample(&capture.sub, sample)?,
+        HirKind::Concat(hirs) => {
+            for hir in hirs {
+                write_sample(hir, sample)?;
+            }
+        }
+        HirKind::Alternation(hirs) => write_sample(hirs.first()?, sample)?,
+    }
+    Some(())
+}
+
+/// set the field at `path` of `event`, replacing non-object parents
+fn insert(event: &mut Value, path: &[Arc<str>], value: Value) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+    let mut current = event;
+    for key in parents {
+        if !current.get(&**key).is_some_and(Value::is_object) {
+            current[&**key] = Value::Object(Map::new());
+        }
+        current = &mut current[&**key];
+    }
+    current[&**last] = value;
+}
+
+/// remove the field at `path` of `event`
+fn remove(event: &mut Value, path: &[Arc<str>]) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+    let parent = parents
+        .iter()
+        .try_fold(event, |current, key| current.get_mut(&**key));
+    if let Some(Value::Object(parent)) = parent {
+        parent.remove(&**last);
+    }
+}
+
+/// merge the fields of `sample` into `event`, replacing its values
+fn merge(event: &mut Value, sample: &Value) {
+    match (event, sample) {
+        (Value::Object(event), Value::Object(sample)) => {
+            for (key, value) in sample {
+                match event.get_mut(key) {
+                    Some(existing) if existing.is_object() && value.is_object() => {
+                        merge(existing, value)
+                    }
+                    _ => {
+                        event.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (event, sample) => *event = sample.clone(),
+    }
+}
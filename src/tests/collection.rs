@@ -1,7 +1,13 @@
 use crate::collection::*;
+use crate::{AuditdParser, LoadFilter, Overrides, Pipeline, RuleWarning};
+#[cfg(feature = "glob")]
+use crate::DirOptions;
 use crate::event::{Event, LogSource};
+use crate::rule::{RelationType, SigmaRule, Source, Status, TagKind};
+use super::{rule_yaml, test_rule};
 use serde_json::json;
 use std::collections::HashMap;
+use std::sync::Arc;
 
 pub static COLLECTION: &str = r#"
 title: Correlation - Multiple Failed Logins Followed by Successful Login
@@ -130,6 +136,8 @@ fn test_filter_matching_metadata() {
             ..Default::default()
         },
         metadata: HashMap::default(),
+        tenant: None,
+        context: None,
         data: json!({
             "EventID": 4624,
             "User": "test"
@@ -150,6 +158,8 @@ fn test_filter_no_match_with_metadata() {
             ..Default::default()
         },
         metadata: HashMap::default(),
+        tenant: None,
+        context: None,
         data: json!({
             "EventID": 4624,
             "User": "test"
@@ -278,3 +288,2200 @@ detection:
         "a rule's filter in a collection should not affect another rule"
     );
 }
+
+#[test]
+fn test_logsource_dispatch() {
+    let mut collection: SigmaCollection = r#"
+title: Successful login
+id: successful-login
+logsource:
+    product: windows
+detection:
+    selection:
+        EventID: 4624
+    condition: selection
+---
+title: Any user
+id: any-user
+logsource:
+    product: windows
+detection:
+    selection:
+        User|exists: true
+    condition: selection
+"#
+    .parse()
+    .unwrap();
+
+    let event = Event::new(json!({"EventID": 4624, "User": "mimikatz"}));
+    for product in ["windows", "linux", "macos"] {
+        let event = event
+            .clone()
+            .logsource(LogSource::default().product(product));
+        let res = collection.get_detection_matches(&event);
+        assert_eq!(res.len(), if product == "windows" { 2 } else { 0 });
+    }
+    assert_eq!(collection.get_detection_matches(&event).len(), 2);
+    assert_eq!(
+        collection
+            .get_detection_matches(&Event::new(json!({"User": "mimikatz"})))
+            .len(),
+        1
+    );
+
+    collection
+        .load_from_str(
+            r#"
+title: Any login
+id: any-login
+logsource:
+    product: linux
+detection:
+    selection:
+        EventID: 4624
+    condition: selection
+"#,
+        )
+        .unwrap();
+    let linux = event
+        .clone()
+        .logsource(LogSource::default().product("linux"));
+    assert_eq!(collection.get_detection_matches(&linux).len(), 1);
+    collection.remove("any-login").unwrap();
+    assert!(collection.get_detection_matches(&linux).is_empty());
+}
+
+#[test]
+fn test_related() {
+    let collection: SigmaCollection = r#"
+title: Successful login
+id: 4d0a2c83-c62c-4ed4-b475-c7e23a9269b8
+related:
+    - id: 53ba33fd-3a50-4468-a5ef-c583635cfa92
+      type: derived
+    - id: 0c0b8c97-14c1-4f88-b304-0a2d77e0b1ba
+      type: obsoletes
+logsource:
+    product: windows
+detection:
+    selection:
+        EventID: 4624
+    condition: selection
+---
+title: Single failed login
+id: 53ba33fd-3a50-4468-a5ef-c583635cfa92
+logsource:
+    product: windows
+detection:
+    selection:
+        EventID: 4625
+    condition: selection
+"#
+    .parse()
+    .unwrap();
+
+    let related = collection.related("4d0a2c83-c62c-4ed4-b475-c7e23a9269b8");
+    assert!(related.len() == 1);
+    assert_eq!(related[0].0.relation_type, RelationType::Derived);
+    assert_eq!(&*related[0].1.id, "53ba33fd-3a50-4468-a5ef-c583635cfa92");
+
+    assert!(
+        collection.obsoleted().is_empty(),
+        "an obsoleted rule that is not loaded should not be reported"
+    );
+}
+
+#[test]
+fn test_obsoleted() {
+    let mut collection: SigmaCollection = r#"
+title: New login rule
+id: new-rule
+related:
+    - id: old-rule
+      type: obsoletes
+    - id: other-rule
+      type: supersedes
+logsource:
+    product: windows
+detection:
+    selection:
+        EventID: 4624
+    condition: selection
+---
+title: Old login rule
+id: old-rule
+logsource:
+    product: windows
+detection:
+    selection:
+        EventID: 4624
+    condition: selection
+"#
+    .parse()
+    .unwrap();
+
+    let rule = collection.get("new-rule").unwrap();
+    let related = rule.related.as_ref().unwrap();
+    assert_eq!(related[1].relation_type, RelationType::Other("supersedes".into()));
+    assert_eq!(
+        serde_json::to_value(&related[1].relation_type).unwrap(),
+        json!("supersedes")
+    );
+
+    assert_eq!(collection.obsoleted(), vec![("new-rule".into(), "old-rule".into())]);
+    let warnings = collection
+        .warnings()
+        .into_iter()
+        .map(|(rule, warning)| (rule.id.to_string(), warning.to_string()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        warnings,
+        vec![("old-rule".to_string(), "obsoleted by new-rule, also loaded".to_string())]
+    );
+
+    collection.remove("new-rule").unwrap();
+    assert!(collection.warnings().is_empty());
+}
+
+#[test]
+fn test_tags() {
+    let collection: SigmaCollection = r#"
+title: Successful login
+id: 4d0a2c83-c62c-4ed4-b475-c7e23a9269b8
+tags:
+    - attack.initial-access
+    - attack.t1078
+    - cve.2021-44228
+    - detection.threat-hunting
+    - car.2016-04-004
+logsource:
+    product: windows
+detection:
+    selection:
+        EventID: 4624
+    condition: selection
+"#
+    .parse()
+    .unwrap();
+
+    let rule = collection.get("4d0a2c83-c62c-4ed4-b475-c7e23a9269b8").unwrap();
+    let kinds = rule
+        .tags
+        .as_ref()
+        .unwrap()
+        .iter()
+        .map(|t| t.kind.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        vec![
+            TagKind::AttackTactic("initial-access".to_string()),
+            TagKind::AttackTechnique("T1078".to_string()),
+            TagKind::Cve("CVE-2021-44228".to_string()),
+            TagKind::Detection("threat-hunting".to_string()),
+            TagKind::Other,
+        ]
+    );
+    assert!(collection.to_string().contains("- attack.t1078\n"));
+}
+
+#[test]
+fn test_load_from_dir() {
+    let dir = std::env::temp_dir().join(format!("sigmars-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(dir.join("nested")).unwrap();
+    for (i, rule) in COLLECTION.split("\n---\n").enumerate() {
+        std::fs::write(dir.join("nested").join(format!("{}.yml", i)), rule).unwrap();
+    }
+
+    let collection = SigmaCollection::new_from_dir(dir.to_str().unwrap()).unwrap();
+    assert!(collection.len() == 8);
+
+    let source = Source::File(dir.join("nested").join("0.yml"));
+    let rules = collection.from_source(&source);
+    assert_eq!(rules.len(), 1);
+    assert_eq!(rules[0].id.as_ref(), "b180ead8-d58f-40b2-ae54-c8940995b9b6");
+    let programmatic: SigmaCollection = COLLECTION.parse().unwrap();
+    assert_eq!(programmatic.from_source(&Source::Programmatic).len(), 8);
+
+    std::fs::write(dir.join("invalid.yml"), "title: invalid rule").unwrap();
+    let err = SigmaCollection::new_from_dir(dir.to_str().unwrap()).unwrap_err();
+    assert!(
+        err.to_string().contains("invalid.yml"),
+        "errors should report the file that failed to load"
+    );
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[cfg(feature = "glob")]
+#[test]
+fn test_load_filter() {
+    let dir = std::env::temp_dir().join(format!("sigmars-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(dir.join("deprecated")).unwrap();
+    let rule = |id: &str, fields: &str| test_rule(id, fields, "bar");
+    std::fs::write(dir.join("a.yml"), rule("a", "tags:\n  - attack.t1078")).unwrap();
+    std::fs::write(dir.join("b.yml"), rule("b", "status: deprecated")).unwrap();
+    std::fs::write(dir.join("c.yml"), rule("c", "status: stable")).unwrap();
+    std::fs::write(dir.join("deprecated").join("d.yml"), rule("d", "")).unwrap();
+    let path = dir.to_str().unwrap();
+
+    let filter = LoadFilter::new()
+        .exclude_status("deprecated")
+        .exclude_path("**/deprecated/*.yml")
+        .unwrap();
+    let collection = SigmaCollection::builder()
+        .load_filter(filter)
+        .build_from_dir(path)
+        .unwrap();
+    assert_eq!(collection.len(), 2);
+    assert!(collection.get("a").is_some() && collection.get("c").is_some());
+
+    let filter = LoadFilter::new()
+        .include_tag("ATTACK.T1078")
+        .include_id("b")
+        .exclude_id("B");
+    let collection = SigmaCollection::builder()
+        .load_filter(filter)
+        .build_from_dir(path)
+        .unwrap();
+    assert_eq!(collection.len(), 1);
+    assert!(collection.get("a").is_some());
+
+    std::fs::write(dir.join("e.yml"), rule("e", "status: unsupported\n")).unwrap();
+    let collection = SigmaCollection::builder()
+        .load_filter(LoadFilter::new().exclude_inactive())
+        .build_from_dir(path)
+        .unwrap();
+    assert_eq!(collection.len(), 3);
+    assert!(collection.get("b").is_none() && collection.get("e").is_none());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_unknown_status() {
+    let rule = |id: &str, status: &str| test_rule(id, &format!("status: {status}"), "bar");
+    let rules = [rule("a", "unsupported"), rule("b", "retired")].join("---\n");
+
+    let collection: SigmaCollection = rules.parse().unwrap();
+    let b = collection.get("b").unwrap();
+    assert_eq!(b.status, Some(Status::Other("retired".to_string())));
+    assert!(serde_yml::to_string(b)
+        .unwrap()
+        .contains("status: retired"));
+
+    let mut collection = SigmaCollection::new().load_filter(LoadFilter::new().exclude_inactive());
+    collection.load_from_str(&rules).unwrap();
+    assert!(collection.get("a").is_none() && collection.get("b").is_some());
+
+    let mut collection = SigmaCollection::new().load_filter(LoadFilter::new().exclude_status("retired"));
+    collection.load_from_str(&rules).unwrap();
+    assert!(collection.get("a").is_some() && collection.get("b").is_none());
+}
+
+#[cfg(feature = "embedded_rules")]
+#[test]
+fn test_builtin_core() {
+    let collection = SigmaCollection::builtin_core().unwrap();
+    assert!(!collection.is_empty());
+    let results = collection.run_rule_tests();
+    assert!(results.iter().all(|result| result.passed()), "{:?}", results);
+    let rules: Vec<Arc<SigmaRule>> = collection.into();
+    assert!(rules
+        .iter()
+        .all(|rule| matches!(rule.source, Source::Embedded(_))));
+}
+
+#[test]
+fn test_reload_changed() {
+    let dir = std::env::temp_dir().join(format!("sigmars-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let rule = |id: &str, value: &str| test_rule(id, "", value);
+    let touch = |path: &std::path::Path, secs: u64| {
+        let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+        std::fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+    };
+    std::fs::write(dir.join("a.yml"), rule("a", "bar")).unwrap();
+    std::fs::write(dir.join("b.yml"), rule("b", "bar")).unwrap();
+    touch(&dir.join("a.yml"), 1);
+    touch(&dir.join("b.yml"), 1);
+
+    let mut collection = SigmaCollection::new_from_dir(dir.to_str().unwrap()).unwrap();
+    assert_eq!(collection.reload_changed().unwrap(), 0);
+
+    let event = Event::new(json!({"foo": "baz"}));
+    assert!(collection.get_detection_matches(&event).is_empty());
+
+    std::fs::write(dir.join("a.yml"), rule("a", "baz")).unwrap();
+    touch(&dir.join("a.yml"), 2);
+    std::fs::remove_file(dir.join("b.yml")).unwrap();
+    std::fs::write(dir.join("c.yml"), rule("c", "baz")).unwrap();
+    assert_eq!(collection.reload_changed().unwrap(), 2);
+    assert_eq!(collection.len(), 2);
+    let mut matches = collection.get_detection_matches(&event).into_vec();
+    matches.sort();
+    assert_eq!(matches, vec!["a".into(), "c".into()]);
+
+    std::fs::write(dir.join("a.yml"), "title: invalid rule").unwrap();
+    touch(&dir.join("a.yml"), 3);
+    assert!(collection.reload_changed().is_err());
+    assert_eq!(collection.len(), 2, "failed reloads leave the collection unchanged");
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_drop_raw_detections() {
+    let rule: SigmaRule = serde_yml::from_str(
+        r#"
+title: Successful login
+id: 4d0a2c83-c62c-4ed4-b475-c7e23a9269b8
+logsource:
+    product: windows
+detection:
+    selection:
+        EventID: 4624
+    condition: selection
+"#,
+    )
+    .unwrap();
+
+    let mut collection = SigmaCollection::new().drop_raw_detections(true);
+    collection.add(rule).unwrap();
+
+    let event = Event {
+        data: json!({
+            "EventID": 4624,
+            "User": "test"
+        }),
+        ..Default::default()
+    };
+    assert!(collection.get_detection_matches(&event).len() == 1);
+    assert!(!collection.to_string().contains("detection:"));
+}
+
+#[test]
+fn test_max_matches() {
+    let collection: SigmaCollection = r#"
+title: first rule
+id: first-rule
+logsource:
+    product: windows
+detection:
+    selection:
+        EventID: 4624
+    condition: selection
+---
+title: second rule
+id: second-rule
+logsource:
+    product: windows
+detection:
+    selection:
+        User: test
+    condition: selection
+"#
+    .parse()
+    .unwrap();
+
+    let event = Event {
+        data: json!({
+            "EventID": 4624,
+            "User": "test"
+        }),
+        ..Default::default()
+    };
+    assert_eq!(collection.get_detection_matches(&event).len(), 2);
+
+    let collection = collection.first_match_only(true);
+    assert_eq!(collection.get_detection_matches(&event).len(), 1);
+    assert_eq!(collection.get_detection_matches_unfiltered(&event).len(), 1);
+    let collection = collection.max_matches(2);
+    assert_eq!(collection.get_detection_matches(&event).len(), 2);
+    let collection = collection.max_matches(0);
+    assert!(collection.get_detection_matches(&event).is_empty());
+}
+
+#[test]
+fn test_match_ids_are_shared() {
+    let collection: SigmaCollection = COLLECTION.parse().unwrap();
+
+    let event = Event {
+        data: json!({
+            "EventID": 4624,
+            "User": "test"
+        }),
+        ..Default::default()
+    };
+    let res = collection.get_detection_matches(&event);
+    let rule = collection.get(&res[0]).unwrap();
+    assert!(std::sync::Arc::ptr_eq(&res[0], &rule.id));
+}
+
+#[test]
+fn test_shared_add_remove() {
+    let collection: SigmaCollection = COLLECTION.parse().unwrap();
+    let shared = crate::SharedSigmaCollection::new(collection.clone());
+
+    let event = Event {
+        data: json!({
+            "EventID": 7045,
+            "ServiceName": "Google Update"
+        }),
+        ..Default::default()
+    };
+    assert!(shared.get_detection_matches(&event).len() == 1);
+
+    #[cfg(feature = "correlation")]
+    assert!(
+        shared.remove("0c0b8c97-14c1-4f88-b304-0a2d77e0b1ba").is_err(),
+        "rules that correlations depend on should not be removed"
+    );
+
+    shared
+        .remove("c3d98fc1-ecc8-44e1-9601-bb456834e0df")
+        .unwrap()
+        .unwrap();
+    let removed = shared
+        .remove("0c0b8c97-14c1-4f88-b304-0a2d77e0b1ba")
+        .unwrap()
+        .unwrap();
+    assert!(shared.load().len() == 6);
+    assert!(shared.get_detection_matches(&event).is_empty());
+    assert!(
+        collection.get_detection_matches(&event).len() == 1,
+        "clones of a collection should not be affected"
+    );
+
+    drop(collection);
+    shared.add(Arc::try_unwrap(removed).unwrap()).unwrap();
+    assert!(shared.get_detection_matches(&event).len() == 1);
+}
+
+#[test]
+fn test_custom_matcher() {
+    use crate::matcher::{Matcher, Selection, SelectionItem};
+
+    /// matches field values exactly, ignoring modifiers
+    #[derive(Debug)]
+    struct Exact;
+
+    impl Matcher for Exact {
+        fn is_match(&self, selection: &Selection, event: &serde_json::Value) -> bool {
+            selection.items().all(|item| match item {
+                SelectionItem::Field { path, values, .. } => path
+                    .iter()
+                    .try_fold(event, |v, key| v.get(&**key))
+                    .is_some_and(|v| values.contains(v)),
+                SelectionItem::Keywords { .. } => false,
+            })
+        }
+    }
+
+    let collection: SigmaCollection = COLLECTION.parse().unwrap();
+    let collection = collection.matcher(Arc::new(Exact));
+
+    let event = Event {
+        data: json!({
+            "EventID": 7045,
+            "ServiceName": "Google Update"
+        }),
+        ..Default::default()
+    };
+    assert!(collection.get_detection_matches(&event).len() == 1);
+
+    let event = Event {
+        data: json!({
+            "EventID": 7045,
+            "ServiceName": "google update"
+        }),
+        ..Default::default()
+    };
+    assert!(collection.get_detection_matches(&event).is_empty());
+}
+
+#[test]
+fn test_parse_limits() {
+    let mut collection = SigmaCollection::new().limits(crate::ParseLimits::untrusted());
+    assert!(collection.load_from_str(COLLECTION).unwrap() > 0);
+
+    let rule = |selection: &str, condition: &str| {
+        rule_yaml(
+            "limits",
+            "",
+            "  category: test",
+            &format!("  selection:\n{selection}\n  condition: {condition}"),
+        )
+    };
+    let limits = crate::ParseLimits {
+        max_values: 2,
+        max_condition_length: 32,
+        max_nesting_depth: 2,
+        ..Default::default()
+    };
+
+    let mut collection = SigmaCollection::new().limits(limits);
+    let err = collection
+        .load_from_str(&rule(
+            "    foo:\n      - a\n      - b\n      - c",
+            "selection",
+        ))
+        .unwrap_err();
+    assert!(err.to_string().contains("too many values"), "{}", err);
+
+    let err = collection
+        .load_from_str(&rule(
+            "    foo: a",
+            &format!("{}selection", "selection or ".repeat(4)),
+        ))
+        .unwrap_err();
+    assert!(err.to_string().contains("condition too long"));
+
+    let err = collection
+        .load_from_str(&rule("    foo: a", "(((selection)))"))
+        .unwrap_err();
+    assert!(err.to_string().contains("nested too deeply"));
+
+    assert!(collection
+        .load_from_str(&rule("    foo:\n      - a\n      - b", "((selection))"))
+        .is_ok());
+    assert!(
+        rule("    foo: [a, b, c]", "(((selection)))")
+            .parse::<SigmaCollection>()
+            .is_ok(),
+        "limits should only apply to the collection they are set on"
+    );
+}
+
+#[test]
+fn test_parse_malformed() {
+    // panics in the YAML scanner
+    assert!("\nvalue_count        se".parse::<SigmaCollection>().is_err());
+    assert!("\nvalue_count        se".parse::<crate::Overrides>().is_err());
+
+    // overflows the stack when parsed or evaluated
+    for condition in [
+        format!("{}selection{}", "(".repeat(100_000), ")".repeat(100_000)),
+        format!("{}selection", "not ".repeat(100_000)),
+        format!("selection{}", " or selection".repeat(100_000)),
+    ] {
+        let err = rule_yaml(
+            "deep",
+            "",
+            "  category: test",
+            &format!("  selection:\n    foo: a\n  condition: {condition}"),
+        )
+        .parse::<SigmaCollection>()
+        .unwrap_err();
+        assert!(err.to_string().contains("condition too complex"), "{}", err);
+        assert!(condition.parse::<crate::Condition>().is_err());
+    }
+}
+
+#[cfg(feature = "signing")]
+#[test]
+fn test_signed_rules() {
+    static RULE: &str = r#"title: signed rule
+id: 5b9f8e4e-7d2c-4c35-9b0a-0f3c6d1e2a44
+logsource:
+    category: test
+detection:
+    selection:
+        foo: bar
+    condition: selection
+"#;
+    static SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUQRIjNEVWZ3iEYJAtITqw1oOBn9m6KnJbbgx8N59xLB19LYW5fZ76pw6ahThkfb9jNv4VPjIzw/dU0wnFZa75856Oh3VMlXSwU=
+trusted comment: timestamp:1700000000\tfile:rule.yml
+Hk7JoyEtmWEqcf9JoX9g1jzufBbSN48ErZOcrlAHd4+AopivCLAVGcvGiwcY//r0CB35g92QnZ9/HWHr7UYgCg==
+";
+    let key =
+        crate::PublicKey::from_base64("RWQRIjNEVWZ3iAOhB7/zzhC+HXDdGOdLwJln5NYwm6UNXx3chmQSVTG4")
+            .unwrap();
+
+    let mut collection = SigmaCollection::new().require_signatures([key.clone()]);
+    assert!(collection.load_from_signed_str(RULE, SIGNATURE).unwrap() == 1);
+    assert!(collection
+        .load_from_signed_str(&RULE.replace("bar", "baz"), SIGNATURE)
+        .is_err());
+
+    let dir = std::env::temp_dir().join(format!("sigmars-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("rule.yml"), RULE).unwrap();
+    std::fs::write(dir.join("rule.yml.minisig"), SIGNATURE).unwrap();
+
+    let mut collection = SigmaCollection::new().require_signatures([key]);
+    assert!(collection.load_from_dir(dir.to_str().unwrap()).unwrap() == 1);
+
+    std::fs::write(dir.join("unsigned.yml"), RULE.replace("signed rule", "unsigned rule"))
+        .unwrap();
+    let err = collection.load_from_dir(dir.to_str().unwrap()).unwrap_err();
+    assert!(
+        err.to_string().contains("unsigned.yml"),
+        "unsigned files should be rejected"
+    );
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_run_rule_tests() {
+    let collection: SigmaCollection = r#"
+title: passing rule
+id: a
+logsource:
+    product: windows
+detection:
+    selection:
+        EventID: 4624
+    condition: selection
+tests:
+    positive:
+        - EventID: 4624
+    negative:
+        - EventID: 4625
+---
+title: failing rule
+id: b
+logsource:
+    product: windows
+detection:
+    selection:
+        EventID: 4624
+    condition: selection
+tests:
+    positive:
+        - EventID: 4625
+        - EventID: 4624
+---
+title: invalid tests
+id: c
+logsource:
+    product: windows
+detection:
+    selection:
+        EventID: 4624
+    condition: selection
+tests:
+    matching:
+        - EventID: 4624
+---
+title: untested rule
+id: d
+logsource:
+    product: windows
+detection:
+    selection:
+        EventID: 4624
+    condition: selection
+"#
+    .parse()
+    .unwrap();
+
+    let results = collection.run_rule_tests();
+    assert_eq!(results.len(), 3);
+    assert!(results[0].passed());
+    assert!(!results[1].passed());
+    assert_eq!(results[1].missed, vec![0]);
+    assert!(results[1].false_positives.is_empty());
+    assert!(results[2].error.as_ref().unwrap().contains("matching"));
+}
+
+#[cfg(feature = "otel")]
+#[test]
+fn test_otlp_log_record() {
+    use opentelemetry_proto::tonic::common::v1::{any_value, AnyValue, KeyValue, KeyValueList};
+    use opentelemetry_proto::tonic::logs::v1::LogRecord;
+    use opentelemetry_proto::tonic::resource::v1::Resource;
+
+    let value = |value| AnyValue { value: Some(value) };
+    let string = |s: &str| value(any_value::Value::StringValue(s.to_string()));
+    let kv = |key: &str, value| KeyValue {
+        key: key.to_string(),
+        value: Some(value),
+    };
+
+    let record = LogRecord {
+        body: Some(value(any_value::Value::KvlistValue(KeyValueList {
+            values: vec![
+                kv("channel", string("Security")),
+                kv("EventID", value(any_value::Value::IntValue(4625))),
+                kv("TargetUserName", string("body")),
+            ],
+        }))),
+        attributes: vec![kv("TargetUserName", string("admin"))],
+        ..Default::default()
+    };
+    let resource = Resource {
+        attributes: vec![kv("host.name", string("dc01")), kv("os.type", string("windows"))],
+        ..Default::default()
+    };
+
+    let event = Event::from_otlp_log_record(&record, Some(&resource));
+    assert_eq!(event.data["EventID"], json!(4625));
+    assert_eq!(event.data["TargetUserName"], json!("admin"));
+    assert_eq!(event.metadata["host.name"], json!("dc01"));
+    assert_eq!(event.logsource.product.as_deref(), Some("windows"));
+    assert_eq!(event.logsource.service.as_deref(), Some("security"));
+
+    let collection: SigmaCollection = r#"
+title: failed logon
+id: failed-logon
+logsource:
+    product: windows
+    service: security
+detection:
+    selection:
+        EventID: 4625
+    condition: selection
+"#
+    .parse()
+    .unwrap();
+    assert_eq!(collection.get_detection_matches(&event), vec!["failed-logon".into()]);
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+fn test_scan_parquet() {
+    use arrow_array::types::Int32Type;
+    use arrow_array::{
+        ArrayRef, DictionaryArray, Int64Array, RecordBatch, StringArray, StructArray,
+    };
+    use arrow_schema::{DataType, Field};
+
+    let collection: SigmaCollection = r#"
+title: encoded powershell
+id: encoded-powershell
+logsource:
+    category: process_creation
+detection:
+    selection:
+        process.name: powershell.exe
+        CommandLine|contains: ' -enc '
+    condition: selection
+---
+title: logon type
+id: logon-type
+logsource:
+    category: process_creation
+detection:
+    selection:
+        LogonType: 3
+    condition: selection
+"#
+    .parse()
+    .unwrap();
+
+    let process = StructArray::from(vec![(
+        Arc::new(Field::new("name", DataType::Utf8, true)),
+        Arc::new(StringArray::from(vec![
+            "powershell.exe",
+            "cmd.exe",
+            "powershell.exe",
+        ])) as ArrayRef,
+    )]);
+    let command_line: DictionaryArray<Int32Type> =
+        vec!["powershell -enc AAAA", "cmd -enc AAAA", "powershell -c dir"]
+            .into_iter()
+            .collect();
+    let batch = RecordBatch::try_from_iter([
+        ("process", Arc::new(process) as ArrayRef),
+        ("CommandLine", Arc::new(command_line) as ArrayRef),
+        (
+            "LogonType",
+            Arc::new(Int64Array::from(vec![Some(2), None, Some(3)])) as ArrayRef,
+        ),
+        (
+            "unused",
+            Arc::new(StringArray::from(vec!["a", "b", "c"])) as ArrayRef,
+        ),
+    ])
+    .unwrap();
+
+    let logsource = LogSource::default().category("process_creation");
+    let matches = collection.scan_record_batch(&batch, &logsource);
+    assert_eq!(matches["encoded-powershell"], vec![0]);
+    assert_eq!(matches["logon-type"], vec![2]);
+
+    let path = std::env::temp_dir().join(format!("sigmars-{}.parquet", uuid::Uuid::new_v4()));
+    let file = std::fs::File::create(&path).unwrap();
+    let mut writer = parquet::arrow::ArrowWriter::try_new(file, batch.schema(), None).unwrap();
+    writer.write(&batch).unwrap();
+    writer.write(&batch).unwrap();
+    writer.close().unwrap();
+
+    let matches = collection.scan_parquet(&path, &logsource).unwrap();
+    assert_eq!(matches["encoded-powershell"], vec![0, 3]);
+    assert_eq!(matches["logon-type"], vec![2, 5]);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_sysmon_ecs_pipeline() {
+    static RULES: &str = r#"
+title: encoded powershell
+id: encoded-powershell
+logsource:
+  category: process_creation
+  product: windows
+detection:
+  selection:
+    Image|endswith: '\powershell.exe'
+    CommandLine|contains: ' -enc '
+    IntegrityLevel: High
+  condition: selection
+---
+title: linux command line
+id: linux-command-line
+logsource:
+  category: process_creation
+  product: linux
+detection:
+  selection:
+    CommandLine|contains: whoami
+  condition: selection
+"#;
+
+    let collection = SigmaCollection::builder()
+        .pipeline(Pipeline::sysmon_ecs())
+        .build_from_str(RULES)
+        .unwrap();
+
+    let winlogbeat = Event::new(json!({
+        "process": {
+            "executable": "C:\\Windows\\System32\\WindowsPowerShell\\v1.0\\powershell.exe",
+            "command_line": "powershell -enc AAAA",
+        },
+        "winlog": {"event_data": {"IntegrityLevel": "High"}},
+    }));
+    assert_eq!(
+        collection.get_detection_matches(&winlogbeat),
+        vec!["encoded-powershell".into()]
+    );
+    let flat = Event::new(json!({
+        "Image": "C:\\Windows\\System32\\WindowsPowerShell\\v1.0\\powershell.exe",
+        "CommandLine": "powershell -enc AAAA",
+        "IntegrityLevel": "High",
+    }));
+    assert!(collection.get_detection_matches(&flat).is_empty());
+
+    // rules for other products keep their fields
+    let linux = Event::new(json!({"CommandLine": "whoami"}));
+    assert_eq!(
+        collection.get_detection_matches(&linux),
+        vec!["linux-command-line".into()]
+    );
+}
+
+#[test]
+fn test_cloudtrail_records() {
+    static RULES: &str = r#"
+title: bucket made public
+id: s3-public-acl
+logsource:
+  product: aws
+  service: cloudtrail
+detection:
+  selection:
+    eventSource: s3.amazonaws.com
+    eventName: PutBucketAcl
+    requestParameters.x-amz-acl: public-read
+  condition: selection
+"#;
+    let collection: SigmaCollection = RULES.parse().unwrap();
+
+    let events = Event::from_cloudtrail(json!({"Records": [
+        {
+            "eventSource": "s3.amazonaws.com",
+            "eventName": "PutBucketAcl",
+            "requestParameters": {"bucketName": "logs", "x-amz-acl": "private"},
+        },
+        {
+            "eventSource": "s3.amazonaws.com",
+            "eventName": "PutBucketAcl",
+            "requestParameters": {"bucketName": "data", "x-amz-acl": "public-read"},
+        },
+    ]}));
+    let matches = events
+        .iter()
+        .map(|event| collection.get_detection_matches(event).len())
+        .collect::<Vec<_>>();
+    assert_eq!(matches, vec![0, 1]);
+
+    // a single record
+    let events = Event::from_cloudtrail(json!({
+        "eventSource": "s3.amazonaws.com",
+        "eventName": "PutBucketAcl",
+        "requestParameters": {"x-amz-acl": "public-read"},
+    }));
+    assert_eq!(events.len(), 1);
+    assert_eq!(collection.get_detection_matches(&events[0]).len(), 1);
+}
+
+#[test]
+fn test_network_pipelines() {
+    static RULES: &str = r#"
+title: dns query for a tunneling domain
+id: dns-tunnel
+logsource:
+  category: dns
+detection:
+  selection:
+    query|endswith: .tunnel.example
+  condition: selection
+---
+title: proxy request with a scripting user agent
+id: proxy-user-agent
+logsource:
+  category: proxy
+detection:
+  selection:
+    c-useragent|startswith: python-requests
+    dst_ip: 10.0.0.1
+  condition: selection
+"#;
+
+    let suricata = SigmaCollection::builder()
+        .pipeline(Pipeline::suricata_eve())
+        .build_from_str(RULES)
+        .unwrap();
+    let event = Event::from_suricata_eve(json!({
+        "event_type": "dns",
+        "dest_ip": "10.0.0.53",
+        "dns": {"type": "query", "rrname": "abc.tunnel.example", "rrtype": "TXT"},
+    }));
+    assert_eq!(
+        suricata.get_detection_matches(&event),
+        vec!["dns-tunnel".into()]
+    );
+    let event = Event::from_suricata_eve(json!({
+        "event_type": "http",
+        "dest_ip": "10.0.0.1",
+        "http": {"hostname": "internal", "http_user_agent": "python-requests/2.31"},
+    }));
+    assert_eq!(
+        suricata.get_detection_matches(&event),
+        vec!["proxy-user-agent".into()]
+    );
+
+    let zeek = SigmaCollection::builder()
+        .pipeline(Pipeline::zeek())
+        .build_from_str(RULES)
+        .unwrap();
+    let event = Event::from_zeek(json!({
+        "_path": "http",
+        "id.orig_h": "10.0.0.2",
+        "id.resp_h": "10.0.0.1",
+        "user_agent": "python-requests/2.31",
+    }));
+    assert_eq!(event.logsource.service.as_deref(), Some("http"));
+    assert_eq!(
+        zeek.get_detection_matches(&event),
+        vec!["proxy-user-agent".into()]
+    );
+    // the category keeps DNS rules away from HTTP logs
+    let event = Event::from_zeek(json!({"_path": "http", "query": "abc.tunnel.example"}));
+    assert!(zeek.get_detection_matches(&event).is_empty());
+}
+
+#[test]
+fn test_auditd_events() {
+    static RULES: &str = r#"
+title: download to tmp
+id: wget-tmp
+logsource:
+  product: linux
+  service: auditd
+detection:
+  selection:
+    type: EXECVE
+    a0: wget
+    cwd: /tmp
+  condition: selection
+---
+title: shadow read
+id: shadow-read
+logsource:
+  product: linux
+  service: auditd
+detection:
+  selection:
+    type: PATH
+    name: /etc/shadow
+    exe|endswith: /cat
+  condition: selection
+"#;
+    let collection: SigmaCollection = RULES.parse().unwrap();
+
+    // proctitle and the `a1` argument are hex-encoded as they contain spaces
+    let log = r#"type=SYSCALL msg=audit(1700000000.123:42): arch=c000003e syscall=59 success=yes exit=0 a0=55d0e8a1b2c0 a1=55d0e8a1b300 comm="wget" exe="/usr/bin/wget" key=(null)
+type=EXECVE msg=audit(1700000000.123:42): argc=2 a0="wget" a1=687474703A2F2F6578616D706C652E636F6D2F6120622E7368
+type=CWD msg=audit(1700000000.123:42): cwd="/tmp"
+type=PATH msg=audit(1700000000.123:42): item=0 name="/usr/bin/wget" nametype=NORMAL
+type=PROCTITLE msg=audit(1700000000.123:42): proctitle=7767657400687474703A2F2F6578616D706C652E636F6D2F6120622E7368
+type=EOE msg=audit(1700000000.123:42):
+type=SYSCALL msg=audit(1700000001.000:43): arch=c000003e syscall=257 success=yes exit=3 comm="cat" exe="/usr/bin/cat"
+type=PATH msg=audit(1700000001.000:43): item=0 name="/etc/ld.so.cache" nametype=NORMAL
+type=PATH msg=audit(1700000001.000:43): item=1 name="/etc/shadow" nametype=NORMAL
+type=USER_LOGIN msg=audit(1700000002.000:44): pid=1 uid=0 msg='op=login acct="root" exe="/usr/sbin/sshd" res=failed'"#;
+
+    let mut parser = AuditdParser::new();
+    let mut events = log
+        .lines()
+        .filter_map(|line| parser.push(line))
+        .collect::<Vec<_>>();
+    events.extend(parser.finish());
+    assert_eq!(events.len(), 3);
+
+    assert_eq!(events[0].data["a1"], "http://example.com/a b.sh");
+    assert_eq!(events[0].data["proctitle"], "wget http://example.com/a b.sh");
+    assert_eq!(events[0].metadata["serial"], 42);
+    assert_eq!(events[1].data["name"], json!(["/etc/ld.so.cache", "/etc/shadow"]));
+    assert_eq!(events[2].data["acct"], "root");
+    assert_eq!(events[2].data["res"], "failed");
+
+    let matches = events
+        .iter()
+        .map(|event| collection.get_detection_matches(event))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        matches,
+        vec![vec!["wget-tmp".into()], vec!["shadow-read".into()], vec![]]
+    );
+}
+
+#[test]
+fn test_extract_kv() {
+    static RULES: &str = r#"
+title: firewall deny to admin port
+id: fw-deny-admin
+logsource:
+  category: firewall
+detection:
+  selection:
+    action: deny
+    dst_port: '22'
+  condition: selection
+"#;
+    let collection: SigmaCollection = RULES.parse().unwrap();
+
+    let event = Event::new(json!({
+        "message": "Oct 17 10:00:00 fw01 action=deny proto=tcp src=10.0.0.2 dst_port=22 note=\"a = b\"",
+        "action": "logged",
+    }))
+    .logsource(LogSource::default().category("firewall"))
+    .extract_kv("message");
+    assert_eq!(event.data["note"], "a = b");
+    // fields of the event take precedence over those of the message
+    assert_eq!(event.data["action"], "logged");
+    assert!(collection.get_detection_matches(&event).is_empty());
+
+    let event = Event::new(json!({"syslog": {"message": "action=deny,dst_port=22"}}))
+        .logsource(LogSource::default().category("firewall"))
+        .extract_kv("syslog.message");
+    assert_eq!(
+        collection.get_detection_matches(&event),
+        vec!["fw-deny-admin".into()]
+    );
+}
+
+#[cfg(feature = "webhook")]
+#[tokio::test]
+async fn test_webhook_sink() {
+    use crate::sinks::{AlertFormat, WebhookSink};
+    use std::time::Duration;
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/alerts", listener.local_addr().unwrap());
+    // fails the first request, then accepts batches until closed
+    let server = tokio::spawn(async move {
+        let mut batches = vec![];
+        for status in ["503 Service Unavailable", "200 OK", "200 OK"] {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut stream = BufReader::new(stream);
+            let mut length = 0;
+            loop {
+                let mut line = String::new();
+                stream.read_line(&mut line).await.unwrap();
+                if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                    length = value.trim().parse().unwrap();
+                }
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; length];
+            stream.read_exact(&mut body).await.unwrap();
+            let response = format!(
+                "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                status
+            );
+            stream
+                .get_mut()
+                .write_all(response.as_bytes())
+                .await
+                .unwrap();
+            batches.push(serde_json::from_slice::<serde_json::Value>(&body).unwrap());
+        }
+        batches
+    });
+
+    let sink = WebhookSink::new(&url)
+        .format(AlertFormat::Ecs)
+        .batch_size(2)
+        .flush_interval(Duration::from_secs(60))
+        .backoff(Duration::from_millis(10))
+        .spawn();
+    let collection = SigmaCollection::builder()
+        .on_match(sink.observer())
+        .build_from_str(
+            r#"
+title: test rule
+id: test-rule
+logsource:
+  category: test
+detection:
+  selection:
+    foo: bar
+  condition: selection
+"#,
+        )
+        .unwrap();
+    let event = Event::new(json!({"foo": "bar"}));
+    for _ in 0..3 {
+        assert_eq!(collection.get_detection_matches(&event).len(), 1);
+    }
+
+    let report = sink.close().await;
+    assert_eq!((report.delivered, report.dropped), (3, 0));
+    let batches = server.await.unwrap();
+    // the failed first batch is retried, the last is sent on close
+    assert_eq!(batches[0], batches[1]);
+    assert_eq!(batches[1].as_array().unwrap().len(), 2);
+    assert_eq!(batches[2].as_array().unwrap().len(), 1);
+    assert_eq!(batches[2][0]["event"]["kind"], "alert");
+}
+
+#[cfg(feature = "webhook")]
+#[tokio::test]
+async fn test_webhook_sink_queue_full() {
+    use crate::sinks::WebhookSink;
+
+    // nothing listens on the endpoint
+    let sink = WebhookSink::new("http://127.0.0.1:1/alerts")
+        .batch_size(1)
+        .max_retries(0)
+        .queue_size(1)
+        .spawn();
+    let collection = SigmaCollection::builder()
+        .on_match(sink.observer())
+        .build_from_str(&test_rule("test-rule", "", "bar"))
+        .unwrap();
+    let event = Event::new(json!({"foo": "bar"}));
+    for _ in 0..5 {
+        assert_eq!(collection.get_detection_matches(&event).len(), 1);
+    }
+
+    // one alert is queued and fails, the others are dropped at once
+    let report = sink.close().await;
+    assert_eq!((report.delivered, report.dropped), (0, 5));
+}
+
+#[cfg(feature = "kafka")]
+#[tokio::test]
+async fn test_kafka_sink_undelivered() {
+    use crate::sinks::{KafkaSink, MessageKey};
+
+    // nothing listens on the broker, so the alerts are never acknowledged
+    let sink = KafkaSink::new("127.0.0.1:1", "alerts")
+        .key(MessageKey::GroupBy)
+        .set("message.timeout.ms", "100")
+        .spawn()
+        .unwrap();
+    let collection = SigmaCollection::builder()
+        .on_match(sink.observer())
+        .build_from_str(
+            r#"
+title: test rule
+id: test-rule
+logsource:
+  category: test
+detection:
+  selection:
+    foo: bar
+  condition: selection
+"#,
+        )
+        .unwrap();
+    let event = Event::new(json!({"foo": "bar"}));
+    for _ in 0..2 {
+        assert_eq!(collection.get_detection_matches(&event).len(), 1);
+    }
+
+    let report = sink.close().await;
+    assert_eq!((report.delivered, report.dropped), (0, 2));
+}
+
+#[cfg(feature = "syslog")]
+#[tokio::test]
+async fn test_syslog_sink() {
+    use crate::sinks::SyslogSink;
+    use tokio::io::AsyncReadExt;
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut received = String::new();
+        stream.read_to_string(&mut received).await.unwrap();
+        received
+    });
+
+    let sink = SyslogSink::tcp(&address)
+        .cef()
+        .hostname("host-1")
+        .spawn()
+        .unwrap();
+    let collection = SigmaCollection::builder()
+        .on_match(sink.observer())
+        .build_from_str(
+            r#"
+title: test rule
+id: test-rule
+level: high
+logsource:
+  category: test
+detection:
+  selection:
+    foo: bar
+  condition: selection
+"#,
+        )
+        .unwrap();
+    let event = Event::new(json!({"foo": "bar"}));
+    for _ in 0..2 {
+        assert_eq!(collection.get_detection_matches(&event).len(), 1);
+    }
+
+    let report = sink.close().await;
+    assert_eq!((report.delivered, report.dropped), (2, 0));
+    // messages are framed with their length
+    let received = server.await.unwrap();
+    let mut rest = received.as_str();
+    let mut messages = vec![];
+    while let Some((length, frame)) = rest.split_once(' ') {
+        let (message, next) = frame.split_at(length.parse().unwrap());
+        messages.push(message);
+        rest = next;
+    }
+    assert_eq!(messages.len(), 2);
+    // local0.err
+    assert!(messages[0].starts_with("<131>1 "));
+    assert!(messages[0].contains(" host-1 sigmars "));
+    assert!(messages[0].contains(" sigma - CEF:0|crowdalert|sigmars|"));
+    assert!(messages[0].contains("|test-rule|test rule|8|"));
+}
+
+#[test]
+fn test_alert_template() {
+    use crate::{AlertTemplate, TemplateError};
+    use std::sync::Mutex;
+
+    let template: AlertTemplate = concat!(
+        "{{rule.id}}: {{#if rule.description}}{{rule.description}}{{else}}no description{{/if}}",
+        "{{#each event.hosts}} [{{this.name}}]{{/each}} {{event.missing}}{{event.count}}",
+    )
+    .parse()
+    .unwrap();
+    let rendered = Arc::new(Mutex::new(vec![]));
+    let sink = rendered.clone();
+    let collection = SigmaCollection::builder()
+        .on_match(template.observer(move |text| sink.lock().unwrap().push(text)))
+        .build_from_str(
+            r#"
+title: test rule
+id: test-rule
+logsource:
+  category: test
+detection:
+  selection:
+    foo: bar
+  condition: selection
+"#,
+        )
+        .unwrap();
+    let event = Event::new(json!({
+        "foo": "bar",
+        "hosts": [{"name": "web-1"}, {"name": "web-2"}],
+        "count": 3,
+    }));
+    assert_eq!(collection.get_detection_matches(&event).len(), 1);
+    assert_eq!(
+        *rendered.lock().unwrap(),
+        vec!["test-rule: no description [web-1] [web-2] 3".to_string()]
+    );
+
+    for (template, err) in [
+        ("{{rule.id", TemplateError::UnclosedTag(0)),
+        (
+            "{{#if rule.id}}",
+            TemplateError::UnclosedSection("if".into()),
+        ),
+        (
+            "{{#each rule.tags}}{{/if}}",
+            TemplateError::InvalidTag("/if".into(), 19),
+        ),
+        ("{{else}}", TemplateError::InvalidTag("else".into(), 0)),
+    ] {
+        assert_eq!(template.parse::<AlertTemplate>(), Err(err));
+    }
+}
+
+#[cfg(feature = "correlation")]
+#[test]
+fn test_rule_json_schema() {
+    use crate::schema::{CORRELATION_TYPES, MODIFIERS};
+
+    // every modifier of the schema is accepted by the parser
+    for modifier in MODIFIERS {
+        let key = match *modifier {
+            "utf16le" | "utf16be" | "utf16" | "wide" => format!("foo|{}|base64", modifier),
+            _ => format!("foo|{}", modifier),
+        };
+        let rule = format!(
+            r#"
+title: test rule
+id: test-rule
+logsource:
+  category: test
+detection:
+  selection:
+    {}: '1'
+  condition: selection
+"#,
+            key
+        );
+        assert!(rule.parse::<SigmaCollection>().is_ok(), "{}", modifier);
+    }
+    // as are the correlation types, with a condition of their schema
+    for kind in CORRELATION_TYPES {
+        let condition = match *kind {
+            "event_count" => "{gte: 2}",
+            "value_count" => "{gte: 2, field: user}",
+            "baseline" => "{window: 1m, deviation: 3}",
+            _ => "null",
+        };
+        let rule = format!(
+            r#"
+title: test correlation
+id: test-correlation
+correlation:
+  type: {}
+  rules: [test-rule]
+  group-by: [user]
+  timespan: 10m
+  condition: {}
+"#,
+            kind, condition
+        );
+        assert!(serde_yml::from_str::<SigmaRule>(&rule).is_ok(), "{}", kind);
+    }
+
+    let schema = SigmaRule::json_schema();
+    let detection = &schema["properties"]["detection"]["additionalProperties"]["anyOf"][0];
+    let fields =
+        regex::Regex::new(detection["propertyNames"]["pattern"].as_str().unwrap()).unwrap();
+    for field in [
+        "Image|endswith",
+        "CommandLine|base64offset|contains",
+        "Path|re|i|m",
+        "a.b",
+    ] {
+        assert!(fields.is_match(field), "{}", field);
+    }
+    for field in ["Image|endwith", "Path|i|re"] {
+        assert!(!fields.is_match(field), "{}", field);
+    }
+    assert_eq!(
+        schema["properties"]["correlation"]["properties"]["type"]["enum"],
+        json!(CORRELATION_TYPES)
+    );
+}
+
+#[cfg(all(feature = "blocking", feature = "mem_backend"))]
+#[test]
+fn test_blocking_shared_collection() {
+    use crate::blocking;
+
+    let rules = r#"
+title: test rule
+id: test-rule
+logsource:
+  category: test
+detection:
+  selection:
+    foo: bar
+  condition: selection
+---
+title: repeated test rule
+id: repeated-test-rule
+correlation:
+  type: event_count
+  rules:
+    - test-rule
+  group-by:
+    - user
+  timespan: 1m
+  condition:
+    gte: 2
+"#;
+    let shared = blocking::SharedSigmaCollection::new(SigmaCollection::new()).unwrap();
+    let mut backend = shared.block_on(crate::MemBackend::new());
+    shared.replace(rules.parse().unwrap(), &mut backend);
+
+    let event = Event::new(json!({"foo": "bar", "user": "root"}));
+    assert_eq!(
+        shared.get_matches(&event).unwrap(),
+        vec!["test-rule".into()]
+    );
+    assert_eq!(
+        shared
+            .get_matches_batch(&[event.clone(), event.clone()])
+            .unwrap(),
+        vec![
+            vec!["test-rule".into(), "repeated-test-rule".into()],
+            vec!["test-rule".into(), "repeated-test-rule".into()],
+        ]
+    );
+    assert_eq!(
+        shared.get_detection_matches(&event),
+        vec!["test-rule".into()]
+    );
+}
+
+#[cfg(all(feature = "correlation", feature = "mem_backend"))]
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_generate_missing_ids() {
+    let rules = r#"
+title: private rule
+name: private_rule
+logsource:
+  category: test
+detection:
+  selection:
+    foo: bar
+  condition: selection
+---
+title: repeated private rule
+correlation:
+  type: event_count
+  rules:
+    - private_rule
+  group-by:
+    - user
+  timespan: 1m
+  condition:
+    gte: 2
+"#;
+    assert!(rules.parse::<SigmaCollection>().is_err());
+
+    let load = || {
+        let mut collection = SigmaCollection::new().generate_missing_ids(true);
+        collection.load_from_str(rules).unwrap();
+        collection
+    };
+    let mut collection = load();
+    let mut backend = crate::MemBackend::new().await;
+    collection.init(&mut backend).await;
+
+    let event = Event::new(json!({"foo": "bar", "user": "root"}));
+    let detection = collection.get_matches(&event).await.unwrap();
+    assert_eq!(detection.len(), 1);
+    let rule = collection.get(&detection[0]).unwrap();
+    assert!(rule.generated_id);
+    assert_eq!(uuid::Uuid::parse_str(&rule.id).unwrap().get_version_num(), 8);
+
+    let matches = collection.get_matches(&event).await.unwrap();
+    assert_eq!(matches.len(), 2);
+    assert!(collection.get(&matches[1]).unwrap().generated_id);
+    assert_eq!(
+        collection
+            .get_matches_batch(std::slice::from_ref(&event))
+            .await
+            .unwrap(),
+        vec![matches.clone()]
+    );
+
+    // the generated IDs are stable across loads
+    let reloaded = load();
+    assert!(matches.iter().all(|id| reloaded.get(id).is_some()));
+}
+
+
+#[cfg(feature = "correlation")]
+#[test]
+fn test_collect_and_extend() {
+    let rule = |yaml: &str| serde_yml::from_str::<SigmaRule>(yaml).unwrap();
+    let detection = || {
+        rule(
+            r#"
+title: test rule
+id: test-rule
+logsource:
+  category: test
+detection:
+  selection:
+    foo: bar
+  condition: selection
+"#,
+        )
+    };
+    let correlation = || {
+        rule(
+            r#"
+title: repeated test rule
+id: repeated-test-rule
+correlation:
+  type: event_count
+  rules:
+    - test-rule
+  group-by:
+    - user
+  timespan: 1m
+  condition:
+    gte: 2
+"#,
+        )
+    };
+
+    // dependencies are solved once all rules are collected
+    let collection = [correlation(), detection()]
+        .into_iter()
+        .collect::<Result<SigmaCollection, _>>()
+        .unwrap();
+    assert_eq!(collection.len(), 2);
+    assert!(matches!(
+        [correlation()].into_iter().collect::<Result<SigmaCollection, _>>(),
+        Err(CollectionError::DependencyMissing(..))
+    ));
+
+    let mut collection = SigmaCollection::new();
+    assert!(collection.try_extend([correlation()]).is_err());
+    assert_eq!(collection.len(), 0);
+    collection.try_extend([correlation(), detection()]).unwrap();
+    assert_eq!(collection.len(), 2);
+    assert_eq!(
+        collection.get_detection_matches(&Event::new(json!({"foo": "bar"}))),
+        vec!["test-rule".into()]
+    );
+}
+
+#[cfg(feature = "correlation")]
+#[test]
+fn test_warnings() {
+    let collection: SigmaCollection = r#"
+title: test rule
+id: test-rule
+custom: value
+tests:
+  - event:
+      foo: bar
+    match: true
+logsource:
+  category: test
+detection:
+  selection:
+    foo: bar
+    any: '*'
+    inner: b*r
+    leading: '*bar'
+  literal:
+    foo|contains: 'b*r'
+  condition: selection or literal
+---
+title: legacy rule
+id: legacy-rule
+logsource:
+  category: test
+detection:
+  selection:
+    foo: bar
+  timeframe: 1m
+  condition: selection | count() > 2
+"#
+    .parse()
+    .unwrap();
+
+    let warnings = collection
+        .warnings()
+        .into_iter()
+        .map(|(rule, warning)| (rule.id.to_string(), warning.clone()))
+        .collect::<Vec<_>>();
+    assert_eq!(warnings.len(), 5);
+    assert_eq!(warnings.iter().filter(|(id, _)| id == "legacy-rule").count(), 1);
+    assert!(warnings
+        .iter()
+        .any(|(_, w)| matches!(w, RuleWarning::Deprecated { .. })));
+    for value in ["*", "b*r"] {
+        assert!(warnings.iter().any(|(id, w)| id == "test-rule"
+            && matches!(w, RuleWarning::Wildcard { value: v, .. } if v == value)));
+    }
+    assert!(warnings.contains(&(
+        "test-rule".to_string(),
+        RuleWarning::UnknownField {
+            field: "custom".to_string()
+        }
+    )));
+    assert!(collection.get("test-rule").unwrap().warnings.len() == 4);
+}
+
+#[cfg(feature = "glob")]
+#[test]
+fn test_dir_options() {
+    let dir = std::env::temp_dir().join(format!("sigmars-{}", uuid::Uuid::new_v4()));
+    let rules = dir.join("rules");
+    std::fs::create_dir_all(rules.join("a").join("b")).unwrap();
+    std::fs::create_dir_all(rules.join("deprecated")).unwrap();
+    let rule = |path: std::path::PathBuf, id: &str| {
+        std::fs::write(path, test_rule(id, "", "bar")).unwrap()
+    };
+    rule(rules.join("top.yml"), "top");
+    rule(rules.join("yaml.yaml"), "yaml");
+    rule(rules.join("a").join("one.yml"), "one");
+    rule(rules.join("a").join("b").join("two.yml"), "two");
+    rule(rules.join("deprecated").join("old.yml"), "old");
+    std::fs::write(rules.join("notes.txt"), "not a rule").unwrap();
+    rule(dir.join("linked.yml"), "linked");
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(dir.join("linked.yml"), rules.join("linked.yml")).unwrap();
+        // a cycle, traversed once
+        std::os::unix::fs::symlink(&rules, rules.join("a").join("loop")).unwrap();
+    }
+
+    let load = |options: DirOptions| {
+        let mut collection = SigmaCollection::new().dir_options(options);
+        collection.load_from_dir(rules.to_str().unwrap()).unwrap();
+        ["linked", "old", "one", "top", "two", "yaml"]
+            .into_iter()
+            .filter(|id| collection.get(id).is_some())
+            .collect::<Vec<_>>()
+    };
+
+    let all = load(DirOptions::new());
+    assert!(all.contains(&"yaml") && all.contains(&"two"));
+    assert_eq!(all.len(), if cfg!(unix) { 6 } else { 5 });
+    assert_eq!(
+        load(
+            DirOptions::new()
+                .extensions(["yml"])
+                .exclude("deprecated")
+                .unwrap()
+                .max_depth(1)
+                .follow_symlinks(false)
+        ),
+        ["one", "top"]
+    );
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_overrides() {
+    let dir = std::env::temp_dir().join(format!("sigmars-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(dir.join("rules")).unwrap();
+    std::fs::create_dir_all(dir.join("overrides")).unwrap();
+    let rule = |id: &str, value: &str| {
+        rule_yaml(
+            id,
+            "level: high",
+            "  category: test",
+            &format!(
+                "  selection:\n    foo: {value}\n  filter:\n    user: root\n\
+                 \x20 condition: selection and not filter"
+            ),
+        )
+    };
+    std::fs::write(dir.join("rules").join("a.yml"), rule("a", "bar")).unwrap();
+    std::fs::write(dir.join("rules").join("b.yml"), rule("b", "bar")).unwrap();
+    std::fs::write(dir.join("rules").join("c.yml"), rule("c", "bar")).unwrap();
+    std::fs::write(
+        dir.join("overrides").join("tuning.yml"),
+        r#"
+rule: a
+level: low
+filter:
+  filter_admin:
+    user: admin
+---
+rule: b
+enabled: false
+---
+rule: c
+level: low
+filter:
+  filter:
+    user: admin
+"#,
+    )
+    .unwrap();
+
+    let overrides = Overrides::from_dir(dir.join("overrides").to_str().unwrap()).unwrap();
+    assert_eq!(overrides.len(), 3);
+    let mut collection = SigmaCollection::new().overrides(overrides);
+    assert_eq!(collection.load_from_dir(dir.join("rules").to_str().unwrap()).unwrap(), 2);
+    assert!(collection.get("b").is_none());
+
+    let check = |collection: &SigmaCollection| {
+        assert_eq!(collection.get("a").unwrap().level.as_deref(), Some("low"));
+        for (user, matches) in [("admin", 1), ("root", 0), ("guest", 2)] {
+            let event = Event::new(json!({"foo": "bar", "user": user}));
+            assert_eq!(collection.get_detection_matches(&event).len(), matches);
+        }
+        // a conflicting filter leaves the rule unchanged
+        let c = collection.get("c").unwrap();
+        assert_eq!(c.level.as_deref(), Some("high"));
+        assert!(matches!(c.warnings[..], [RuleWarning::Override { .. }]));
+    };
+    check(&collection);
+
+    // overrides apply to updated upstream rules
+    let modified = std::time::SystemTime::now() + std::time::Duration::from_secs(10);
+    std::fs::write(dir.join("rules").join("a.yml"), rule("a", "bar")).unwrap();
+    std::fs::File::options()
+        .write(true)
+        .open(dir.join("rules").join("a.yml"))
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+    assert_eq!(collection.reload_changed().unwrap(), 1);
+    check(&collection);
+
+    assert!("rule: a\nfilter:\n  f:\n    foo|invalid: bar\n".parse::<Overrides>().is_err());
+    assert!("rule: a\nlevle: low\n".parse::<Overrides>().is_err());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_metadata_overrides() {
+    let overrides = "rule: test-rule\nlevel: medium\ntags:\n  - attack.t1059\n"
+        .parse::<Overrides>()
+        .unwrap()
+        .level("test-rule", "critical")
+        .owner("test-rule", "soc-team")
+        .tags("other-rule", ["attack.discovery"]);
+    assert_eq!(overrides.len(), 4);
+    let collection = SigmaCollection::builder()
+        .overrides(overrides)
+        .build_from_str(
+            r#"
+title: test rule
+id: test-rule
+level: low
+tags:
+  - attack.execution
+logsource:
+  category: test
+detection:
+  selection:
+    foo: bar
+  condition: selection
+"#,
+        )
+        .unwrap();
+
+    // later overrides win, tags are replaced
+    let rule = collection.get("test-rule").unwrap();
+    assert_eq!(rule.level.as_deref(), Some("critical"));
+    assert_eq!(rule.owner.as_deref(), Some("soc-team"));
+    let finding = serde_json::Value::from(rule);
+    assert_eq!(finding["severity_id"], 5);
+    assert_eq!(finding["severity"], "critical");
+    assert_eq!(finding["metadata"]["labels"], json!(["attack.t1059"]));
+    assert_eq!(finding["unmapped"]["owner"], "soc-team");
+}
+
+#[cfg(feature = "time")]
+#[test]
+fn test_schedule() {
+    use chrono::{TimeZone, Utc, Weekday};
+
+    let at = |y, m, d, h| Utc.with_ymd_and_hms(y, m, d, h, 0, 0).unwrap();
+    // 2024-06-07 is a Friday
+    let nights = crate::Schedule::new()
+        .between("22:00", "06:00")
+        .unwrap()
+        .days([Weekday::Fri]);
+    assert!(nights.is_active(at(2024, 6, 7, 23)));
+    assert!(nights.is_active(at(2024, 6, 8, 5)));
+    assert!(!nights.is_active(at(2024, 6, 8, 23)));
+    assert!(!nights.is_active(at(2024, 6, 7, 5)));
+    assert!(!nights.is_active(at(2024, 6, 7, 12)));
+
+    let offset = chrono::FixedOffset::east_opt(2 * 3600).unwrap();
+    let local = crate::Schedule::new()
+        .between("09:00", "17:00")
+        .unwrap()
+        .utc_offset(offset);
+    assert!(local.is_active(at(2024, 6, 7, 7)));
+    assert!(!local.is_active(at(2024, 6, 7, 16)));
+    assert!(crate::Schedule::new().between("9h", "17h").is_err());
+
+    let collection = SigmaCollection::builder()
+        .schedule("weekend", crate::Schedule::new().days([Weekday::Sat, Weekday::Sun]))
+        .build_from_str(
+            r#"
+title: test rule
+id: test-rule
+name: weekend
+logsource:
+  category: test
+detection:
+  selection:
+    foo: bar
+  condition: selection
+"#,
+        )
+        .unwrap();
+    for (time, matches) in [("2024-06-08T10:00:00Z", 1), ("2024-06-10T10:00:00Z", 0)] {
+        let event = Event::new(json!({"foo": "bar", "@timestamp": time}));
+        assert_eq!(collection.get_detection_matches(&event).len(), matches);
+    }
+}
+
+#[test]
+fn test_telemetry() {
+    let rules = SigmaCollection::builder()
+        .track_telemetry(true)
+        .build_from_str(
+            r#"
+title: foo rule
+id: foo-rule
+logsource:
+  category: test
+detection:
+  selection:
+    foo: bar
+  condition: selection
+---
+title: any rule
+id: any-rule
+logsource:
+  category: test
+detection:
+  selection:
+    foo|exists: true
+  condition: selection
+"#,
+        )
+        .unwrap();
+    assert!(SigmaCollection::new().telemetry().is_none());
+    assert_eq!(rules.telemetry().unwrap().events, 0);
+
+    let clone = rules.clone();
+    for value in ["bar", "baz", "bar", "quux"] {
+        clone.get_detection_matches(&Event::new(json!({"foo": value})));
+    }
+    clone.get_detection_matches(&Event::new(json!({"other": "bar"})));
+
+    let telemetry = rules.telemetry().unwrap();
+    assert_eq!(telemetry.events, 5, "telemetry is shared by clones");
+    assert_eq!(
+        telemetry.rule_matches,
+        vec![("any-rule".into(), 4), ("foo-rule".into(), 2)]
+    );
+    assert_eq!(telemetry.top_rules(1), &[("any-rule".into(), 4)]);
+    assert!(telemetry.latency_p50 > std::time::Duration::ZERO);
+    assert!(telemetry.latency_p50 <= telemetry.latency_p90);
+    assert!(telemetry.latency_p90 <= telemetry.latency_p99);
+}
+
+#[cfg(feature = "time")]
+#[test]
+fn test_explain() {
+    use crate::{RuleOutcome, Schedule, Suppression};
+
+    let rules = SigmaCollection::builder()
+        .schedule("night-rule", Schedule::new().between("22:00", "06:00").unwrap())
+        .build_from_str(
+            r#"
+title: filtered rule
+id: filtered-rule
+logsource:
+  category: test
+detection:
+  selection:
+    foo: bar
+  filter:
+    user: admin
+  condition: selection and not filter
+---
+title: command rule
+id: command-rule
+logsource:
+  category: test
+detection:
+  selection:
+    CommandLine|contains: whoami
+  condition: selection
+---
+title: night rule
+id: night-rule
+logsource:
+  category: test
+detection:
+  selection:
+    foo: bar
+  condition: selection
+---
+title: other rule
+id: other-rule
+logsource:
+  category: other
+detection:
+  selection:
+    foo: bar
+  condition: selection
+"#,
+        )
+        .unwrap();
+    let suppression = rules
+        .suppressions()
+        .add(Suppression::new("filtered-rule").field("user", "root"));
+
+    let event = |user: &str| {
+        Event::new(json!({"foo": "bar", "user": user, "@timestamp": "2024-06-01T12:00:00Z"}))
+            .logsource(LogSource::default().category("test"))
+    };
+    let explanations = rules.explain(&event("admin"));
+    let ids = explanations.iter().map(|e| e.rule.clone()).collect::<Vec<_>>();
+    assert_eq!(
+        ids,
+        vec!["command-rule".into(), "filtered-rule".into(), "night-rule".into()]
+    );
+    assert_eq!(explanations[0].outcome, RuleOutcome::NotMatched);
+    assert_eq!(explanations[0].missing_fields, vec!["CommandLine".into()]);
+    assert_eq!(explanations[1].outcome, RuleOutcome::NotMatched);
+    assert_eq!(
+        explanations[1].selections,
+        [("filter".to_string(), true), ("selection".to_string(), true)].into()
+    );
+    assert_eq!(explanations[2].outcome, RuleOutcome::NotScheduled);
+
+    let explanations = rules.explain(&event("guest"));
+    assert!(explanations[1].matched());
+    assert_eq!(explanations[1].failed_selections().collect::<Vec<_>>(), vec!["filter"]);
+
+    let explanations = rules.explain(&event("root"));
+    assert_eq!(explanations[1].outcome, RuleOutcome::Suppressed(suppression));
+    assert_eq!(rules.suppressions().suppressed(), 0, "explaining has no side effects");
+}
+
+#[test]
+fn test_overlaps() {
+    use crate::{OverlapKind, RuleOverlap};
+
+    let rule = |id: &str, category: &str, detection: &str| {
+        rule_yaml(id, "", &format!("  category: {category}"), detection)
+    };
+    let rules = [
+        rule(
+            "a",
+            "test",
+            r#"
+  selection:
+    Image: ['/bin/sh', '/bin/BASH']
+  condition: selection
+"#,
+        ),
+        // the same values in another order and case
+        rule(
+            "b",
+            "test",
+            r#"
+  selection:
+    Image: ['/bin/bash', '/bin/sh']
+  condition: selection
+"#,
+        ),
+        rule(
+            "c",
+            "test",
+            r#"
+  sel_sh:
+    Image|endswith: '/sh'
+  sel_zsh:
+    Image: '*/zsh'
+  condition: 1 of sel_*
+"#,
+        ),
+        rule(
+            "d",
+            "test",
+            r#"
+  selection:
+    Image|endswith: '/sh'
+  filter:
+    User: admin
+  condition: selection and not filter
+"#,
+        ),
+        // filters more users than `d`
+        rule(
+            "e",
+            "test",
+            r#"
+  selection:
+    Image: '/bin/sh'
+    User: root
+  filter:
+    User|startswith: adm
+  condition: selection and not filter
+"#,
+        ),
+        rule(
+            "f",
+            "other",
+            r#"
+  selection:
+    Image: '/bin/sh'
+  condition: selection
+"#,
+        ),
+    ]
+    .join("---\n");
+    let collection: SigmaCollection = rules.parse().unwrap();
+
+    let overlap = |general: &str, specific: &str, kind| RuleOverlap {
+        general: general.into(),
+        specific: specific.into(),
+        kind,
+    };
+    assert_eq!(
+        collection.overlaps(),
+        vec![
+            overlap("a", "b", OverlapKind::Identical),
+            overlap("a", "e", OverlapKind::Subsumes),
+            overlap("b", "e", OverlapKind::Subsumes),
+            // `c` matches `/bin/sh` but not `/bin/bash`
+            overlap("c", "d", OverlapKind::Subsumes),
+            overlap("c", "e", OverlapKind::Subsumes),
+            overlap("d", "e", OverlapKind::Subsumes),
+        ]
+    );
+}
+
+#[test]
+fn test_required_logsources() {
+    let rule = |id: &str, logsource: &str| {
+        rule_yaml(id, "", logsource, &format!("  selection:\n    foo: {id}\n  condition: selection"))
+    };
+    let rules = [
+        rule("a", "  product: windows\n  service: security"),
+        rule("b", "  category: process_creation\n  product: windows"),
+        rule("c", "  product: windows\n  service: security\n  definition: audit policy enabled"),
+        rule("d", "  category: process_creation\n  product: linux"),
+        rule("e", "  product: windows\n  service: security"),
+    ]
+    .join("---\n");
+    let collection: SigmaCollection = rules.parse().unwrap();
+
+    let logsources = collection
+        .required_logsources()
+        .into_iter()
+        .map(|(l, count)| (l.category, l.product, l.service, count))
+        .collect::<Vec<_>>();
+    let some = |s: &str| Some(s.to_string());
+    assert_eq!(
+        logsources,
+        vec![
+            (None, some("windows"), some("security"), 3),
+            (some("process_creation"), some("linux"), None, 1),
+            (some("process_creation"), some("windows"), None, 1),
+        ]
+    );
+
+    // log sources differing by their definition only are the same
+    let by_logsource = collection.referenced_fields_by_logsource();
+    assert_eq!(by_logsource.len(), 3);
+    assert!(by_logsource[0].0.extra.is_empty());
+    assert_eq!(by_logsource[0].1.fields.iter().collect::<Vec<_>>(), ["foo"]);
+}
+
+#[test]
+fn test_logsource_fields() {
+    let collection: SigmaCollection = r#"
+title: ssh failed password
+id: ssh-failed-password
+logsource:
+  product: linux
+detection:
+  selection:
+    '@logsource.service': sshd
+    message|contains: Failed password
+  condition: selection
+---
+title: auth service
+id: auth-service
+logsource:
+  product: linux
+detection:
+  selection:
+    '@logsource.service|startswith': auth
+  condition: selection
+"#
+    .parse()
+    .unwrap();
+
+    let event = |service: &str, message: &str| {
+        Event::new(json!({"message": message}))
+            .logsource(LogSource::default().product("linux").service(service))
+    };
+    let matches = collection.get_detection_matches(&event("sshd", "Failed password for root"));
+    assert_eq!(matches.iter().map(|id| &**id).collect::<Vec<_>>(), ["ssh-failed-password"]);
+    assert!(collection
+        .get_detection_matches(&event("cron", "Failed password for root"))
+        .is_empty());
+
+    let matches = collection.get_detection_matches(&event("authpriv", "session opened"));
+    assert_eq!(matches.iter().map(|id| &**id).collect::<Vec<_>>(), ["auth-service"]);
+    assert!(collection
+        .get("auth-service")
+        .unwrap()
+        .is_match(&event("authpriv", "")));
+
+    // the log source an event is evaluated as is matched
+    let sshd = LogSource::default().product("linux").service("sshd");
+    let matches = collection.get_detection_matches_for(&event("cron", "Failed password for root"), &sshd);
+    assert_eq!(matches.iter().map(|id| &**id).collect::<Vec<_>>(), ["ssh-failed-password"]);
+
+    // log source fields are not fields of events
+    let fields = collection.referenced_fields();
+    assert_eq!(fields.fields.iter().collect::<Vec<_>>(), ["message"]);
+
+    // the sample events of a rule come from its log source
+    let tested: SigmaCollection = r#"
+title: sshd
+id: sshd
+logsource:
+  product: linux
+  service: sshd
+detection:
+  selection:
+    '@logsource.service': sshd
+  condition: selection
+tests:
+  positive:
+    - message: Accepted publickey for root
+"#
+    .parse()
+    .unwrap();
+    assert!(tested.run_rule_tests()[0].passed());
+}
@@ -2,6 +2,7 @@ use serde_json::json;
 use tokio::test;
 
 use crate::{collection::*, event::{Event, LogSource}};
+use crate::{Match, SigmaRule};
 use std::collections::HashMap;
 
 pub static COLLECTION: &str = r#"
@@ -81,6 +82,310 @@ async fn test_event_count() {
     assert!(res.len() == 2);
 }
 
+#[test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_event_count_wal() {
+    use crate::correlation::state::mem::MemBackend;
+
+    let path = std::env::temp_dir().join(format!("sigmars-{}.wal", uuid::Uuid::new_v4()));
+    let event = Event::new(json!({
+        "foo": "bar",
+        "correlation_group_by": "test"
+    }));
+
+    let mut backend = MemBackend::with_wal(&path).await.unwrap();
+    let mut collection: SigmaCollection = COLLECTION.parse().unwrap();
+    collection.init(&mut backend).await;
+    let res = collection.get_matches(&event).await.unwrap();
+    assert!(res.len() == 1);
+    backend.health().await.unwrap();
+    backend.shutdown().await.unwrap();
+    drop(collection);
+    drop(backend);
+
+    // the increment of the first event is replayed from the log
+    let mut backend = MemBackend::with_wal(&path).await.unwrap();
+    let mut collection: SigmaCollection = COLLECTION.parse().unwrap();
+    collection.init(&mut backend).await;
+    let res = collection.get_matches(&event).await.unwrap();
+    assert!(res.len() == 2);
+
+    backend.checkpoint().await.unwrap();
+    let log = std::fs::read_to_string(&path).unwrap();
+    assert_eq!(log.lines().count(), 2);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_event_count_snapshot() {
+    use crate::correlation::state::mem::{MemBackend, StateSnapshot};
+
+    let event = Event::new(json!({
+        "foo": "bar",
+        "correlation_group_by": "test"
+    }));
+
+    let mut active = MemBackend::new().await;
+    let mut collection: SigmaCollection = COLLECTION.parse().unwrap();
+    collection.init(&mut active).await;
+    let res = collection.get_matches(&event).await.unwrap();
+    assert!(res.len() == 1);
+
+    let snapshot = active.snapshot_all().await;
+    assert_eq!(snapshot.epoch, 1);
+    assert_eq!(snapshot.len(), 1);
+    let snapshot = serde_json::to_string(&snapshot).unwrap();
+
+    // the passive instance takes over with the window of the first event
+    let mut passive = MemBackend::new().await;
+    let mut collection: SigmaCollection = COLLECTION.parse().unwrap();
+    collection.init(&mut passive).await;
+    let snapshot: StateSnapshot = serde_json::from_str(&snapshot).unwrap();
+    passive.restore(&snapshot).await.unwrap();
+    assert_eq!(passive.epoch(), 1);
+    let res = collection.get_matches(&event).await.unwrap();
+    assert!(res.len() == 2);
+    assert_eq!(passive.epoch(), 2);
+
+    passive.restore(&snapshot).await.unwrap();
+    assert_eq!(passive.snapshot_all().await.len(), 1);
+}
+
+#[cfg(feature = "mock_clock")]
+#[test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_event_count_advance() {
+    use crate::correlation::state::mem::MemBackend;
+    use crate::correlation::state::Key;
+    use std::time::Duration;
+
+    let event = Event::new(json!({
+        "foo": "bar",
+        "correlation_group_by": "test"
+    }));
+    let key = Key::EventCount(vec![("correlation_group_by".to_string(), json!("test"))]);
+
+    let mut backend = MemBackend::new().await;
+    let mut collection: SigmaCollection = COLLECTION.parse().unwrap();
+    collection.init(&mut backend).await;
+    collection.get_matches(&event).await.unwrap();
+    backend.advance(Duration::from_secs(300));
+    assert!(collection.get_matches(&event).await.unwrap().len() == 2);
+    assert_eq!(backend.count("2", &key).await, 2);
+
+    // the first increment expires, the second is left
+    backend.advance(Duration::from_secs(301));
+    assert_eq!(backend.count("2", &key).await, 1);
+    assert!(collection.get_matches(&event).await.unwrap().len() == 2);
+
+    crate::testing::assert_expires(&backend, "2", &key, Duration::from_secs(600)).await;
+    assert!(collection.get_matches(&event).await.unwrap().len() == 1);
+}
+
+#[cfg(feature = "mock_clock")]
+#[test(flavor = "multi_thread", worker_threads = 2)]
+#[should_panic(expected = "expired from 2 before")]
+async fn test_assert_expires_early() {
+    use crate::correlation::state::mem::MemBackend;
+    use crate::correlation::state::Key;
+    use std::time::Duration;
+
+    let event = Event::new(json!({"foo": "bar", "correlation_group_by": "test"}));
+    let key = Key::EventCount(vec![("correlation_group_by".to_string(), json!("test"))]);
+
+    let mut backend = MemBackend::new().await;
+    let mut collection: SigmaCollection = COLLECTION.parse().unwrap();
+    collection.init(&mut backend).await;
+    collection.get_matches(&event).await.unwrap();
+
+    // the key expires after 10 minutes, well before the asserted timespan
+    crate::testing::assert_expires(&backend, "2", &key, Duration::from_secs(1200)).await;
+}
+
+#[test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_hash_keys() {
+    use crate::correlation::state::mem::MemBackend;
+    use crate::correlation::state::Key;
+
+    let path = std::env::temp_dir().join(format!("sigmars-{}.wal", uuid::Uuid::new_v4()));
+    let event = Event::new(json!({
+        "foo": "bar",
+        "correlation_group_by": "alice"
+    }));
+
+    let mut backend = MemBackend::with_wal(&path).await.unwrap().hash_keys("salt").unwrap();
+    let mut collection: SigmaCollection = COLLECTION.parse().unwrap();
+    collection.init(&mut backend).await;
+    assert!(collection.get_matches(&event).await.unwrap().len() == 1);
+    assert!(collection.get_matches(&event).await.unwrap().len() == 2);
+
+    // other values are grouped apart
+    let other = Event::new(json!({
+        "foo": "bar",
+        "correlation_group_by": "bob"
+    }));
+    assert!(collection.get_matches(&other).await.unwrap().len() == 1);
+
+    // distinct values are counted, and reported, as hashes
+    for value in ["10.0.0.1", "10.0.0.2"] {
+        let event = Event::new(json!({
+            "baz": "quux",
+            "correlation_group_by": "carol",
+            "correlation_field": value
+        }));
+        collection.evaluate(&event).await.unwrap();
+    }
+    let event = Event::new(json!({
+        "baz": "quux",
+        "correlation_group_by": "carol",
+        "correlation_field": "10.0.0.1"
+    }));
+    let res = collection.evaluate(&event).await.unwrap();
+    assert_eq!(res.values["3"].len(), 2);
+    assert!(!res.values["3"].contains(&json!("10.0.0.1")));
+
+    let key = Key::EventCount(vec![("correlation_group_by".to_string(), json!("alice"))]);
+    assert!(backend.ttl("2", &key).await.is_some());
+
+    backend.shutdown().await.unwrap();
+    let snapshot = serde_json::to_string(&backend.snapshot_all().await).unwrap();
+    let log = std::fs::read_to_string(&path).unwrap();
+    for state in [snapshot, log] {
+        assert!(state.contains("correlation_group_by"));
+        for raw in ["alice", "bob", "carol", "10.0.0."] {
+            assert!(!state.contains(raw));
+        }
+    }
+    std::fs::remove_file(&path).unwrap();
+
+    // the hash depends on the salt
+    assert_ne!(
+        <&Key as Into<(String, Option<String>)>>::into(&key.hashed(b"salt")),
+        <&Key as Into<(String, Option<String>)>>::into(&key.hashed(b"pepper")),
+    );
+
+    // the state of registered rules is not hashed
+    assert!(backend.hash_keys("salt").is_err());
+}
+
+#[test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_event_count_ttl() {
+    use crate::correlation::state::Key;
+    use std::time::{Duration, SystemTime};
+
+    let mut backend = crate::correlation::state::mem::MemBackend::new().await;
+    let mut collection: SigmaCollection = COLLECTION.parse().unwrap();
+    collection.init(&mut backend).await;
+
+    let key = Key::EventCount(vec![("correlation_group_by".to_string(), json!("test"))]);
+    assert!(backend.ttl("2", &key).await.is_none());
+
+    let event = Event::new(json!({
+        "foo": "bar",
+        "correlation_group_by": "test"
+    }));
+    collection.get_matches(&event).await.unwrap();
+
+    let ttl = backend.ttl("2", &key).await.unwrap();
+    assert!(ttl <= Duration::from_secs(600) && ttl > Duration::from_secs(590));
+    let expires_at = backend.expires_at("2", &key).await.unwrap();
+    assert!(expires_at <= SystemTime::now() + Duration::from_secs(600));
+}
+
+#[test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_event_count_rate() {
+    let rules = r#"
+title: event count detection
+id: 0
+logsource:
+  category: correlation
+detection:
+  selection:
+    foo: bar
+  condition: selection
+---
+title: event rate
+id: 1
+correlation:
+    type: event_count
+    rules:
+        - "0"
+    group-by:
+        - correlation_group_by
+    timespan: 1h
+    condition:
+        gte: 2
+        per: 1s
+"#;
+    let mut backend = crate::correlation::state::mem::MemBackend::new().await;
+    let mut collection: SigmaCollection = rules.parse().unwrap();
+    collection.init(&mut backend).await;
+
+    let event = Event::new(json!({
+        "foo": "bar",
+        "correlation_group_by": "test"
+    }));
+    assert!(collection.get_matches(&event).await.unwrap().len() == 1);
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    assert!(collection.get_matches(&event).await.unwrap().len() == 1);
+    assert!(collection.get_matches(&event).await.unwrap().len() == 2);
+    assert!(collection.to_string().parse::<SigmaCollection>().is_ok());
+
+    let rules = rules.replace("per: 1s", "per: 2h");
+    assert!(rules.parse::<SigmaCollection>().is_err());
+}
+
+#[test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_baseline() {
+    let rules = r#"
+title: event count detection
+id: 0
+logsource:
+  category: correlation
+detection:
+  selection:
+    foo: bar
+  condition: selection
+---
+title: unusual volume
+id: 1
+correlation:
+    type: baseline
+    rules:
+        - "0"
+    group-by:
+        - correlation_group_by
+    timespan: 2s
+    condition:
+        window: 1s
+        deviation: 1
+        minimum: 3
+"#;
+    let mut backend = crate::correlation::state::mem::MemBackend::new().await;
+    let mut collection: SigmaCollection = rules.parse().unwrap();
+    collection.init(&mut backend).await;
+
+    let event = Event::new(json!({
+        "foo": "bar",
+        "correlation_group_by": "test"
+    }));
+    let mut matches = vec![];
+    for _ in 0..3 {
+        matches.push(collection.get_matches(&event).await.unwrap().len());
+    }
+    // the previous window is empty, so reaching the minimum is unusual
+    assert_eq!(matches, vec![1, 1, 2]);
+
+    tokio::time::sleep(std::time::Duration::from_millis(1200)).await;
+    let mut matches = vec![];
+    for _ in 0..4 {
+        matches.push(collection.get_matches(&event).await.unwrap().len());
+    }
+    // the previous window had 3 events
+    assert_eq!(matches, vec![1, 1, 1, 2]);
+
+    let rules = rules.replace("window: 1s", "window: 2s");
+    assert!(rules.parse::<SigmaCollection>().is_err());
+}
+
 #[test(flavor = "multi_thread", worker_threads = 2)]
 async fn test_event_count_no_matching_groupby() {
     let mut backend = crate::correlation::state::mem::MemBackend::new().await;
@@ -112,6 +417,83 @@ async fn test_event_count_no_matching_groupby() {
     assert!(res.len() == 1);
 }
 
+#[test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_event_count_observer() {
+    let mut backend = crate::correlation::state::mem::MemBackend::new().await;
+    let matches = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+    let observed = matches.clone();
+    let observer = move |rule: &SigmaRule, _: &Event, m: &Match| {
+        observed
+            .lock()
+            .unwrap()
+            .push((rule.id.clone(), m.correlation));
+    };
+    let mut collection = COLLECTION
+        .parse::<SigmaCollection>()
+        .unwrap()
+        .on_match(observer);
+    collection.init(&mut backend).await;
+
+    let event = Event::new(json!({
+        "foo": "bar",
+        "correlation_group_by": "test"
+    }));
+    collection.get_matches(&event).await.unwrap();
+    collection.get_matches(&event).await.unwrap();
+
+    assert_eq!(
+        *matches.lock().unwrap(),
+        vec![("0".into(), false), ("0".into(), false), ("2".into(), true)]
+    );
+}
+
+#[test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_event_count_timeout() {
+    let mut backend = crate::correlation::state::mem::MemBackend::new().await;
+    let mut collection = COLLECTION
+        .parse::<SigmaCollection>()
+        .unwrap()
+        .timeout(std::time::Duration::from_secs(60));
+    collection.init(&mut backend).await;
+
+    let event = Event::new(json!({
+        "foo": "bar",
+        "correlation_group_by": "test"
+    }));
+    collection.evaluate(&event).await.unwrap();
+    let res = collection.evaluate(&event).await.unwrap();
+    assert!(res.is_complete());
+    assert_eq!(res.matches, vec!["0".into(), "2".into()]);
+
+    let collection = collection.timeout(std::time::Duration::ZERO);
+    let res = collection.evaluate(&event).await.unwrap();
+    assert!(res.matches.is_empty());
+    assert!(res.not_evaluated.contains(&"0".into()));
+}
+
+#[test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_event_count_tenants() {
+    let mut backend = crate::correlation::state::mem::MemBackend::new().await;
+    let mut collection: SigmaCollection = COLLECTION.parse().unwrap();
+    collection.init(&mut backend).await;
+
+    let data = json!({
+        "foo": "bar",
+        "correlation_group_by": "test"
+    });
+    let first = Event::new(data.clone()).tenant("first");
+    let second = Event::new(data).tenant("second");
+
+    let res = collection.get_matches(&first).await.unwrap();
+    assert!(res.len() == 1);
+
+    let res = collection.get_matches(&second).await.unwrap();
+    assert!(res.len() == 1, "tenants should not share correlation state");
+
+    let res = collection.get_matches(&first).await.unwrap();
+    assert!(res.len() == 2);
+}
+
 #[test(flavor = "multi_thread", worker_threads = 1)]
 async fn test_event_count_no_groupby() {
     
@@ -168,6 +550,33 @@ async fn test_value_count() {
     assert!(res.len() == 2);
 }
 
+#[test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_value_count_values() {
+    let mut backend = crate::correlation::state::mem::MemBackend::new().await;
+    let mut collection: SigmaCollection = COLLECTION.parse().unwrap();
+    collection.init(&mut backend).await;
+
+    for value in ["first", "second"] {
+        let event = Event::new(json!({
+            "baz": "quux",
+            "correlation_group_by": "test",
+            "correlation_field": value
+        }));
+        collection.evaluate(&event).await.unwrap();
+    }
+    let event = Event::new(json!({
+        "baz": "quux",
+        "correlation_group_by": "test",
+        "correlation_field": 3
+    }));
+    let res = collection.evaluate(&event).await.unwrap();
+
+    let mut values = res.values["3"].clone();
+    values.sort_by_key(|value| value.to_string());
+    assert_eq!(values, vec![json!("first"), json!("second"), json!(3)]);
+    assert!(!res.values.contains_key("1"));
+}
+
 #[test(flavor = "multi_thread", worker_threads = 2)]
 async fn test_value_count_unmatched_groupby() {
     let mut backend = crate::correlation::state::mem::MemBackend::new().await;
@@ -248,6 +657,8 @@ detection:
     let firstevent = Event {
         logsource: LogSource::default(),
         metadata: HashMap::new(),
+        tenant: None,
+        context: None,
         data: json!({
                 "test": "yes",
                 "first": "firstvalue"
@@ -261,6 +672,8 @@ detection:
     let secondevent = Event {
         logsource: LogSource::default(),
         metadata: HashMap::new(),
+        tenant: None,
+        context: None,
         data: json!({
                 "test": "yes",
                 "second": "secondvalue"
@@ -330,6 +743,8 @@ detection:
     let firstevent = Event {
         logsource: LogSource::default(),
         metadata: HashMap::new(),
+        tenant: None,
+        context: None,
         data: json!({
                 "test": "yes",
                 "first": "firstvalue"
@@ -343,6 +758,8 @@ detection:
     let secondevent = Event {
         logsource: LogSource::default(),
         metadata: HashMap::new(),
+        tenant: None,
+        context: None,
         data: json!({
                 "test": "yes",
                 "second": "secondvalue"
@@ -366,3 +783,1070 @@ detection:
         "out-of-order events should not match temporal ordered correlations"
     );
 }
+
+#[test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_shared_collection_keeps_state() {
+    let mut backend = crate::correlation::state::mem::MemBackend::new().await;
+    let shared = crate::SharedSigmaCollection::new(SigmaCollection::new());
+    shared
+        .replace(COLLECTION.parse().unwrap(), &mut backend)
+        .await;
+
+    let event = Event {
+        data: json!({
+                "foo": "bar",
+                "correlation_group_by": "test"
+            }
+        ),
+        ..Default::default()
+    };
+
+    let res = shared.get_matches(&event).await.unwrap();
+    assert!(res.len() == 1);
+
+    let previous = shared
+        .replace(COLLECTION.parse().unwrap(), &mut backend)
+        .await;
+    assert!(previous.len() == 4);
+
+    let res = shared.get_matches(&event).await.unwrap();
+    assert!(
+        res.len() == 2,
+        "correlation state should be handed over to the new collection"
+    );
+}
+
+#[test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_legacy_timeframe_aggregation() {
+    let rules = r#"
+title: Multiple failed logons
+id: 6309ffc4-8fa2-47cf-96b8-a2f72e58e538
+logsource:
+    product: windows
+    service: security
+detection:
+    selection:
+        EventID: 4625
+    timeframe: 5m
+    condition: selection | count() by TargetUserName > 2
+"#;
+    let mut backend = crate::correlation::state::mem::MemBackend::new().await;
+    let mut collection: SigmaCollection = rules.parse().unwrap();
+    collection.init(&mut backend).await;
+    assert!(collection.len() == 2);
+
+    let event = Event {
+        data: json!({
+                "EventID": 4625,
+                "TargetUserName": "admin"
+            }
+        ),
+        ..Default::default()
+    };
+
+    for _ in 0..2 {
+        let res = collection.get_matches(&event).await.unwrap();
+        assert!(res.len() == 1);
+        assert!(&*res[0] == "6309ffc4-8fa2-47cf-96b8-a2f72e58e538-detection");
+    }
+
+    let res = collection.get_matches(&event).await.unwrap();
+    assert!(res.len() == 2);
+    assert!(&*res[1] == "6309ffc4-8fa2-47cf-96b8-a2f72e58e538");
+
+    let rules = r#"
+title: Missing timeframe
+id: 3e3ec9d2-4a41-4a85-8d1e-4ee5b3c1b8f8
+logsource:
+    category: test
+detection:
+    selection:
+        foo: bar
+    condition: selection | count() > 2
+"#;
+    assert!(rules.parse::<SigmaCollection>().is_err());
+}
+
+#[test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_legacy_near_aggregation() {
+    let rules = r#"
+title: In-memory credential dumping
+id: c0478ead-5336-46c2-bd5e-b4c84bc3a36e
+logsource:
+    product: windows
+    service: sysmon
+detection:
+    selector:
+        EventID: 10
+        TargetImage: 'C:\windows\system32\lsass.exe'
+    dllload1:
+        EventID: 7
+        ImageLoaded: '*\vaultcli.dll'
+    dllload2:
+        EventID: 7
+        ImageLoaded: '*\wlanapi.dll'
+    timeframe: 30s
+    condition: selector | near dllload1 and dllload2
+"#;
+    let mut backend = crate::correlation::state::mem::MemBackend::new().await;
+    let mut collection: SigmaCollection = rules.parse().unwrap();
+    collection.init(&mut backend).await;
+    assert!(collection.len() == 4);
+
+    let events = [
+        json!({"EventID": 7, "ImageLoaded": "C:\\windows\\system32\\vaultcli.dll"}),
+        json!({"EventID": 7, "ImageLoaded": "C:\\windows\\system32\\wlanapi.dll"}),
+        json!({"EventID": 10, "TargetImage": "C:\\windows\\system32\\lsass.exe"}),
+    ];
+
+    for data in &events[..2] {
+        let res = collection
+            .get_matches(&Event {
+                data: data.clone(),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert!(res.len() == 1);
+    }
+
+    let res = collection
+        .get_matches(&Event {
+            data: events[2].clone(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+    assert!(res.len() == 2);
+    assert!(&*res[1] == "c0478ead-5336-46c2-bd5e-b4c84bc3a36e");
+
+    let rules = rules.replace("dllload1 and dllload2", "dllload1 and not dllload2");
+    assert!(rules.parse::<SigmaCollection>().is_err());
+}
+
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_dependency_graph() {
+    let rules = format!(
+        "{}---\n{}",
+        COLLECTION,
+        r#"
+title: missing correlation
+id: 4
+correlation:
+    type: event_count
+    rules:
+        - event_count_detection
+        - missing
+    group-by:
+        - correlation_group_by
+    timespan: 10m
+    condition:
+        gte: 2
+"#
+    );
+    let mut collection = SigmaCollection::new().allow_missing_dependencies(true);
+    collection.load_from_str(&rules).unwrap();
+
+    let graph = collection.dependency_graph();
+    assert_eq!(graph["2"], vec!["0".into()]);
+    assert_eq!(graph["4"], vec!["0".into(), "missing".into()]);
+
+    let dot = collection.dependency_dot();
+    assert!(dot.contains("\"0\" -> \"4\";"));
+    assert!(dot.contains("\"missing\" -> \"4\";"));
+    assert!(dot.contains("\"missing\" [style=dashed];"));
+    assert!(dot.contains("\"4\" [label=\"missing correlation\\n4\", shape=box];"));
+}
+
+#[test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_chained_correlation() {
+    let rules = format!(
+        "{}---\n{}",
+        COLLECTION,
+        r#"
+title: repeated event correlation
+id: 4
+correlation:
+    type: event_count
+    rules:
+        - event_correlation
+    group-by:
+        - correlation_group_by
+    timespan: 10m
+    condition:
+        gte: 2
+"#
+    );
+    let event = Event::new(json!({
+        "foo": "bar",
+        "correlation_group_by": "test"
+    }));
+
+    // correlation rules are reached through the correlations they
+    // depend on, and only from the detections they depend on
+    let mut backend = crate::correlation::state::mem::MemBackend::new().await;
+    let mut collection: SigmaCollection = rules.parse().unwrap();
+    collection.init(&mut backend).await;
+    let mut matches = vec![];
+    for _ in 0..3 {
+        matches.push(collection.get_matches(&event).await.unwrap());
+    }
+    assert_eq!(matches[0], vec!["0".into()]);
+    assert_eq!(matches[1], vec!["0".into(), "2".into()]);
+    assert_eq!(matches[2], vec!["0".into(), "2".into(), "4".into()]);
+
+    let mut backend = crate::correlation::state::mem::MemBackend::new().await;
+    let mut collection: SigmaCollection = rules.parse().unwrap();
+    collection.init(&mut backend).await;
+    let batch = collection
+        .get_matches_batch(&[event.clone(), event.clone(), event])
+        .await
+        .unwrap();
+    assert_eq!(batch, matches);
+}
+
+#[test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_temporal_group_by_mapping() {
+    static RULES: &str = r#"
+title: linux failed login
+id: linux
+logsource:
+  category: correlation
+detection:
+  selection:
+    source: linux
+  condition: selection
+---
+title: windows failed login
+id: windows
+name: windows_failed_login
+logsource:
+  category: correlation
+detection:
+  selection:
+    source: windows
+  condition: selection
+---
+title: failed logins on both
+id: both
+correlation:
+    type: temporal
+    rules:
+        - linux
+        - windows
+    group-by:
+        - user
+    timespan: 10m
+"#;
+    let mut backend = crate::correlation::state::mem::MemBackend::new().await;
+    let mut collection = SigmaCollection::builder()
+        .map_group_by_field("linux", "user", "username")
+        .map_group_by_field("windows_failed_login", "user", "TargetUserName")
+        .build_from_str(RULES)
+        .unwrap();
+    collection.init(&mut backend).await;
+
+    let linux = Event::new(json!({"source": "linux", "username": "alice"}));
+    let windows = Event::new(json!({"source": "windows", "TargetUserName": "alice"}));
+    let other = Event::new(json!({"source": "windows", "TargetUserName": "bob"}));
+
+    let res = collection.get_matches(&linux).await.unwrap();
+    assert_eq!(res, vec!["linux".into()]);
+    let res = collection.get_matches(&other).await.unwrap();
+    assert_eq!(res, vec!["windows".into()]);
+    let res = collection.get_matches(&windows).await.unwrap();
+    assert_eq!(res, vec!["windows".into(), "both".into()]);
+}
+
+#[test]
+async fn test_referenced_fields() {
+    static RULES: &str = r#"
+title: linux failed login
+id: linux
+logsource:
+  product: linux
+detection:
+  selection:
+    source: linux
+    process.name|fieldref: parent.name
+  condition: selection
+---
+title: windows failed login
+id: windows
+logsource:
+  product: windows
+detection:
+  selection:
+    source: windows
+  condition: selection
+---
+title: windows keywords
+id: keywords
+logsource:
+  product: windows
+detection:
+  keywords:
+    - mimikatz
+  condition: keywords
+---
+title: failed logins on both
+id: both
+correlation:
+    type: temporal
+    rules:
+        - linux
+        - windows
+    group-by:
+        - user
+    timespan: 10m
+---
+title: failed logins from many hosts
+id: hosts
+correlation:
+    type: value_count
+    rules:
+        - both
+    group-by:
+        - user
+    timespan: 1h
+    condition:
+        gte: 3
+        field: host
+"#;
+    let collection = SigmaCollection::builder()
+        .map_group_by_field("windows", "user", "TargetUserName")
+        .build_from_str(RULES)
+        .unwrap();
+
+    let by_logsource = collection.referenced_fields_by_logsource();
+    assert_eq!(by_logsource.len(), 2);
+    let (linux, windows) = (&by_logsource[0], &by_logsource[1]);
+    assert_eq!(linux.0.product.as_deref(), Some("linux"));
+    assert_eq!(
+        linux.1.fields.iter().collect::<Vec<_>>(),
+        ["host", "parent.name", "process.name", "source", "user"]
+    );
+    assert!(!linux.1.keywords);
+    assert_eq!(windows.0.product.as_deref(), Some("windows"));
+    assert_eq!(
+        windows.1.fields.iter().collect::<Vec<_>>(),
+        ["TargetUserName", "host", "source"]
+    );
+    assert!(windows.1.keywords);
+
+    let fields = collection.referenced_fields();
+    assert_eq!(fields.fields.len(), 6);
+    assert!(fields.keywords);
+    assert!(fields.contains("anything"));
+}
+
+#[test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_custom_correlation_type() {
+    use crate::correlation::custom::CustomCorrelation;
+    use crate::correlation::state::{GroupBy, Key, RuleState};
+
+    /// matches every `every`th event of a group
+    struct Every(u64);
+
+    #[async_trait::async_trait]
+    impl CustomCorrelation for Every {
+        async fn is_match(
+            &self,
+            _: &Event,
+            matched: &[&str],
+            group_by: GroupBy,
+            state: &dyn RuleState,
+        ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+            if matched.is_empty() {
+                return Ok(false);
+            }
+            Ok(state.incr(&Key::EventCount(group_by)).await % self.0 == 0)
+        }
+    }
+
+    let rules = r#"
+title: event count detection
+id: 0
+logsource:
+  category: correlation
+detection:
+  selection:
+    foo: bar
+  condition: selection
+---
+title: every other event
+id: 1
+correlation:
+    type: every
+    every: 2
+    rules:
+        - "0"
+    group-by:
+        - correlation_group_by
+    timespan: 10m
+"#;
+    assert!(rules
+        .parse::<SigmaCollection>()
+        .unwrap_err()
+        .to_string()
+        .contains("unknown correlation type: every"));
+
+    let collection = SigmaCollection::builder()
+        .allow_unknown_correlation_types(true)
+        .build_from_str(rules)
+        .unwrap();
+    assert_eq!(
+        collection.unknown_correlation_types(),
+        vec![("1".into(), "every".to_string())]
+    );
+    assert!(collection.to_string().contains("every: 2"));
+
+    let mut backend = crate::correlation::state::mem::MemBackend::new().await;
+    let mut collection = SigmaCollection::builder()
+        .correlation_type("every", |config: &serde_json::Value| {
+            let n = config["every"].as_u64().ok_or("every is required")?;
+            Ok(Box::new(Every(n)) as Box<dyn CustomCorrelation>)
+        })
+        .build_from_str(rules)
+        .unwrap();
+    collection.init(&mut backend).await;
+    assert!(collection.unknown_correlation_types().is_empty());
+
+    let event = Event::new(json!({
+        "foo": "bar",
+        "correlation_group_by": "test"
+    }));
+    let mut matches = vec![];
+    for _ in 0..4 {
+        matches.push(collection.get_matches(&event).await.unwrap().len());
+    }
+    assert_eq!(matches, vec![1, 2, 1, 2]);
+
+    let rules = rules.replace("type: every", "type: event_count");
+    assert!(rules
+        .parse::<SigmaCollection>()
+        .unwrap_err()
+        .to_string()
+        .contains("invalid event_count correlation"));
+}
+
+#[test]
+async fn test_mem_backend_without_runtime() {
+    let rules = r#"
+title: event count detection
+id: 0
+logsource:
+  category: correlation
+detection:
+  selection:
+    foo: bar
+  condition: selection
+---
+title: event count correlation
+id: 1
+correlation:
+    type: event_count
+    rules:
+        - "0"
+    group-by:
+        - correlation_group_by
+    timespan: 1s
+    condition:
+        gte: 2
+"#;
+    let event = Event::new(json!({
+        "foo": "bar",
+        "correlation_group_by": "test"
+    }));
+    // a thread outside of the tokio runtime, driven by another executor
+    let matches = std::thread::spawn(move || {
+        futures_executor::block_on(async {
+            let mut backend = crate::correlation::state::mem::MemBackend::new().await;
+            let mut collection: SigmaCollection = rules.parse().unwrap();
+            collection.init(&mut backend).await;
+
+            let mut matches = vec![];
+            for _ in 0..2 {
+                matches.push(collection.get_matches(&event).await.unwrap().len());
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1100));
+            backend.expire().await;
+            for _ in 0..2 {
+                matches.push(collection.get_matches(&event).await.unwrap().len());
+            }
+            matches
+        })
+    })
+    .join()
+    .unwrap();
+    assert_eq!(matches, vec![1, 2, 1, 2]);
+}
+
+#[test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_get_matches_batch() {
+    let events = [
+        json!({"foo": "bar", "correlation_group_by": "a"}),
+        json!({"foo": "bar", "correlation_group_by": "b"}),
+        json!({"baz": "quux", "correlation_group_by": "a", "correlation_field": 1}),
+        json!({"foo": "bar", "correlation_group_by": "a"}),
+        json!({"foo": "bar"}),
+        json!({"baz": "quux", "correlation_group_by": "a", "correlation_field": 2}),
+        json!({"foo": "bar", "correlation_group_by": "b"}),
+    ]
+    .map(Event::new);
+
+    let mut backend = crate::correlation::state::mem::MemBackend::new().await;
+    let mut collection: SigmaCollection = COLLECTION.parse().unwrap();
+    collection.init(&mut backend).await;
+    let mut expected = vec![];
+    for event in &events {
+        expected.push(collection.get_matches(event).await.unwrap());
+    }
+
+    let mut backend = crate::correlation::state::mem::MemBackend::new().await;
+    let mut collection: SigmaCollection = COLLECTION.parse().unwrap();
+    collection.init(&mut backend).await;
+    let matches = collection.get_matches_batch(&events).await.unwrap();
+    assert_eq!(matches, expected);
+    assert_eq!(matches[3].len(), 2);
+    assert_eq!(matches[6].len(), 2);
+}
+
+#[test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_mem_backend_concurrent() {
+    let mut backend = crate::correlation::state::mem::MemBackend::new().await;
+    let mut collection: SigmaCollection = COLLECTION.parse().unwrap();
+    collection.init(&mut backend).await;
+    let collection = std::sync::Arc::new(collection);
+
+    let tasks = (0..8)
+        .map(|group| {
+            let collection = collection.clone();
+            tokio::spawn(async move {
+                let event = Event::new(json!({"foo": "bar", "correlation_group_by": group}));
+                let mut correlations = 0;
+                for _ in 0..50 {
+                    correlations += collection.get_matches(&event).await.unwrap().len() - 1;
+                }
+                correlations
+            })
+        })
+        .collect::<Vec<_>>();
+    for task in tasks {
+        assert_eq!(task.await.unwrap(), 49);
+    }
+
+    let key = crate::correlation::state::Key::EventCount(vec![(
+        "correlation_group_by".to_string(),
+        json!(3),
+    )]);
+    assert!(backend.ttl("2", &key).await.is_some());
+}
+
+#[test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_scan_ndjson() {
+    let rules = r#"
+title: logon
+id: logon
+logsource:
+  category: test
+detection:
+  selection:
+    action: logon
+  condition: selection
+---
+title: exfil
+id: exfil
+logsource:
+  category: test
+detection:
+  selection:
+    action: upload
+  condition: selection
+---
+title: logon then exfil
+id: logon-then-exfil
+correlation:
+    type: temporal_ordered
+    rules:
+        - logon
+        - exfil
+    group-by:
+        - user
+    timespan: 10m
+"#;
+    let log = r#"{"action": "logon", "user": "alice", "@timestamp": "2024-01-01T10:05:00Z"}
+
+{"action": "upload", "user": "alice", "@timestamp": "2024-01-01T10:00:00Z"}
+{"action": 
+{"action": "logon", "user": "bob"}
+"#;
+    let logsource = LogSource::default().category("test");
+
+    let mut backend = crate::correlation::state::mem::MemBackend::new().await;
+    let mut collection: SigmaCollection = rules.parse().unwrap();
+    collection.init(&mut backend).await;
+
+    // events are evaluated in stream order, by the detection rules only
+    let mut matches = collection.scan_ndjson(log.as_bytes(), &logsource);
+    let lines = matches
+        .by_ref()
+        .take(2)
+        .map(|m| {
+            let m = m.unwrap();
+            (m.line, Vec::from(m.matches))
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(lines, vec![(1, vec!["logon".into()]), (3, vec!["exfil".into()])]);
+    // malformed lines are reported, and the stream continues past them
+    assert!(matches.next().unwrap().unwrap_err().to_string().starts_with("line 4:"));
+    assert_eq!(matches.next().unwrap().unwrap().line, 5);
+    assert!(matches.next().is_none());
+
+    // the correlation state is left untouched
+    let event = Event::new(json!({"action": "upload", "user": "alice"}))
+        .logsource(logsource.clone());
+    assert_eq!(collection.get_matches(&event).await.unwrap(), vec!["exfil".into()]);
+}
+
+#[test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_get_matches_spawned() {
+    let mut backend = crate::correlation::state::mem::MemBackend::new().await;
+    let mut collection: SigmaCollection = COLLECTION.parse().unwrap();
+    collection.init(&mut backend).await;
+    let collection = std::sync::Arc::new(collection);
+
+    let event = Event::new(json!({"foo": "bar", "correlation_group_by": "test"}));
+    let tasks = (0..2)
+        .map(|_| {
+            let (collection, event) = (collection.clone(), event.clone());
+            tokio::spawn(async move { collection.get_matches(&event).await })
+        })
+        .collect::<Vec<_>>();
+    let mut matches = vec![];
+    for task in tasks {
+        matches.push(task.await.unwrap().unwrap().len());
+    }
+    matches.sort();
+    assert_eq!(matches, vec![1, 2]);
+}
+
+#[test]
+async fn test_memory_footprint() {
+    let mut rules: SigmaCollection = r#"
+title: test rule
+id: test-rule
+logsource:
+  category: test
+detection:
+  selection:
+    foo: bar
+  condition: selection
+---
+title: repeated test rule
+id: repeated-test-rule
+correlation:
+  type: event_count
+  rules:
+    - test-rule
+  group-by:
+    - user
+  timespan: 1m
+  condition:
+    gte: 2
+"#
+    .parse()
+    .unwrap();
+
+    // state is only reported once the rules are registered
+    let footprint = rules.memory_footprint().await;
+    assert!(footprint.rules > 0 && footprint.indexes > 0);
+    assert_eq!(footprint.correlation_state, None);
+
+    let mut backend = crate::correlation::state::mem::MemBackend::new().await;
+    rules.init(&mut backend).await;
+    assert_eq!(rules.memory_footprint().await.correlation_state, Some(0));
+
+    for user in ["alice", "bob", "carol"] {
+        let event = Event::new(json!({"foo": "bar", "user": user}));
+        rules.get_matches(&event).await.unwrap();
+    }
+    let state = rules.memory_footprint().await.correlation_state.unwrap();
+    assert!(state > 0);
+    let event = Event::new(json!({"foo": "bar", "user": "dave"}));
+    rules.get_matches(&event).await.unwrap();
+    assert!(rules.memory_footprint().await.correlation_state.unwrap() > state);
+}
+
+#[test]
+async fn test_suppressions() {
+    let mut rules: SigmaCollection = r#"
+title: test rule
+id: test-rule
+logsource:
+  category: test
+detection:
+  selection:
+    foo: bar
+  condition: selection
+---
+title: repeated test rule
+id: repeated-test-rule
+name: repeated
+correlation:
+  type: event_count
+  rules:
+    - test-rule
+  group-by:
+    - user
+  timespan: 1m
+  condition:
+    gte: 2
+"#
+    .parse()
+    .unwrap();
+    let mut backend = crate::correlation::state::mem::MemBackend::new().await;
+    rules.init(&mut backend).await;
+
+    let store = rules.suppressions().clone();
+    let scanner = store.add(
+        crate::Suppression::new("test-rule")
+            .field("user", "Scanner")
+            .reason("vulnerability scanner"),
+    );
+    store.add(crate::Suppression::new("repeated").field("user", "admin"));
+    store.add(
+        crate::Suppression::new("test-rule")
+            .field("user", "guest")
+            .expires_at(std::time::SystemTime::now() - std::time::Duration::from_secs(1)),
+    );
+
+    // suppressed detections are dropped and do not feed correlations
+    let scan = Event::new(json!({"foo": "bar", "user": "scanner"}));
+    for _ in 0..2 {
+        assert!(rules.get_matches(&scan).await.unwrap().is_empty());
+    }
+    let admin = Event::new(json!({"foo": "bar", "user": "admin"}));
+    rules.get_matches(&admin).await.unwrap();
+    assert_eq!(
+        rules.get_matches(&admin).await.unwrap(),
+        vec!["test-rule".into()]
+    );
+    let guest = Event::new(json!({"foo": "bar", "user": "guest"}));
+    assert_eq!(rules.get_matches(&guest).await.unwrap().len(), 1);
+
+    let entries = store.list();
+    assert_eq!(entries.len(), 3);
+    assert_eq!(entries[0].suppressed, 2);
+    assert_eq!(entries[1].suppressed, 1);
+    assert_eq!(store.suppressed(), 3);
+
+    let expired = store.expire();
+    assert_eq!(expired.len(), 1);
+    assert_eq!(expired[0].suppression.rule, "test-rule");
+    assert_eq!(store.list().len(), 2);
+
+    assert!(store.remove(scanner).is_some());
+    assert!(store.remove(scanner).is_none());
+    assert_eq!(rules.get_matches(&scan).await.unwrap().len(), 1);
+}
+
+#[test]
+async fn test_throttle() {
+    let matched = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+    let observed = matched.clone();
+    let mut rules = SigmaCollection::builder()
+        .throttle(
+            crate::Throttle::new(1, std::time::Duration::from_secs(60))
+                .rules(["test-rule"])
+                .group_by(["user"]),
+        )
+        .on_match(move |rule, _, _| observed.lock().unwrap().push(rule.id.clone()))
+        .build_from_str(
+            r#"
+title: test rule
+id: test-rule
+logsource:
+  category: test
+detection:
+  selection:
+    foo: bar
+  condition: selection
+---
+title: repeated test rule
+id: repeated-test-rule
+correlation:
+  type: event_count
+  rules:
+    - test-rule
+  group-by:
+    - user
+  timespan: 1m
+  condition:
+    gte: 3
+"#,
+        )
+        .unwrap();
+    let mut backend = crate::correlation::state::mem::MemBackend::new().await;
+    rules.init(&mut backend).await;
+
+    // throttled detections still count towards correlations
+    let admin = Event::new(json!({"foo": "bar", "user": "admin"}));
+    assert_eq!(
+        rules.get_matches(&admin).await.unwrap(),
+        vec!["test-rule".into()]
+    );
+    let evaluation = rules.evaluate(&admin).await.unwrap();
+    assert!(evaluation.matches.is_empty());
+    assert_eq!(evaluation.throttled, vec!["test-rule".into()]);
+    assert_eq!(
+        rules.get_matches(&admin).await.unwrap(),
+        vec!["repeated-test-rule".into()]
+    );
+
+    let guest = Event::new(json!({"foo": "bar", "user": "guest"}));
+    let evaluations = rules.evaluate_batch(&[guest.clone(), guest]).await.unwrap();
+    assert_eq!(evaluations[0].matches, vec!["test-rule".into()]);
+    assert!(evaluations[1].matches.is_empty());
+    assert_eq!(evaluations[1].throttled, vec!["test-rule".into()]);
+
+    assert_eq!(
+        *matched.lock().unwrap(),
+        vec![
+            "test-rule".into(),
+            "repeated-test-rule".into(),
+            "test-rule".into()
+        ]
+    );
+}
+
+#[test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_throttle_concurrent() {
+    let mut rules = SigmaCollection::builder()
+        .throttle(crate::Throttle::new(3, std::time::Duration::from_secs(60)))
+        .build_from_str(
+            r#"
+title: test rule
+id: test-rule
+logsource:
+  category: test
+detection:
+  selection:
+    foo: bar
+  condition: selection
+"#,
+        )
+        .unwrap();
+    let mut backend = crate::correlation::state::mem::MemBackend::new().await;
+    rules.init(&mut backend).await;
+    let rules = std::sync::Arc::new(rules);
+
+    // concurrent matches are checked and counted at once
+    let tasks = (0..32)
+        .map(|_| {
+            let rules = rules.clone();
+            tokio::spawn(async move {
+                let event = Event::new(json!({"foo": "bar"}));
+                rules.get_matches(&event).await.unwrap().len()
+            })
+        })
+        .collect::<Vec<_>>();
+    let mut matched = 0;
+    for task in tasks {
+        matched += task.await.unwrap();
+    }
+    assert_eq!(matched, 3);
+}
+
+#[test]
+async fn test_dedup() {
+    let matched = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+    let observed = matched.clone();
+    let mut rules = SigmaCollection::builder()
+        .dedup(crate::Dedup::new(std::time::Duration::from_secs(60)).fields(["user"]))
+        .on_match(move |rule, _, _| observed.lock().unwrap().push(rule.id.clone()))
+        .build_from_str(
+            r#"
+title: test rule
+id: test-rule
+logsource:
+  category: test
+detection:
+  selection:
+    foo: bar
+  condition: selection
+---
+title: repeated test rule
+id: repeated-test-rule
+correlation:
+  type: event_count
+  rules:
+    - test-rule
+  group-by:
+    - user
+  timespan: 1h
+  condition:
+    gte: 3
+"#,
+        )
+        .unwrap();
+    let mut backend = crate::correlation::state::mem::MemBackend::new().await;
+    rules.init(&mut backend).await;
+
+    let event = |user: &str, time: &str| {
+        Event::new(json!({"foo": "bar", "user": user, "@timestamp": time}))
+    };
+    let admin = event("admin", "2024-06-01T12:00:00Z");
+    let evaluations = rules
+        .evaluate_batch(&[admin.clone(), admin.clone(), admin.clone(), admin])
+        .await
+        .unwrap();
+    assert_eq!(evaluations[0].matches, vec!["test-rule".into()]);
+    assert!(evaluations[1].matches.is_empty());
+    assert_eq!(evaluations[1].duplicates, vec!["test-rule".into()]);
+    // duplicate detections still count towards correlations
+    assert_eq!(evaluations[2].matches, vec!["repeated-test-rule".into()]);
+    assert!(evaluations[3].matches.is_empty());
+    assert_eq!(evaluations[3].duplicates.len(), 2);
+
+    let guest = event("guest", "2024-06-01T12:00:30Z");
+    assert_eq!(rules.get_matches(&guest).await.unwrap(), vec!["test-rule".into()]);
+    let admin = event("admin", "2024-06-01T12:01:00Z");
+    assert_eq!(rules.get_matches(&admin).await.unwrap().len(), 2);
+
+    assert_eq!(
+        *matched.lock().unwrap(),
+        vec![
+            "test-rule".into(),
+            "repeated-test-rule".into(),
+            "test-rule".into(),
+            "test-rule".into(),
+            "repeated-test-rule".into(),
+        ]
+    );
+}
+
+#[cfg(feature = "updater")]
+#[test]
+async fn test_rule_updater() {
+    use crate::updater::{RuleSource, RuleUpdater, Update};
+    use crate::SharedSigmaCollection;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let dir = std::env::temp_dir().join(format!("sigmars-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let write = |detection: &str, secs: u64| {
+        let path = dir.join("rules.yml");
+        std::fs::write(&path, format!(
+            "{}tests:\n  positive:\n    - foo: bar\n---\ntitle: twice\nid: twice\ncorrelation:\n  type: event_count\n  rules:\n    - a\n  group-by:\n    - user\n  timespan: 1m\n  condition:\n    gte: 2\n",
+            super::test_rule("a", "", detection)
+        ))
+        .unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(secs))
+            .unwrap();
+    };
+    write("bar", 1);
+
+    let shared = Arc::new(SharedSigmaCollection::new(SigmaCollection::new()));
+    let updates = Arc::new(AtomicUsize::new(0));
+    let counter = updates.clone();
+    let backend = crate::correlation::state::mem::MemBackend::new().await;
+    let source = RuleSource::dir(dir.to_str().unwrap());
+    let mut updater = RuleUpdater::new(source, shared.clone(), backend).on_update(move |_| {
+        counter.fetch_add(1, Ordering::SeqCst);
+    });
+    assert_eq!(updater.update().await, Update::Applied { rules: 2 });
+    assert_eq!(updater.update().await, Update::Unchanged);
+
+    // correlation rules are registered with the backend
+    let event = Event::new(json!({"foo": "bar", "user": "alice"}));
+    shared.get_matches(&event).await.unwrap();
+    let matches = shared.get_matches(&event).await.unwrap().into_vec();
+    assert!(matches.contains(&"twice".into()));
+
+    // the rule no longer matches its positive sample event
+    write("baz", 2);
+    assert!(matches!(updater.update().await, Update::Rejected(_)));
+    assert_eq!(shared.get_detection_matches(&event).len(), 1);
+
+    write("bar\n  invalid", 3);
+    assert!(matches!(updater.update().await, Update::Failed(_)));
+    assert_eq!(shared.load().len(), 2);
+    assert_eq!(updates.load(Ordering::SeqCst), 4);
+
+    let mut updater = RuleUpdater::new(
+        RuleSource::dir(dir.to_str().unwrap()),
+        shared.clone(),
+        crate::correlation::state::mem::MemBackend::new().await,
+    )
+    .validate(|_, failures| match failures.len() {
+        1 => Ok(()),
+        _ => Err("unexpected failures".to_string()),
+    });
+    write("baz", 4);
+    assert_eq!(updater.update().await, Update::Applied { rules: 2 });
+    assert!(shared.get_detection_matches(&event).is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+async fn test_warm_up() {
+    let matched = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+    let observed = matched.clone();
+    let mut rules = SigmaCollection::builder()
+        .on_match(move |rule, _, _| observed.lock().unwrap().push(rule.id.clone()))
+        .build_from_str(
+            r#"
+title: test rule
+id: test-rule
+logsource:
+  category: test
+detection:
+  selection:
+    foo: bar
+  condition: selection
+---
+title: repeated test rule
+id: repeated-test-rule
+correlation:
+  type: event_count
+  rules:
+    - test-rule
+  group-by:
+    - user
+  timespan: 1h
+  condition:
+    gte: 3
+"#,
+        )
+        .unwrap();
+    let mut backend = crate::correlation::state::mem::MemBackend::new().await;
+    rules.init(&mut backend).await;
+
+    let recent = chrono::Utc::now().to_rfc3339();
+    let event = |time: &str| Event::new(json!({"foo": "bar", "user": "admin", "@timestamp": time}));
+    // the event from last year is outside the timespan of the correlation
+    let history = [
+        event(&recent),
+        event("2024-06-01T12:00:00Z"),
+        event(&recent),
+    ];
+    assert_eq!(rules.warm_up(&history).await.unwrap(), 2);
+    assert!(matched.lock().unwrap().is_empty());
+
+    let matches = rules.get_matches(&event(&recent)).await.unwrap();
+    assert_eq!(
+        matches,
+        vec!["test-rule".into(), "repeated-test-rule".into()]
+    );
+    assert_eq!(
+        *matched.lock().unwrap(),
+        vec!["test-rule".into(), "repeated-test-rule".into()]
+    );
+}
